@@ -17,7 +17,8 @@ use crate::{
     errors::{Kind as ErrorKind, Result},
 };
 use std::fmt::{Debug, Display};
-use tremor_script::Value;
+use tremor_script::prelude::*;
+pub(crate) mod avro;
 pub(crate) mod binary;
 pub(crate) mod binflux;
 pub(crate) mod csv;
@@ -25,6 +26,8 @@ pub(crate) mod influx;
 pub(crate) mod json;
 pub(crate) mod msgpack;
 pub(crate) mod null;
+pub(crate) mod prometheus;
+pub(crate) mod protobuf;
 pub(crate) mod statsd;
 pub(crate) mod string;
 pub(crate) mod syslog;
@@ -103,26 +106,76 @@ impl Debug for dyn Codec {
 ///
 /// # Errors
 ///  * if the codec doesn't exist
+///  * if the codec config is invalid
 pub fn resolve(config: &config::Codec) -> Result<Box<dyn Codec>> {
+    let codec_config = config.config.as_ref();
     match config.name.as_str() {
-        "json" => Ok(Box::new(json::Json::<json::Unsorted>::default())),
-        "json-sorted" => Ok(Box::new(json::Json::<json::Sorted>::default())),
-        "msgpack" => Ok(Box::new(msgpack::MsgPack {})),
-        "influx" => Ok(Box::new(influx::Influx {})),
-        "binflux" => Ok(Box::new(binflux::BInflux {})),
-        "null" => Ok(Box::new(null::Null {})),
-        "string" => Ok(Box::new(string::String {})),
-        "statsd" => Ok(Box::new(statsd::StatsD {})),
-        "yaml" => Ok(Box::new(yaml::Yaml {})),
-        "binary" => Ok(Box::new(binary::Binary {})),
-        "syslog" => Ok(Box::new(syslog::Syslog::utcnow())),
-        "csv" => Ok(Box::new(csv::Csv {})),
+        "json" => json::from_config(codec_config),
+        "json-sorted" => Ok(Box::new(json::Json::<json::Sorted>::from_config(
+            codec_config,
+        )?)),
+        "msgpack" => without_config(config, msgpack::MsgPack {}),
+        "influx" => without_config(config, influx::Influx {}),
+        "binflux" => without_config(config, binflux::BInflux {}),
+        "null" => without_config(config, null::Null {}),
+        "string" => without_config(config, string::String {}),
+        "statsd" => without_config(config, statsd::StatsD {}),
+        "prometheus" => without_config(config, prometheus::Prometheus {}),
+        prometheus::REMOTE_WRITE => without_config(config, prometheus::RemoteWrite {}),
+        "yaml" => without_config(config, yaml::Yaml {}),
+        "binary" => without_config(config, binary::Binary {}),
+        "syslog" => without_config(config, syslog::Syslog::utcnow()),
+        "csv" => Ok(Box::new(csv::Csv::from_config(codec_config)?)),
+        "protobuf" => Ok(Box::new(protobuf::Protobuf::from_config(codec_config)?)),
+        "avro" => Ok(Box::new(avro::Avro::from_config(codec_config)?)),
         s => Err(ErrorKind::CodecNotFound(s.into()).into()),
     }
 }
 
+/// Deserializes the options of the codec `name`, using the defaults if none are given
+///
+/// # Errors
+///  * if the options are invalid or contain unknown fields
+pub(crate) fn options<T>(name: &str, config: Option<&Value>) -> Result<T>
+where
+    T: serde::Deserialize<'static> + Default,
+{
+    config.map_or_else(|| Ok(T::default()), |_| required_options(name, config))
+}
+
+/// Deserializes the options of the codec `name`, that can't do without them
+///
+/// # Errors
+///  * if no options are given, or they are invalid or contain unknown fields
+pub(crate) fn required_options<T>(name: &str, config: Option<&Value>) -> Result<T>
+where
+    T: serde::Deserialize<'static>,
+{
+    let config = config.ok_or_else(|| ErrorKind::MissingConfiguration(format!("codec {name}")))?;
+    tremor_value::structurize(config.clone_static())
+        .map_err(|e| ErrorKind::InvalidConfiguration(format!("codec {name}"), e.to_string()).into())
+}
+
+/// Makes sure a codec that has no options isn't configured with any
+fn without_config<C>(config: &config::Codec, codec: C) -> Result<Box<dyn Codec>>
+where
+    C: Codec + 'static,
+{
+    match config.config.as_ref() {
+        Some(c) if !c.as_object().map_or_else(|| c.is_null(), |o| o.is_empty()) => {
+            Err(ErrorKind::InvalidConfiguration(
+                format!("codec {}", config.name),
+                "This codec doesn't take any options".to_string(),
+            )
+            .into())
+        }
+        _ => Ok(Box::new(codec)),
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use tremor_value::literal;
 
     #[test]
     fn lookup() {
@@ -136,9 +189,37 @@ mod test {
         assert!(super::resolve(&"statsd".into()).is_ok());
         assert!(super::resolve(&"yaml".into()).is_ok());
         assert!(super::resolve(&"syslog".into()).is_ok());
+        assert!(super::resolve(&"prometheus".into()).is_ok());
+        assert!(super::resolve(&"prometheus-remote-write".into()).is_ok());
         assert_eq!(
             super::resolve(&"snot".into()).err().unwrap().to_string(),
             "Codec \"snot\" not found."
         )
     }
+
+    #[test]
+    fn lookup_with_config() {
+        let config = |name: &str, config| crate::config::Codec {
+            name: name.to_string(),
+            config: Some(config),
+        };
+        assert!(super::resolve(&config("json", literal!({"pretty": true}))).is_ok());
+        assert!(super::resolve(&config("json", literal!({"sorted": true}))).is_ok());
+        assert!(super::resolve(&config("csv", literal!({"delimiter": ";"}))).is_ok());
+        assert!(super::resolve(&config("string", literal!({}))).is_ok());
+        assert_eq!(
+            super::resolve(&config("json", literal!({"snot": "badger"})))
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid Configuration for codec json: unknown field `snot`, expected `sorted` or `pretty`"
+        );
+        assert_eq!(
+            super::resolve(&config("string", literal!({"snot": "badger"})))
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid Configuration for codec string: This codec doesn't take any options"
+        );
+    }
 }
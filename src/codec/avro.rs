@@ -0,0 +1,513 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Apache Avro codec
+//!
+//! Without a `registry` it reads and writes raw avro datums of the configured `schema`.
+//! With a `registry` it uses the confluent wire format: a zero magic byte and the big endian
+//! schema id in front of the datum. Schemas for decoding are looked up in the registry by
+//! their id, once per id. Encoding uses the latest schema registered for `subject`, it is looked up
+//! when the codec is created and refreshed in the background once it is older than a minute.
+//!
+//! Codecs are synchronous, so registry lookups run in tasks of their own and never block the
+//! connector: events are failed while the schema they need is being looked up. Lookups time out
+//! after 5 seconds, a failed lookup is only retried after a backoff, doubling up to a minute.
+
+use crate::codec::prelude::*;
+use apache_avro::{from_avro_datum, to_avro_datum, types::Value as AvroValue, Schema};
+use async_std::future::timeout;
+use beef::Cow;
+use hashbrown::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MAGIC_BYTE: u8 = 0;
+/// timeout of requests to the registry
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(5);
+/// time after which the latest schema of the `subject` is looked up again
+const LATEST_SCHEMA_TTL: Duration = Duration::from_secs(60);
+/// time after which a failed lookup is retried the first time
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// longest time after which a failed lookup is retried
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Options of the avro codec
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// the avro schema, as a record or a json string
+    #[serde(default)]
+    schema: Option<Value<'static>>,
+    /// base url of a confluent schema registry
+    #[serde(default)]
+    registry: Option<String>,
+    /// the registry subject whose latest schema is used for encoding
+    #[serde(default)]
+    subject: Option<String>,
+}
+
+fn invalid_config<E: ToString>(e: E) -> Error {
+    ErrorKind::InvalidConfiguration("codec avro".to_string(), e.to_string()).into()
+}
+
+/// A schema as returned by the registry
+#[derive(Debug, Deserialize)]
+struct RegisteredSchema {
+    #[serde(default)]
+    id: Option<u32>,
+    schema: String,
+}
+
+/// A schema to look up in the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    /// the schema with the given id
+    Id(u32),
+    /// the latest schema of the `subject`
+    Latest,
+}
+
+/// The state of a schema lookup
+enum Lookup {
+    /// the lookup is in progress
+    Pending,
+    Found {
+        id: u32,
+        schema: Arc<Schema>,
+        fetched: Instant,
+    },
+    /// the lookup failed, it is retried after `retry_at`
+    Failed {
+        error: String,
+        retry_at: Instant,
+        backoff: Duration,
+    },
+}
+
+/// Client of a confluent schema registry, caching the schemas it looked up
+#[derive(Clone)]
+struct Registry {
+    url: String,
+    /// the registry subject to encode with
+    subject: Option<String>,
+    /// shared with the lookup tasks, the lock is never held across a request
+    schemas: Arc<Mutex<HashMap<Key, Lookup>>>,
+}
+
+impl Registry {
+    fn new(url: &str, subject: Option<String>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            subject,
+            schemas: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn describe(&self, key: Key) -> String {
+        match (key, &self.subject) {
+            (Key::Id(id), _) => format!("schema {id}"),
+            (Key::Latest, Some(subject)) => format!("latest schema of {subject}"),
+            (Key::Latest, None) => "latest schema".to_string(),
+        }
+    }
+
+    async fn fetch(&self, key: Key) -> Result<(u32, Schema)> {
+        let path = match (key, &self.subject) {
+            (Key::Id(id), _) => format!("schemas/ids/{id}"),
+            (Key::Latest, Some(subject)) => format!("subjects/{subject}/versions/latest"),
+            (Key::Latest, None) => {
+                return Err("Encoding with a schema registry needs a `subject`".into())
+            }
+        };
+        let url = format!("{}/{path}", self.url);
+        let registered: RegisteredSchema = timeout(REGISTRY_TIMEOUT, surf::get(&url).recv_json())
+            .await
+            .map_err(|_| format!("Timeout fetching schema from {url}"))?
+            .map_err(|e| format!("Error fetching schema from {url}: {e}"))?;
+        let id = match key {
+            Key::Id(id) => id,
+            Key::Latest => registered
+                .id
+                .ok_or_else(|| format!("Missing id of the {}", self.describe(key)))?,
+        };
+        Ok((id, Schema::parse_str(&registered.schema)?))
+    }
+
+    /// looks up the schema in a task of its own, `backoff` is the time to wait before retrying if it fails
+    fn lookup(&self, key: Key, backoff: Duration) {
+        let registry = self.clone();
+        async_std::task::spawn(async move {
+            let res = registry.fetch(key).await;
+            let mut schemas = match registry.schemas.lock() {
+                Ok(schemas) => schemas,
+                Err(_) => return error!("Poisoned avro schema cache"),
+            };
+            match res {
+                Ok((id, schema)) => {
+                    schemas.insert(
+                        key,
+                        Lookup::Found {
+                            id,
+                            schema: Arc::new(schema),
+                            fetched: Instant::now(),
+                        },
+                    );
+                }
+                // keep using the schema we have until the registry is reachable again
+                Err(e) if matches!(schemas.get(&key), Some(Lookup::Found { .. })) => {
+                    warn!("Error refreshing the {}: {e}", registry.describe(key));
+                }
+                Err(e) => {
+                    warn!("Error looking up the {}: {e}", registry.describe(key));
+                    schemas.insert(
+                        key,
+                        Lookup::Failed {
+                            error: e.to_string(),
+                            retry_at: Instant::now() + backoff,
+                            backoff,
+                        },
+                    );
+                }
+            }
+        });
+    }
+
+    /// returns the schema if it was looked up already, starts looking it up otherwise
+    fn get(&self, key: Key) -> Result<(u32, Arc<Schema>)> {
+        let mut schemas = self
+            .schemas
+            .lock()
+            .map_err(|_| "Poisoned avro schema cache")?;
+        let backoff = match schemas.get_mut(&key) {
+            Some(Lookup::Found {
+                id,
+                schema,
+                fetched,
+            }) => {
+                if key == Key::Latest && fetched.elapsed() >= LATEST_SCHEMA_TTL {
+                    // refreshed in the background, meanwhile the one we have is used
+                    *fetched = Instant::now();
+                    self.lookup(key, MIN_BACKOFF);
+                }
+                return Ok((*id, schema.clone()));
+            }
+            Some(Lookup::Pending) => None,
+            Some(Lookup::Failed {
+                error,
+                retry_at,
+                backoff,
+            }) => {
+                if Instant::now() < *retry_at {
+                    return Err(format!(
+                        "Error looking up the {}: {error}, retrying in {}s",
+                        self.describe(key),
+                        retry_at.saturating_duration_since(Instant::now()).as_secs()
+                    )
+                    .into());
+                }
+                Some((*backoff * 2).min(MAX_BACKOFF))
+            }
+            None => Some(MIN_BACKOFF),
+        };
+        if let Some(backoff) = backoff {
+            schemas.insert(key, Lookup::Pending);
+            self.lookup(key, backoff);
+        }
+        Err(format!("Looking up the {}", self.describe(key)).into())
+    }
+}
+
+#[derive(Clone)]
+pub struct Avro {
+    /// the schema to use, when there is no registry
+    schema: Option<Schema>,
+    registry: Option<Registry>,
+}
+
+impl Avro {
+    pub(crate) fn from_config(config: Option<&Value>) -> Result<Self> {
+        let config: Config = super::required_options("avro", config)?;
+        let schema = config
+            .schema
+            .as_ref()
+            .map(|schema| match schema.as_str() {
+                Some(s) => Schema::parse_str(s),
+                None => Schema::parse_str(&schema.encode()),
+            })
+            .transpose()
+            .map_err(invalid_config)?;
+        match (config.registry, config.subject) {
+            (Some(url), subject) => {
+                let registry = Registry::new(&url, subject);
+                if registry.subject.is_some() {
+                    // look up the schema to encode with ahead of the first event, it isn't there yet
+                    let _pending = registry.get(Key::Latest);
+                }
+                Ok(Self {
+                    schema: None,
+                    registry: Some(registry),
+                })
+            }
+            (None, Some(_)) => Err(invalid_config("`subject` needs a `registry`")),
+            (None, None) => {
+                let schema = schema.ok_or_else(|| invalid_config("Missing `schema`"))?;
+                Ok(Self {
+                    schema: Some(schema),
+                    registry: None,
+                })
+            }
+        }
+    }
+
+    /// encodes in the confluent wire format with the latest schema of the `subject`
+    fn encode_latest(registry: &Registry, data: &Value) -> Result<Vec<u8>> {
+        if registry.subject.is_none() {
+            return Err("Encoding with a schema registry needs a `subject`".into());
+        }
+        let (id, schema) = registry.get(Key::Latest)?;
+        let value = apache_avro::to_value(data)?.resolve(&schema)?;
+        let datum = to_avro_datum(&schema, value)?;
+        let mut res = Vec::with_capacity(datum.len() + 5);
+        res.push(MAGIC_BYTE);
+        res.extend_from_slice(&id.to_be_bytes());
+        res.extend_from_slice(&datum);
+        Ok(res)
+    }
+}
+
+fn to_value(value: AvroValue) -> Result<Value<'static>> {
+    Ok(match value {
+        AvroValue::Null => Value::null(),
+        AvroValue::Boolean(b) => Value::from(b),
+        AvroValue::Int(i) | AvroValue::Date(i) | AvroValue::TimeMillis(i) => Value::from(i),
+        AvroValue::Long(i)
+        | AvroValue::TimeMicros(i)
+        | AvroValue::TimestampMillis(i)
+        | AvroValue::TimestampMicros(i) => Value::from(i),
+        AvroValue::Float(f) => Value::from(f64::from(f)),
+        AvroValue::Double(f) => Value::from(f),
+        AvroValue::Bytes(b) | AvroValue::Fixed(_, b) => Value::Bytes(b.into()),
+        AvroValue::String(s) | AvroValue::Enum(_, s) => Value::from(s),
+        AvroValue::Uuid(u) => Value::from(u.to_string()),
+        AvroValue::Union(_, v) => to_value(*v)?,
+        AvroValue::Array(values) => Value::from(
+            values
+                .into_iter()
+                .map(to_value)
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        AvroValue::Map(entries) => Value::from(
+            entries
+                .into_iter()
+                .map(|(k, v)| Ok((Cow::from(k), to_value(v)?)))
+                .collect::<Result<Object>>()?,
+        ),
+        AvroValue::Record(fields) => Value::from(
+            fields
+                .into_iter()
+                .map(|(k, v)| Ok((Cow::from(k), to_value(v)?)))
+                .collect::<Result<Object>>()?,
+        ),
+        AvroValue::Decimal(_) | AvroValue::Duration(_) => {
+            return Err("Avro decimals and durations are not supported".into())
+        }
+    })
+}
+
+impl Codec for Avro {
+    fn name(&self) -> &str {
+        "avro"
+    }
+
+    fn mime_types(&self) -> Vec<&'static str> {
+        vec!["avro/binary"]
+    }
+
+    fn decode<'input>(
+        &mut self,
+        data: &'input mut [u8],
+        _ingest_ns: u64,
+    ) -> Result<Option<Value<'input>>> {
+        let value = match (&self.registry, &self.schema) {
+            (Some(registry), _) => match &*data {
+                [MAGIC_BYTE, a, b, c, d, datum @ ..] => {
+                    let id = u32::from_be_bytes([*a, *b, *c, *d]);
+                    let (_, schema) = registry.get(Key::Id(id))?;
+                    let mut datum: &[u8] = datum;
+                    from_avro_datum(&schema, &mut datum, None)?
+                }
+                [MAGIC_BYTE, ..] => {
+                    return Err("Invalid confluent wire format: missing schema id".into())
+                }
+                _ => return Err("Invalid confluent wire format: missing magic byte".into()),
+            },
+            (None, Some(schema)) => from_avro_datum(schema, &mut &*data, None)?,
+            (None, None) => return Err("Missing avro schema for decoding".into()),
+        };
+        Ok(Some(to_value(value)?))
+    }
+
+    fn encode(&self, data: &Value) -> Result<Vec<u8>> {
+        match (&self.registry, &self.schema) {
+            (Some(registry), _) => Self::encode_latest(registry, data),
+            (None, Some(schema)) => {
+                let value = apache_avro::to_value(data)?.resolve(schema)?;
+                Ok(to_avro_datum(schema, value)?)
+            }
+            (None, None) => Err("Missing avro schema for encoding".into()),
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Codec> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_std::net::TcpListener;
+    use tremor_value::literal;
+
+    const SCHEMA: &str = r#"{
+        "type": "record",
+        "name": "snot",
+        "fields": [
+            {"name": "name", "type": "string"},
+            {"name": "count", "type": "int"},
+            {"name": "tags", "type": {"type": "array", "items": "string"}},
+            {"name": "scores", "type": {"type": "map", "values": "double"}},
+            {"name": "kind", "type": {"type": "enum", "name": "kind", "symbols": ["snot", "badger"]}},
+            {"name": "comment", "type": ["null", "string"], "default": null},
+            {"name": "payload", "type": "bytes"}
+        ]
+    }"#;
+
+    fn value() -> Value<'static> {
+        literal!({
+            "name": "snot",
+            "count": 3,
+            "tags": ["a", "b"],
+            "scores": {"badger": 0.5},
+            "kind": "badger",
+            "comment": null,
+            "payload": Value::Bytes(b"badger".to_vec().into())
+        })
+    }
+
+    #[test]
+    fn raw_roundtrip() -> Result<()> {
+        let config = literal!({ "schema": SCHEMA });
+        let mut codec = Avro::from_config(Some(&config))?;
+        let mut data = codec.encode(&value())?;
+        assert_eq!(Some(value()), codec.decode(&mut data, 0)?);
+
+        // schemas can be given as records as well
+        let schema = tremor_value::parse_to_value(&mut SCHEMA.as_bytes().to_vec())?.into_static();
+        let config = literal!({ "schema": schema });
+        let mut codec = Avro::from_config(Some(&config))?;
+        let mut data = codec.encode(&value())?;
+        assert_eq!(Some(value()), codec.decode(&mut data, 0)?);
+
+        assert!(codec.encode(&literal!({"name": "snot"})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(Avro::from_config(None).is_err());
+        assert!(Avro::from_config(Some(&literal!({}))).is_err());
+        assert!(Avro::from_config(Some(&literal!({"schema": "snot"}))).is_err());
+        assert!(Avro::from_config(Some(&literal!({"subject": "snot"}))).is_err());
+    }
+
+    /// retries `f` until it succeeds or the schema lookups had plenty of time
+    fn eventually<T>(mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut res = f();
+        for _ in 0..50 {
+            if res.is_ok() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            res = f();
+        }
+        res
+    }
+
+    #[test]
+    fn unreachable_registry() -> Result<()> {
+        let config = literal!({
+            "registry": "http://127.0.0.1:1",
+            "subject": "snot"
+        });
+        // only the events needing the registry fail
+        let mut codec = Avro::from_config(Some(&config))?;
+        assert!(codec.encode(&value()).is_err());
+        let mut data = vec![MAGIC_BYTE, 0, 0, 0, 42, 1];
+        assert!(codec.decode(&mut data, 0).is_err());
+
+        // failed lookups are not retried right away
+        let failed = eventually(|| match codec.encode(&value()) {
+            Err(e) if e.to_string().contains("retrying in") => Ok(()),
+            Err(e) => Err(e),
+            Ok(_) => Err("Encoded without a schema".into()),
+        });
+        assert!(failed.is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn registry_roundtrip() -> Result<()> {
+        let addr = async_std::task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            listener.local_addr()
+        })?;
+        let mut server = tide::new();
+        server.at("/subjects/snot/versions/latest").get(|_| async {
+            Ok::<_, tide::Error>(tide::Body::from_json(
+                &literal!({"id": 42, "version": 1, "schema": SCHEMA}),
+            )?)
+        });
+        server.at("/schemas/ids/42").get(|_| async {
+            Ok::<_, tide::Error>(tide::Body::from_json(&literal!({ "schema": SCHEMA }))?)
+        });
+        let server = async_std::task::spawn(server.listen(addr.to_string()));
+        // wait for the server to come up
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let config = literal!({
+            "registry": format!("http://{addr}/"),
+            "subject": "snot"
+        });
+        let mut codec = Avro::from_config(Some(&config))?;
+        // schemas are looked up in the background
+        let data = eventually(|| codec.encode(&value()))?;
+        assert_eq!(&[MAGIC_BYTE, 0, 0, 0, 42], &data[..5]);
+        let decoded = eventually(|| {
+            let mut data = data.clone();
+            codec
+                .decode(&mut data, 0)
+                .map(|value| value.map(Value::into_static))
+        })?;
+        assert_eq!(Some(value()), decoded);
+
+        let mut data = vec![1, 2, 3];
+        assert!(codec.decode(&mut data, 0).is_err());
+        let mut data = vec![MAGIC_BYTE, 0, 0];
+        assert!(codec.decode(&mut data, 0).is_err());
+
+        async_std::task::block_on(server.cancel());
+        Ok(())
+    }
+}
@@ -14,8 +14,190 @@
 use crate::codec::prelude::*;
 use beef::Cow;
 
+/// Quoting of fields when encoding
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum QuoteStyle {
+    /// quote all fields
+    Always,
+    /// quote only fields that contain the delimiter, quotes or line breaks
+    Necessary,
+    /// quote all fields that aren't numbers
+    NonNumeric,
+    /// never quote fields
+    Never,
+}
+
+impl Default for QuoteStyle {
+    fn default() -> Self {
+        Self::Necessary
+    }
+}
+
+impl From<QuoteStyle> for csv::QuoteStyle {
+    fn from(style: QuoteStyle) -> Self {
+        match style {
+            QuoteStyle::Always => Self::Always,
+            QuoteStyle::Necessary => Self::Necessary,
+            QuoteStyle::NonNumeric => Self::NonNumeric,
+            QuoteStyle::Never => Self::Never,
+        }
+    }
+}
+
+/// Where the names of the columns come from
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Headers {
+    /// `true` reads them from the first line of each stream,
+    /// `false` leaves the columns unnamed so lines decode to arrays
+    FirstLine(bool),
+    /// explicitly configured names
+    Names(Vec<String>),
+}
+
+impl Default for Headers {
+    fn default() -> Self {
+        Self::FirstLine(false)
+    }
+}
+
+/// Options of the csv codec
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// the field delimiter, a single byte
+    #[serde(default = "default_delimiter")]
+    delimiter: String,
+    /// the quote character, a single byte
+    #[serde(default = "default_quote")]
+    quote: String,
+    /// when to quote fields on encoding
+    #[serde(default)]
+    quote_style: QuoteStyle,
+    /// the names of the columns
+    #[serde(default)]
+    headers: Headers,
+    /// decode fields that look like booleans or numbers to those types
+    #[serde(default)]
+    infer_types: bool,
+}
+
+fn default_delimiter() -> String {
+    ",".to_string()
+}
+
+fn default_quote() -> String {
+    "\"".to_string()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            delimiter: default_delimiter(),
+            quote: default_quote(),
+            quote_style: QuoteStyle::default(),
+            headers: Headers::default(),
+            infer_types: false,
+        }
+    }
+}
+
+/// makes sure the option `name` is a single byte
+fn single_byte(name: &str, value: &str) -> Result<u8> {
+    match value.as_bytes() {
+        [byte] => Ok(*byte),
+        _ => Err(ErrorKind::InvalidConfiguration(
+            "codec csv".to_string(),
+            format!("Invalid '{name}': \"{value}\", must be 1 byte."),
+        )
+        .into()),
+    }
+}
+
+/// decodes a field, inferring its type if asked to
+fn field(value: &str, infer_types: bool) -> Value<'static> {
+    if infer_types {
+        if let Ok(b) = value.parse::<bool>() {
+            return Value::from(b);
+        } else if let Ok(i) = value.parse::<i64>() {
+            return Value::from(i);
+        } else if let Ok(u) = value.parse::<u64>() {
+            return Value::from(u);
+        } else if let Ok(f) = value.parse::<f64>() {
+            // don't turn words like `inf` or `NaN` into floats
+            if f.is_finite() {
+                return Value::from(f);
+            }
+        }
+    }
+    Value::from(value.to_string())
+}
+
 #[derive(Clone)]
-pub struct Csv {}
+pub struct Csv {
+    delimiter: u8,
+    quote: u8,
+    quote_style: QuoteStyle,
+    /// read the headers from the first line
+    read_headers: bool,
+    headers: Option<Vec<String>>,
+    infer_types: bool,
+}
+
+impl Default for Csv {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            quote_style: QuoteStyle::default(),
+            read_headers: false,
+            headers: None,
+            infer_types: false,
+        }
+    }
+}
+
+impl Csv {
+    pub(crate) fn from_config(config: Option<&Value>) -> Result<Self> {
+        let options: Config = super::options("csv", config)?;
+        Ok(Self {
+            delimiter: single_byte("delimiter", &options.delimiter)?,
+            quote: single_byte("quote", &options.quote)?,
+            quote_style: options.quote_style,
+            read_headers: matches!(options.headers, Headers::FirstLine(true)),
+            headers: match options.headers {
+                Headers::Names(names) => Some(names),
+                Headers::FirstLine(_) => None,
+            },
+            infer_types: options.infer_types,
+        })
+    }
+
+    /// the fields of `data` in column order
+    fn fields(&self, data: &Value) -> Result<Vec<String>> {
+        if let Some(values) = data.as_array() {
+            Ok(values.iter().map(ToString::to_string).collect())
+        } else if let Some(record) = data.as_object() {
+            let value = |name: &str| {
+                record
+                    .get(name)
+                    .map(ToString::to_string)
+                    .unwrap_or_default()
+            };
+            if let Some(headers) = &self.headers {
+                // values of keys that aren't a column are dropped
+                Ok(headers.iter().map(|name| value(name.as_str())).collect())
+            } else {
+                let mut names: Vec<&str> = record.keys().map(|name| &**name).collect();
+                names.sort();
+                Ok(names.into_iter().map(value).collect())
+            }
+        } else {
+            Err(ErrorKind::NotCSVSerializableValue(format!("{:?}", data.value_type())).into())
+        }
+    }
+}
 
 impl Codec for Csv {
     fn name(&self) -> &str {
@@ -33,6 +215,8 @@ impl Codec for Csv {
     ) -> Result<Option<Value<'input>>> {
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(false)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
             .from_reader(&*data); // the reborrow here is needed because std::io::Read is implemented only for &[u8], not &mut [u8]
 
         let record = match reader.records().next() {
@@ -41,35 +225,52 @@ impl Codec for Csv {
             None => return Ok(None),
         }?;
 
-        let mut fields = vec![];
-        for field in record.iter() {
-            fields.push(Value::String(Cow::from(field.to_string())));
+        if self.read_headers && self.headers.is_none() {
+            self.headers = Some(record.iter().map(ToString::to_string).collect());
+            return Ok(None);
         }
 
-        Ok(Some(Value::Array(fields)))
+        if let Some(headers) = &self.headers {
+            if headers.len() != record.len() {
+                return Err(format!(
+                    "Invalid CSV record: expected {} fields, got {}",
+                    headers.len(),
+                    record.len()
+                )
+                .into());
+            }
+            let mut fields = Object::with_capacity(headers.len());
+            for (name, value) in headers.iter().zip(record.iter()) {
+                fields.insert(Cow::from(name.clone()), field(value, self.infer_types));
+            }
+            Ok(Some(Value::from(fields)))
+        } else {
+            let fields = record
+                .iter()
+                .map(|value| field(value, self.infer_types))
+                .collect();
+            Ok(Some(Value::Array(fields)))
+        }
     }
 
     fn encode(&self, data: &Value) -> Result<Vec<u8>> {
-        if let Some(values) = data.as_array() {
-            let fields: Vec<String> = values.iter().map(ToString::to_string).collect();
+        let fields = self.fields(data)?;
 
-            let mut result = vec![];
-            let mut writer = csv::Writer::from_writer(&mut result);
-            writer.write_record(&fields)?;
-            writer.flush()?;
-            drop(writer);
+        let mut result = vec![];
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .quote_style(self.quote_style.into())
+            .from_writer(&mut result);
+        writer.write_record(&fields)?;
+        writer.flush()?;
+        drop(writer);
 
-            while result.last() == Some(&b'\n') || result.last() == Some(&b'\r') {
-                result.pop();
-            }
-
-            return Ok(result);
+        while result.last() == Some(&b'\n') || result.last() == Some(&b'\r') {
+            result.pop();
         }
 
-        Err(
-            crate::errors::ErrorKind::NotCSVSerializableValue(format!("{:?}", data.value_type()))
-                .into(),
-        )
+        Ok(result)
     }
 
     fn boxed_clone(&self) -> Box<dyn Codec> {
@@ -83,7 +284,7 @@ mod tests {
 
     #[test]
     fn test_can_decode_csv() {
-        let mut codec = Csv {};
+        let mut codec = Csv::default();
         let mut data = b"a,b,c,123".to_vec();
         let result = codec.decode(&mut data, 0);
 
@@ -92,11 +293,72 @@ mod tests {
 
     #[test]
     fn test_can_encode_csv() {
-        let codec = Csv {};
+        let codec = Csv::default();
         let data = literal!(["a", "b", "c", 123]);
 
         let result = codec.encode(&data).unwrap();
 
         assert_eq!(b"a,b,c,123".to_vec(), result);
     }
+
+    #[test]
+    fn test_csv_options() -> Result<()> {
+        let config = literal!({"delimiter": ";", "quote": "'", "quote_style": "always"});
+        let mut codec = Csv::from_config(Some(&config))?;
+        let mut data = b"a;'b;c';123".to_vec();
+        let result = codec.decode(&mut data, 0)?;
+        assert_eq!(Some(literal!(["a", "b;c", "123"])), result);
+
+        let result = codec.encode(&literal!(["a", "b;c"]))?;
+        assert_eq!(b"'a';'b;c'".to_vec(), result);
+
+        let config = literal!({"delimiter": "::"});
+        assert!(Csv::from_config(Some(&config)).is_err());
+        let config = literal!({"seperator": ";"});
+        assert!(Csv::from_config(Some(&config)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_headers_from_first_line() -> Result<()> {
+        let config = literal!({"headers": true, "infer_types": true});
+        let mut codec = Csv::from_config(Some(&config))?;
+        let mut data = b"name,count,ratio,ok".to_vec();
+        assert_eq!(None, codec.decode(&mut data, 0)?);
+        let mut data = b"snot,3,0.5,true".to_vec();
+        let result = codec.decode(&mut data, 0)?;
+        assert_eq!(
+            Some(literal!({"name": "snot", "count": 3, "ratio": 0.5, "ok": true})),
+            result
+        );
+        let mut data = b"snot,3".to_vec();
+        assert!(codec.decode(&mut data, 0).is_err());
+
+        // a new stream reads its own header row
+        let mut codec = Csv::from_config(Some(&config))?;
+        let mut data = b"a,b".to_vec();
+        assert_eq!(None, codec.decode(&mut data, 0)?);
+        let mut data = b"inf,badger".to_vec();
+        let result = codec.decode(&mut data, 0)?;
+        assert_eq!(Some(literal!({"a": "inf", "b": "badger"})), result);
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_headers() -> Result<()> {
+        let config = literal!({"headers": ["b", "a", "c"]});
+        let mut codec = Csv::from_config(Some(&config))?;
+        let mut data = b"1,2,3".to_vec();
+        let result = codec.decode(&mut data, 0)?;
+        assert_eq!(Some(literal!({"b": "1", "a": "2", "c": "3"})), result);
+
+        let result = codec.encode(&literal!({"a": "x", "b": 1, "d": "dropped"}))?;
+        assert_eq!(b"1,x,".to_vec(), result);
+
+        // without headers records are written in key order
+        let codec = Csv::default();
+        let result = codec.encode(&literal!({"c": 3, "a": 1, "b": 2}))?;
+        assert_eq!(b"1,2,3".to_vec(), result);
+        Ok(())
+    }
 }
@@ -36,15 +36,58 @@ impl Sorting for Sorted {
     const SORTED: bool = true;
 }
 
+/// Options of the json codec
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// encode objects with their keys in sorted order
+    #[serde(default)]
+    sorted: bool,
+    /// encode pretty printed json
+    #[serde(default)]
+    pretty: bool,
+}
+
+/// Creates a json codec from its options, sorted or unsorted
+pub(crate) fn from_config(config: Option<&Value>) -> Result<Box<dyn Codec>> {
+    let options: Config = super::options("json", config)?;
+    if options.sorted {
+        Ok(Box::new(Json::<Sorted>::from_config(config)?))
+    } else {
+        Ok(Box::new(Json::<Unsorted>::from_config(config)?))
+    }
+}
+
 pub struct Json<S: Sorting> {
     _phantom: PhantomData<S>,
     input_buffer: AlignedBuf,
     string_buffer: Vec<u8>,
+    pretty: bool,
+}
+
+impl<S: Sorting> Json<S> {
+    pub(crate) fn from_config(config: Option<&Value>) -> Result<Self> {
+        let options: Config = super::options("json", config)?;
+        if S::SORTED && options.pretty {
+            return Err(ErrorKind::InvalidConfiguration(
+                "codec json".to_string(),
+                "sorted output can't be pretty printed".to_string(),
+            )
+            .into());
+        }
+        Ok(Self {
+            pretty: options.pretty,
+            ..Self::default()
+        })
+    }
 }
 
 impl<S: Sorting> Clone for Json<S> {
     fn clone(&self) -> Self {
-        Self::default()
+        Self {
+            pretty: self.pretty,
+            ..Self::default()
+        }
     }
 }
 
@@ -54,6 +97,7 @@ impl<S: Sorting> Default for Json<S> {
             _phantom: PhantomData::default(),
             input_buffer: AlignedBuf::with_capacity(1024),
             string_buffer: Vec::with_capacity(1024),
+            pretty: false,
         }
     }
 }
@@ -100,7 +144,13 @@ impl<S: Sorting> Codec for Json<S> {
         }
     }
     fn encode_into(&self, data: &Value, dst: &mut Vec<u8>) -> Result<()> {
-        data.write(dst)?;
+        if S::SORTED {
+            dst.extend_from_slice(sorted_serialize(data)?.as_bytes());
+        } else if self.pretty {
+            data.write_pp(dst)?;
+        } else {
+            data.write(dst)?;
+        }
         Ok(())
     }
 
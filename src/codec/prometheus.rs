@@ -0,0 +1,474 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Prometheus codecs
+//!
+//! `prometheus` handles a single sample of the text exposition format, use it with the
+//! `separate` preprocessor for whole scrapes. The line
+//!
+//! ```text
+//! http_requests_total{method="post",code="200"} 1027 1395066363000
+//! ```
+//!
+//! will be translated to the same structure the `influx` codec uses:
+//!
+//! ```json
+//! {
+//!     "measurement": "http_requests_total",
+//!     "tags": {"method": "post", "code": "200"},
+//!     "fields": {"value": 1027.0},
+//!     "timestamp": 1395066363000000000
+//! }
+//! ```
+//!
+//! Comment lines decode to nothing. Encoding writes one line per field, named after the
+//! measurement for the field `value` and `<measurement>_<field>` otherwise.
+//!
+//! `prometheus-remote-write` handles the snappy compressed protobuf `WriteRequest` of the
+//! remote write protocol. A request decodes to an array of records, one per sample, and
+//! records or arrays of records encode to a request.
+//!
+//! With `http_client` a (batched) event is sent as a single request, carrying the
+//! `Content-Encoding: snappy` and `X-Prometheus-Remote-Write-Version: 0.1.0` headers.
+//! `http_server` decodes every request with the `X-Prometheus-Remote-Write-Version`
+//! header with this codec, regardless of the configured one. The request is answered
+//! once the pipeline sends a response, e.g. with an empty body and a `204` status.
+//!
+//! ## Configuration
+//!
+//! These codecs take no configuration
+
+use super::prelude::*;
+use beef::Cow;
+use prost::Message;
+use std::fmt::Write;
+use tremor_common::time::nanotime;
+
+/// The name of the remote write codec
+pub(crate) const REMOTE_WRITE: &str = "prometheus-remote-write";
+/// The header identifying remote write requests
+pub(crate) const REMOTE_WRITE_VERSION_HEADER: &str = "X-Prometheus-Remote-Write-Version";
+/// The headers of remote write requests besides their content type
+pub(crate) const REMOTE_WRITE_HEADERS: [(&str, &str); 2] = [
+    ("Content-Encoding", "snappy"),
+    (REMOTE_WRITE_VERSION_HEADER, "0.1.0"),
+];
+
+const NAME_LABEL: &str = "__name__";
+const VALUE_FIELD: &str = "value";
+const NS_PER_MS: u64 = 1_000_000;
+
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// milliseconds since the epoch
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// A single sample, the common ground of both formats
+#[derive(Debug, PartialEq)]
+struct Metric {
+    name: String,
+    labels: Vec<(String, String)>,
+    value: f64,
+    /// nanoseconds since the epoch
+    timestamp: Option<u64>,
+}
+
+impl Metric {
+    fn into_value(self, default_timestamp: u64) -> Value<'static> {
+        let tags: Object<'static> = self
+            .labels
+            .into_iter()
+            .map(|(k, v)| (Cow::from(k), Value::from(v)))
+            .collect();
+        literal!({
+            "measurement": self.name,
+            "tags": tags,
+            "fields": { "value": self.value },
+            "timestamp": self.timestamp.unwrap_or(default_timestamp)
+        })
+    }
+
+    /// the metrics in a record, one per field
+    fn from_value(value: &Value) -> Result<Vec<Self>> {
+        let measurement = value
+            .get_str("measurement")
+            .ok_or_else(|| invalid_metric("missing `measurement`"))?;
+        let fields = value
+            .get_object("fields")
+            .ok_or_else(|| invalid_metric("missing `fields`"))?;
+        let labels: Vec<(String, String)> = value
+            .get_object("tags")
+            .map(|tags| {
+                tags.iter()
+                    .map(|(k, v)| {
+                        (
+                            k.to_string(),
+                            v.as_str().map_or_else(|| v.encode(), ToString::to_string),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let timestamp = value.get_u64("timestamp");
+        fields
+            .iter()
+            .map(|(field, v)| {
+                let value = v
+                    .as_bool()
+                    .map(|b| if b { 1.0 } else { 0.0 })
+                    .or_else(|| v.cast_f64())
+                    .ok_or_else(|| invalid_metric(&format!("field `{field}` is not a number")))?;
+                let name = if field == VALUE_FIELD {
+                    measurement.to_string()
+                } else {
+                    format!("{measurement}_{field}")
+                };
+                Ok(Self {
+                    name,
+                    labels: labels.clone(),
+                    value,
+                    timestamp,
+                })
+            })
+            .collect()
+    }
+}
+
+fn invalid_metric(msg: &str) -> Error {
+    format!("Invalid metric: {msg}").into()
+}
+
+/// the metrics in a record or (nested) array of records
+fn metrics(data: &Value) -> Result<Vec<Metric>> {
+    if let Some(records) = data.as_array() {
+        let mut res = Vec::with_capacity(records.len());
+        for record in records {
+            res.append(&mut metrics(record)?);
+        }
+        Ok(res)
+    } else {
+        Metric::from_value(data)
+    }
+}
+
+/// parses a line of the text exposition format, comments and empty lines yield `None`
+fn parse_line(line: &str) -> Result<Option<Metric>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .ok_or_else(|| invalid_metric("missing value"))?;
+    let (name, mut rest) = line.split_at(name_end);
+    let mut labels = Vec::new();
+    if let Some(mut label_str) = rest.strip_prefix('{') {
+        loop {
+            label_str = label_str.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+            if let Some(r) = label_str.strip_prefix('}') {
+                rest = r;
+                break;
+            }
+            let (label, r) = label_str
+                .split_once("=\"")
+                .ok_or_else(|| invalid_metric("invalid label"))?;
+            let mut value = String::new();
+            let mut chars = r.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, c)) => value.push(c),
+                        None => return Err(invalid_metric("unterminated label value")),
+                    },
+                    Some((i, '"')) => break i,
+                    Some((_, c)) => value.push(c),
+                    None => return Err(invalid_metric("unterminated label value")),
+                }
+            };
+            labels.push((label.trim().to_string(), value));
+            label_str = &r[end + 1..];
+        }
+    }
+    let mut parts = rest.split_whitespace();
+    let value = parts
+        .next()
+        .ok_or_else(|| invalid_metric("missing value"))?
+        .parse::<f64>()?;
+    let timestamp = parts
+        .next()
+        .map(|ts| ts.parse::<u64>().map(|ms| ms.saturating_mul(NS_PER_MS)))
+        .transpose()?;
+    Ok(Some(Metric {
+        name: name.to_string(),
+        labels,
+        value,
+        timestamp,
+    }))
+}
+
+fn write_line(metric: &Metric, dst: &mut String) -> std::fmt::Result {
+    dst.push_str(&metric.name);
+    if !metric.labels.is_empty() {
+        dst.push('{');
+        for (i, (name, value)) in metric.labels.iter().enumerate() {
+            if i > 0 {
+                dst.push(',');
+            }
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            write!(dst, "{name}=\"{value}\"")?;
+        }
+        dst.push('}');
+    }
+    if metric.value.is_nan() {
+        dst.push_str(" NaN");
+    } else if metric.value.is_infinite() {
+        dst.push_str(if metric.value > 0.0 { " +Inf" } else { " -Inf" });
+    } else {
+        write!(dst, " {}", metric.value)?;
+    }
+    if let Some(timestamp) = metric.timestamp {
+        write!(dst, " {}", timestamp / NS_PER_MS)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct Prometheus {}
+
+impl Codec for Prometheus {
+    fn name(&self) -> &str {
+        "prometheus"
+    }
+
+    fn decode<'input>(
+        &mut self,
+        data: &'input mut [u8],
+        ingest_ns: u64,
+    ) -> Result<Option<Value<'input>>> {
+        let line = std::str::from_utf8(data)?;
+        Ok(parse_line(line)?.map(|metric| metric.into_value(ingest_ns)))
+    }
+
+    fn encode(&self, data: &Value) -> Result<Vec<u8>> {
+        let mut res = String::new();
+        for (i, metric) in metrics(data)?.iter().enumerate() {
+            if i > 0 {
+                res.push('\n');
+            }
+            write_line(metric, &mut res).map_err(|e| Error::from(e.to_string()))?;
+        }
+        Ok(res.into_bytes())
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Codec> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Clone)]
+pub struct RemoteWrite {}
+
+impl Codec for RemoteWrite {
+    fn name(&self) -> &str {
+        REMOTE_WRITE
+    }
+
+    fn decode<'input>(
+        &mut self,
+        data: &'input mut [u8],
+        ingest_ns: u64,
+    ) -> Result<Option<Value<'input>>> {
+        let data = snap::raw::Decoder::new().decompress_vec(data)?;
+        let request = WriteRequest::decode(data.as_slice())?;
+        let mut records = Vec::new();
+        for series in request.timeseries {
+            let mut name = String::new();
+            let mut labels = Vec::with_capacity(series.labels.len());
+            for label in series.labels {
+                if label.name == NAME_LABEL {
+                    name = label.value;
+                } else {
+                    labels.push((label.name, label.value));
+                }
+            }
+            for sample in series.samples {
+                let metric = Metric {
+                    name: name.clone(),
+                    labels: labels.clone(),
+                    value: sample.value,
+                    timestamp: u64::try_from(sample.timestamp)
+                        .ok()
+                        .map(|ms| ms.saturating_mul(NS_PER_MS)),
+                };
+                records.push(metric.into_value(ingest_ns));
+            }
+        }
+        Ok(Some(Value::from(records)))
+    }
+
+    fn encode(&self, data: &Value) -> Result<Vec<u8>> {
+        let now = nanotime();
+        let timeseries = metrics(data)?
+            .into_iter()
+            .map(|metric| {
+                let mut labels = Vec::with_capacity(metric.labels.len() + 1);
+                labels.push(Label {
+                    name: NAME_LABEL.to_string(),
+                    value: metric.name,
+                });
+                labels.extend(
+                    metric
+                        .labels
+                        .into_iter()
+                        .map(|(name, value)| Label { name, value }),
+                );
+                // remote write wants the labels sorted by name
+                labels.sort_by(|l1, l2| l1.name.cmp(&l2.name));
+                let timestamp = i64::try_from(metric.timestamp.unwrap_or(now) / NS_PER_MS)?;
+                Ok(TimeSeries {
+                    labels,
+                    samples: vec![Sample {
+                        value: metric.value,
+                        timestamp,
+                    }],
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let request = WriteRequest { timeseries }.encode_to_vec();
+        Ok(snap::raw::Encoder::new().compress_vec(&request)?)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Codec> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_roundtrip() -> Result<()> {
+        let mut codec = Prometheus {};
+        let mut data =
+            br#"http_requests_total{method="post",code="200"} 1027 1395066363000"#.to_vec();
+        let decoded = codec.decode(&mut data, 42)?;
+        let expected = literal!({
+            "measurement": "http_requests_total",
+            "tags": {"method": "post", "code": "200"},
+            "fields": {"value": 1027.0},
+            "timestamp": 1_395_066_363_000_000_000_u64
+        });
+        assert_eq!(Some(expected.clone()), decoded);
+        assert_eq!(
+            br#"http_requests_total{method="post",code="200"} 1027 1395066363000"#.to_vec(),
+            codec.encode(&expected)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn text_details() -> Result<()> {
+        let mut codec = Prometheus {};
+        let mut data = b"# HELP snot the snot".to_vec();
+        assert_eq!(None, codec.decode(&mut data, 42)?);
+
+        let mut data =
+            br#"snot{path="C:\\DIR\\FILE.TXT",error="Cannot find \"x\"\n"} +Inf"#.to_vec();
+        let decoded = codec.decode(&mut data, 42)?;
+        assert_eq!(
+            Some(literal!({
+                "measurement": "snot",
+                "tags": {"path": "C:\\DIR\\FILE.TXT", "error": "Cannot find \"x\"\n"},
+                "fields": {"value": f64::INFINITY},
+                "timestamp": 42
+            })),
+            decoded
+        );
+
+        let record = literal!({
+            "measurement": "snot",
+            "tags": {},
+            "fields": {"value": 1, "badger": true}
+        });
+        assert_eq!(b"snot 1\nsnot_badger 1".to_vec(), codec.encode(&record)?);
+
+        let mut data = b"snot{".to_vec();
+        assert!(codec.decode(&mut data, 42).is_err());
+        let mut data = b"snot badger".to_vec();
+        assert!(codec.decode(&mut data, 42).is_err());
+        assert!(codec
+            .encode(&literal!({"measurement": "snot", "fields": {"value": "badger"}}))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn remote_write_roundtrip() -> Result<()> {
+        let mut codec = RemoteWrite {};
+        let records = literal!([
+            {
+                "measurement": "snot",
+                "tags": {"host": "badger"},
+                "fields": {"value": 0.5},
+                "timestamp": 1_000_000_000
+            },
+            {
+                "measurement": "cpu",
+                "tags": {},
+                "fields": {"value": 2.0},
+                "timestamp": 2_000_000_000
+            }
+        ]);
+        let mut data = codec.encode(&records)?;
+        // batches of records encode to the same request
+        let batch = Value::from(vec![records.clone()]);
+        assert_eq!(data, codec.encode(&batch)?);
+        assert_eq!(Some(records), codec.decode(&mut data, 42)?);
+
+        let mut data = b"snot".to_vec();
+        assert!(codec.decode(&mut data, 42).is_err());
+        Ok(())
+    }
+}
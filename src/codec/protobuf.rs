@@ -0,0 +1,543 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Protocol buffers codec, the messages are described by a descriptor set or `.proto` file
+//! given in the codec config, so no generated code is needed. `.proto` files are compiled with
+//! `protoc`, taken from the `PROTOC` environment variable or the `PATH` like `prost-build` does.
+//!
+//! Messages decode to records with a key per field. Enums decode to the name of their value,
+//! maps to records with the keys turned into strings, and bytes to binary values.
+//! Unset fields of a `oneof` and unset message fields are left out.
+
+use crate::codec::prelude::*;
+use beef::Cow;
+use prost::Message;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, FieldDescriptor, Kind, MapKey, MessageDescriptor,
+    Value as PbValue,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+/// Options of the protobuf codec
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// path to a `FileDescriptorSet`, as written by `protoc --include_imports --descriptor_set_out`
+    #[serde(default)]
+    descriptor: Option<String>,
+    /// path to a `.proto` file
+    #[serde(default)]
+    proto: Option<String>,
+    /// directories to look up the imports of `proto` in, besides its own directory
+    #[serde(default)]
+    include: Vec<String>,
+    /// the fully qualified name of the message, e.g. `package.Message`
+    message: String,
+}
+
+fn invalid_config<E: ToString>(e: E) -> Error {
+    ErrorKind::InvalidConfiguration("codec protobuf".to_string(), e.to_string()).into()
+}
+
+/// compiles a `.proto` file and its imports into a `FileDescriptorSet`
+fn compile(proto: &str, include: &[String]) -> Result<Vec<u8>> {
+    let proto = Path::new(proto);
+    let out = std::env::temp_dir().join(format!(
+        "tremor-protobuf-{}-{}.desc",
+        std::process::id(),
+        tremor_common::time::nanotime()
+    ));
+    let protoc = std::env::var_os("PROTOC").unwrap_or_else(|| "protoc".into());
+    let mut cmd = Command::new(protoc);
+    cmd.arg("--include_imports")
+        .arg("--descriptor_set_out")
+        .arg(&out);
+    match proto.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => cmd.arg("--proto_path").arg(dir),
+        _ => cmd.arg("--proto_path").arg("."),
+    };
+    for dir in include {
+        cmd.arg("--proto_path").arg(dir);
+    }
+    let output = cmd
+        .arg(proto)
+        .output()
+        .map_err(|e| invalid_config(format!("Error running protoc: {e}")))?;
+    let descriptor_set = if output.status.success() {
+        std::fs::read(&out).map_err(Error::from)
+    } else {
+        Err(invalid_config(String::from_utf8_lossy(&output.stderr)))
+    };
+    let _ = std::fs::remove_file(&out);
+    descriptor_set
+}
+
+/// loads the descriptors, compiling the `.proto` file if one was given
+fn descriptor_pool(config: &Config) -> Result<DescriptorPool> {
+    let descriptor_set = match (&config.descriptor, &config.proto) {
+        (Some(descriptor), None) => std::fs::read(descriptor)?,
+        (None, Some(proto)) => compile(proto, &config.include)?,
+        _ => {
+            return Err(invalid_config(
+                "Exactly one of `descriptor` or `proto` is required",
+            ))
+        }
+    };
+    DescriptorPool::decode(descriptor_set.as_slice()).map_err(invalid_config)
+}
+
+#[derive(Clone)]
+pub struct Protobuf {
+    message: MessageDescriptor,
+}
+
+impl Protobuf {
+    pub(crate) fn from_config(config: Option<&Value>) -> Result<Self> {
+        let config: Config = super::required_options("protobuf", config)?;
+        let message = descriptor_pool(&config)?
+            .get_message_by_name(&config.message)
+            .ok_or_else(|| invalid_config(format!("Unknown message {}", config.message)))?;
+        Ok(Self { message })
+    }
+}
+
+fn map_key_to_string(key: &MapKey) -> String {
+    match key {
+        MapKey::Bool(b) => b.to_string(),
+        MapKey::I32(i) => i.to_string(),
+        MapKey::I64(i) => i.to_string(),
+        MapKey::U32(u) => u.to_string(),
+        MapKey::U64(u) => u.to_string(),
+        MapKey::String(s) => s.clone(),
+    }
+}
+
+fn string_to_map_key(kind: &Kind, key: &str) -> Option<MapKey> {
+    match kind {
+        Kind::Bool => key.parse().ok().map(MapKey::Bool),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => key.parse().ok().map(MapKey::I32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => key.parse().ok().map(MapKey::I64),
+        Kind::Uint32 | Kind::Fixed32 => key.parse().ok().map(MapKey::U32),
+        Kind::Uint64 | Kind::Fixed64 => key.parse().ok().map(MapKey::U64),
+        Kind::String => Some(MapKey::String(key.to_string())),
+        _ => None,
+    }
+}
+
+fn message_to_value(message: &DynamicMessage) -> Value<'static> {
+    let mut record = Object::new();
+    for field in message.descriptor().fields() {
+        let is_message =
+            !field.is_list() && !field.is_map() && matches!(field.kind(), Kind::Message(_));
+        if !message.has_field(&field) && (is_message || field.containing_oneof().is_some()) {
+            continue;
+        }
+        let value = to_value(&message.get_field(&field), &field);
+        record.insert(Cow::from(field.name().to_string()), value);
+    }
+    Value::from(record)
+}
+
+fn to_value(value: &PbValue, field: &FieldDescriptor) -> Value<'static> {
+    match value {
+        PbValue::Bool(b) => Value::from(*b),
+        PbValue::I32(i) => Value::from(*i),
+        PbValue::I64(i) => Value::from(*i),
+        PbValue::U32(u) => Value::from(*u),
+        PbValue::U64(u) => Value::from(*u),
+        PbValue::F32(f) => Value::from(f64::from(*f)),
+        PbValue::F64(f) => Value::from(*f),
+        PbValue::String(s) => Value::from(s.clone()),
+        PbValue::Bytes(b) => Value::Bytes(b.to_vec().into()),
+        PbValue::EnumNumber(n) => match field.kind() {
+            Kind::Enum(e) => e
+                .get_value(*n)
+                .map_or_else(|| Value::from(*n), |v| Value::from(v.name().to_string())),
+            _ => Value::from(*n),
+        },
+        PbValue::Message(m) => message_to_value(m),
+        PbValue::List(values) => Value::from(
+            values
+                .iter()
+                .map(|v| to_value(v, field))
+                .collect::<Vec<_>>(),
+        ),
+        PbValue::Map(entries) => {
+            let mut record = Object::with_capacity(entries.len());
+            if let Kind::Message(entry) = field.kind() {
+                let value_field = entry.map_entry_value_field();
+                for (k, v) in entries {
+                    record.insert(Cow::from(map_key_to_string(k)), to_value(v, &value_field));
+                }
+            }
+            Value::from(record)
+        }
+    }
+}
+
+fn value_to_message(descriptor: &MessageDescriptor, value: &Value) -> Result<DynamicMessage> {
+    let record = value.as_object().ok_or_else(|| {
+        Error::from(format!(
+            "Expected a record for message {}, got {:?}",
+            descriptor.full_name(),
+            value.value_type()
+        ))
+    })?;
+    let mut message = DynamicMessage::new(descriptor.clone());
+    for (name, value) in record.iter() {
+        if value.is_null() {
+            continue;
+        }
+        let field = descriptor.get_field_by_name(name).ok_or_else(|| {
+            Error::from(format!(
+                "Unknown field {name} in message {}",
+                descriptor.full_name()
+            ))
+        })?;
+        let value = if field.is_map() {
+            to_map(&field, value)?
+        } else if field.is_list() {
+            let values = value
+                .as_array()
+                .ok_or_else(|| invalid_value(&field, value))?;
+            PbValue::List(
+                values
+                    .iter()
+                    .map(|v| to_pb_value(&field, v))
+                    .collect::<Result<_>>()?,
+            )
+        } else {
+            to_pb_value(&field, value)?
+        };
+        message.set_field(&field, value);
+    }
+    Ok(message)
+}
+
+fn invalid_value(field: &FieldDescriptor, value: &Value) -> Error {
+    format!("Invalid value for field {}: {}", field.full_name(), value).into()
+}
+
+fn to_map(field: &FieldDescriptor, value: &Value) -> Result<PbValue> {
+    let (entries, entry) = match (value.as_object(), field.kind()) {
+        (Some(entries), Kind::Message(entry)) => (entries, entry),
+        _ => return Err(invalid_value(field, value)),
+    };
+    let key_kind = entry.map_entry_key_field().kind();
+    let value_field = entry.map_entry_value_field();
+    let mut map = HashMap::with_capacity(entries.len());
+    for (k, v) in entries.iter() {
+        let key = string_to_map_key(&key_kind, k).ok_or_else(|| invalid_value(field, value))?;
+        map.insert(key, to_pb_value(&value_field, v)?);
+    }
+    Ok(PbValue::Map(map))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn to_pb_value(field: &FieldDescriptor, value: &Value) -> Result<PbValue> {
+    let pb_value = match field.kind() {
+        Kind::Double => value.cast_f64().map(PbValue::F64),
+        // there is no lossless way to narrow a float
+        Kind::Float => value.cast_f64().map(|f| PbValue::F32(f as f32)),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => value.as_i32().map(PbValue::I32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => value.as_i64().map(PbValue::I64),
+        Kind::Uint32 | Kind::Fixed32 => value.as_u32().map(PbValue::U32),
+        Kind::Uint64 | Kind::Fixed64 => value.as_u64().map(PbValue::U64),
+        Kind::Bool => value.as_bool().map(PbValue::Bool),
+        Kind::String => value.as_str().map(|s| PbValue::String(s.to_string())),
+        Kind::Bytes => value
+            .as_bytes()
+            .or_else(|| value.as_str().map(str::as_bytes))
+            .map(|b| PbValue::Bytes(bytes::Bytes::copy_from_slice(b))),
+        Kind::Enum(e) => value
+            .as_str()
+            .and_then(|name| e.get_value_by_name(name))
+            .map(|v| v.number())
+            .or_else(|| value.as_i32())
+            .map(PbValue::EnumNumber),
+        Kind::Message(m) => Some(PbValue::Message(value_to_message(&m, value)?)),
+    };
+    pb_value.ok_or_else(|| invalid_value(field, value))
+}
+
+impl Codec for Protobuf {
+    fn name(&self) -> &str {
+        "protobuf"
+    }
+
+    fn mime_types(&self) -> Vec<&'static str> {
+        vec!["application/x-protobuf"]
+    }
+
+    fn decode<'input>(
+        &mut self,
+        data: &'input mut [u8],
+        _ingest_ns: u64,
+    ) -> Result<Option<Value<'input>>> {
+        let message = DynamicMessage::decode(self.message.clone(), &*data)?;
+        Ok(Some(message_to_value(&message)))
+    }
+
+    fn encode(&self, data: &Value) -> Result<Vec<u8>> {
+        Ok(value_to_message(&self.message, data)?.encode_to_vec())
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Codec> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use tremor_value::literal;
+
+    /// writes a protobuf field with its key
+    #[allow(clippy::cast_possible_truncation)]
+    fn field(buf: &mut Vec<u8>, number: u64, value: Field) {
+        fn varint(buf: &mut Vec<u8>, mut v: u64) {
+            while v >= 0x80 {
+                buf.push((v as u8) | 0x80);
+                v >>= 7;
+            }
+            buf.push(v as u8);
+        }
+        match value {
+            Field::Int(v) => {
+                varint(buf, number << 3);
+                varint(buf, v);
+            }
+            Field::Bytes(b) => {
+                varint(buf, (number << 3) | 2);
+                varint(buf, b.len() as u64);
+                buf.extend_from_slice(b);
+            }
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Field<'a> {
+        Int(u64),
+        Bytes(&'a [u8]),
+    }
+
+    fn message(fields: &[(u64, Field)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (number, value) in fields {
+            field(&mut buf, *number, *value);
+        }
+        buf
+    }
+
+    /// a `FieldDescriptorProto`, `label` 1 is optional and 3 repeated
+    fn field_descriptor(
+        name: &str,
+        number: u64,
+        label: u64,
+        kind: u64,
+        type_name: Option<&str>,
+        oneof: Option<u64>,
+    ) -> Vec<u8> {
+        let mut fields = vec![
+            (1, Field::Bytes(name.as_bytes())),
+            (3, Field::Int(number)),
+            (4, Field::Int(label)),
+            (5, Field::Int(kind)),
+        ];
+        if let Some(type_name) = type_name {
+            fields.push((6, Field::Bytes(type_name.as_bytes())));
+        }
+        if let Some(oneof) = oneof {
+            fields.push((9, Field::Int(oneof)));
+        }
+        message(&fields)
+    }
+
+    /// The `FileDescriptorSet` of
+    ///
+    /// ```proto
+    /// syntax = "proto3";
+    /// package snot;
+    ///
+    /// enum Kind {
+    ///     KIND_UNKNOWN = 0;
+    ///     KIND_BADGER = 1;
+    /// }
+    ///
+    /// message Inner {
+    ///     string name = 1;
+    ///     repeated int64 values = 2;
+    /// }
+    ///
+    /// message Outer {
+    ///     uint32 id = 1;
+    ///     Kind kind = 2;
+    ///     Inner inner = 3;
+    ///     repeated Inner inners = 4;
+    ///     map<string, double> scores = 5;
+    ///     bytes payload = 6;
+    ///     oneof choice {
+    ///         string text = 7;
+    ///         bool flag = 8;
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// as written by `protoc`, written by hand so the tests don't need it
+    fn descriptor_set() -> Vec<u8> {
+        const STRING: u64 = 9;
+        const INT64: u64 = 3;
+        const UINT32: u64 = 13;
+        const DOUBLE: u64 = 1;
+        const BOOL: u64 = 8;
+        const MESSAGE: u64 = 11;
+        const BYTES: u64 = 12;
+        const ENUM: u64 = 14;
+
+        let enum_value = |name: &str, number| {
+            message(&[(1, Field::Bytes(name.as_bytes())), (2, Field::Int(number))])
+        };
+        let kind = message(&[
+            (1, Field::Bytes(b"Kind")),
+            (2, Field::Bytes(&enum_value("KIND_UNKNOWN", 0))),
+            (2, Field::Bytes(&enum_value("KIND_BADGER", 1))),
+        ]);
+        let inner = message(&[
+            (1, Field::Bytes(b"Inner")),
+            (
+                2,
+                Field::Bytes(&field_descriptor("name", 1, 1, STRING, None, None)),
+            ),
+            (
+                2,
+                Field::Bytes(&field_descriptor("values", 2, 3, INT64, None, None)),
+            ),
+        ]);
+        let scores_entry = message(&[
+            (1, Field::Bytes(b"ScoresEntry")),
+            (
+                2,
+                Field::Bytes(&field_descriptor("key", 1, 1, STRING, None, None)),
+            ),
+            (
+                2,
+                Field::Bytes(&field_descriptor("value", 2, 1, DOUBLE, None, None)),
+            ),
+            // `map_entry = true`
+            (7, Field::Bytes(&message(&[(7, Field::Int(1))]))),
+        ]);
+        let outer_fields = [
+            field_descriptor("id", 1, 1, UINT32, None, None),
+            field_descriptor("kind", 2, 1, ENUM, Some(".snot.Kind"), None),
+            field_descriptor("inner", 3, 1, MESSAGE, Some(".snot.Inner"), None),
+            field_descriptor("inners", 4, 3, MESSAGE, Some(".snot.Inner"), None),
+            field_descriptor(
+                "scores",
+                5,
+                3,
+                MESSAGE,
+                Some(".snot.Outer.ScoresEntry"),
+                None,
+            ),
+            field_descriptor("payload", 6, 1, BYTES, None, None),
+            field_descriptor("text", 7, 1, STRING, None, Some(0)),
+            field_descriptor("flag", 8, 1, BOOL, None, Some(0)),
+        ];
+        let choice = message(&[(1, Field::Bytes(b"choice"))]);
+        let mut outer = vec![(1, Field::Bytes(b"Outer"))];
+        outer.extend(outer_fields.iter().map(|f| (2, Field::Bytes(f))));
+        outer.push((3, Field::Bytes(&scores_entry)));
+        outer.push((8, Field::Bytes(&choice)));
+        let outer = message(&outer);
+        let file = message(&[
+            (1, Field::Bytes(b"snot.proto")),
+            (2, Field::Bytes(b"snot")),
+            (4, Field::Bytes(&inner)),
+            (4, Field::Bytes(&outer)),
+            (5, Field::Bytes(&kind)),
+            (12, Field::Bytes(b"proto3")),
+        ]);
+        message(&[(1, Field::Bytes(&file))])
+    }
+
+    fn codec() -> Result<(tempfile::TempDir, Protobuf)> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snot.desc");
+        std::fs::File::create(&path)?.write_all(&descriptor_set())?;
+        let config = literal!({
+            "descriptor": path.to_string_lossy().to_string(),
+            "message": "snot.Outer"
+        });
+        let codec = Protobuf::from_config(Some(&config))?;
+        Ok((dir, codec))
+    }
+
+    #[test]
+    fn roundtrip() -> Result<()> {
+        let (_dir, mut codec) = codec()?;
+        let value = literal!({
+            "id": 42,
+            "kind": "KIND_BADGER",
+            "inner": {"name": "badger", "values": [1, -2, 3]},
+            "inners": [{"name": "a", "values": []}, {"name": "b", "values": [4]}],
+            "scores": {"snot": 0.5},
+            "payload": Value::Bytes(b"snot".to_vec().into()),
+            "flag": true
+        });
+        let mut data = codec.encode(&value)?;
+        let decoded = codec.decode(&mut data, 0)?;
+        assert_eq!(Some(value), decoded);
+        Ok(())
+    }
+
+    #[test]
+    fn unset_fields() -> Result<()> {
+        let (_dir, mut codec) = codec()?;
+        let mut data = codec.encode(&literal!({"id": 1, "inner": null}))?;
+        let decoded = codec.decode(&mut data, 0)?;
+        assert_eq!(
+            Some(literal!({
+                "id": 1,
+                "kind": "KIND_UNKNOWN",
+                "inners": [],
+                "scores": {},
+                "payload": Value::Bytes(Vec::new().into())
+            })),
+            decoded
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() -> Result<()> {
+        let (_dir, codec) = codec()?;
+        assert!(codec.encode(&literal!({"snot": 1})).is_err());
+        assert!(codec.encode(&literal!({"id": -1})).is_err());
+        assert!(codec.encode(&literal!({"kind": "KIND_SNOT"})).is_err());
+        assert!(codec
+            .encode(&literal!({"scores": {"snot": "badger"}}))
+            .is_err());
+        assert!(codec.encode(&literal!([1, 2])).is_err());
+
+        let config = literal!({"message": "snot.Outer"});
+        assert!(Protobuf::from_config(Some(&config)).is_err());
+        let config = literal!({"proto": "/snot/does/not/exist.proto", "message": "snot.Outer"});
+        assert!(Protobuf::from_config(Some(&config)).is_err());
+        assert!(Protobuf::from_config(None).is_err());
+        Ok(())
+    }
+}
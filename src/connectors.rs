@@ -157,6 +157,21 @@ impl Addr {
         self.send(Msg::Resume).await
     }
 
+    /// seeks the source part of the connector to the given position,
+    /// the format of the position is specific to the connector
+    ///
+    /// # Errors
+    ///   * if the connector has no source part, doesn't support seeking or the position is invalid
+    pub async fn seek(&self, position: Value<'static>) -> Result<()> {
+        if let Some(source) = self.source.as_ref() {
+            let (tx, rx) = bounded(1);
+            source.addr.send(SourceMsg::Seek(position, tx)).await?;
+            rx.recv().await?
+        } else {
+            Err(ErrorKind::InvalidSeek(self.alias.clone(), "No source to seek".to_string()).into())
+        }
+    }
+
     /// report status of the connector instance
     ///
     /// # Errors
@@ -1169,6 +1184,7 @@ pub(crate) trait ConnectorBuilder: Sync + Send + std::fmt::Debug {
 pub(crate) fn builtin_connector_types() -> Vec<Box<dyn ConnectorBuilder + 'static>> {
     vec![
         Box::new(impls::file::Builder::default()),
+        Box::new(impls::dir::Builder::default()),
         Box::new(impls::metrics::Builder::default()),
         Box::new(impls::stdio::Builder::default()),
         Box::new(impls::tcp::client::Builder::default()),
@@ -1188,6 +1204,14 @@ pub(crate) fn builtin_connector_types() -> Vec<Box<dyn ConnectorBuilder + 'stati
         Box::new(impls::s3::reader::Builder::default()),
         Box::new(impls::kafka::consumer::Builder::default()),
         Box::new(impls::kafka::producer::Builder::default()),
+        Box::new(impls::mqtt::Builder::default()),
+        Box::new(impls::amqp::consumer::Builder::default()),
+        Box::new(impls::amqp::producer::Builder::default()),
+        Box::new(impls::nats::Builder::default()),
+        Box::new(impls::redis::Builder::default()),
+        Box::new(impls::postgres::Builder::default()),
+        Box::new(impls::clickhouse::Builder::default()),
+        Box::new(impls::loki::Builder::default()),
         #[cfg(unix)]
         Box::new(impls::unix_socket::server::Builder::default()),
         #[cfg(unix)]
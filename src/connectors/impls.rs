@@ -12,12 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+/// AMQP 0.9.1 consumer and producer
+pub(crate) mod amqp;
 /// benchmarking connector
 pub(crate) mod bench;
 /// connector for checking guaranteed delivery and circuit breaker logic
 pub(crate) mod cb;
+/// `ClickHouse` batched inserts
+pub(crate) mod clickhouse;
 /// Crononome
 pub(crate) mod crononome;
+/// Directory source reading every new file
+pub(crate) mod dir;
 /// Discord connector
 pub(crate) mod discord;
 /// DNS
@@ -37,14 +43,24 @@ pub(crate) mod http;
 pub(crate) mod kafka;
 /// KV
 pub(crate) mod kv;
+/// Grafana Loki push sink
+pub(crate) mod loki;
 /// Home of the famous metrics collector
 pub(crate) mod metrics;
 /// Metronome
 pub(crate) mod metronome;
+/// MQTT publisher and subscriber
+pub(crate) mod mqtt;
+/// NATS and NATS `JetStream`
+pub(crate) mod nats;
 /// Never send any events and swallow all events it receives into the void.
 pub(crate) mod null;
 /// `OpenTelemetry`
 pub(crate) mod otel;
+/// `PostgreSQL` inserts and change data capture
+pub(crate) mod postgres;
+/// Redis streams, pub/sub and commands
+pub(crate) mod redis;
 /// AWS S3 connectors
 pub(crate) mod s3;
 /// std streams connector (stdout, stderr, stdin)
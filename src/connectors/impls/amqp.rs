@@ -0,0 +1,171 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub(crate) mod consumer;
+pub(crate) mod producer;
+
+use crate::connectors::prelude::*;
+use crate::errors::err_conector_def;
+use async_std::task;
+use beef::Cow;
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::uri::AMQPUri;
+use lapin::{Connection, ConnectionProperties};
+use std::collections::BTreeMap;
+
+/// metadata namespace of both the consumer and the producer
+const AMQP_META_KEY: &str = "amqp";
+/// reply code for closing channels and connections regularly
+const REPLY_SUCCESS: u16 = 200;
+
+/// verify the broker uri in amqp connector configs
+fn parse_uri(alias: &str, url: &str) -> Result<AMQPUri> {
+    url.parse()
+        .map_err(|e: String| err_conector_def(alias, &format!("Invalid `url`: {e}")))
+}
+
+/// Connects to the broker at `url` and notifies the runtime once the connection breaks
+async fn connect<C>(ctx: &C, url: &str) -> Result<Connection>
+where
+    C: Context + Send + Sync + 'static,
+{
+    let connection = Connection::connect(url, ConnectionProperties::default()).await?;
+    let ctx = ctx.clone();
+    connection.on_error(move |e| {
+        error!("{ctx} AMQP connection error: {e}");
+        let ctx = ctx.clone();
+        task::spawn(async move {
+            ctx.swallow_err(
+                ctx.notifier().connection_lost().await,
+                "Error notifying about the lost connection",
+            );
+        });
+    });
+    Ok(connection)
+}
+
+/// converts header values received from the broker
+fn to_value(value: &AMQPValue) -> Value<'static> {
+    match value {
+        AMQPValue::Boolean(b) => Value::from(*b),
+        AMQPValue::ShortShortInt(i) => Value::from(*i),
+        AMQPValue::ShortShortUInt(i) => Value::from(*i),
+        AMQPValue::ShortInt(i) => Value::from(*i),
+        AMQPValue::ShortUInt(i) => Value::from(*i),
+        AMQPValue::LongInt(i) => Value::from(*i),
+        AMQPValue::LongUInt(i) => Value::from(*i),
+        AMQPValue::LongLongInt(i) => Value::from(*i),
+        AMQPValue::Timestamp(t) => Value::from(*t),
+        AMQPValue::Float(f) => Value::from(f64::from(*f)),
+        AMQPValue::Double(f) => Value::from(*f),
+        AMQPValue::DecimalValue(d) => {
+            Value::from(f64::from(d.value) / 10_f64.powi(i32::from(d.scale)))
+        }
+        AMQPValue::ShortString(s) => Value::from(s.as_str().to_string()),
+        AMQPValue::LongString(s) => match std::str::from_utf8(s.as_bytes()) {
+            Ok(s) => Value::from(s.to_string()),
+            Err(_) => Value::Bytes(s.as_bytes().to_vec().into()),
+        },
+        AMQPValue::ByteArray(b) => Value::Bytes(b.as_slice().to_vec().into()),
+        AMQPValue::FieldArray(a) => {
+            Value::from(a.as_slice().iter().map(to_value).collect::<Vec<_>>())
+        }
+        AMQPValue::FieldTable(t) => to_record(t),
+        AMQPValue::Void => Value::null(),
+    }
+}
+
+fn to_record(table: &FieldTable) -> Value<'static> {
+    Value::from(
+        table
+            .inner()
+            .iter()
+            .map(|(k, v)| (Cow::from(k.as_str().to_string()), to_value(v)))
+            .collect::<Object>(),
+    )
+}
+
+/// converts header values to send to the broker
+fn from_value(value: &Value) -> AMQPValue {
+    match value {
+        Value::String(s) => AMQPValue::LongString(s.to_string().into()),
+        Value::Bytes(b) => AMQPValue::ByteArray(b.to_vec().into()),
+        Value::Array(a) => {
+            AMQPValue::FieldArray(a.iter().map(from_value).collect::<Vec<_>>().into())
+        }
+        Value::Object(o) => AMQPValue::FieldTable(from_record(o)),
+        Value::Static(_) => {
+            if let Some(b) = value.as_bool() {
+                AMQPValue::Boolean(b)
+            } else if let Some(i) = value.as_i64() {
+                AMQPValue::LongLongInt(i)
+            } else if let Some(u) = value.as_u64() {
+                AMQPValue::Timestamp(u)
+            } else if let Some(f) = value.as_f64() {
+                AMQPValue::Double(f)
+            } else {
+                AMQPValue::Void
+            }
+        }
+    }
+}
+
+fn from_record(record: &Object) -> FieldTable {
+    record
+        .iter()
+        .map(|(k, v)| (k.to_string().into(), from_value(v)))
+        .collect::<BTreeMap<_, _>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let headers = literal!({
+            "string": "snot",
+            "bool": true,
+            "int": -42,
+            "float": 0.5,
+            "null": null,
+            "bytes": Value::Bytes(b"badger".to_vec().into()),
+            "array": [1, "two"],
+            "record": {"nested": 1}
+        });
+        let table = headers.as_object().map(from_record).unwrap_or_default();
+        assert_eq!(headers, to_record(&table));
+    }
+
+    #[test]
+    fn header_values() {
+        assert_eq!(Value::from(8_u8), to_value(&AMQPValue::ShortShortUInt(8)));
+        assert_eq!(
+            Value::from(1.5),
+            to_value(&AMQPValue::DecimalValue(lapin::types::DecimalValue {
+                scale: 1,
+                value: 15
+            }))
+        );
+        assert_eq!(
+            Value::from("snot"),
+            to_value(&AMQPValue::ShortString("snot".into()))
+        );
+        assert_eq!(
+            Value::from(u64::MAX),
+            to_value(&from_value(&Value::from(u64::MAX)))
+        );
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion of tremor values to `ClickHouse` column values
+
+use crate::errors::{Error, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use clickhouse_rs::types::{SqlType, Value as CValue};
+use either::Either;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::Arc;
+use tremor_value::Value;
+use value_trait::ValueAccess;
+
+/// The type of a column, as reported by `system.columns`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ColumnType {
+    Bool,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    String,
+    DateTime,
+    /// sub-second precision as number of decimal digits
+    DateTime64(u32),
+    Ipv4,
+    Ipv6,
+    Uuid,
+    Nullable(Box<ColumnType>),
+    Array(Box<ColumnType>),
+}
+
+/// Splits `Name(args)` into name and arguments
+fn split_type(s: &str) -> (&str, Option<&str>) {
+    match (s.find('('), s.strip_suffix(')')) {
+        (Some(open), Some(without_close)) => (&s[..open], Some(&without_close[open + 1..])),
+        _ => (s, None),
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let unsupported = || Error::from(format!("Unsupported ClickHouse column type `{s}`"));
+        Ok(match split_type(s) {
+            ("Bool", None) => Self::Bool,
+            ("UInt8", None) => Self::UInt8,
+            ("UInt16", None) => Self::UInt16,
+            ("UInt32", None) => Self::UInt32,
+            ("UInt64", None) => Self::UInt64,
+            ("Int8", None) => Self::Int8,
+            ("Int16", None) => Self::Int16,
+            ("Int32", None) => Self::Int32,
+            ("Int64", None) => Self::Int64,
+            ("Float32", None) => Self::Float32,
+            ("Float64", None) => Self::Float64,
+            ("String", None) => Self::String,
+            // the timezone only affects how values are displayed
+            ("DateTime", _) => Self::DateTime,
+            ("DateTime64", Some(args)) => {
+                let precision = args.split(',').next().unwrap_or_default().trim();
+                Self::DateTime64(precision.parse().map_err(|_| unsupported())?)
+            }
+            ("IPv4", None) => Self::Ipv4,
+            ("IPv6", None) => Self::Ipv6,
+            ("UUID", None) => Self::Uuid,
+            ("Nullable", Some(inner)) => Self::Nullable(Box::new(inner.parse()?)),
+            ("Array", Some(inner)) => Self::Array(Box::new(inner.parse()?)),
+            // dictionary encoding is transparent for inserts
+            ("LowCardinality", Some(inner)) => inner.parse()?,
+            _ => return Err(unsupported()),
+        })
+    }
+}
+
+/// Timestamps are either nanoseconds since the unix epoch or RFC 3339 strings
+fn timestamp_ns(value: &Value) -> Option<i64> {
+    if let Some(ns) = value.as_i64() {
+        Some(ns)
+    } else {
+        let dt = DateTime::parse_from_rfc3339(value.as_str()?).ok()?;
+        dt.timestamp()
+            .checked_mul(1_000_000_000)?
+            .checked_add(i64::from(dt.timestamp_subsec_nanos()))
+    }
+}
+
+impl ColumnType {
+    fn sql_type(&self) -> SqlType {
+        match self {
+            Self::Bool => SqlType::Bool,
+            Self::UInt8 => SqlType::UInt8,
+            Self::UInt16 => SqlType::UInt16,
+            Self::UInt32 => SqlType::UInt32,
+            Self::UInt64 => SqlType::UInt64,
+            Self::Int8 => SqlType::Int8,
+            Self::Int16 => SqlType::Int16,
+            Self::Int32 => SqlType::Int32,
+            Self::Int64 => SqlType::Int64,
+            Self::Float32 => SqlType::Float32,
+            Self::Float64 => SqlType::Float64,
+            Self::String => SqlType::String,
+            Self::DateTime => SqlType::DateTime(clickhouse_rs::types::DateTimeType::DateTime32),
+            Self::DateTime64(precision) => SqlType::DateTime(
+                clickhouse_rs::types::DateTimeType::DateTime64(*precision, Tz::UTC),
+            ),
+            Self::Ipv4 => SqlType::Ipv4,
+            Self::Ipv6 => SqlType::Ipv6,
+            Self::Uuid => SqlType::Uuid,
+            Self::Nullable(inner) => SqlType::Nullable(inner.sql_type().into()),
+            Self::Array(inner) => SqlType::Array(inner.sql_type().into()),
+        }
+    }
+
+    /// The value of fields missing from a record, for columns without a default expression
+    fn default_value(&self) -> CValue {
+        match self {
+            Self::Bool => CValue::Bool(false),
+            Self::UInt8 => CValue::UInt8(0),
+            Self::UInt16 => CValue::UInt16(0),
+            Self::UInt32 => CValue::UInt32(0),
+            Self::UInt64 => CValue::UInt64(0),
+            Self::Int8 => CValue::Int8(0),
+            Self::Int16 => CValue::Int16(0),
+            Self::Int32 => CValue::Int32(0),
+            Self::Int64 => CValue::Int64(0),
+            Self::Float32 => CValue::Float32(0.0),
+            Self::Float64 => CValue::Float64(0.0),
+            Self::String => CValue::String(Arc::new(Vec::new())),
+            Self::DateTime => CValue::DateTime(0, Tz::UTC),
+            Self::DateTime64(precision) => CValue::DateTime64(0, (*precision, Tz::UTC)),
+            Self::Ipv4 => CValue::Ipv4([0; 4]),
+            Self::Ipv6 => CValue::Ipv6([0; 16]),
+            Self::Uuid => CValue::Uuid([0; 16]),
+            Self::Nullable(inner) => CValue::Nullable(Either::Left(inner.sql_type().into())),
+            Self::Array(inner) => CValue::Array(inner.sql_type().into(), Arc::new(Vec::new())),
+        }
+    }
+
+    /// Converts a field of a record to a value of this type
+    pub(crate) fn convert(&self, value: Option<&Value>) -> Result<CValue> {
+        let value = match value {
+            Some(value) if !value.is_null() => value,
+            _ => return Ok(self.default_value()),
+        };
+        let mismatch = || Error::from(format!("Could not convert {value} to `{self:?}`"));
+        Ok(match self {
+            Self::Bool => CValue::Bool(value.as_bool().ok_or_else(mismatch)?),
+            Self::UInt8 => CValue::UInt8(value.as_u8().ok_or_else(mismatch)?),
+            Self::UInt16 => CValue::UInt16(value.as_u16().ok_or_else(mismatch)?),
+            Self::UInt32 => CValue::UInt32(value.as_u32().ok_or_else(mismatch)?),
+            Self::UInt64 => CValue::UInt64(value.as_u64().ok_or_else(mismatch)?),
+            Self::Int8 => CValue::Int8(value.as_i8().ok_or_else(mismatch)?),
+            Self::Int16 => CValue::Int16(value.as_i16().ok_or_else(mismatch)?),
+            Self::Int32 => CValue::Int32(value.as_i32().ok_or_else(mismatch)?),
+            Self::Int64 => CValue::Int64(value.as_i64().ok_or_else(mismatch)?),
+            #[allow(clippy::cast_possible_truncation)]
+            Self::Float32 => CValue::Float32(value.cast_f64().ok_or_else(mismatch)? as f32),
+            Self::Float64 => CValue::Float64(value.cast_f64().ok_or_else(mismatch)?),
+            Self::String => {
+                CValue::String(Arc::new(value.as_bytes().ok_or_else(mismatch)?.to_vec()))
+            }
+            Self::DateTime => {
+                let secs = timestamp_ns(value).ok_or_else(mismatch)? / 1_000_000_000;
+                CValue::DateTime(u32::try_from(secs)?, Tz::UTC)
+            }
+            Self::DateTime64(precision) => {
+                let ns = timestamp_ns(value).ok_or_else(mismatch)?;
+                let ticks = match 9_u32.checked_sub(*precision) {
+                    Some(digits) => ns / 10_i64.pow(digits),
+                    None => return Err(mismatch()),
+                };
+                CValue::DateTime64(ticks, (*precision, Tz::UTC))
+            }
+            Self::Ipv4 => {
+                let ip: Ipv4Addr = value.as_str().ok_or_else(mismatch)?.parse()?;
+                CValue::Ipv4(ip.octets())
+            }
+            Self::Ipv6 => {
+                let ip: Ipv6Addr = value.as_str().ok_or_else(mismatch)?.parse()?;
+                CValue::Ipv6(ip.octets())
+            }
+            Self::Uuid => {
+                CValue::from(uuid::Uuid::parse_str(value.as_str().ok_or_else(mismatch)?)?)
+            }
+            Self::Nullable(inner) => {
+                CValue::Nullable(Either::Right(Box::new(inner.convert(Some(value))?)))
+            }
+            Self::Array(inner) => {
+                let values = value
+                    .as_array()
+                    .ok_or_else(mismatch)?
+                    .iter()
+                    .map(|v| inner.convert(Some(v)))
+                    .collect::<Result<Vec<_>>>()?;
+                CValue::Array(inner.sql_type().into(), Arc::new(values))
+            }
+        })
+    }
+}
+
+/// A column of a table
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) ty: ColumnType,
+    /// if the column has a default expression
+    pub(crate) has_default: bool,
+}
+
+impl Column {
+    /// if the field of this column is missing from `record`, so it is omitted for `ClickHouse` to
+    /// fill in its default expression
+    pub(crate) fn omitted(&self, record: &Value) -> bool {
+        self.has_default && record.get(self.name.as_str()).is_none()
+    }
+
+    /// converts the field of this column in `record`
+    pub(crate) fn convert(&self, record: &Value) -> Result<(String, CValue)> {
+        let value = self
+            .ty
+            .convert(record.get(self.name.as_str()))
+            .map_err(|e| Error::from(format!("Column `{}`: {e}", self.name)))?;
+        Ok((self.name.clone(), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tremor_value::literal;
+
+    #[test]
+    fn parse_types() -> Result<()> {
+        assert_eq!(ColumnType::UInt64, "UInt64".parse()?);
+        assert_eq!(ColumnType::DateTime, "DateTime('UTC')".parse()?);
+        assert_eq!(ColumnType::DateTime64(3), "DateTime64(3, 'UTC')".parse()?);
+        assert_eq!(
+            ColumnType::Array(Box::new(ColumnType::Nullable(Box::new(ColumnType::String)))),
+            "Array(Nullable(String))".parse()?
+        );
+        assert_eq!(ColumnType::String, "LowCardinality(String)".parse()?);
+        assert!("Decimal(10, 2)".parse::<ColumnType>().is_err());
+        assert!("Snot".parse::<ColumnType>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn convert_values() -> Result<()> {
+        let record = literal!({
+            "count": 42,
+            "name": "snot",
+            "ts": 1_500_000_000_123_456_789_u64,
+            "ip": "127.0.0.1",
+            "tags": ["snot", "badger"],
+            "maybe": null
+        });
+        let column = |name: &str, ty: &str| -> Result<Column> {
+            Ok(Column {
+                name: name.to_string(),
+                ty: ty.parse()?,
+                has_default: false,
+            })
+        };
+        assert_eq!(
+            ("count".to_string(), CValue::UInt16(42)),
+            column("count", "UInt16")?.convert(&record)?
+        );
+        assert!(column("count", "String")?.convert(&record).is_err());
+        assert!(column("name", "UInt8")?.convert(&record).is_err());
+        assert_eq!(
+            CValue::DateTime(1_500_000_000, Tz::UTC),
+            column("ts", "DateTime")?.convert(&record)?.1
+        );
+        assert_eq!(
+            CValue::DateTime64(1_500_000_000_123, (3, Tz::UTC)),
+            column("ts", "DateTime64(3)")?.convert(&record)?.1
+        );
+        assert_eq!(
+            CValue::Ipv4([127, 0, 0, 1]),
+            column("ip", "IPv4")?.convert(&record)?.1
+        );
+        assert_eq!(
+            CValue::Array(
+                SqlType::String.into(),
+                Arc::new(vec![
+                    CValue::from("snot".to_string()),
+                    CValue::from("badger".to_string())
+                ])
+            ),
+            column("tags", "Array(String)")?.convert(&record)?.1
+        );
+        assert_eq!(
+            CValue::Nullable(Either::Left(SqlType::Int32.into())),
+            column("maybe", "Nullable(Int32)")?.convert(&record)?.1
+        );
+        // missing fields get the default of their type
+        assert_eq!(
+            CValue::UInt8(0),
+            column("missing", "UInt8")?.convert(&record)?.1
+        );
+        // or are omitted for columns with a default expression
+        let mut defaulted = column("missing", "UInt8")?;
+        assert!(!defaulted.omitted(&record));
+        defaulted.has_default = true;
+        assert!(defaulted.omitted(&record));
+        defaulted.name = "count".to_string();
+        assert!(!defaulted.omitted(&record));
+        Ok(())
+    }
+}
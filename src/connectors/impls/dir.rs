@@ -0,0 +1,585 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Directory source - reads every new file dropped into a directory
+//!
+//! The directory `path` is scanned every `poll_interval_ms` for files matching one of the `include`
+//! glob patterns (relative to `path`, `*` by default) and none of the `exclude` patterns. A file is
+//! picked up once its size and modification time did not change between two scans, so files still
+//! being written are left alone.
+//!
+//! Every file is read as its own stream and ends with an `EndStream`, its path is available as
+//! `$dir.path`. Once all of its events are acknowledged, the file is `processed`:
+//!
+//! * `mark` (default): an empty marker file with `processed_suffix` appended to its name is created next to it
+//! * `move`: it is moved to `processed_dir`, keeping its path relative to `path`
+//! * `delete`: it is deleted
+//!
+//! Files with failed events are left untouched and are only read again after a restart.
+
+use crate::connectors::prelude::*;
+use crate::errors::err_conector_def;
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::fs;
+use async_std::task::{self, JoinHandle};
+use futures::AsyncReadExt;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tremor_common::asy::file;
+
+const URL_SCHEME: &str = "tremor-dir";
+
+/// What to do with a file once all of its events are acknowledged
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Processed {
+    /// create a marker file next to it
+    Mark,
+    /// move it to `processed_dir`
+    Move,
+    /// delete it
+    Delete,
+}
+
+impl Default for Processed {
+    fn default() -> Self {
+        Self::Mark
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// the directory to read files from
+    path: PathBuf,
+    /// glob patterns, relative to `path`, of the files to read
+    #[serde(default = "default_include")]
+    include: Vec<String>,
+    /// glob patterns, relative to `path`, of files not to read
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// what to do with a file once all of its events are acknowledged
+    #[serde(default)]
+    processed: Processed,
+    /// the directory to move processed files to
+    processed_dir: Option<PathBuf>,
+    /// suffix of the marker files of processed files
+    #[serde(default = "default_processed_suffix")]
+    processed_suffix: String,
+    /// interval in milliseconds to scan the directory for new files
+    #[serde(default = "default_poll_interval_ms")]
+    poll_interval_ms: u64,
+    /// chunk_size to read from the files
+    #[serde(default = "default_buf_size")]
+    chunk_size: usize,
+}
+
+fn default_include() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_processed_suffix() -> String {
+    ".processed".to_string()
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+impl ConfigImpl for Config {}
+
+/// Directory connector
+pub(crate) struct Dir {
+    config: Config,
+}
+
+/// Builder for the directory connector
+#[derive(Default, Debug)]
+pub(crate) struct Builder {}
+
+#[async_trait::async_trait]
+impl ConnectorBuilder for Builder {
+    fn connector_type(&self) -> ConnectorType {
+        "dir".into()
+    }
+
+    async fn build_cfg(
+        &self,
+        alias: &str,
+        _: &ConnectorConfig,
+        config: &Value,
+    ) -> Result<Box<dyn Connector>> {
+        let config = Config::new(config)?;
+        if config.processed == Processed::Move && config.processed_dir.is_none() {
+            return Err(err_conector_def(
+                alias,
+                "`processed_dir` is required to `move` processed files",
+            ));
+        }
+        if config.processed == Processed::Mark && config.processed_suffix.is_empty() {
+            return Err(err_conector_def(
+                alias,
+                "`processed_suffix` must not be empty to `mark` processed files",
+            ));
+        }
+        for pattern in config.include.iter().chain(config.exclude.iter()) {
+            glob::Pattern::new(pattern)
+                .map_err(|e| err_conector_def(alias, &format!("Invalid pattern {pattern}: {e}")))?;
+        }
+        Ok(Box::new(Dir { config }))
+    }
+}
+
+#[async_trait::async_trait]
+impl Connector for Dir {
+    async fn create_source(
+        &mut self,
+        source_context: SourceContext,
+        builder: SourceManagerBuilder,
+    ) -> Result<Option<SourceAddr>> {
+        let source = DirSource::new(self.config.clone());
+        builder.spawn(source, source_context).map(Some)
+    }
+
+    fn codec_requirements(&self) -> CodecReq {
+        CodecReq::Required
+    }
+}
+
+/// A reply of the scanning task
+struct Read {
+    reply: SourceReply,
+    path: PathBuf,
+    pull_id: u64,
+}
+
+/// A file whose events are in flight
+struct Processing {
+    path: PathBuf,
+    /// pull ids of the chunks whose events are not acknowledged yet
+    pending: BTreeSet<u64>,
+    /// if the stream of the file ended
+    ended: bool,
+    failed: bool,
+}
+
+struct DirSource {
+    config: Config,
+    rx: Receiver<Read>,
+    task: Option<JoinHandle<()>>,
+    next_stream: u64,
+    /// files by stream id
+    streams: HashMap<u64, Processing>,
+    /// files with failed events, not to be read again
+    failed: HashSet<PathBuf>,
+}
+
+impl DirSource {
+    fn new(config: Config) -> Self {
+        let (_tx, rx) = bounded(1);
+        Self {
+            config,
+            rx,
+            task: None,
+            next_stream: DEFAULT_STREAM_ID,
+            streams: HashMap::new(),
+            failed: HashSet::new(),
+        }
+    }
+
+    /// handles a file once all of its events are acknowledged
+    async fn done(&mut self, stream_id: u64, ctx: &SourceContext) {
+        let finished = self
+            .streams
+            .get(&stream_id)
+            .map_or(false, |p| p.ended && p.pending.is_empty());
+        if !finished {
+            return;
+        }
+        if let Some(Processing { path, failed, .. }) = self.streams.remove(&stream_id) {
+            if failed {
+                warn!(
+                    "{ctx} Leaving {} in place, not all events were processed",
+                    path.display()
+                );
+                self.failed.insert(path);
+            } else if let Err(e) = self.process(&path).await {
+                error!(
+                    "{ctx} Error handling processed file {}: {e}",
+                    path.display()
+                );
+                // don't read it again
+                self.failed.insert(path);
+            } else {
+                debug!("{ctx} Processed {}", path.display());
+            }
+        }
+    }
+
+    async fn process(&self, path: &Path) -> Result<()> {
+        match self.config.processed {
+            Processed::Mark => {
+                let mut marker = path.as_os_str().to_owned();
+                marker.push(&self.config.processed_suffix);
+                fs::write(marker, b"").await?;
+            }
+            Processed::Move => {
+                let processed_dir = self
+                    .config
+                    .processed_dir
+                    .as_ref()
+                    .ok_or("Missing `processed_dir`")?;
+                let target =
+                    processed_dir.join(path.strip_prefix(&self.config.path).unwrap_or(path));
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                if fs::rename(path, &target).await.is_err() {
+                    // on another filesystem
+                    fs::copy(path, &target).await?;
+                    fs::remove_file(path).await?;
+                }
+            }
+            Processed::Delete => fs::remove_file(path).await?,
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Source for DirSource {
+    async fn connect(&mut self, ctx: &SourceContext, _attempt: &Attempt) -> Result<bool> {
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        if !fs::metadata(&self.config.path).await?.is_dir() {
+            return Err(format!("{} is not a directory", self.config.path.display()).into());
+        }
+        // a new channel, so we don't receive anything from the previous task
+        let (tx, rx) = bounded(QSIZE.load(Ordering::Relaxed));
+        self.rx = rx;
+        // files from before a reconnect that are not read completely are not acknowledged completely either
+        self.streams.retain(|_, processing| processing.ended);
+        let seen = self
+            .streams
+            .values()
+            .map(|processing| processing.path.clone())
+            .chain(self.failed.iter().cloned())
+            .collect();
+        let scan = Scan {
+            config: self.config.clone(),
+            seen,
+            candidates: HashMap::new(),
+            next_stream: self.next_stream,
+            buf: vec![0; self.config.chunk_size],
+            ctx: ctx.clone(),
+            tx,
+        };
+        self.task = Some(task::spawn(scan.run()));
+        Ok(true)
+    }
+
+    async fn pull_data(&mut self, pull_id: &mut u64, _ctx: &SourceContext) -> Result<SourceReply> {
+        let Read {
+            reply,
+            path,
+            pull_id: read_pull_id,
+        } = self.rx.recv().await?;
+        *pull_id = read_pull_id;
+        match &reply {
+            SourceReply::Data {
+                stream: Some(stream),
+                ..
+            } => {
+                self.next_stream = self.next_stream.max(stream + 1);
+                self.streams
+                    .entry(*stream)
+                    .or_insert_with(|| Processing {
+                        path,
+                        pending: BTreeSet::new(),
+                        ended: false,
+                        failed: false,
+                    })
+                    .pending
+                    .insert(read_pull_id);
+            }
+            SourceReply::EndStream { stream, .. } => {
+                self.next_stream = self.next_stream.max(stream + 1);
+                let processing = self.streams.entry(*stream).or_insert_with(|| Processing {
+                    path,
+                    pending: BTreeSet::new(),
+                    ended: false,
+                    failed: false,
+                });
+                // events flushed at the end of the stream carry this pull id
+                processing.pending.insert(read_pull_id);
+                processing.ended = true;
+            }
+            SourceReply::StreamFail(stream) => {
+                // the file could not be read completely
+                self.streams.remove(stream);
+                self.failed.insert(path);
+            }
+            _ => {}
+        }
+        Ok(reply)
+    }
+
+    async fn ack(&mut self, stream_id: u64, pull_id: u64, ctx: &SourceContext) -> Result<()> {
+        if let Some(processing) = self.streams.get_mut(&stream_id) {
+            // acks are cumulative, acknowledged batches only carry the highest pull id of every stream
+            // and events dropped in a pipeline are never acknowledged, so all earlier chunks are done too
+            processing.pending = processing.pending.split_off(&(pull_id + 1));
+        }
+        self.done(stream_id, ctx).await;
+        Ok(())
+    }
+
+    async fn fail(&mut self, stream_id: u64, pull_id: u64, ctx: &SourceContext) -> Result<()> {
+        if let Some(processing) = self.streams.get_mut(&stream_id) {
+            processing.failed = true;
+            processing.pending.remove(&pull_id);
+        }
+        self.done(stream_id, ctx).await;
+        Ok(())
+    }
+
+    async fn on_events(
+        &mut self,
+        pull_id: u64,
+        stream_id: u64,
+        expected_replies: usize,
+        ctx: &SourceContext,
+    ) -> Result<()> {
+        if let Some(processing) = self.streams.get_mut(&stream_id) {
+            if expected_replies == 0 {
+                // the events go nowhere, they are never acknowledged
+                processing.pending.remove(&pull_id);
+            }
+        }
+        self.done(stream_id, ctx).await;
+        Ok(())
+    }
+
+    async fn on_no_events(
+        &mut self,
+        pull_id: u64,
+        stream_id: u64,
+        ctx: &SourceContext,
+    ) -> Result<()> {
+        if let Some(processing) = self.streams.get_mut(&stream_id) {
+            processing.pending.remove(&pull_id);
+        }
+        self.done(stream_id, ctx).await;
+        Ok(())
+    }
+
+    async fn on_stop(&mut self, _ctx: &SourceContext) -> Result<()> {
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        Ok(())
+    }
+
+    fn is_transactional(&self) -> bool {
+        true
+    }
+
+    fn asynchronous(&self) -> bool {
+        true
+    }
+}
+
+/// The task scanning the directory and reading new files
+struct Scan {
+    config: Config,
+    /// files read already, as long as they are in the directory
+    seen: HashSet<PathBuf>,
+    /// size and modification time of new files during the last scan
+    candidates: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+    next_stream: u64,
+    buf: Vec<u8>,
+    ctx: SourceContext,
+    tx: Sender<Read>,
+}
+
+impl Scan {
+    async fn run(mut self) {
+        let poll_interval = Duration::from_millis(self.config.poll_interval_ms);
+        loop {
+            match self.scan().await {
+                Ok(ready) => {
+                    for path in ready {
+                        if let Err(e) = self.read(path).await {
+                            error!("{} Error sending data: {e}", self.ctx);
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "{} Error scanning {}: {e}",
+                        self.ctx,
+                        self.config.path.display()
+                    );
+                    return;
+                }
+            }
+            task::sleep(poll_interval).await;
+        }
+    }
+
+    fn is_excluded(&self, path: &Path, exclude: &[glob::Pattern]) -> bool {
+        let relative = path.strip_prefix(&self.config.path).unwrap_or(path);
+        exclude.iter().any(|pattern| pattern.matches_path(relative))
+            || self
+                .config
+                .processed_dir
+                .as_ref()
+                .map_or(false, |dir| path.starts_with(dir))
+            || (self.config.processed == Processed::Mark
+                && path
+                    .to_string_lossy()
+                    .ends_with(&self.config.processed_suffix))
+    }
+
+    /// returns the new files that did not change since the last scan
+    async fn scan(&mut self) -> Result<Vec<PathBuf>> {
+        let exclude = self
+            .config
+            .exclude
+            .iter()
+            .map(String::as_str)
+            .map(glob::Pattern::new)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut matched = HashSet::new();
+        for include in &self.config.include {
+            let pattern = self.config.path.join(include).display().to_string();
+            for path in glob::glob(&pattern)?.filter_map(std::result::Result::ok) {
+                if !self.is_excluded(&path, &exclude) {
+                    matched.insert(path);
+                }
+            }
+        }
+        let mut ready = Vec::new();
+        let mut candidates = HashMap::new();
+        for path in &matched {
+            if self.seen.contains(path) {
+                continue;
+            }
+            let meta = match fs::metadata(path).await {
+                Ok(meta) if meta.is_file() => meta,
+                // removed in the meantime or not a file
+                _ => continue,
+            };
+            if self.config.processed == Processed::Mark {
+                let mut marker = path.as_os_str().to_owned();
+                marker.push(&self.config.processed_suffix);
+                if fs::metadata(PathBuf::from(marker)).await.is_ok() {
+                    continue;
+                }
+            }
+            let state = (meta.len(), meta.modified().ok());
+            if self.candidates.get(path) == Some(&state) {
+                ready.push(path.clone());
+            } else {
+                candidates.insert(path.clone(), state);
+            }
+        }
+        // forget about files that are gone, a new file with the same name is read again
+        self.seen.retain(|path| matched.contains(path));
+        self.candidates = candidates;
+        ready.sort();
+        Ok(ready)
+    }
+
+    /// reads a file as a new stream
+    async fn read(&mut self, path: PathBuf) -> Result<()> {
+        self.seen.insert(path.clone());
+        let stream = self.next_stream;
+        self.next_stream += 1;
+        let origin_uri = EventOriginUri {
+            scheme: URL_SCHEME.to_string(),
+            host: hostname(),
+            port: None,
+            path: vec![path.display().to_string()],
+        };
+        let meta = self.ctx.meta(literal!({
+            "path": path.display().to_string()
+        }));
+        info!("{} Reading {}", self.ctx, path.display());
+        let mut offset = 0_u64;
+        let mut file = match file::open(&path).await {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("{} {e}", self.ctx);
+                return self.fail(stream, path).await;
+            }
+        };
+        loop {
+            match file.read(&mut self.buf).await {
+                Ok(0) => break,
+                Ok(bytes_read) => {
+                    offset += bytes_read as u64;
+                    let reply = SourceReply::Data {
+                        origin_uri: origin_uri.clone(),
+                        // ALLOW: `read` ensures `bytes_read` is within the buffer
+                        data: self.buf[..bytes_read].to_vec(),
+                        meta: Some(meta.clone()),
+                        stream: Some(stream),
+                        port: Some(OUT),
+                        codec_overwrite: None,
+                    };
+                    self.tx
+                        .send(Read {
+                            reply,
+                            path: path.clone(),
+                            pull_id: offset,
+                        })
+                        .await?;
+                }
+                Err(e) => {
+                    warn!("{} Error reading {}: {e}", self.ctx, path.display());
+                    return self.fail(stream, path).await;
+                }
+            }
+        }
+        let reply = SourceReply::EndStream {
+            origin_uri,
+            stream,
+            meta: Some(meta),
+        };
+        // the pull id of the end of the stream follows the one of the last chunk
+        self.tx
+            .send(Read {
+                reply,
+                path,
+                pull_id: offset + 1,
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn fail(&self, stream: u64, path: PathBuf) -> Result<()> {
+        self.tx
+            .send(Read {
+                reply: SourceReply::StreamFail(stream),
+                path,
+                pull_id: 0,
+            })
+            .await?;
+        Ok(())
+    }
+}
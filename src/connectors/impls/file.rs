@@ -12,6 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod rolling;
+mod tail;
+
 use std::{ffi::OsStr, path::PathBuf};
 
 use crate::connectors::prelude::*;
@@ -31,6 +34,8 @@ const URL_SCHEME: &str = "tremor-file";
 pub(crate) enum Mode {
     /// read from file
     Read,
+    /// follow the files matching `path` like `tail -F`, including rotated and truncated ones
+    Tail,
     /// equivalent to `truncate` only here because it has such a nice name
     Write,
     /// append to the file
@@ -39,13 +44,19 @@ pub(crate) enum Mode {
     Truncate,
     /// just write to it and overwrite existing contents, do not truncate
     Overwrite,
+    /// write to files named by the `path` template, rotating them by size or age
+    Rolling,
 }
 
 impl Mode {
+    fn is_source(&self) -> bool {
+        matches!(self, Self::Read | Self::Tail)
+    }
+
     fn as_open_options(&self) -> OpenOptions {
         let mut o = OpenOptions::new();
         match self {
-            Self::Read => {
+            Self::Read | Self::Tail => {
                 o.read(true);
             }
             Self::Append => {
@@ -54,7 +65,7 @@ impl Mode {
             Self::Write | Self::Truncate => {
                 o.create(true).write(true).truncate(true);
             }
-            Self::Overwrite => {
+            Self::Overwrite | Self::Rolling => {
                 o.create(true).write(true);
             }
         }
@@ -66,13 +77,37 @@ impl Mode {
 #[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct Config {
-    /// path to the file
+    /// path to the file, a glob pattern in `tail` mode and a template in `rolling` mode
     pub(crate) path: PathBuf,
     /// how to interface with the file
     pub(crate) mode: Mode, // whether we read or write (in various forms)
     /// chunk_size to read from the file
     #[serde(default = "default_buf_size")]
     pub(crate) chunk_size: usize,
+    /// interval in milliseconds to check for new files and data in `tail` mode
+    #[serde(default = "default_poll_interval_ms")]
+    pub(crate) poll_interval_ms: u64,
+    /// file to persist the offsets of acknowledged data to in `tail` mode,
+    /// reading resumes from them after a restart
+    pub(crate) offsets_path: Option<PathBuf>,
+    /// read files present at startup from their end in `tail` mode,
+    /// unless there is a persisted offset for them
+    #[serde(default = "default_false")]
+    pub(crate) start_from_end: bool,
+    /// rotate files in `rolling` mode once they reach this size in bytes
+    pub(crate) max_size: Option<u64>,
+    /// rotate files in `rolling` mode once they are older than this many seconds
+    pub(crate) rotate_interval_s: Option<u64>,
+    /// compress rotated files in `rolling` mode with this algorithm of the `compress` postprocessor
+    pub(crate) compression: Option<String>,
+    /// compression level for `compression`
+    pub(crate) compression_level: Option<i64>,
+    /// keep at most this many rotated files in `rolling` mode, deleting the oldest ones
+    pub(crate) max_files: Option<usize>,
+}
+
+fn default_poll_interval_ms() -> u64 {
+    500
 }
 
 impl ConfigImpl for Config {}
@@ -96,11 +131,14 @@ impl ConnectorBuilder for Builder {
 
     async fn build_cfg(
         &self,
-        _: &str,
+        alias: &str,
         _: &ConnectorConfig,
         config: &Value,
     ) -> Result<Box<dyn Connector>> {
         let config = Config::new(config)?;
+        if config.mode == Mode::Rolling {
+            rolling::validate(alias, &config)?;
+        }
         Ok(Box::new(File { config }))
     }
 }
@@ -112,8 +150,11 @@ impl Connector for File {
         sink_context: SinkContext,
         builder: SinkManagerBuilder,
     ) -> Result<Option<SinkAddr>> {
-        if self.config.mode == Mode::Read {
+        if self.config.mode.is_source() {
             Ok(None)
+        } else if self.config.mode == Mode::Rolling {
+            let sink = rolling::RollingSink::new(&self.config);
+            builder.spawn(sink, sink_context).map(Some)
         } else {
             let sink = FileSink::new(self.config.clone());
             builder.spawn(sink, sink_context).map(Some)
@@ -125,7 +166,10 @@ impl Connector for File {
         source_context: SourceContext,
         builder: SourceManagerBuilder,
     ) -> Result<Option<SourceAddr>> {
-        if self.config.mode == Mode::Read {
+        if self.config.mode == Mode::Tail {
+            let source = tail::TailSource::new(self.config.clone());
+            builder.spawn(source, source_context).map(Some)
+        } else if self.config.mode == Mode::Read {
             let source = FileSource::new(self.config.clone());
             builder.spawn(source, source_context).map(Some)
         } else {
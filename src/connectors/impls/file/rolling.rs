@@ -0,0 +1,436 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writing to rolling files
+//!
+//! `path` is a template: strftime-style `%` specifiers are replaced with the UTC time a file is
+//! opened, `{name}` placeholders with `$file.name` from the metadata of each event. Every distinct
+//! result of the placeholders, a partition, has its own open file.
+//!
+//! A file is closed once it reached `max_size` bytes or is older than `rotate_interval_s` seconds,
+//! the next event for its partition opens a new one. Closed files are compressed with the
+//! `compression` algorithm of the `compress` postprocessor. If `max_files` is set, only that many
+//! closed files of the same partition are kept, the oldest ones are deleted. Files of other
+//! partitions or not written by the connector are left alone. Metadata values must not contain
+//! path separators, events with such values are failed.
+
+use super::Config;
+use crate::connectors::prelude::*;
+use crate::errors::err_conector_def;
+use crate::postprocessor::{Compress, Postprocessor};
+use async_std::fs::{self, File as FSFile, OpenOptions};
+use async_std::task;
+use chrono::format::{Item, StrftimeItems};
+use chrono::Utc;
+use futures::AsyncWriteExt;
+use halfbrown::HashMap;
+use std::io::{ErrorKind as IoErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tremor_common::time::nanotime;
+
+/// checks the rolling mode settings of `config`
+pub(super) fn validate(alias: &str, config: &Config) -> Result<()> {
+    if config.max_size.is_none() && config.rotate_interval_s.is_none() {
+        return Err(err_conector_def(
+            alias,
+            "`rolling` mode requires `max_size` or `rotate_interval_s`",
+        ));
+    }
+    let template = config.path.display().to_string();
+    let mut rest = template.as_str();
+    while let Some((_, tail)) = rest.split_once('{') {
+        let (name, tail) = tail
+            .split_once('}')
+            .ok_or_else(|| err_conector_def(alias, "Unclosed `{` in the `path` template"))?;
+        if name.is_empty() {
+            return Err(err_conector_def(alias, "Empty `{}` in the `path` template"));
+        }
+        rest = tail;
+    }
+    if StrftimeItems::new(&template).any(|item| matches!(item, Item::Error)) {
+        return Err(err_conector_def(
+            alias,
+            "Invalid `%` specifier in the `path` template",
+        ));
+    }
+    if let Some(compression) = compression_config(config) {
+        Compress::from_config(Some(&compression)).map_err(|e| err_conector_def(alias, &e))?;
+    }
+    Ok(())
+}
+
+fn compression_config(config: &Config) -> Option<Value<'static>> {
+    config
+        .compression
+        .as_ref()
+        .map(|algorithm| match config.compression_level {
+            Some(level) => literal!({ "algorithm": algorithm.clone(), "level": level }),
+            None => literal!({ "algorithm": algorithm.clone() }),
+        })
+}
+
+/// file name extension of files compressed with `algorithm`
+fn extension(algorithm: &str) -> &str {
+    match algorithm {
+        "gzip" => "gz",
+        "xz2" => "xz",
+        "zstd" => "zst",
+        "snappy" => "sz",
+        other => other,
+    }
+}
+
+/// the characters a strftime specifier produces, as a regex
+fn specifier_regex(spec: char) -> &'static str {
+    match spec {
+        'a' | 'A' | 'b' | 'B' | 'h' | 'p' | 'P' | 'Z' => "[A-Za-z]+",
+        'C' | 'Y' | 'y' | 'G' | 'g' | 'm' | 'd' | 'e' | 'H' | 'k' | 'I' | 'l' | 'M' | 'S' | 'j'
+        | 'U' | 'W' | 'V' | 'u' | 'w' | 's' => "[0-9 ]+",
+        'f' => r"\.?[0-9]+",
+        'z' => "[+-][0-9:]+",
+        'F' => "[0-9-]+",
+        'T' | 'R' | 'X' => "[0-9:]+",
+        'D' | 'x' => "[0-9/]+",
+        _ => "[^/]*",
+    }
+}
+
+/// turns a partition into a glob pattern to list the files it produced and a regex matching only
+/// them, including compressed ones and ones made unique with a counter
+fn file_patterns(partition: &str, extension: Option<&str>) -> (String, String) {
+    let mut pattern = String::with_capacity(partition.len() + 1);
+    let mut regex = String::with_capacity(partition.len() + 32);
+    regex.push('^');
+    let mut literal = String::new();
+    let mut chars = partition.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => literal.push('%'),
+            Some(mut spec) => {
+                // skip padding flags, widths and precisions up to the actual specifier
+                while !(spec.is_ascii_alphabetic() || spec == '+') {
+                    match chars.next() {
+                        Some(next) => spec = next,
+                        None => break,
+                    }
+                }
+                pattern.push_str(&glob::Pattern::escape(&literal));
+                pattern.push('*');
+                regex.push_str(&regex::escape(&literal));
+                regex.push_str(specifier_regex(spec));
+                literal.clear();
+            }
+            None => literal.push('%'),
+        }
+    }
+    pattern.push_str(&glob::Pattern::escape(&literal));
+    pattern.push('*');
+    regex.push_str(&regex::escape(&literal));
+    regex.push_str(r"(\.[0-9]+)?");
+    if let Some(extension) = extension {
+        regex.push_str(&format!(r"(\.{})?", regex::escape(extension)));
+    }
+    regex.push('$');
+    (pattern, regex)
+}
+
+struct RollingFile {
+    path: PathBuf,
+    file: FSFile,
+    size: u64,
+    opened: u64,
+}
+
+pub(super) struct RollingSink {
+    template: String,
+    max_size: Option<u64>,
+    rotate_interval: Option<u64>,
+    compression: Option<Value<'static>>,
+    max_files: Option<usize>,
+    files: HashMap<String, RollingFile>,
+}
+
+impl RollingSink {
+    pub(super) fn new(config: &Config) -> Self {
+        Self {
+            template: config.path.display().to_string(),
+            max_size: config.max_size,
+            rotate_interval: config.rotate_interval_s.map(|s| s * 1_000_000_000),
+            compression: compression_config(config),
+            max_files: config.max_files,
+            files: HashMap::new(),
+        }
+    }
+
+    /// replaces the `{name}` placeholders of the template with the metadata of an event
+    fn partition(&self, meta: &Value) -> Result<String> {
+        let meta = meta.get("file");
+        let mut partition = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some((literal, tail)) = rest.split_once('{') {
+            let (name, tail) = tail
+                .split_once('}')
+                .ok_or_else(|| Error::from("Unclosed `{` in the `path` template"))?;
+            let value = meta
+                .get(name)
+                .and_then(|v| {
+                    v.as_str()
+                        .map(ToString::to_string)
+                        .or_else(|| v.as_i64().map(|i| i.to_string()))
+                })
+                .ok_or_else(|| {
+                    Error::from(format!(
+                        "Missing metadata `$file.{name}` for the `path` template"
+                    ))
+                })?;
+            // values must not leave the directory of the template
+            if value.contains(['/', '\\']) || value == "." || value == ".." {
+                return Err(format!("Invalid metadata `$file.{name}`: {value}").into());
+            }
+            partition.push_str(literal);
+            // `%` in metadata is not a time specifier
+            partition.push_str(&value.replace('%', "%%"));
+            rest = tail;
+        }
+        partition.push_str(rest);
+        Ok(partition)
+    }
+
+    fn expired(&self, file: &RollingFile) -> bool {
+        self.rotate_interval.map_or(false, |interval| {
+            nanotime().saturating_sub(file.opened) >= interval
+        })
+    }
+
+    fn compressed_path(&self, path: &Path) -> Option<PathBuf> {
+        let algorithm = self.compression.as_ref().get_str("algorithm")?;
+        let mut compressed = path.as_os_str().to_os_string();
+        compressed.push(".");
+        compressed.push(extension(algorithm));
+        Some(PathBuf::from(compressed))
+    }
+
+    /// opens a new file for `partition`, never reusing an existing one
+    async fn open(&self, partition: &str) -> Result<RollingFile> {
+        let base = Utc::now().format(partition).to_string();
+        let mut n = 0_u64;
+        loop {
+            let path = if n == 0 {
+                PathBuf::from(&base)
+            } else {
+                PathBuf::from(format!("{base}.{n}"))
+            };
+            n += 1;
+            if let Some(compressed) = self.compressed_path(&path) {
+                if fs::metadata(&compressed).await.is_ok() {
+                    continue;
+                }
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(file) => {
+                    return Ok(RollingFile {
+                        path,
+                        file,
+                        size: 0,
+                        opened: nanotime(),
+                    })
+                }
+                Err(e) if e.kind() == IoErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// closes the file of `partition`, compresses it and removes old files
+    async fn rotate(&mut self, partition: &str, ctx: &SinkContext) {
+        if let Some(file) = self.files.remove(partition) {
+            self.close(file, ctx).await;
+            if let Some(max_files) = self.max_files {
+                self.cleanup(partition, max_files, ctx).await;
+            }
+        }
+    }
+
+    async fn close(&self, file: RollingFile, ctx: &SinkContext) {
+        let RollingFile { path, file, .. } = file;
+        if let Err(e) = file.sync_all().await {
+            error!("{ctx} Error flushing file {}: {e}", path.display());
+        }
+        drop(file);
+        debug!("{ctx} Closed {}", path.display());
+        if let (Some(config), Some(target)) =
+            (self.compression.clone(), self.compressed_path(&path))
+        {
+            let source = path.clone();
+            let res = task::spawn_blocking(move || -> Result<()> {
+                let data = std::fs::read(&source)?;
+                let mut compress = Compress::from_config(Some(&config))?;
+                let mut out = std::fs::File::create(&target)?;
+                for chunk in compress.process(0, 0, &data)? {
+                    out.write_all(&chunk)?;
+                }
+                out.sync_all()?;
+                std::fs::remove_file(&source)?;
+                Ok(())
+            })
+            .await;
+            if let Err(e) = res {
+                error!("{ctx} Error compressing file {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// deletes the oldest closed files of `partition` beyond `max_files`
+    async fn cleanup(&self, partition: &str, max_files: usize, ctx: &SinkContext) {
+        let extension = self
+            .compression
+            .as_ref()
+            .get_str("algorithm")
+            .map(extension);
+        let (pattern, regex) = file_patterns(partition, extension);
+        let (paths, regex) = match (glob::glob(&pattern), regex::Regex::new(&regex)) {
+            (Ok(paths), Ok(regex)) => (paths, regex),
+            (Err(e), _) => {
+                error!("{ctx} Invalid pattern {pattern}: {e}");
+                return;
+            }
+            (_, Err(e)) => {
+                error!("{ctx} Invalid pattern {regex}: {e}");
+                return;
+            }
+        };
+        let mut closed = Vec::new();
+        for path in paths.filter_map(std::result::Result::ok) {
+            // the glob pattern matches files of other partitions as well
+            if !regex.is_match(&path.to_string_lossy())
+                || self.files.values().any(|file| file.path == path)
+            {
+                continue;
+            }
+            if let Ok(metadata) = fs::metadata(&path).await {
+                if let (true, Ok(modified)) = (metadata.is_file(), metadata.modified()) {
+                    closed.push((modified, path));
+                }
+            }
+        }
+        closed.sort();
+        let excess = closed.len().saturating_sub(max_files);
+        for (_, path) in closed.into_iter().take(excess) {
+            match fs::remove_file(&path).await {
+                Ok(()) => debug!("{ctx} Deleted {}", path.display()),
+                Err(e) => warn!("{ctx} Error deleting file {}: {e}", path.display()),
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for RollingSink {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        event: Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        _start: u64,
+    ) -> Result<SinkReply> {
+        let ingest_ns = event.ingest_ns;
+        for (value, meta) in event.value_meta_iter() {
+            let partition = self.partition(meta)?;
+            if self
+                .files
+                .get(&partition)
+                .map_or(false, |file| self.expired(file))
+            {
+                self.rotate(&partition, ctx).await;
+            }
+            let data = serializer.serialize(value, ingest_ns)?;
+            if !self.files.contains_key(&partition) {
+                let file = self.open(&partition).await?;
+                debug!("{ctx} Opened {}", file.path.display());
+                self.files.insert(partition.clone(), file);
+            }
+            let file = self
+                .files
+                .get_mut(&partition)
+                .ok_or_else(|| Error::from("No file available."))?;
+            for chunk in data {
+                if let Err(e) = file.file.write_all(&chunk).await {
+                    error!("{ctx} Error writing to file {}: {e}", file.path.display());
+                    self.files.remove(&partition);
+                    return Err(e.into());
+                }
+                file.size += chunk.len() as u64;
+            }
+            if let Err(e) = file.file.flush().await {
+                error!("{ctx} Error flushing file {}: {e}", file.path.display());
+                self.files.remove(&partition);
+                return Err(e.into());
+            }
+            if self
+                .max_size
+                .map_or(false, |max_size| file.size >= max_size)
+            {
+                self.rotate(&partition, ctx).await;
+            }
+        }
+        Ok(SinkReply::NONE)
+    }
+
+    async fn on_signal(
+        &mut self,
+        _signal: Event,
+        ctx: &SinkContext,
+        _serializer: &mut EventSerializer,
+    ) -> Result<SinkReply> {
+        let expired: Vec<String> = self
+            .files
+            .iter()
+            .filter(|(_, file)| self.expired(file))
+            .map(|(partition, _)| partition.clone())
+            .collect();
+        for partition in expired {
+            self.rotate(&partition, ctx).await;
+        }
+        Ok(SinkReply::NONE)
+    }
+
+    fn auto_ack(&self) -> bool {
+        true
+    }
+
+    fn asynchronous(&self) -> bool {
+        false
+    }
+
+    async fn on_stop(&mut self, ctx: &SinkContext) -> Result<()> {
+        let partitions: Vec<String> = self.files.keys().cloned().collect();
+        for partition in partitions {
+            self.rotate(&partition, ctx).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,638 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Following files like `tail -F`
+//!
+//! Files are identified by device and inode, so a rotated file is read until its end before it is
+//! dropped, while the new file at its path becomes a new stream. A file shrinking below the read
+//! position is considered truncated and read again from its start as a new stream.
+//!
+//! Data is only emitted up to the last newline read, every line on its own, so the `pull_id` of an
+//! event is the offset of the line following it. Acks are cumulative, a line is acknowledged once all
+//! of its events are, together with all lines before it. The offset up to which all data of a file is
+//! acknowledged is persisted to `offsets_path` at most every `poll_interval_ms` and when the connector
+//! stops. The offset isn't advanced past failed data, so it is read again after the connector restarted.
+
+use super::{Config, URL_SCHEME};
+use crate::connectors::prelude::*;
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::fs::{self, File as FSFile};
+use async_std::io::{prelude::SeekExt, SeekFrom};
+use async_std::task::{self, JoinHandle};
+use futures::AsyncReadExt;
+use halfbrown::HashMap;
+use std::collections::VecDeque;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tremor_common::asy::file;
+
+/// Identifies a file independent of its path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FileKey {
+    dev: u64,
+    ino: u64,
+}
+
+impl FileKey {
+    #[cfg(unix)]
+    fn of(meta: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            dev: meta.dev(),
+            ino: meta.ino(),
+        }
+    }
+
+    /// without inodes the creation time is the closest we get
+    #[cfg(not(unix))]
+    fn of(meta: &std::fs::Metadata) -> Self {
+        let ino = meta
+            .created()
+            .ok()
+            .and_then(|created| created.duration_since(std::time::UNIX_EPOCH).ok())
+            .and_then(|since| u64::try_from(since.as_nanos()).ok())
+            .unwrap_or_default();
+        Self { dev: 0, ino }
+    }
+}
+
+/// The offset of acknowledged data of a file, as persisted in `offsets_path`
+#[derive(Serialize, Deserialize, Debug)]
+struct FileOffset {
+    dev: u64,
+    ino: u64,
+    /// last known path, for humans only
+    path: PathBuf,
+    offset: u64,
+}
+
+async fn load_offsets(path: &Path) -> Result<HashMap<FileKey, (PathBuf, u64)>> {
+    match fs::read(path).await {
+        Ok(mut data) => {
+            let offsets: Vec<FileOffset> = simd_json::from_slice(&mut data)?;
+            Ok(offsets
+                .into_iter()
+                .map(|o| {
+                    (
+                        FileKey {
+                            dev: o.dev,
+                            ino: o.ino,
+                        },
+                        (o.path, o.offset),
+                    )
+                })
+                .collect())
+        }
+        Err(e) if e.kind() == IoErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// writes the offsets to a temporary file first, so a crash never leaves a partially written file behind
+async fn persist_offsets(path: &Path, offsets: &HashMap<FileKey, (PathBuf, u64)>) -> Result<()> {
+    let offsets: Vec<FileOffset> = offsets
+        .iter()
+        .map(|(key, (path, offset))| FileOffset {
+            dev: key.dev,
+            ino: key.ino,
+            path: path.clone(),
+            offset: *offset,
+        })
+        .collect();
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, simd_json::to_vec(&offsets)?).await?;
+    fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+/// A reply of the tailing task
+struct Read {
+    reply: SourceReply,
+    key: FileKey,
+    path: PathBuf,
+    /// offset following the data
+    offset: u64,
+}
+
+/// A tailed file with data waiting for acknowledgement
+struct Stream {
+    key: FileKey,
+    path: PathBuf,
+    /// offsets following the emitted lines, in order, with the number of acks still expected for
+    /// them, which is unknown until their events are created
+    unacked: VecDeque<(u64, Option<usize>)>,
+    /// offset following the first failed line, the persisted offset stays before it
+    failed: Option<u64>,
+}
+
+impl Stream {
+    fn get_mut(&mut self, pull_id: u64) -> Option<&mut Option<usize>> {
+        let i = self
+            .unacked
+            .binary_search_by_key(&pull_id, |(offset, _)| *offset)
+            .ok()?;
+        self.unacked.get_mut(i).map(|(_, expected)| expected)
+    }
+
+    /// drops the acknowledged lines up to the first unacknowledged or failed one,
+    /// returns the offset following them
+    fn commit(&mut self) -> Option<u64> {
+        let mut committed = None;
+        while let Some((offset, Some(0))) = self.unacked.front().copied() {
+            if self.failed.map_or(false, |failed| offset >= failed) {
+                break;
+            }
+            self.unacked.pop_front();
+            committed = Some(offset);
+        }
+        committed
+    }
+}
+
+pub(super) struct TailSource {
+    config: Config,
+    tx: Sender<Read>,
+    rx: Receiver<Read>,
+    task: Option<JoinHandle<()>>,
+    next_stream: u64,
+    /// tailed files by stream id
+    streams: HashMap<u64, Stream>,
+    /// offsets of acknowledged data by file
+    acked: HashMap<FileKey, (PathBuf, u64)>,
+    offsets_loaded: bool,
+    dirty: bool,
+    last_persisted: Instant,
+}
+
+impl TailSource {
+    pub(super) fn new(config: Config) -> Self {
+        let (tx, rx) = bounded(QSIZE.load(Ordering::Relaxed));
+        Self {
+            config,
+            tx,
+            rx,
+            task: None,
+            next_stream: DEFAULT_STREAM_ID,
+            streams: HashMap::new(),
+            acked: HashMap::new(),
+            offsets_loaded: false,
+            dirty: false,
+            last_persisted: Instant::now(),
+        }
+    }
+
+    async fn persist(&mut self) -> Result<()> {
+        if let Some(offsets_path) = self.config.offsets_path.as_ref() {
+            if self.dirty {
+                persist_offsets(offsets_path, &self.acked).await?;
+                self.dirty = false;
+            }
+        }
+        self.last_persisted = Instant::now();
+        Ok(())
+    }
+
+    /// records the offset up to which the data of the stream is acknowledged
+    fn commit(&mut self, stream_id: u64) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            if let Some(offset) = stream.commit() {
+                self.acked.insert(stream.key, (stream.path.clone(), offset));
+                self.dirty = true;
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Source for TailSource {
+    async fn connect(&mut self, ctx: &SourceContext, _attempt: &Attempt) -> Result<bool> {
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        if !self.offsets_loaded {
+            if let Some(offsets_path) = self.config.offsets_path.as_ref() {
+                self.acked = load_offsets(offsets_path).await?;
+            }
+            self.offsets_loaded = true;
+        }
+        let pattern = self.config.path.display().to_string();
+        // fail early on invalid patterns
+        glob::Pattern::new(&pattern)?;
+
+        // a new channel, so we don't receive anything from the previous task
+        let (tx, rx) = bounded(QSIZE.load(Ordering::Relaxed));
+        self.tx = tx;
+        self.rx = rx;
+        self.streams.clear();
+        let tail = Tail {
+            pattern,
+            poll_interval: Duration::from_millis(self.config.poll_interval_ms),
+            start_from_end: self.config.start_from_end,
+            offsets: self
+                .acked
+                .iter()
+                .map(|(key, (_path, offset))| (*key, *offset))
+                .collect(),
+            files: Vec::new(),
+            next_stream: self.next_stream,
+            buf: vec![0; self.config.chunk_size],
+            ctx: ctx.clone(),
+            tx: self.tx.clone(),
+        };
+        self.task = Some(task::spawn(tail.run()));
+        Ok(true)
+    }
+
+    async fn pull_data(&mut self, pull_id: &mut u64, _ctx: &SourceContext) -> Result<SourceReply> {
+        let Read {
+            reply,
+            key,
+            path,
+            offset,
+        } = self.rx.recv().await?;
+        match &reply {
+            SourceReply::Data {
+                stream: Some(stream),
+                ..
+            } => {
+                self.next_stream = self.next_stream.max(stream + 1);
+                let tracked = self.streams.entry(*stream).or_insert_with(|| Stream {
+                    key,
+                    path: path.clone(),
+                    unacked: VecDeque::new(),
+                    failed: None,
+                });
+                tracked.path = path;
+                tracked.unacked.push_back((offset, None));
+                *pull_id = offset;
+            }
+            SourceReply::EndStream { stream, .. } => {
+                // the file is gone or truncated, its offset is meaningless now
+                self.streams.remove(stream);
+                self.dirty |= self.acked.remove(&key).is_some();
+            }
+            _ => {}
+        }
+        Ok(reply)
+    }
+
+    async fn ack(&mut self, stream_id: u64, pull_id: u64, _ctx: &SourceContext) -> Result<()> {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            let done = match stream.get_mut(pull_id) {
+                Some(Some(expected)) => {
+                    *expected = expected.saturating_sub(1);
+                    *expected == 0
+                }
+                _ => false,
+            };
+            // acknowledged batches only carry the highest pull id and events dropped in a pipeline
+            // are never acknowledged, so once a line is done all lines before it are done too
+            if done {
+                for (offset, expected) in &mut stream.unacked {
+                    if *offset > pull_id {
+                        break;
+                    }
+                    *expected = Some(0);
+                }
+            }
+        }
+        // only persist the offset up to which everything is acknowledged
+        self.commit(stream_id);
+        if self.last_persisted.elapsed() >= Duration::from_millis(self.config.poll_interval_ms) {
+            self.persist().await?;
+        }
+        Ok(())
+    }
+
+    async fn fail(&mut self, stream_id: u64, pull_id: u64, ctx: &SourceContext) -> Result<()> {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            if stream.failed.map_or(true, |failed| pull_id < failed) {
+                warn!(
+                    "{ctx} Data of {} up to offset {pull_id} failed, it is read again after a restart",
+                    stream.path.display()
+                );
+                // keep the offset before the failed data, acks of earlier data still advance it
+                stream.failed = Some(pull_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_events(
+        &mut self,
+        pull_id: u64,
+        stream_id: u64,
+        expected_replies: usize,
+        _ctx: &SourceContext,
+    ) -> Result<()> {
+        if let Some(expected) = self
+            .streams
+            .get_mut(&stream_id)
+            .and_then(|stream| stream.get_mut(pull_id))
+        {
+            // events going nowhere are never acknowledged
+            *expected = Some(expected_replies);
+        }
+        self.commit(stream_id);
+        Ok(())
+    }
+
+    async fn on_no_events(
+        &mut self,
+        pull_id: u64,
+        stream_id: u64,
+        _ctx: &SourceContext,
+    ) -> Result<()> {
+        if let Some(expected) = self
+            .streams
+            .get_mut(&stream_id)
+            .and_then(|stream| stream.get_mut(pull_id))
+        {
+            *expected = Some(0);
+        }
+        self.commit(stream_id);
+        Ok(())
+    }
+
+    async fn on_stop(&mut self, ctx: &SourceContext) -> Result<()> {
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        if let Err(e) = self.persist().await {
+            error!("{} Error persisting offsets: {}", &ctx, e);
+        }
+        Ok(())
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.config.offsets_path.is_some()
+    }
+
+    fn asynchronous(&self) -> bool {
+        true
+    }
+}
+
+/// A file being followed
+struct Tailed {
+    key: FileKey,
+    path: PathBuf,
+    file: FSFile,
+    /// offset of the next byte to read
+    read: u64,
+    /// data read after the last newline
+    partial: Vec<u8>,
+    stream: u64,
+    origin_uri: EventOriginUri,
+    meta: Value<'static>,
+    /// if the file matched the pattern during the last scan
+    matched: bool,
+}
+
+impl Tailed {
+    /// a line, `offset` is the one following it
+    fn data(&self, data: Vec<u8>, offset: u64) -> Read {
+        Read {
+            reply: SourceReply::Data {
+                origin_uri: self.origin_uri.clone(),
+                data,
+                meta: Some(self.meta.clone()),
+                stream: Some(self.stream),
+                port: Some(OUT),
+                codec_overwrite: None,
+            },
+            key: self.key,
+            path: self.path.clone(),
+            offset,
+        }
+    }
+
+    fn end(&self) -> Read {
+        Read {
+            reply: SourceReply::EndStream {
+                origin_uri: self.origin_uri.clone(),
+                stream: self.stream,
+                meta: Some(self.meta.clone()),
+            },
+            key: self.key,
+            path: self.path.clone(),
+            offset: self.read,
+        }
+    }
+
+    fn set_path(&mut self, path: PathBuf, ctx: &SourceContext) {
+        self.origin_uri = EventOriginUri {
+            scheme: URL_SCHEME.to_string(),
+            host: hostname(),
+            port: None,
+            path: vec![path.display().to_string()],
+        };
+        self.meta = ctx.meta(literal!({
+            "path": path.display().to_string()
+        }));
+        self.path = path;
+    }
+}
+
+/// The task reading the tailed files
+struct Tail {
+    pattern: String,
+    poll_interval: Duration,
+    start_from_end: bool,
+    /// offsets to start reading files from
+    offsets: HashMap<FileKey, u64>,
+    files: Vec<Tailed>,
+    next_stream: u64,
+    buf: Vec<u8>,
+    ctx: SourceContext,
+    tx: Sender<Read>,
+}
+
+impl Tail {
+    async fn run(mut self) {
+        let mut initial = true;
+        loop {
+            if let Err(e) = self.scan(initial).await {
+                error!("{} Error scanning files: {}", self.ctx, e);
+                // let the runtime reconnect, which starts following the files again
+                if let Err(e) = self.ctx.notifier().connection_lost().await {
+                    error!("{} Error notifying the runtime: {}", self.ctx, e);
+                }
+                return;
+            }
+            initial = false;
+            let scanned = Instant::now();
+            // read until there is nothing more to read or it is time to scan again
+            loop {
+                match self.read().await {
+                    Ok(true) if scanned.elapsed() < self.poll_interval => {}
+                    Ok(true) => break,
+                    Ok(false) => {
+                        task::sleep(self.poll_interval.saturating_sub(scanned.elapsed())).await;
+                        break;
+                    }
+                    Err(e) => {
+                        error!("{} Error sending data: {}", self.ctx, e);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// checks the files matching the pattern for new, renamed and truncated ones
+    async fn scan(&mut self, initial: bool) -> Result<()> {
+        for tailed in &mut self.files {
+            tailed.matched = false;
+        }
+        for entry in glob::glob(&self.pattern)? {
+            let path = match entry {
+                Ok(path) => path,
+                Err(e) => {
+                    warn!("{} Error checking {}: {}", self.ctx, self.pattern, e);
+                    continue;
+                }
+            };
+            let meta = match fs::metadata(&path).await {
+                Ok(meta) if meta.is_file() => meta,
+                Ok(_) => continue,
+                Err(e) => {
+                    // removed in the meantime
+                    debug!("{} Error checking {}: {}", self.ctx, path.display(), e);
+                    continue;
+                }
+            };
+            let key = FileKey::of(&meta);
+            if let Some(tailed) = self.files.iter_mut().find(|tailed| tailed.key == key) {
+                tailed.matched = true;
+                if tailed.path != path {
+                    info!(
+                        "{} {} was renamed to {}",
+                        self.ctx,
+                        tailed.path.display(),
+                        path.display()
+                    );
+                    tailed.set_path(path, &self.ctx);
+                }
+                if meta.len() < tailed.read {
+                    info!("{} {} was truncated", self.ctx, tailed.path.display());
+                    self.tx.send(tailed.end()).await?;
+                    tailed.file.seek(SeekFrom::Start(0)).await?;
+                    tailed.read = 0;
+                    tailed.partial.clear();
+                    tailed.stream = self.next_stream;
+                    self.next_stream += 1;
+                }
+            } else {
+                let start = match self.offsets.remove(&key) {
+                    // the inode might have been reused
+                    Some(offset) if offset <= meta.len() => offset,
+                    _ if initial && self.start_from_end => meta.len(),
+                    _ => 0,
+                };
+                match file::open(&path).await {
+                    Ok(mut file) => {
+                        file.seek(SeekFrom::Start(start)).await?;
+                        info!(
+                            "{} Following {} from offset {}",
+                            self.ctx,
+                            path.display(),
+                            start
+                        );
+                        let mut tailed = Tailed {
+                            key,
+                            path: PathBuf::new(),
+                            file,
+                            read: start,
+                            partial: Vec::new(),
+                            stream: self.next_stream,
+                            origin_uri: EventOriginUri::default(),
+                            meta: Value::null(),
+                            matched: true,
+                        };
+                        tailed.set_path(path, &self.ctx);
+                        self.next_stream += 1;
+                        self.files.push(tailed);
+                    }
+                    Err(e) => warn!("{} {}", self.ctx, e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// reads a chunk from every file, returns if any data was read
+    async fn read(&mut self) -> Result<bool> {
+        let mut read_any = false;
+        let mut i = 0;
+        while i < self.files.len() {
+            let tailed = &mut self.files[i];
+            match tailed.file.read(&mut self.buf).await {
+                Ok(0) if !tailed.matched => {
+                    // rotated away or removed and read completely
+                    let tailed = self.files.remove(i);
+                    self.finish(tailed).await?;
+                    continue;
+                }
+                Ok(0) => {}
+                Ok(bytes_read) => {
+                    read_any = true;
+                    tailed.read += bytes_read as u64;
+                    // ALLOW: `read` ensures `bytes_read` is within the buffer
+                    let data = &self.buf[..bytes_read];
+                    if let Some(newline) = data.iter().rposition(|b| *b == b'\n') {
+                        let mut lines = std::mem::take(&mut tailed.partial);
+                        lines.extend_from_slice(&data[..=newline]);
+                        tailed.partial.extend_from_slice(&data[newline + 1..]);
+                        // every line gets a pull id of its own, so it can be acknowledged on its own
+                        let mut offset = tailed.read - (tailed.partial.len() + lines.len()) as u64;
+                        for line in lines.split_inclusive(|b| *b == b'\n') {
+                            offset += line.len() as u64;
+                            self.tx.send(tailed.data(line.to_vec(), offset)).await?;
+                        }
+                    } else {
+                        tailed.partial.extend_from_slice(data);
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "{} Error reading {}: {}",
+                        self.ctx,
+                        tailed.path.display(),
+                        e
+                    );
+                    let tailed = self.files.remove(i);
+                    self.finish(tailed).await?;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        Ok(read_any)
+    }
+
+    /// emits the remaining data of a file we stop following and ends its stream
+    async fn finish(&mut self, mut tailed: Tailed) -> Result<()> {
+        info!("{} Done with {}", self.ctx, tailed.path.display());
+        if !tailed.partial.is_empty() {
+            let partial = std::mem::take(&mut tailed.partial);
+            let offset = tailed.read;
+            self.tx.send(tailed.data(partial, offset)).await?;
+        }
+        self.tx.send(tailed.end()).await?;
+        Ok(())
+    }
+}
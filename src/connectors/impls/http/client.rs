@@ -275,7 +275,6 @@ impl Sink for HttpRequestSink {
                 None
             };
             let mut origin_uri = self.origin_uri.clone();
-            let ingest_ns = event.ingest_ns;
 
             // take the metadata from the first element of the batch
             let event_meta = event.value_meta_iter().next().map(|t| t.1);
@@ -303,12 +302,10 @@ impl Sink for HttpRequestSink {
             if !request_is_chunked {
                 // if the request is not chunked
                 // we need to populate the request body from the (possibly batched) event payloads first
-                for value in event.value_iter() {
-                    ctx.bail_err(
-                        builder.append(value, ingest_ns, serializer).await,
-                        "Error serializing event into request body",
-                    )?;
-                }
+                ctx.bail_err(
+                    builder.append_event(&event, serializer).await,
+                    "Error serializing event into request body",
+                )?;
                 // the request will only be available after finalizing
                 request = ctx.bail_err(
                     builder.finalize(serializer).await,
@@ -399,12 +396,10 @@ impl Sink for HttpRequestSink {
 
             if request_is_chunked {
                 // if we have a chunked request we still gotta do some work (sending the chunks)
-                for value in event.value_iter() {
-                    ctx.bail_err(
-                        builder.append(value, ingest_ns, serializer).await,
-                        "Error serializing event into request body",
-                    )?;
-                }
+                ctx.bail_err(
+                    builder.append_event(&event, serializer).await,
+                    "Error serializing event into request body",
+                )?;
                 ctx.bail_err(
                     builder.finalize(serializer).await,
                     "Error serializing final parts of the event into request body",
@@ -14,6 +14,7 @@
 
 use super::client;
 use super::utils::{FixedBodyReader, RequestId, StreamingBodyReader};
+use crate::codec::prometheus;
 use crate::connectors::{prelude::*, utils::mime::MimeCodecMap};
 use async_std::channel::{unbounded, Sender};
 use either::Either;
@@ -41,6 +42,8 @@ pub(crate) struct HttpRequestBuilder {
     request: Option<Request>,
     body_data: BodyData,
     codec_overwrite: Option<String>,
+    /// if all values of a batch are encoded into a single body
+    single_body: bool,
 }
 
 // TODO: do some deduplication with SinkResponse
@@ -141,6 +144,17 @@ impl HttpRequestBuilder {
                 request.set_content_type(ct);
             }
         }
+        // the remote write protocol requires some more headers and a single
+        // `WriteRequest` per request
+        let single_body =
+            codec_overwrite.as_deref().unwrap_or(configured_codec) == prometheus::REMOTE_WRITE;
+        if single_body {
+            for (name, value) in prometheus::REMOTE_WRITE_HEADERS {
+                if request.header(name).is_none() {
+                    request.insert_header(name, value);
+                }
+            }
+        }
         // handle AUTH
         if let Some(auth_header) = config.auth.as_header_value()? {
             request.insert_header(headers::AUTHORIZATION, auth_header);
@@ -164,9 +178,27 @@ impl HttpRequestBuilder {
             request: Some(request),
             body_data,
             codec_overwrite,
+            single_body,
         })
     }
 
+    /// Appends the values of a (possibly batched) event
+    pub(super) async fn append_event(
+        &mut self,
+        event: &Event,
+        serializer: &mut EventSerializer,
+    ) -> Result<()> {
+        if self.single_body {
+            let values: Value = event.value_iter().cloned().collect();
+            self.append(&values, event.ingest_ns, serializer).await
+        } else {
+            for value in event.value_iter() {
+                self.append(value, event.ingest_ns, serializer).await?;
+            }
+            Ok(())
+        }
+    }
+
     pub(super) async fn append<'event>(
         &mut self,
         value: &'event Value<'event>,
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::codec::prometheus;
 use crate::connectors::{
     prelude::*,
     utils::{mime::MimeCodecMap, tls::TLSServerConfig},
@@ -253,6 +254,7 @@ impl Source for HttpServerSource {
             data,
             request_meta,
             content_type,
+            remote_write,
             response_channel,
         } = self.request_rx.recv().await?;
 
@@ -282,7 +284,10 @@ impl Source for HttpServerSource {
         } else {
             // codec overwrite, depending on requests content-type
             // only set the overwrite if it is different than the configured codec
-            let codec_overwrite = if let Some(content_type) = content_type {
+            let codec_overwrite = if remote_write {
+                // remote write requests use the generic protobuf content-type
+                Some(prometheus::REMOTE_WRITE.to_string()).filter(|c| c != &self.configured_codec)
+            } else if let Some(content_type) = content_type {
                 let maybe_codec = self.codec_map.get_codec_name(content_type.as_str());
                 maybe_codec
                     .filter(|c| *c != &self.configured_codec)
@@ -708,6 +713,8 @@ struct RawRequestData {
     // metadata about the request, not the ready event meta, still needs to be wrapped
     request_meta: Value<'static>,
     content_type: Option<String>,
+    /// if this is a prometheus remote write request
+    remote_write: bool,
     response_channel: Sender<Response>,
 }
 
@@ -727,6 +734,9 @@ async fn handle_request(mut req: tide::Request<HttpServerState>) -> tide::Result
 async fn _handle_request(req: &mut tide::Request<HttpServerState>) -> tide::Result<tide::Response> {
     let request_meta = extract_request_meta(req.as_ref());
     let content_type = req.content_type().map(|mime| mime.essence().to_string());
+    let remote_write = req
+        .header(prometheus::REMOTE_WRITE_VERSION_HEADER)
+        .is_some();
     let data = req.body_bytes().await?;
 
     // Dispatch
@@ -737,6 +747,7 @@ async fn _handle_request(req: &mut tide::Request<HttpServerState>) -> tide::Resu
             data,
             request_meta,
             content_type,
+            remote_write,
             response_channel: response_tx,
         })
         .await?;
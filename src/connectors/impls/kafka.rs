@@ -15,14 +15,161 @@ pub(crate) mod consumer;
 pub(crate) mod producer;
 
 use crate::errors::{err_conector_def, Result};
+use async_std::sync::Arc;
+use consumer::{SeekEpochs, TopicResolver, TremorConsumer};
 use core::future::Future;
+use dashmap::DashMap;
 use futures::future;
-use rdkafka::{error::KafkaError, util::AsyncRuntime};
+use rdkafka::consumer::{Consumer, ConsumerGroupMetadata};
+use rdkafka::{error::KafkaError, util::AsyncRuntime, TopicPartitionList};
 use rdkafka_sys::RDKafkaErrorCode;
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
+use tremor_pipeline::EventId;
 
 const KAFKA_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
 
+/// A consumer in `exactly_once` mode, whose offsets are committed by transactional producers
+struct ExactlyOnceConsumer {
+    /// the live consumer, its group metadata changes with every rebalance
+    consumer: Arc<TremorConsumer>,
+    topic_resolver: TopicResolver,
+    seek_epochs: Arc<SeekEpochs>,
+}
+
+lazy_static! {
+    /// consumers in `exactly_once` mode by the id of their source
+    static ref EXACTLY_ONCE_CONSUMERS: DashMap<u64, Arc<ExactlyOnceConsumer>> = DashMap::new();
+}
+
+fn register_exactly_once_consumer(
+    source_id: u64,
+    consumer: Arc<TremorConsumer>,
+    topic_resolver: TopicResolver,
+    seek_epochs: Arc<SeekEpochs>,
+) {
+    EXACTLY_ONCE_CONSUMERS.insert(
+        source_id,
+        Arc::new(ExactlyOnceConsumer {
+            consumer,
+            topic_resolver,
+            seek_epochs,
+        }),
+    );
+}
+
+fn unregister_exactly_once_consumer(source_id: u64) {
+    EXACTLY_ONCE_CONSUMERS.remove(&source_id);
+}
+
+/// A partition an event was consumed from by a consumer in `exactly_once` mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ConsumedPartition {
+    source_id: u64,
+    stream_id: u64,
+}
+
+impl ConsumedPartition {
+    /// whether the partition was seeked since the message with the pull id was consumed
+    fn is_stale(&self, pull_id: u64) -> bool {
+        EXACTLY_ONCE_CONSUMERS
+            .get(&self.source_id)
+            .map_or(false, |consumer| {
+                consumer.seek_epochs.is_stale(self.stream_id, pull_id)
+            })
+    }
+}
+
+/// The earliest and latest offset consumed from a partition
+#[derive(Debug, Clone, Copy)]
+struct ConsumedRange {
+    earliest: u64,
+    latest: u64,
+}
+
+/// Offsets consumed by consumers in `exactly_once` mode events originate from
+#[derive(Debug, Default)]
+struct ConsumedOffsets(HashMap<ConsumedPartition, ConsumedRange>);
+
+impl ConsumedOffsets {
+    fn of(event_id: &EventId) -> Self {
+        let mut offsets = HashMap::new();
+        for entry in EXACTLY_ONCE_CONSUMERS.iter() {
+            let source_id = *entry.key();
+            for stream_id in event_id.get_streams(source_id) {
+                let earliest = event_id.get_min_by_stream(source_id, stream_id);
+                let latest = event_id.get_max_by_stream(source_id, stream_id);
+                if let Some((earliest, latest)) = earliest.zip(latest) {
+                    offsets.insert(
+                        ConsumedPartition {
+                            source_id,
+                            stream_id,
+                        },
+                        ConsumedRange { earliest, latest },
+                    );
+                }
+            }
+        }
+        Self(offsets)
+    }
+
+    /// adds the offsets consumed by another event
+    fn merge(&mut self, other: Self) {
+        for (partition, range) in other.0 {
+            self.0
+                .entry(partition)
+                .and_modify(|r| {
+                    r.earliest = r.earliest.min(range.earliest);
+                    r.latest = r.latest.max(range.latest);
+                })
+                .or_insert(range);
+        }
+    }
+
+    /// the earliest consumed offset of every partition
+    fn earliest(&self) -> impl Iterator<Item = (&ConsumedPartition, u64)> + '_ {
+        self.0
+            .iter()
+            .map(|(partition, range)| (partition, range.earliest))
+    }
+
+    /// the offsets to commit, the ones after the latest consumed message of every partition,
+    /// with the current group metadata of their consumer
+    fn to_commit(&self) -> Result<Vec<(ConsumerGroupMetadata, TopicPartitionList)>> {
+        let mut source_ids: Vec<u64> = self.0.keys().map(|p| p.source_id).collect();
+        source_ids.sort_unstable();
+        source_ids.dedup();
+        let mut commits = Vec::with_capacity(source_ids.len());
+        for source_id in source_ids {
+            let consumer = EXACTLY_ONCE_CONSUMERS
+                .get(&source_id)
+                .map(|entry| entry.value().clone())
+                .ok_or("Consumer of the offsets to commit is gone")?;
+            // fetched from the live consumer, as it changes with every rebalance
+            let group_metadata = consumer
+                .consumer
+                .group_metadata()
+                .ok_or("Consumer group metadata not available")?;
+            let mut tpl = TopicPartitionList::new();
+            // offsets consumed before a seek would move the committed offset away from it
+            for (partition, range) in self
+                .0
+                .iter()
+                .filter(|(p, range)| p.source_id == source_id && !p.is_stale(range.latest))
+            {
+                if let Some((topic, partition, next)) = consumer
+                    .topic_resolver
+                    .resolve_topic(partition.stream_id, range.latest.saturating_add(1))
+                {
+                    tpl.add_partition_offset(topic, partition, next)?;
+                }
+            }
+            commits.push((group_metadata, tpl));
+        }
+        Ok(commits)
+    }
+}
+
 pub struct SmolRuntime;
 
 impl AsyncRuntime for SmolRuntime {
@@ -20,10 +20,12 @@ use super::SmolRuntime;
 use crate::connectors::impls::kafka::{is_failed_connect_error, KAFKA_CONNECT_TIMEOUT};
 use crate::connectors::prelude::*;
 use crate::connectors::utils::metrics::make_metrics_payload;
+use crate::errors::err_conector_def;
 use async_broadcast::{broadcast, Receiver as BroadcastReceiver, Sender as BroadcastSender};
 use async_std::channel::{bounded, Receiver, Sender};
 use async_std::prelude::{FutureExt, StreamExt};
 use async_std::task::{self, JoinHandle};
+use dashmap::DashMap;
 use halfbrown::HashMap;
 use indexmap::IndexMap;
 use log::Level::Debug;
@@ -33,6 +35,7 @@ use rdkafka::error::KafkaError;
 use rdkafka::message::{BorrowedMessage, Headers, Message};
 use rdkafka::{ClientContext, Offset, TopicPartitionList};
 use rdkafka_sys::RDKafkaErrorCode;
+use tremor_common::ids::Id;
 
 const KAFKA_CONSUMER_META_KEY: &str = "kafka_consumer";
 
@@ -41,8 +44,17 @@ const KAFKA_CONSUMER_META_KEY: &str = "kafka_consumer";
 pub struct Config {
     /// consumer group id to register with
     pub group_id: String,
-    /// List of topics to subscribe to
+    /// List of topics to subscribe to, mutually exclusive with `partitions`
+    #[serde(default)]
     pub topics: Vec<String>,
+    /// List of partitions to consume from, instead of subscribing to `topics` and
+    /// having partitions assigned by the consumer group
+    #[serde(default)]
+    pub partitions: Vec<Partition>,
+    /// Where to start consuming `partitions` from, unless set for a partition itself.
+    ///
+    /// Defaults to the committed offset of the consumer group, falling back to `auto.offset.reset`.
+    pub start_offset: Option<StartOffset>,
     /// List of bootstrap brokers
     pub brokers: Vec<String>,
 
@@ -60,12 +72,59 @@ pub struct Config {
     #[serde(default = "default_false")]
     pub retry_failed_events: bool,
 
+    /// If set to `true` offsets are not committed upon acks. Instead `kafka_producer` connectors with a
+    /// `transactional_id` commit them in the transactions producing the records of the consumed events.
+    ///
+    /// Failed events are always retried. Sets `enable.auto.commit` to `false` and only reads
+    /// committed records by setting `isolation.level` to `read_committed`.
+    #[serde(default = "default_false")]
+    pub exactly_once: bool,
+
     /// Optional rdkafka configuration
     pub rdkafka_options: Option<HashMap<String, String>>,
 }
 
 impl ConfigImpl for Config {}
 
+/// A partition to consume from
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Partition {
+    /// topic of the partition
+    pub topic: String,
+    /// the partition number
+    pub partition: i32,
+    /// Where to start consuming this partition from, overrides `start_offset`
+    pub offset: Option<StartOffset>,
+}
+
+/// A position in a partition
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StartOffset {
+    /// the earliest message still available
+    Earliest,
+    /// the end of the partition, only messages produced from now on are consumed
+    Latest,
+    /// the earliest message with a timestamp (in nanoseconds) equal to or later than the given one
+    Timestamp(u64),
+    /// the message with the given offset
+    Offset(i64),
+}
+
+/// A position to seek the assigned partitions to at runtime
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Seek {
+    /// only seek partitions of this topic
+    topic: Option<String>,
+    /// only seek partitions with this number
+    partition: Option<i32>,
+    offset: StartOffset,
+}
+
+impl ConfigImpl for Seek {}
+
 #[derive(Default, Debug)]
 pub(crate) struct Builder {}
 
@@ -83,6 +142,18 @@ impl ConnectorBuilder for Builder {
     ) -> Result<Box<dyn Connector>> {
         let metrics_interval_s = config.metrics_interval_s;
         let config = Config::new(raw_config)?;
+        if config.topics.is_empty() == config.partitions.is_empty() {
+            return Err(err_conector_def(
+                alias,
+                "Exactly one of `topics` or `partitions` needs to be provided",
+            ));
+        }
+        if config.partitions.is_empty() && config.start_offset.is_some() {
+            return Err(err_conector_def(
+                alias,
+                "`start_offset` requires `partitions`",
+            ));
+        }
         // returns the first broker if all are valid
         let (host, port) = super::verify_brokers(alias, &config.brokers)?;
         let origin_uri = EventOriginUri {
@@ -105,6 +176,12 @@ impl ConnectorBuilder for Builder {
         // .set("auto.commit.interval.ms", "5000")
         // .set("enable.auto.offset.store", "true");
 
+        if config.exactly_once {
+            client_config
+                .set("enable.auto.commit", "false")
+                .set("isolation.level", "read_committed");
+        }
+
         if let Some(metrics_interval_s) = metrics_interval_s {
             // enable stats collection
             client_config.set(
@@ -119,6 +196,12 @@ impl ConnectorBuilder for Builder {
             .for_each(|(k, v)| {
                 client_config.set(k, v);
             });
+        if config.exactly_once && client_config.get("enable.auto.commit") == Some("true") {
+            return Err(err_conector_def(
+                alias,
+                "`exactly_once` requires `enable.auto.commit` to be `false`",
+            ));
+        }
 
         debug!(
             "[Connector::{}] Kafka Consumer Config: {:?}",
@@ -136,7 +219,7 @@ impl ConnectorBuilder for Builder {
 #[derive(Debug, Clone)]
 struct KafkaStats {}
 
-struct TremorConsumerContext {
+pub(super) struct TremorConsumerContext {
     ctx: SourceContext,
     connect_tx: Sender<Result<bool>>,
     metrics_tx: BroadcastSender<EventPayload>,
@@ -313,7 +396,7 @@ impl TremorConsumerContext {
     }
 }
 
-type TremorConsumer = StreamConsumer<TremorConsumerContext, SmolRuntime>;
+pub(super) type TremorConsumer = StreamConsumer<TremorConsumerContext, SmolRuntime>;
 
 struct KafkaConsumerConnector {
     config: Config,
@@ -369,9 +452,16 @@ struct KafkaConsumerSource {
     client_config: ClientConfig,
     origin_uri: EventOriginUri,
     topics: Vec<String>,
+    partitions: Vec<Partition>,
+    start_offset: Option<StartOffset>,
     topic_resolver: TopicResolver,
     transactional: bool,
     retry_failed_events: bool,
+    exactly_once: bool,
+    /// the offsets failed events were read again from, by stream
+    rewinds: HashMap<u64, u64>,
+    /// shared with the consumer task, which tags the pull ids with them
+    seek_epochs: Arc<SeekEpochs>,
     seek_timeout: Duration,
     source_tx: Sender<(SourceReply, Option<u64>)>,
     source_rx: Receiver<(SourceReply, Option<u64>)>,
@@ -386,10 +476,17 @@ impl KafkaConsumerSource {
     fn new(config: Config, client_config: ClientConfig, origin_uri: EventOriginUri) -> Self {
         let Config {
             topics,
+            partitions,
+            start_offset,
             retry_failed_events,
+            exactly_once,
             ..
         } = config;
-        let topic_resolver = TopicResolver::new(topics.clone());
+        let mut resolved_topics = topics.clone();
+        resolved_topics.extend(partitions.iter().map(|p| p.topic.clone()));
+        resolved_topics.sort();
+        resolved_topics.dedup();
+        let topic_resolver = TopicResolver::new(resolved_topics);
         let auto_commit = client_config
             .get("enable.auto.commit")
             .map_or(true, |v| v == "true");
@@ -408,9 +505,14 @@ impl KafkaConsumerSource {
             client_config,
             origin_uri,
             topics,
+            partitions,
+            start_offset,
             topic_resolver,
             transactional: !auto_commit,
             retry_failed_events,
+            exactly_once,
+            rewinds: HashMap::new(),
+            seek_epochs: Arc::new(SeekEpochs::default()),
             seek_timeout,
             source_tx,
             source_rx,
@@ -444,27 +546,56 @@ impl Source for KafkaConsumerSource {
             TremorConsumerContext::new(ctx, connect_result_tx.clone(), metrics_tx);
         let consumer: TremorConsumer = self.client_config.create_with_context(consumer_context)?;
 
-        let topics: Vec<&str> = self
-            .topics
-            .iter()
-            .map(std::string::String::as_str)
-            .collect();
-        info!("{} Subscribing to: {:?}", &ctx, topics);
+        if self.partitions.is_empty() {
+            let topics: Vec<&str> = self
+                .topics
+                .iter()
+                .map(std::string::String::as_str)
+                .collect();
+            info!("{} Subscribing to: {:?}", &ctx, topics);
 
-        match consumer.subscribe(&topics) {
-            Ok(()) => info!("{} Subscription initiated...", &ctx),
-            Err(e) => {
-                error!("{} Error subscribing: {}", ctx, e);
+            match consumer.subscribe(&topics) {
+                Ok(()) => info!("{} Subscription initiated...", &ctx),
+                Err(e) => {
+                    error!("{} Error subscribing: {}", ctx, e);
+                    return Err(e.into());
+                }
+            };
+        } else {
+            let assignment = resolve_offsets(
+                &consumer,
+                self.partitions.iter().map(|p| {
+                    (
+                        p.topic.as_str(),
+                        p.partition,
+                        p.offset.or(self.start_offset),
+                    )
+                }),
+                self.seek_timeout,
+            )?;
+            info!("{} Assigning: {}", &ctx, format_partitions(&assignment));
+            if let Err(e) = consumer.assign(&assignment) {
+                error!("{} Error assigning partitions: {}", ctx, e);
                 return Err(e.into());
             }
-        };
+        }
+        self.rewinds.clear();
         let arc_consumer = Arc::new(consumer);
+        if self.exactly_once {
+            super::register_exactly_once_consumer(
+                ctx.uid.id(),
+                arc_consumer.clone(),
+                self.topic_resolver.clone(),
+                self.seek_epochs.clone(),
+            );
+        }
         let task_consumer = arc_consumer.clone();
         self.consumer = Some(arc_consumer);
 
         let handle = task::spawn(consumer_task(
             task_consumer,
             self.topic_resolver.clone(),
+            self.seek_epochs.clone(),
             self.origin_uri.clone(),
             connect_result_tx,
             self.source_tx.clone(),
@@ -489,16 +620,44 @@ impl Source for KafkaConsumerSource {
         }
     }
 
-    async fn pull_data(&mut self, pull_id: &mut u64, _ctx: &SourceContext) -> Result<SourceReply> {
-        let (reply, custom_pull_id) = self.source_rx.recv().await?;
-        if let Some(custom_pull_id) = custom_pull_id {
-            *pull_id = custom_pull_id;
+    async fn pull_data(&mut self, pull_id: &mut u64, ctx: &SourceContext) -> Result<SourceReply> {
+        loop {
+            let (reply, custom_pull_id) = self.source_rx.recv().await?;
+            if let Some(custom_pull_id) = custom_pull_id {
+                if let SourceReply::Data {
+                    stream: Some(stream_id),
+                    ..
+                } = &reply
+                {
+                    if self.seek_epochs.is_stale(*stream_id, custom_pull_id) {
+                        debug!("{ctx} Dropping message read before seeking stream={stream_id}.");
+                        continue;
+                    }
+                    // the rewind is done once the failed message, or an earlier one, is read again
+                    if self
+                        .rewinds
+                        .get(stream_id)
+                        .map_or(false, |rewind| custom_pull_id <= *rewind)
+                    {
+                        self.rewinds.remove(stream_id);
+                    }
+                }
+                *pull_id = custom_pull_id;
+            }
+            return Ok(reply);
         }
-        Ok(reply)
     }
 
     async fn ack(&mut self, stream_id: u64, pull_id: u64, ctx: &SourceContext) -> Result<()> {
-        if self.transactional {
+        if self.seek_epochs.is_stale(stream_id, pull_id) {
+            // committing it would move the committed offset away from the seeked position
+            debug!(
+                "{ctx} Ignoring ack of stream={stream_id}, pull_id={pull_id} read before a seek."
+            );
+            return Ok(());
+        }
+        // in `exactly_once` mode offsets are committed by the producer
+        if self.transactional && !self.exactly_once {
             if let Some(consumer) = self.consumer.as_ref() {
                 if let Some((topic, partition, offset)) =
                     self.topic_resolver.resolve_topic(stream_id, pull_id)
@@ -515,7 +674,23 @@ impl Source for KafkaConsumerSource {
     }
 
     async fn fail(&mut self, stream_id: u64, pull_id: u64, ctx: &SourceContext) -> Result<()> {
-        if self.transactional && self.retry_failed_events {
+        if self.seek_epochs.is_stale(stream_id, pull_id) {
+            // seeking back to it would undo the seek
+            debug!(
+                "{ctx} Ignoring fail of stream={stream_id}, pull_id={pull_id} read before a seek."
+            );
+            return Ok(());
+        }
+        if self.exactly_once
+            && self
+                .rewinds
+                .get(&stream_id)
+                .map_or(false, |rewind| *rewind <= pull_id)
+        {
+            // we already seeked back to an earlier message of this partition
+            return Ok(());
+        }
+        if self.transactional && (self.retry_failed_events || self.exactly_once) {
             if let Some(consumer) = self.consumer.as_ref() {
                 if let Some((topic, partition, offset)) =
                     self.topic_resolver.resolve_topic(stream_id, pull_id)
@@ -525,6 +700,9 @@ impl Source for KafkaConsumerSource {
                         &ctx, topic, partition, offset
                     );
                     consumer.seek(topic, partition, offset, self.seek_timeout)?;
+                    if self.exactly_once {
+                        self.rewinds.insert(stream_id, pull_id);
+                    }
                 } else {
                     error!("{} Could not seek back to failed event with stream={}, pull_id={}. Unable to detect topic from internal state.", &ctx, stream_id, pull_id);
                 }
@@ -533,6 +711,52 @@ impl Source for KafkaConsumerSource {
         Ok(())
     }
 
+    async fn on_seek(&mut self, position: &Value<'static>, ctx: &SourceContext) -> Result<()> {
+        let invalid = |msg: String| Error::from(ErrorKind::InvalidSeek(ctx.alias.clone(), msg));
+        let seek = Seek::new(position).map_err(|e| invalid(e.to_string()))?;
+        let consumer = self
+            .consumer
+            .as_ref()
+            .ok_or_else(|| invalid("Not connected".to_string()))?;
+        let assignment = consumer.assignment()?;
+        let elements = assignment.elements();
+        let partitions: Vec<_> = elements
+            .iter()
+            .filter(|elem| {
+                seek.topic
+                    .as_deref()
+                    .map_or(true, |topic| topic == elem.topic())
+                    && seek
+                        .partition
+                        .map_or(true, |partition| partition == elem.partition())
+            })
+            .map(|elem| (elem.topic(), elem.partition(), Some(seek.offset)))
+            .collect();
+        if partitions.is_empty() {
+            return Err(invalid("No matching partition assigned".to_string()));
+        }
+        let offsets = resolve_offsets(consumer, partitions, self.seek_timeout)?;
+        info!("{} Seeking: {}", &ctx, format_partitions(&offsets));
+        for elem in offsets.elements() {
+            consumer.seek(
+                elem.topic(),
+                elem.partition(),
+                elem.offset(),
+                self.seek_timeout,
+            )?;
+            let (stream_id, _) = self.topic_resolver.resolve_stream_and_pull_ids_inner(
+                elem.topic(),
+                elem.partition(),
+                0,
+            );
+            // messages read before are dropped, their acks and fails ignored
+            self.seek_epochs.advance(stream_id);
+            // failed messages before the new position are not read again
+            self.rewinds.remove(&stream_id);
+        }
+        Ok(())
+    }
+
     async fn on_cb_close(&mut self, _ctx: &SourceContext) -> Result<()> {
         if let Some(consumer) = self.consumer.as_ref() {
             consumer
@@ -569,6 +793,9 @@ impl Source for KafkaConsumerSource {
     }
 
     async fn on_stop(&mut self, ctx: &SourceContext) -> Result<()> {
+        if self.exactly_once {
+            super::unregister_exactly_once_consumer(ctx.uid.id());
+        }
         // clear out the consumer
         if let Some(consumer) = self.consumer.take() {
             consumer.unsubscribe();
@@ -603,10 +830,59 @@ impl Source for KafkaConsumerSource {
     }
 }
 
+/// Resolves the offsets to consume the given partitions from, `None` denotes the committed offset
+fn resolve_offsets<'a>(
+    consumer: &TremorConsumer,
+    partitions: impl IntoIterator<Item = (&'a str, i32, Option<StartOffset>)>,
+    timeout: Duration,
+) -> Result<TopicPartitionList> {
+    let mut offsets = TopicPartitionList::new();
+    let mut timestamps = TopicPartitionList::new();
+    for (topic, partition, start_offset) in partitions {
+        let offset = match start_offset {
+            None => Offset::Stored,
+            Some(StartOffset::Earliest) => Offset::Beginning,
+            Some(StartOffset::Latest) => Offset::End,
+            Some(StartOffset::Offset(offset)) => Offset::Offset(offset),
+            Some(StartOffset::Timestamp(ns)) => {
+                // kafka timestamps are in milliseconds
+                let ms = i64::try_from(ns / 1_000_000)?;
+                timestamps.add_partition_offset(topic, partition, Offset::Offset(ms))?;
+                continue;
+            }
+        };
+        offsets.add_partition_offset(topic, partition, offset)?;
+    }
+    if timestamps.count() > 0 {
+        // partitions without messages at or after the timestamp are resolved to their end
+        for elem in consumer.offsets_for_times(timestamps, timeout)?.elements() {
+            offsets.add_partition_offset(elem.topic(), elem.partition(), elem.offset())?;
+        }
+    }
+    Ok(offsets)
+}
+
+fn format_partitions(tpl: &TopicPartitionList) -> String {
+    let offset_strings: Vec<String> = tpl
+        .elements()
+        .iter()
+        .map(|elem| {
+            format!(
+                "[Topic: {}, Partition: {}, Offset: {:?}]",
+                elem.topic(),
+                elem.partition(),
+                elem.offset()
+            )
+        })
+        .collect();
+    offset_strings.join(" ")
+}
+
 /// Kafka consumer main loop - consuming from a kafka stream
 async fn consumer_task(
     task_consumer: Arc<StreamConsumer<TremorConsumerContext, SmolRuntime>>,
     topic_resolver: TopicResolver,
+    seek_epochs: Arc<SeekEpochs>,
     consumer_origin_uri: EventOriginUri,
     connect_result_tx: Sender<Result<bool>>,
     source_tx: Sender<(SourceReply, Option<u64>)>,
@@ -628,6 +904,7 @@ async fn consumer_task(
                 }
                 // handle kafka msg
                 let (stream_id, pull_id) = topic_resolver.resolve_stream_and_pull_ids(&kafka_msg);
+                let pull_id = seek_epochs.tag(stream_id, pull_id);
                 let mut origin_uri = consumer_origin_uri.clone();
                 origin_uri.path = vec![
                     kafka_msg.topic().to_string(),
@@ -714,8 +991,43 @@ async fn consumer_task(
     }
 }
 
+/// The number of seeks of every partition, by stream id.
+///
+/// The upper 16 bits of a pull id hold the seek epoch of its partition when the message was read,
+/// so messages read before a seek can be told apart from the ones read again after it.
+#[derive(Default)]
+pub(super) struct SeekEpochs(DashMap<u64, u64>);
+impl SeekEpochs {
+    const SHIFT: u32 = 48;
+    const OFFSET_MASK: u64 = (1 << Self::SHIFT) - 1;
+
+    fn current(&self, stream_id: u64) -> u64 {
+        self.0.get(&stream_id).map_or(0, |epoch| *epoch)
+    }
+
+    fn advance(&self, stream_id: u64) {
+        let mut epoch = self.0.entry(stream_id).or_default();
+        *epoch = (*epoch + 1) & (u64::MAX >> Self::SHIFT);
+    }
+
+    /// Tags the pull id of a message with the current seek epoch of its partition
+    fn tag(&self, stream_id: u64, pull_id: u64) -> u64 {
+        (self.current(stream_id) << Self::SHIFT) | (pull_id & Self::OFFSET_MASK)
+    }
+
+    /// Whether the partition was seeked since the message with the pull id was read
+    pub(super) fn is_stale(&self, stream_id: u64, pull_id: u64) -> bool {
+        pull_id >> Self::SHIFT != self.current(stream_id)
+    }
+
+    /// The offset of the message with the pull id
+    fn offset(pull_id: u64) -> u64 {
+        pull_id & Self::OFFSET_MASK
+    }
+}
+
 #[derive(Clone)]
-struct TopicResolver(IndexMap<String, u64>);
+pub(super) struct TopicResolver(IndexMap<String, u64>);
 impl TopicResolver {
     fn new(mut topics: Vec<String>) -> Self {
         // we sort topics alphabetically, to ensure reproducability in case of different orderings in configs
@@ -732,7 +1044,11 @@ impl TopicResolver {
 
     /// Resolve topic, partition and message offset for the given `stream_id` and `pull_id`
     #[allow(clippy::cast_possible_wrap)] // we are limited by rdkafka types
-    fn resolve_topic(&self, stream_id: u64, pull_id: u64) -> Option<(&str, i32, Offset)> {
+    pub(super) fn resolve_topic(
+        &self,
+        stream_id: u64,
+        pull_id: u64,
+    ) -> Option<(&str, i32, Offset)> {
         let partition = (stream_id >> 32) as i32;
         let topic_id = stream_id & 0xffff_ffff;
 
@@ -744,7 +1060,8 @@ impl TopicResolver {
                 *idx, topic_id,
                 "topic_id and retrieved idx are not equal in topic_indices map"
             );
-            (topic.as_str(), partition, Offset::Offset(pull_id as i64))
+            let offset = SeekEpochs::offset(pull_id);
+            (topic.as_str(), partition, Offset::Offset(offset as i64))
         })
     }
 
@@ -779,7 +1096,7 @@ impl TopicResolver {
 #[cfg(test)]
 mod test {
 
-    use super::{Offset, TopicResolver};
+    use super::{Offset, SeekEpochs, TopicResolver};
     use proptest::prelude::*;
 
     fn topics_and_index() -> BoxedStrategy<(Vec<String>, usize)> {
@@ -795,7 +1112,8 @@ mod test {
         fn topic_resolver_prop(
             (topics, topic_idx) in topics_and_index(),
             partition in 0..i32::MAX,
-            offset in 0..i64::MAX
+            // the upper bits of the pull id hold the seek epoch
+            offset in 0..(1_i64 << 48)
         ) {
             let topic = topics.get(topic_idx).unwrap().to_string();
             let resolver = TopicResolver::new(topics);
@@ -809,4 +1127,19 @@ mod test {
             assert_eq!(Offset::Offset(offset), resolved_offset);
         }
     }
+
+    #[test]
+    fn seek_epochs() {
+        let epochs = SeekEpochs::default();
+        let pull_id = epochs.tag(1, 42);
+        assert_eq!(42, pull_id);
+        assert!(!epochs.is_stale(1, pull_id));
+
+        epochs.advance(1);
+        assert!(epochs.is_stale(1, pull_id));
+        assert!(!epochs.is_stale(2, pull_id));
+        let pull_id = epochs.tag(1, 42);
+        assert!(!epochs.is_stale(1, pull_id));
+        assert_eq!(42, SeekEpochs::offset(pull_id));
+    }
 }
@@ -14,13 +14,31 @@
 
 //! Kafka Producer Connector
 //! Sending events from tremor to a kafka topic
+//!
+//! Records can be customized via `$kafka_producer` metadata:
+//!
+//! * `key` - string or bytes, overrides the configured `key`
+//! * `headers` - record of string or bytes values
+//! * `partition` - partition to send the record to
+//! * `timestamp` - nanoseconds since the epoch
+//!
+//! These are the same fields the `kafka_consumer` connector exposes in `$kafka_consumer` metadata,
+//! so `let $kafka_producer = $kafka_consumer;` preserves key, headers, partition and timestamp.
+//!
+//! With a `transactional_id` records are produced in transactions, which also commit the offsets of the
+//! messages their events were consumed from by `kafka_consumer` connectors in `exactly_once` mode.
+//! These are tracked via the event ids. A transaction is committed once it contains `transaction_size`
+//! events or is older than `transaction_interval_ms`, its events are acked once it is committed.
+//! If it is aborted its events are failed and the consumer reads their messages again, all later
+//! events of the same partitions are failed until then.
 
 use std::time::Duration;
 
-use super::SmolRuntime;
+use super::{ConsumedOffsets, ConsumedPartition, SmolRuntime};
 use crate::connectors::impls::kafka::{is_failed_connect_error, KAFKA_CONNECT_TIMEOUT};
 use crate::connectors::metrics::make_metrics_payload;
 use crate::connectors::prelude::*;
+use crate::errors::err_conector_def;
 use async_broadcast::{broadcast, Receiver as BroadcastReceiver, Sender as BroadcastSender};
 use async_std::channel::{bounded, Sender};
 use async_std::prelude::FutureExt;
@@ -39,6 +57,7 @@ use rdkafka_sys::RDKafkaErrorCode;
 use tremor_common::time::nanotime;
 
 const KAFKA_PRODUCER_META_KEY: &str = "kafka_producer";
+const KAFKA_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Deserialize, Clone)]
 pub struct Config {
@@ -50,6 +69,16 @@ pub struct Config {
     // Overwritten by `kafka.key` in metadata if present.
     #[serde(default = "Default::default")]
     key: Option<String>,
+    /// enables transactions and exactly-once processing together with `kafka_consumer` connectors
+    /// in `exactly_once` mode, used as `transactional.id`
+    #[serde(default = "Default::default")]
+    transactional_id: Option<String>,
+    /// the maximum number of events produced in one transaction
+    #[serde(default = "default_transaction_size")]
+    transaction_size: usize,
+    /// the maximum time in milliseconds a transaction is kept open
+    #[serde(default = "default_transaction_interval_ms")]
+    transaction_interval_ms: u64,
     // a map (string keys and string values) of [librdkafka options](https://github.com/edenhill/librdkafka/blob/master/CONFIGURATION.md) (default: None) - Note this can overwrite default settings.
     ///
     /// Default settings for librdkafka:
@@ -64,6 +93,14 @@ pub struct Config {
 
 impl ConfigImpl for Config {}
 
+fn default_transaction_size() -> usize {
+    100
+}
+
+fn default_transaction_interval_ms() -> u64 {
+    100
+}
+
 #[derive(Default, Debug)]
 pub(crate) struct Builder {}
 
@@ -83,6 +120,12 @@ impl ConnectorBuilder for Builder {
         let config = Config::new(raw_config)?;
 
         super::verify_brokers(alias, &config.brokers)?;
+        if config.transaction_size == 0 {
+            return Err(err_conector_def(
+                alias,
+                "`transaction_size` must be greater than 0",
+            ));
+        }
         let mut producer_config = ClientConfig::new();
 
         // ENABLE LIBRDKAFKA DEBUGGING:
@@ -93,6 +136,11 @@ impl ConnectorBuilder for Builder {
         producer_config
             .set("client.id", &client_id)
             .set("bootstrap.servers", config.brokers.join(","));
+        if let Some(transactional_id) = config.transactional_id.as_ref() {
+            producer_config
+                .set("transactional.id", transactional_id)
+                .set("enable.idempotence", "true");
+        }
         // .set("message.timeout.ms", "5000")
         // .set("queue.buffering.max.ms", "0"); // set to 0 for sending each message out immediately without kafka client internal batching --> low latency, busy network
         if let Some(metrics_interval_s) = metrics_interval_s {
@@ -204,12 +252,30 @@ impl Connector for KafkaProducerConnector {
     }
 }
 
+type TremorProducer = FutureProducer<TremorProducerContext, SmolRuntime>;
+
+/// An open transaction of a producer with a `transactional_id`
+struct Transaction {
+    /// when the transaction was begun
+    since: u64,
+    /// the number of events produced in the transaction
+    size: usize,
+    /// the events produced in the transaction, with the time they were received
+    events: Vec<(ContraflowData, u64)>,
+    /// the offsets the events were consumed from
+    consumed: ConsumedOffsets,
+}
+
 struct KafkaProducerSink {
     config: Config,
     producer_config: ClientConfig,
-    producer: Option<FutureProducer<TremorProducerContext, SmolRuntime>>,
+    producer: Option<TremorProducer>,
     reply_tx: Sender<AsyncSinkReply>,
     metrics_rx: Option<BroadcastReceiver<EventPayload>>,
+    /// the currently open transaction
+    transaction: Option<Transaction>,
+    /// partitions with failed transactions and the offsets their messages are read again from
+    rewinds: HashMap<ConsumedPartition, u64>,
 }
 
 impl KafkaProducerSink {
@@ -224,31 +290,20 @@ impl KafkaProducerSink {
             producer: None,
             reply_tx,
             metrics_rx: None,
+            transaction: None,
+            rewinds: HashMap::new(),
         }
     }
-}
 
-#[async_trait::async_trait()]
-impl Sink for KafkaProducerSink {
-    async fn on_event(
-        &mut self,
-        _input: &str,
-        event: Event,
+    /// enqueues the records of an event
+    async fn enqueue(
+        &self,
+        producer: &TremorProducer,
+        event: &Event,
         ctx: &SinkContext,
         serializer: &mut EventSerializer,
-        start: u64,
-    ) -> Result<SinkReply> {
-        let producer = self
-            .producer
-            .as_ref()
-            .ok_or_else(|| ErrorKind::ProducerNotAvailable(ctx.alias().to_string()))?;
-        let transactional = event.transactional;
-        let mut delivery_futures: Vec<DeliveryFuture> = if transactional {
-            Vec::with_capacity(event.len())
-        } else {
-            // no need to wait for anything, fire and forget
-            vec![]
-        };
+        delivery_futures: &mut Vec<DeliveryFuture>,
+    ) -> Result<()> {
         let ingest_ns = event.ingest_ns;
         for (value, meta) in event.value_meta_iter() {
             let kafka_meta = meta.get(KAFKA_PRODUCER_META_KEY);
@@ -268,6 +323,8 @@ impl Sink for KafkaProducerSink {
                         // supporting string or bytes as headers value
                         if let Some(v_bytes) = v.as_bytes() {
                             headers = headers.add(k, v_bytes);
+                        } else {
+                            warn!("{ctx} Ignoring header {k}: only string or bytes values are supported.");
                         }
                     }
                     record = record.headers(headers);
@@ -295,6 +352,178 @@ impl Sink for KafkaProducerSink {
                 }
             }
         }
+        Ok(())
+    }
+
+    /// produces the records of an event in the open transaction, committing it once it is full
+    async fn on_event_in_transaction(
+        &mut self,
+        event: &Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        start: u64,
+    ) -> Result<SinkReply> {
+        let producer = self
+            .producer
+            .clone()
+            .ok_or_else(|| ErrorKind::ProducerNotAvailable(ctx.alias().to_string()))?;
+        let consumed = ConsumedOffsets::of(&event.id);
+        // failed messages of partitions seeked since are not consumed again
+        self.rewinds
+            .retain(|partition, rewind| !partition.is_stale(*rewind));
+        // events consumed after a failed one are failed until the failed one is consumed again
+        let rewinding = consumed.earliest().any(|(partition, offset)| {
+            self.rewinds
+                .get(partition)
+                .map_or(false, |rewind| offset > *rewind)
+        });
+        if rewinding {
+            debug!(
+                "{ctx} Failing event {} consumed before a failed event was consumed again.",
+                event.id
+            );
+            return Ok(SinkReply::fail_or_none(event.transactional));
+        }
+        for (partition, _) in consumed.earliest() {
+            self.rewinds.remove(partition);
+        }
+
+        if self.transaction.is_none() {
+            producer.begin_transaction()?;
+            self.transaction = Some(Transaction {
+                since: nanotime(),
+                size: 0,
+                events: Vec::new(),
+                consumed: ConsumedOffsets::default(),
+            });
+        }
+        // the delivery futures resolve when the transaction is committed
+        let mut delivery_futures = Vec::with_capacity(event.len());
+        let enqueued = self
+            .enqueue(&producer, event, ctx, serializer, &mut delivery_futures)
+            .await;
+        if let Some(transaction) = self.transaction.as_mut() {
+            // the offsets are committed, and the records produced, even if the event isn't transactional
+            if event.transactional {
+                transaction
+                    .events
+                    .push((ContraflowData::from(event), start));
+            }
+            transaction.consumed.merge(consumed);
+            transaction.size += 1;
+        }
+        if let Err(e) = enqueued {
+            error!("{ctx} Error producing records in a transaction: {e}. Aborting it.");
+            self.abort_transaction(ctx).await?;
+        } else if self
+            .transaction
+            .as_ref()
+            .map_or(false, |t| t.size >= self.config.transaction_size)
+        {
+            self.commit_transaction(ctx).await?;
+        }
+        Ok(SinkReply::NONE)
+    }
+
+    /// commits the open transaction together with the consumed offsets and acks its events
+    async fn commit_transaction(&mut self, ctx: &SinkContext) -> Result<()> {
+        let (producer, transaction) = match (self.producer.clone(), self.transaction.take()) {
+            (Some(producer), Some(transaction)) => (producer, transaction),
+            _ => return Ok(()),
+        };
+        let committed = match transaction.consumed.to_commit() {
+            Ok(offsets) => {
+                let txn_producer = producer.clone();
+                task::spawn_blocking(move || {
+                    for (group_metadata, tpl) in &offsets {
+                        txn_producer.send_offsets_to_transaction(
+                            tpl,
+                            group_metadata,
+                            KAFKA_TRANSACTION_TIMEOUT,
+                        )?;
+                    }
+                    txn_producer.commit_transaction(KAFKA_TRANSACTION_TIMEOUT)
+                })
+                .await
+                .map_err(Error::from)
+            }
+            Err(e) => Err(e),
+        };
+        match committed {
+            Ok(()) => {
+                for (cf_data, start) in transaction.events {
+                    let reply = AsyncSinkReply::Ack(cf_data, nanotime() - start);
+                    ctx.swallow_err(self.reply_tx.send(reply).await, "Error sending contraflow");
+                }
+                Ok(())
+            }
+            Err(e) => {
+                error!("{ctx} Error committing transaction: {e}. Aborting it.");
+                self.transaction = Some(transaction);
+                self.abort_transaction(ctx).await
+            }
+        }
+    }
+
+    /// aborts the open transaction and fails its events, their messages are consumed again
+    async fn abort_transaction(&mut self, ctx: &SinkContext) -> Result<()> {
+        let (producer, transaction) = match (self.producer.clone(), self.transaction.take()) {
+            (Some(producer), Some(transaction)) => (producer, transaction),
+            _ => return Ok(()),
+        };
+        let aborted =
+            task::spawn_blocking(move || producer.abort_transaction(KAFKA_TRANSACTION_TIMEOUT))
+                .await;
+        if let Err(e) = aborted {
+            error!("{ctx} Error aborting transaction: {e}. Attempting a reconnect.");
+            ctx.notifier().connection_lost().await?;
+        }
+        if !transaction.events.is_empty() {
+            for (partition, offset) in transaction.consumed.earliest() {
+                self.rewinds
+                    .entry(*partition)
+                    .and_modify(|rewind| *rewind = (*rewind).min(offset))
+                    .or_insert(offset);
+            }
+        }
+        for (cf_data, _start) in transaction.events {
+            ctx.swallow_err(
+                self.reply_tx.send(AsyncSinkReply::Fail(cf_data)).await,
+                "Error sending contraflow",
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait()]
+impl Sink for KafkaProducerSink {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        event: Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        start: u64,
+    ) -> Result<SinkReply> {
+        if self.config.transactional_id.is_some() {
+            return self
+                .on_event_in_transaction(&event, ctx, serializer, start)
+                .await;
+        }
+        let producer = self
+            .producer
+            .as_ref()
+            .ok_or_else(|| ErrorKind::ProducerNotAvailable(ctx.alias().to_string()))?;
+        let transactional = event.transactional;
+        let mut delivery_futures: Vec<DeliveryFuture> = if transactional {
+            Vec::with_capacity(event.len())
+        } else {
+            // no need to wait for anything, fire and forget
+            vec![]
+        };
+        self.enqueue(producer, &event, ctx, serializer, &mut delivery_futures)
+            .await?;
         if !delivery_futures.is_empty() {
             let cf_data = if transactional {
                 Some(ContraflowData::from(&event))
@@ -312,7 +541,33 @@ impl Sink for KafkaProducerSink {
         Ok(SinkReply::NONE)
     }
 
+    async fn on_signal(
+        &mut self,
+        _signal: Event,
+        ctx: &SinkContext,
+        _serializer: &mut EventSerializer,
+    ) -> Result<SinkReply> {
+        let interval = self.config.transaction_interval_ms * 1_000_000;
+        if self
+            .transaction
+            .as_ref()
+            .map_or(false, |t| nanotime().saturating_sub(t.since) >= interval)
+        {
+            self.commit_transaction(ctx).await?;
+        }
+        Ok(SinkReply::NONE)
+    }
+
     async fn connect(&mut self, ctx: &SinkContext, _attempt: &Attempt) -> Result<bool> {
+        // the open transaction is lost with the previous producer
+        if let Some(transaction) = self.transaction.take() {
+            for (cf_data, _start) in transaction.events {
+                ctx.swallow_err(
+                    self.reply_tx.send(AsyncSinkReply::Fail(cf_data)).await,
+                    "Error sending contraflow",
+                );
+            }
+        }
         // enforce cleaning out the previous producer
         // We might lose some in flight messages
         if let Some(old_producer) = self.producer.take() {
@@ -336,12 +591,19 @@ impl Sink for KafkaProducerSink {
         info!("{ctx} Connecting kafka producer with rdkafka 0x{version_n:08x} {version_s}");
 
         let producer_config = self.producer_config.clone();
-        let producer: FutureProducer<TremorProducerContext, SmolRuntime> =
+        let producer: TremorProducer =
             FutureProducer::from_config_and_context(&producer_config, context)?;
         // check if we receive any error callbacks
         match rx.recv().timeout(KAFKA_CONNECT_TIMEOUT).await {
             Err(_timeout) => {
                 // timeout error, everything is ok, no error
+                if self.config.transactional_id.is_some() {
+                    let txn_producer = producer.clone();
+                    task::spawn_blocking(move || {
+                        txn_producer.init_transactions(KAFKA_TRANSACTION_TIMEOUT)
+                    })
+                    .await?;
+                }
                 self.producer = Some(producer);
                 Ok(true)
             }
@@ -357,6 +619,7 @@ impl Sink for KafkaProducerSink {
     }
 
     async fn on_stop(&mut self, ctx: &SinkContext) -> Result<()> {
+        self.commit_transaction(ctx).await?;
         if let Some(producer) = self.producer.take() {
             let wait_secs = Duration::from_secs(1);
             if producer.in_flight_count() > 0 {
@@ -13,6 +13,19 @@
 // limitations under the License.
 
 // #![cfg_attr(coverage, no_coverage)]
+
+//! Key value store backed by sled
+//!
+//! Commands are sent to the sink via the `$kv` metadata of events, their responses are emitted
+//! by the source, carrying the `$correlation` metadata of the command.
+//!
+//! `put`, `swap` and `cas` accept a `ttl` in milliseconds, a key with a TTL behaves like a missing
+//! key once it expired and is removed every `ttl_check_interval_ms`. Writing a key without `ttl`
+//! removes its TTL.
+//!
+//! `watch` makes the source emit an event for every change of a key starting with the given
+//! prefix, with the new value (`null` for removed keys) as payload, until it is stopped with `unwatch`.
+
 use crate::{
     codec::{
         json::{Json, Sorted},
@@ -23,9 +36,107 @@ use crate::{
 };
 use async_std::channel::{bounded, Receiver, Sender};
 use async_std::path::PathBuf;
+use async_std::task::{self, JoinHandle};
 use serde::Deserialize;
-use sled::{CompareAndSwapError, Db, IVec};
+use sled::{Batch, CompareAndSwapError, Db, IVec, Subscriber, Tree};
+use std::collections::HashMap;
+use std::time::Duration;
 use std::{boxed::Box, convert::TryFrom};
+use tremor_common::time::nanotime;
+
+/// name of the tree indexing keys with a TTL by their expiry time
+const EXPIRY_TREE: &str = "__tremor_kv_expiry";
+/// values with a TTL are stored with this marker and their expiry time in front of them,
+/// encoded JSON never starts with it
+const TTL_MARKER: u8 = 0;
+const TTL_HEADER_LEN: usize = 9;
+
+/// the expiry time of a stored value, if it has one
+fn expires_at(data: &[u8]) -> Option<u64> {
+    match data.split_first() {
+        Some((&TTL_MARKER, rest)) => rest
+            .get(..TTL_HEADER_LEN - 1)
+            .and_then(|at| <[u8; 8]>::try_from(at).ok())
+            .map(u64::from_be_bytes),
+        _ => None,
+    }
+}
+
+fn is_expired(data: &[u8], now: u64) -> bool {
+    expires_at(data).map_or(false, |at| at <= now)
+}
+
+/// the encoded value of a stored value
+fn strip_ttl(data: &[u8]) -> &[u8] {
+    if expires_at(data).is_some() {
+        data.get(TTL_HEADER_LEN..).unwrap_or_default()
+    } else {
+        data
+    }
+}
+
+/// key of the expiry index, ordered by expiry time
+fn expiry_key(expires_at: u64, key: &[u8]) -> Vec<u8> {
+    let mut index_key = Vec::with_capacity(8 + key.len());
+    index_key.extend_from_slice(&expires_at.to_be_bytes());
+    index_key.extend_from_slice(key);
+    index_key
+}
+
+fn decode(codec: &mut Json<Sorted>, v: Option<IVec>, ingest_ns: u64) -> Result<Value<'static>> {
+    if let Some(mut v) = v {
+        if is_expired(&v, nanotime()) {
+            return Ok(Value::null());
+        }
+        let data: &mut [u8] = &mut v;
+        let data = if expires_at(data).is_some() {
+            data.get_mut(TTL_HEADER_LEN..).unwrap_or_default()
+        } else {
+            data
+        };
+        // TODO: We could optimize this
+        Ok(codec
+            .decode(data, ingest_ns)?
+            .unwrap_or_default()
+            .into_static())
+    } else {
+        Ok(Value::null())
+    }
+}
+
+/// A write of a `batch` command
+#[derive(Debug)]
+enum BatchOp<'v> {
+    Put {
+        key: Vec<u8>,
+        value: &'v Value<'v>,
+        ttl: Option<u64>,
+    },
+    Delete {
+        key: Vec<u8>,
+    },
+}
+
+impl<'v> TryFrom<&'v Value<'v>> for BatchOp<'v> {
+    type Error = crate::Error;
+
+    fn try_from(v: &'v Value<'v>) -> Result<Self> {
+        if let Some(key) = v.get_bytes("put").map(<[u8]>::to_vec) {
+            let value = v
+                .get("value")
+                .ok_or_else(|| Error::from(format!("Missing `value` for batch put: {v}")))?;
+            Ok(BatchOp::Put {
+                key,
+                value,
+                ttl: v.get_u64("ttl"),
+            })
+        } else if let Some(key) = v.get_bytes("delete").map(<[u8]>::to_vec) {
+            Ok(BatchOp::Delete { key })
+        } else {
+            Err(format!("Invalid batch operation: {v}").into())
+        }
+    }
+}
 
 #[derive(Debug)]
 enum Command<'v> {
@@ -38,19 +149,19 @@ enum Command<'v> {
     Get { key: Vec<u8> },
     /// Format:
     /// ```json
-    /// {"put": "the-key"}
+    /// {"put": "the-key", "ttl": <optional ttl in ms>}
     /// ```
     /// Event Payload: data to put here
     /// Response: the putted value if successful
-    Put { key: Vec<u8> },
+    Put { key: Vec<u8>, ttl: Option<u64> },
     /// Format:
     /// ```json
-    /// {"swap": "the-key"}
+    /// {"swap": "the-key", "ttl": <optional ttl in ms>}
     /// ```
     /// Event Payload: data to put here
     ///
     /// Response: the old value or `null` is there was no previous value for this key
-    Swap { key: Vec<u8> },
+    Swap { key: Vec<u8>, ttl: Option<u64> },
 
     /// Format:
     /// ```json
@@ -73,10 +184,18 @@ enum Command<'v> {
         end: Option<Vec<u8>>,
     },
     /// Format:
+    /// ```json
+    /// {"scan_prefix": "the-prefix"}
+    /// ```
+    ///
+    /// Response: 1 event for each value with a key starting with "the-prefix"
+    ScanPrefix { prefix: Vec<u8> },
+    /// Format:
     ///  ```json
     /// {
     ///    "cas": "key",
     ///    "old": "<value|null|not-set>",
+    ///    "ttl": <optional ttl in ms>
     /// }
     /// ```
     /// EventPayload: event payload
@@ -85,24 +204,63 @@ enum Command<'v> {
     Cas {
         key: Vec<u8>,
         old: Option<&'v Value<'v>>,
+        ttl: Option<u64>,
     },
+    /// Format:
+    /// ```json
+    /// {
+    ///    "batch": [
+    ///      {"put": "key1", "value": <value>, "ttl": <optional ttl in ms>},
+    ///      {"delete": "key2"}
+    ///    ]
+    /// }
+    /// ```
+    ///
+    /// All writes are applied atomically.
+    ///
+    /// Response: `null` with the written keys in `$kv.ok`
+    Batch { ops: Vec<BatchOp<'v>> },
+    /// Format:
+    /// ```json
+    /// {"watch": "the-prefix"}
+    /// ```
+    ///
+    /// Response: `null`, followed by 1 event for each change of a key starting with "the-prefix"
+    Watch {
+        prefix: Vec<u8>,
+        correlation: Option<&'v Value<'v>>,
+    },
+    /// Format:
+    /// ```json
+    /// {"unwatch": "the-prefix"}
+    /// ```
+    ///
+    /// Response: `null`
+    Unwatch { prefix: Vec<u8> },
 }
 
 impl<'v> TryFrom<&'v Value<'v>> for Command<'v> {
     type Error = crate::Error;
 
-    fn try_from(v: &'v Value<'v>) -> Result<Self> {
-        let v = v.get("kv").ok_or("Missing `$kv` field for commands")?;
+    fn try_from(meta: &'v Value<'v>) -> Result<Self> {
+        let v = meta.get("kv").ok_or("Missing `$kv` field for commands")?;
         if let Some(key) = v.get_bytes("get").map(<[u8]>::to_vec) {
             Ok(Command::Get { key })
         } else if let Some(key) = v.get_bytes("put").map(<[u8]>::to_vec) {
-            Ok(Command::Put { key })
+            Ok(Command::Put {
+                key,
+                ttl: v.get_u64("ttl"),
+            })
         } else if let Some(key) = v.get_bytes("swap").map(<[u8]>::to_vec) {
-            Ok(Command::Swap { key })
+            Ok(Command::Swap {
+                key,
+                ttl: v.get_u64("ttl"),
+            })
         } else if let Some(key) = v.get_bytes("cas").map(<[u8]>::to_vec) {
             Ok(Command::Cas {
                 key,
                 old: v.get("old"),
+                ttl: v.get_u64("ttl"),
             })
         } else if let Some(key) = v.get_bytes("delete").map(<[u8]>::to_vec) {
             Ok(Command::Delete { key })
@@ -111,6 +269,19 @@ impl<'v> TryFrom<&'v Value<'v>> for Command<'v> {
                 start,
                 end: v.get_bytes("end").map(<[u8]>::to_vec),
             })
+        } else if let Some(prefix) = v.get_bytes("scan_prefix").map(<[u8]>::to_vec) {
+            Ok(Command::ScanPrefix { prefix })
+        } else if let Some(ops) = v.get_array("batch") {
+            Ok(Command::Batch {
+                ops: ops.iter().map(BatchOp::try_from).collect::<Result<_>>()?,
+            })
+        } else if let Some(prefix) = v.get_bytes("watch").map(<[u8]>::to_vec) {
+            Ok(Command::Watch {
+                prefix,
+                correlation: meta.get("correlation"),
+            })
+        } else if let Some(prefix) = v.get_bytes("unwatch").map(<[u8]>::to_vec) {
+            Ok(Command::Unwatch { prefix })
         } else {
             Err(format!("Invalid KV command: {}", v).into())
         }
@@ -125,7 +296,11 @@ impl<'v> Command<'v> {
             Command::Swap { .. } => "swap",
             Command::Delete { .. } => "delete",
             Command::Scan { .. } => "scan",
+            Command::ScanPrefix { .. } => "scan_prefix",
             Command::Cas { .. } => "cas",
+            Command::Batch { .. } => "batch",
+            Command::Watch { .. } => "watch",
+            Command::Unwatch { .. } => "unwatch",
         }
     }
 
@@ -136,7 +311,10 @@ impl<'v> Command<'v> {
             | Command::Swap { key, .. }
             | Command::Delete { key }
             | Command::Cas { key, .. } => Some(key.clone()),
-            Command::Scan { .. } => None,
+            Command::ScanPrefix { prefix }
+            | Command::Watch { prefix, .. }
+            | Command::Unwatch { prefix } => Some(prefix.clone()),
+            Command::Scan { .. } | Command::Batch { .. } => None,
         }
     }
 }
@@ -164,6 +342,13 @@ fn oks(
 #[serde(deny_unknown_fields)]
 pub struct Config {
     dir: String,
+    /// interval in milliseconds to remove expired keys
+    #[serde(default = "default_ttl_check_interval_ms")]
+    ttl_check_interval_ms: u64,
+}
+
+fn default_ttl_check_interval_ms() -> u64 {
+    1000
 }
 
 impl ConfigImpl for Config {}
@@ -221,6 +406,7 @@ impl Connector for Kv {
         builder: SinkManagerBuilder,
     ) -> Result<Option<SinkAddr>> {
         let db = sled::open(&self.config.dir)?;
+        let expiry = db.open_tree(EXPIRY_TREE)?;
         let codec = Json::default();
         let origin_uri = EventOriginUri {
             scheme: "tremor-kv".to_string(),
@@ -235,9 +421,13 @@ impl Connector for Kv {
         };
         let s = KvSink {
             db,
+            expiry,
             tx: self.tx.clone(),
             codec,
             origin_uri,
+            ttl_check_interval: Duration::from_millis(self.config.ttl_check_interval_ms),
+            expiry_task: None,
+            watches: HashMap::new(),
         };
         builder.spawn(s, sink_context).map(Some)
     }
@@ -247,59 +437,169 @@ impl Connector for Kv {
     }
 }
 
+/// removes the keys that expired until `now`, unless they were changed in the meantime
+fn expire(db: &Db, expiry: &Tree, now: u64) -> Result<usize> {
+    let mut removed = 0;
+    for entry in expiry.range(..now.saturating_add(1).to_be_bytes()) {
+        let (index_key, _) = entry?;
+        let key = index_key.get(8..).unwrap_or_default();
+        if let Some(current) = db.get(key)? {
+            if is_expired(&current, now)
+                && db
+                    .compare_and_swap(key, Some(current), None::<IVec>)?
+                    .is_ok()
+            {
+                removed += 1;
+            }
+        }
+        expiry.remove(&index_key)?;
+    }
+    Ok(removed)
+}
+
+async fn expire_periodically(db: Db, expiry: Tree, interval: Duration, ctx: SinkContext) {
+    loop {
+        task::sleep(interval).await;
+        match expire(&db, &expiry, nanotime()) {
+            Ok(0) => {}
+            Ok(removed) => debug!("{ctx} Removed {removed} expired keys"),
+            Err(e) => error!("{ctx} Error removing expired keys: {e}"),
+        }
+    }
+}
+
+/// emits an event for every change under the watched prefix
+async fn watch(
+    mut subscriber: Subscriber,
+    prefix: Vec<u8>,
+    correlation: Option<Value<'static>>,
+    origin_uri: EventOriginUri,
+    tx: Sender<SourceReply>,
+    ctx: SinkContext,
+) {
+    let mut codec = Json::<Sorted>::default();
+    while let Some(event) = (&mut subscriber).await {
+        let (key, value, kind) = match event {
+            sled::Event::Insert { key, value } => {
+                match decode(&mut codec, Some(value), nanotime()) {
+                    Ok(value) => (key, value, "insert"),
+                    Err(e) => {
+                        error!("{ctx} Error decoding watched value: {e}");
+                        continue;
+                    }
+                }
+            }
+            sled::Event::Remove { key } => (key, Value::null(), "remove"),
+        };
+        let key: &[u8] = &key;
+        let mut meta = literal!({
+            "kv": {
+                "op": "watch",
+                "watch": Value::Bytes(prefix.clone().into()),
+                "key": Value::Bytes(key.to_vec().into()),
+                "event": kind
+            }
+        });
+        if let Some(correlation) = correlation.as_ref() {
+            meta.try_insert("correlation", correlation.clone());
+        }
+        let reply = SourceReply::Structured {
+            origin_uri: origin_uri.clone(),
+            payload: (value, meta).into(),
+            stream: DEFAULT_STREAM_ID,
+            port: Some(OUT),
+        };
+        if let Err(e) = tx.send(reply).await {
+            error!("{ctx} Failed to send to source: {e}");
+            break;
+        }
+    }
+}
+
 struct KvSink {
     db: Db,
+    expiry: Tree,
     tx: Sender<SourceReply>,
     codec: Json<Sorted>,
     origin_uri: EventOriginUri,
+    ttl_check_interval: Duration,
+    expiry_task: Option<JoinHandle<()>>,
+    /// watch tasks by prefix
+    watches: HashMap<Vec<u8>, JoinHandle<()>>,
 }
 
 impl KvSink {
-    fn decode(&mut self, mut v: Option<IVec>, ingest_ns: u64) -> Result<Value<'static>> {
-        if let Some(v) = v.as_mut() {
-            let data: &mut [u8] = v;
-            // TODO: We could optimize this
-            Ok(self
-                .codec
-                .decode(data, ingest_ns)?
-                .unwrap_or_default()
-                .into_static())
-        } else {
-            Ok(Value::null())
-        }
+    fn decode(&mut self, v: Option<IVec>, ingest_ns: u64) -> Result<Value<'static>> {
+        decode(&mut self.codec, v, ingest_ns)
     }
     fn encode(&self, v: &Value) -> Result<Vec<u8>> {
         self.codec.encode(v)
     }
-    fn execute(
+    /// encodes a value to store, indexing its expiry time if it has a TTL
+    fn store(&self, key: &[u8], v: &Value, ttl: Option<u64>) -> Result<Vec<u8>> {
+        let data = self.encode(v)?;
+        if let Some(ttl) = ttl {
+            let expires_at = nanotime().saturating_add(ttl.saturating_mul(1_000_000));
+            self.expiry.insert(expiry_key(expires_at, key), &[])?;
+            let mut stored = Vec::with_capacity(TTL_HEADER_LEN + data.len());
+            stored.push(TTL_MARKER);
+            stored.extend_from_slice(&expires_at.to_be_bytes());
+            stored.extend_from_slice(&data);
+            Ok(stored)
+        } else {
+            Ok(data)
+        }
+    }
+    async fn execute(
         &mut self,
-        cmd: Command,
+        cmd: Command<'_>,
         op_name: &'static str,
-        value: &Value,
+        value: &Value<'_>,
         ingest_ns: u64,
+        ctx: &SinkContext,
     ) -> Result<Vec<(Value<'static>, Value<'static>)>> {
         match cmd {
             Command::Get { key } => self
                 .decode(self.db.get(&key)?, ingest_ns)
                 .map(|v| oks(op_name, key, v)),
-            Command::Put { key } => self
-                .decode(self.db.insert(&key, self.encode(value)?)?, ingest_ns)
+            Command::Put { key, ttl } => self
+                .decode(
+                    self.db.insert(&key, self.store(&key, value, ttl)?)?,
+                    ingest_ns,
+                )
                 .map(|_old_value| oks(op_name, key, value.clone_static())), // return the new value
-            Command::Swap { key } => self
-                .decode(self.db.insert(&key, self.encode(value)?)?, ingest_ns)
+            Command::Swap { key, ttl } => self
+                .decode(
+                    self.db.insert(&key, self.store(&key, value, ttl)?)?,
+                    ingest_ns,
+                )
                 .map(|old_value| oks(op_name, key, old_value)), // return the old value
             Command::Delete { key } => self
                 .decode(self.db.remove(&key)?, ingest_ns)
                 .map(|v| oks(op_name, key, v)),
-            Command::Cas { key, old } => {
-                if let Err(CompareAndSwapError { current, proposed }) = self.db.compare_and_swap(
-                    &key,
-                    old.map(|v| self.encode(v)).transpose()?,
-                    Some(self.encode(value)?),
-                )? {
+            Command::Cas { key, old, ttl } => {
+                let current = self.db.get(&key)?;
+                let current_data = current
+                    .as_deref()
+                    .filter(|data| !is_expired(data, nanotime()))
+                    .map(strip_ttl);
+                let expected = old.map(|v| self.encode(v)).transpose()?;
+                if current_data != expected.as_deref() {
+                    let current = current_data.map(IVec::from);
+                    return Err(format!(
+                        "CAS error: expected {} but found {}.",
+                        self.decode(expected.map(IVec::from), ingest_ns)?,
+                        self.decode(current, ingest_ns)?,
+                    )
+                    .into());
+                }
+                let proposed = self.store(&key, value, ttl)?;
+                if let Err(CompareAndSwapError { current, .. }) =
+                    self.db.compare_and_swap(&key, current, Some(proposed))?
+                {
                     Err(format!(
                         "CAS error: expected {} but found {}.",
-                        self.decode(proposed, ingest_ns)?,
+                        self.decode(expected.map(IVec::from), ingest_ns)?,
                         self.decode(current, ingest_ns)?,
                     )
                     .into())
@@ -312,21 +612,98 @@ impl KvSink {
                     None => self.db.range(start..),
                     Some(end) => self.db.range(start..end),
                 };
-                let mut res = Vec::with_capacity(i.size_hint().0);
-                for e in i {
-                    let (key, e) = e?;
-                    let key: &[u8] = &key;
-
-                    res.push(ok(op_name, key.to_vec(), self.decode(Some(e), ingest_ns)?));
+                self.collect(op_name, i, ingest_ns)
+            }
+            Command::ScanPrefix { prefix } => {
+                let i = self.db.scan_prefix(prefix);
+                self.collect(op_name, i, ingest_ns)
+            }
+            Command::Batch { ops } => {
+                let mut batch = Batch::default();
+                let mut keys = Vec::with_capacity(ops.len());
+                for op in ops {
+                    match op {
+                        BatchOp::Put { key, value, ttl } => {
+                            batch.insert(key.as_slice(), self.store(&key, value, ttl)?);
+                            keys.push(Value::Bytes(key.into()));
+                        }
+                        BatchOp::Delete { key } => {
+                            batch.remove(key.as_slice());
+                            keys.push(Value::Bytes(key.into()));
+                        }
+                    }
+                }
+                self.db.apply_batch(batch)?;
+                Ok(vec![(
+                    Value::null(),
+                    literal!({
+                        "kv": {
+                            "op": op_name,
+                            "ok": keys
+                        }
+                    }),
+                )])
+            }
+            Command::Watch {
+                prefix,
+                correlation,
+            } => {
+                let watch = watch(
+                    self.db.watch_prefix(&prefix),
+                    prefix.clone(),
+                    correlation.map(Value::clone_static),
+                    self.origin_uri.clone(),
+                    self.tx.clone(),
+                    ctx.clone(),
+                );
+                if let Some(previous) = self.watches.insert(prefix.clone(), task::spawn(watch)) {
+                    previous.cancel().await;
+                }
+                Ok(oks(op_name, prefix, Value::null()))
+            }
+            Command::Unwatch { prefix } => {
+                if let Some(watch) = self.watches.remove(&prefix) {
+                    watch.cancel().await;
                 }
-                Ok(res)
+                Ok(oks(op_name, prefix, Value::null()))
             }
         }
     }
+
+    /// one response for each entry, skipping expired ones
+    fn collect(
+        &mut self,
+        op_name: &'static str,
+        i: sled::Iter,
+        ingest_ns: u64,
+    ) -> Result<Vec<(Value<'static>, Value<'static>)>> {
+        let now = nanotime();
+        let mut res = Vec::with_capacity(i.size_hint().0);
+        for e in i {
+            let (key, e) = e?;
+            if is_expired(&e, now) {
+                continue;
+            }
+            let key: &[u8] = &key;
+
+            res.push(ok(op_name, key.to_vec(), self.decode(Some(e), ingest_ns)?));
+        }
+        Ok(res)
+    }
 }
 
 #[async_trait::async_trait]
 impl Sink for KvSink {
+    async fn on_start(&mut self, ctx: &SinkContext) -> Result<()> {
+        self.expiry_task = Some(task::spawn(expire_periodically(
+            self.db.clone(),
+            self.expiry.clone(),
+            self.ttl_check_interval,
+            ctx.clone(),
+        )));
+        Ok(())
+    }
+
     async fn on_event(
         &mut self,
         _input: &str,
@@ -344,7 +721,8 @@ impl Sink for KvSink {
                 Ok(cmd) => {
                     let name = cmd.op_name();
                     let key = cmd.key();
-                    self.execute(cmd, name, v, ingest_ns)
+                    self.execute(cmd, name, v, ingest_ns, ctx)
+                        .await
                         .map_err(|e| (Some(name), key, e))
                 }
                 Err(e) => Err((None, None, e)),
@@ -394,4 +772,14 @@ impl Sink for KvSink {
     fn auto_ack(&self) -> bool {
         false
     }
+
+    async fn on_stop(&mut self, _ctx: &SinkContext) -> Result<()> {
+        if let Some(expiry_task) = self.expiry_task.take() {
+            expiry_task.cancel().await;
+        }
+        for (_, watch) in self.watches.drain() {
+            watch.cancel().await;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,459 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loki sink - pushes log lines to Grafana Loki
+//!
+//! Every element of an event becomes a log line, serialized with the configured codec
+//! (`string` by default). Its timestamp is `$loki.timestamp` (nanoseconds) or the ingest time of the event.
+//!
+//! Lines are grouped into streams by their labels. Every label named in `labels` is taken from
+//! `$loki.labels` or from the top-level field with the same name of the payload, all other labels in
+//! `$loki.labels` are added as well. Elements without any label are failed.
+//!
+//! Lines are buffered and pushed once `batch_size` lines are buffered or the oldest one is older than
+//! `flush_interval_ms`. Push requests rejected with `429` or `5xx`, or failing to be sent at all, are
+//! retried with exponential backoff up to `max_retries` times. Push requests are sent one after the
+//! other by a task of their own, so the sink keeps buffering lines meanwhile. The events of a push
+//! request are acked once it succeeded and failed otherwise.
+//!
+//! Authorization and TLS are configured just like for the `http_client` connector, the tenant of
+//! a multi-tenant deployment can be set via the `X-Scope-OrgID` header in `headers`.
+
+mod push;
+
+use super::http::auth::Auth;
+use super::http::utils::Header;
+use crate::connectors::prelude::*;
+use crate::connectors::utils::tls::{tls_client_config, TLSClientConfig};
+use crate::errors::err_conector_def;
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::task::{self, JoinHandle};
+use either::Either;
+use halfbrown::HashMap;
+use http_client::h1::H1Client;
+use http_client::HttpClient;
+use http_types::{headers, Method, Request, StatusCode};
+use push::{is_valid_label_name, Encoding, Entry, Labels, Streams};
+use std::sync::Arc;
+use std::time::Duration;
+use tremor_common::time::nanotime;
+
+const CONNECTOR_TYPE: &str = "loki";
+const DEFAULT_CODEC: &str = "string";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// push endpoint, e.g. `http://localhost:3100/loki/api/v1/push`
+    url: Url,
+    /// Authorization method
+    #[serde(default = "Default::default")]
+    auth: Auth,
+    /// Additional HTTP headers
+    #[serde(default = "Default::default")]
+    headers: HashMap<String, Header>,
+    /// optional tls client config
+    #[serde(with = "either::serde_untagged_optional", default = "Default::default")]
+    tls: Option<Either<TLSClientConfig, bool>>,
+    /// names of the labels to take from `$loki.labels` or the payload
+    #[serde(default = "Default::default")]
+    labels: Vec<String>,
+    /// encoding of push requests, `protobuf` or `json`
+    #[serde(default = "Default::default")]
+    encoding: Encoding,
+    /// number of buffered lines that triggers a push
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    /// maximum time in milliseconds to buffer lines before they are pushed
+    #[serde(default = "default_flush_interval_ms")]
+    flush_interval_ms: u64,
+    /// request timeout in nanoseconds
+    #[serde(default = "Default::default")]
+    timeout: Option<u64>,
+    /// maximum number of retries of a push request
+    #[serde(default = "default_max_retries")]
+    max_retries: u32,
+    /// backoff in milliseconds before the first retry, it is doubled for every further retry
+    #[serde(default = "default_backoff_ms")]
+    backoff_ms: u64,
+}
+
+fn default_batch_size() -> usize {
+    1024
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+impl ConfigImpl for Config {}
+
+#[derive(Debug, Default)]
+pub(crate) struct Builder {}
+
+#[async_trait::async_trait]
+impl ConnectorBuilder for Builder {
+    fn connector_type(&self) -> ConnectorType {
+        CONNECTOR_TYPE.into()
+    }
+
+    async fn build_cfg(
+        &self,
+        alias: &str,
+        _: &ConnectorConfig,
+        raw_config: &Value,
+    ) -> Result<Box<dyn Connector>> {
+        let config = Config::new(raw_config)?;
+        if config.batch_size == 0 {
+            return Err(err_conector_def(
+                alias,
+                "`batch_size` must be greater than 0",
+            ));
+        }
+        if let Some(label) = config.labels.iter().find(|l| !is_valid_label_name(l)) {
+            return Err(err_conector_def(
+                alias,
+                &format!("Invalid label name `{label}`"),
+            ));
+        }
+        let tls_client_config = match config.tls.as_ref() {
+            Some(Either::Right(true)) => {
+                Some(tls_client_config(&TLSClientConfig::default()).await?)
+            }
+            Some(Either::Left(tls_config)) => Some(tls_client_config(tls_config).await?),
+            Some(Either::Right(false)) | None => None,
+        };
+        if config.url.scheme() == "https" && tls_client_config.is_none() {
+            return Err(err_conector_def(
+                alias,
+                "missing tls config with 'https' url. Set 'tls' to 'true' or provide a full tls config.",
+            ));
+        }
+        Ok(Box::new(Loki {
+            config,
+            tls_client_config,
+        }))
+    }
+}
+
+/// Loki connector
+pub(crate) struct Loki {
+    config: Config,
+    tls_client_config: Option<rustls::ClientConfig>,
+}
+
+#[async_trait::async_trait]
+impl Connector for Loki {
+    async fn create_sink(
+        &mut self,
+        sink_context: SinkContext,
+        builder: SinkManagerBuilder,
+    ) -> Result<Option<SinkAddr>> {
+        let sink = LokiSink {
+            config: self.config.clone(),
+            tls_client_config: self.tls_client_config.clone(),
+            pusher: None,
+            streams: Streams::default(),
+            events: Vec::new(),
+            since: 0,
+            reply_tx: builder.reply_tx(),
+        };
+        builder.spawn(sink, sink_context).map(Some)
+    }
+
+    fn codec_requirements(&self) -> CodecReq {
+        CodecReq::Optional(DEFAULT_CODEC)
+    }
+}
+
+/// string representation of a label value
+fn label_value(value: &Value) -> String {
+    value
+        .as_str()
+        .map_or_else(|| value.encode(), ToString::to_string)
+}
+
+struct LokiSink {
+    config: Config,
+    tls_client_config: Option<rustls::ClientConfig>,
+    /// the channel to the task sending the push requests
+    pusher: Option<(Sender<Push>, JoinHandle<()>)>,
+    /// buffered lines
+    streams: Streams,
+    /// the events of the buffered lines that need to be acked or failed
+    events: Vec<(ContraflowData, u64)>,
+    /// when the first line was buffered
+    since: u64,
+    reply_tx: Sender<AsyncSinkReply>,
+}
+
+impl LokiSink {
+    /// the labels of a single element
+    fn labels(&self, value: &Value, loki_meta: Option<&Value>) -> Result<Labels> {
+        let meta_labels = loki_meta.and_then(|meta| meta.get_object("labels"));
+        let mut labels = Labels::new();
+        for name in &self.config.labels {
+            let label = meta_labels
+                .and_then(|meta_labels| meta_labels.get(name.as_str()))
+                .or_else(|| value.get(name.as_str()));
+            if let Some(label) = label {
+                labels.insert(name.clone(), label_value(label));
+            }
+        }
+        for (name, label) in meta_labels.into_iter().flatten() {
+            if !is_valid_label_name(name) {
+                return Err(format!("Invalid label name `{name}`").into());
+            }
+            labels.insert(name.to_string(), label_value(label));
+        }
+        if labels.is_empty() {
+            return Err("No labels for the log line".into());
+        }
+        Ok(labels)
+    }
+
+    /// hands the buffered lines to the push task
+    async fn flush(&mut self, ctx: &SinkContext) {
+        if self.streams.is_empty() {
+            return;
+        }
+        let streams = std::mem::take(&mut self.streams);
+        let events = std::mem::take(&mut self.events);
+        let sent = match (streams.encode(self.config.encoding), self.pusher.as_ref()) {
+            (Ok(body), Some((tx, _))) => tx
+                .send(Push { body, events })
+                .await
+                .map_err(|e| (Error::from("The push task is gone"), e.into_inner().events)),
+            (Ok(_), None) => Err((
+                Error::from(ErrorKind::ClientNotAvailable(
+                    "Loki",
+                    "The client is not connected",
+                )),
+                events,
+            )),
+            (Err(e), _) => Err((e, events)),
+        };
+        if let Err((e, events)) = sent {
+            error!("{ctx} Error pushing to Loki: {e}");
+            for (cf_data, _start) in events {
+                ctx.swallow_err(
+                    self.reply_tx.send(AsyncSinkReply::Fail(cf_data)).await,
+                    "Error sending contraflow",
+                );
+            }
+        }
+    }
+}
+
+/// Encoded lines to push, with the events to ack or fail once they are pushed
+struct Push {
+    body: Vec<u8>,
+    events: Vec<(ContraflowData, u64)>,
+}
+
+/// Sends the push requests
+struct Pusher {
+    config: Config,
+    client: H1Client,
+    ctx: SinkContext,
+    reply_tx: Sender<AsyncSinkReply>,
+}
+
+impl Pusher {
+    fn request(&self, body: Vec<u8>) -> Result<Request> {
+        let mut request = Request::new(Method::Post, self.config.url.url().clone());
+        for (name, values) in &self.config.headers {
+            match &values.0 {
+                Either::Left(values) => {
+                    for value in values {
+                        request.append_header(name.as_str(), value.as_str());
+                    }
+                }
+                Either::Right(value) => {
+                    request.append_header(name.as_str(), value.as_str());
+                }
+            }
+        }
+        request.insert_header(headers::CONTENT_TYPE, self.config.encoding.content_type());
+        if let Some(auth_header) = self.config.auth.as_header_value()? {
+            request.insert_header(headers::AUTHORIZATION, auth_header);
+        }
+        request.set_body(body);
+        Ok(request)
+    }
+
+    /// sends a push request, retrying it with exponential backoff if Loki is unavailable or overloaded
+    async fn push(&self, body: Vec<u8>) -> Result<()> {
+        let ctx = &self.ctx;
+        let mut backoff = Duration::from_millis(self.config.backoff_ms);
+        let mut retries = 0;
+        loop {
+            let reason = match self.client.send(self.request(body.clone())?).await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response)
+                    if response.status() == StatusCode::TooManyRequests
+                        || response.status().is_server_error() =>
+                {
+                    format!("status {}", response.status())
+                }
+                Ok(mut response) => {
+                    let body = response.body_string().await.unwrap_or_default();
+                    return Err(format!(
+                        "Push request rejected with status {}: {}",
+                        response.status(),
+                        body.trim()
+                    )
+                    .into());
+                }
+                Err(e) => e.to_string(),
+            };
+            if retries >= self.config.max_retries {
+                return Err(
+                    format!("Push request failed after {retries} retries: {reason}").into(),
+                );
+            }
+            warn!("{ctx} Push request failed: {reason}. Retrying in {backoff:?}.");
+            task::sleep(backoff).await;
+            backoff *= 2;
+            retries += 1;
+        }
+    }
+
+    /// pushes the lines one request after the other, so the lines of a stream stay in order,
+    /// and acks or fails their events
+    async fn run(self, rx: Receiver<Push>) {
+        let ctx = &self.ctx;
+        while let Ok(Push { body, events }) = rx.recv().await {
+            let pushed = self.push(body).await;
+            if let Err(e) = &pushed {
+                error!("{ctx} Error pushing to Loki: {e}");
+            }
+            for (cf_data, start) in events {
+                let reply = if pushed.is_ok() {
+                    AsyncSinkReply::Ack(cf_data, nanotime() - start)
+                } else {
+                    AsyncSinkReply::Fail(cf_data)
+                };
+                ctx.swallow_err(self.reply_tx.send(reply).await, "Error sending contraflow");
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for LokiSink {
+    async fn connect(&mut self, ctx: &SinkContext, _attempt: &Attempt) -> Result<bool> {
+        let timeout = self.config.timeout.map(Duration::from_nanos);
+        let tls_config = self.tls_client_config.as_ref().cloned().map(Arc::new);
+        let client_config = http_client::Config::new()
+            .set_http_keep_alive(true)
+            .set_tcp_no_delay(true)
+            .set_timeout(timeout)
+            .set_tls_config(tls_config);
+        let client = H1Client::try_from(client_config)
+            .map_err(|e| format!("Invalid HTTP Client config: {e}."))?;
+        // the previous task sends what it already got and ends
+        let pusher = Pusher {
+            config: self.config.clone(),
+            client,
+            ctx: ctx.clone(),
+            reply_tx: self.reply_tx.clone(),
+        };
+        let (tx, rx) = bounded(QSIZE.load(Ordering::Relaxed));
+        self.pusher = Some((tx, task::spawn(pusher.run(rx))));
+        Ok(true)
+    }
+
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        event: Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        start: u64,
+    ) -> Result<SinkReply> {
+        let mut entries = Vec::new();
+        for (value, meta) in event.value_meta_iter() {
+            let loki_meta = ctx.extract_meta(meta);
+            let entry = self.labels(value, loki_meta).and_then(|labels| {
+                let line = serializer.serialize(value, event.ingest_ns)?.concat();
+                let line = String::from_utf8(line).map_err(Error::from)?;
+                let timestamp = loki_meta
+                    .and_then(|meta| meta.get_u64("timestamp"))
+                    .unwrap_or(event.ingest_ns);
+                Ok((labels, Entry { timestamp, line }))
+            });
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    error!("{ctx} Invalid event: {e}");
+                    return Ok(SinkReply::fail_or_none(event.transactional));
+                }
+            }
+        }
+
+        if self.streams.is_empty() {
+            self.since = nanotime();
+        }
+        for (labels, entry) in entries {
+            self.streams.push(labels, entry);
+        }
+        if event.transactional {
+            self.events.push((ContraflowData::from(&event), start));
+        }
+        if self.streams.len() >= self.config.batch_size {
+            self.flush(ctx).await;
+        }
+        Ok(SinkReply::NONE)
+    }
+
+    async fn on_signal(
+        &mut self,
+        _signal: Event,
+        ctx: &SinkContext,
+        _serializer: &mut EventSerializer,
+    ) -> Result<SinkReply> {
+        let interval = self.config.flush_interval_ms * 1_000_000;
+        if nanotime().saturating_sub(self.since) >= interval {
+            self.flush(ctx).await;
+        }
+        Ok(SinkReply::NONE)
+    }
+
+    async fn on_stop(&mut self, ctx: &SinkContext) -> Result<()> {
+        self.flush(ctx).await;
+        // wait for the pending push requests
+        if let Some((tx, handle)) = self.pusher.take() {
+            drop(tx);
+            handle.await;
+        }
+        Ok(())
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn asynchronous(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,313 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loki push requests
+//!
+//! See <https://grafana.com/docs/loki/latest/api/#push-log-entries-to-loki> for both encodings.
+
+use crate::errors::Result;
+use prost::Message;
+use std::collections::BTreeMap;
+use tremor_value::{literal, Value};
+use value_trait::{Builder, Mutable, Writable};
+
+const NS_PER_S: u64 = 1_000_000_000;
+
+/// The labels of a stream, sorted by name
+pub(super) type Labels = BTreeMap<String, String>;
+
+/// Encoding of push requests
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Encoding {
+    /// snappy compressed protobuf
+    Protobuf,
+    /// plain JSON
+    Json,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self::Protobuf
+    }
+}
+
+impl Encoding {
+    pub(super) fn content_type(self) -> &'static str {
+        match self {
+            Self::Protobuf => "application/x-protobuf",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+/// label names need to match `[a-zA-Z_][a-zA-Z0-9_]*`
+pub(super) fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A log line
+#[derive(Debug, PartialEq)]
+pub(super) struct Entry {
+    /// nanoseconds since the epoch
+    pub(super) timestamp: u64,
+    pub(super) line: String,
+}
+
+/// Entries grouped into streams by their labels
+#[derive(Debug, Default)]
+pub(super) struct Streams {
+    streams: BTreeMap<Labels, Vec<Entry>>,
+    len: usize,
+}
+
+impl Streams {
+    pub(super) fn push(&mut self, labels: Labels, entry: Entry) {
+        self.streams.entry(labels).or_default().push(entry);
+        self.len += 1;
+    }
+
+    /// number of entries
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// encodes the streams as a push request, the entries of a stream are sorted by their timestamp
+    pub(super) fn encode(mut self, encoding: Encoding) -> Result<Vec<u8>> {
+        for entries in self.streams.values_mut() {
+            entries.sort_by_key(|entry| entry.timestamp);
+        }
+        match encoding {
+            Encoding::Protobuf => self.protobuf(),
+            Encoding::Json => Ok(self.json()),
+        }
+    }
+
+    fn protobuf(self) -> Result<Vec<u8>> {
+        let streams = self
+            .streams
+            .into_iter()
+            .map(|(labels, entries)| {
+                let entries = entries
+                    .into_iter()
+                    .map(|entry| {
+                        Ok(EntryAdapter {
+                            timestamp: Some(Timestamp {
+                                seconds: i64::try_from(entry.timestamp / NS_PER_S)?,
+                                nanos: i32::try_from(entry.timestamp % NS_PER_S)?,
+                            }),
+                            line: entry.line,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(StreamAdapter {
+                    labels: format_labels(&labels),
+                    entries,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let request = PushRequest { streams }.encode_to_vec();
+        Ok(snap::raw::Encoder::new().compress_vec(&request)?)
+    }
+
+    fn json(self) -> Vec<u8> {
+        let streams: Vec<Value> = self
+            .streams
+            .into_iter()
+            .map(|(labels, entries)| {
+                let mut stream = Value::object_with_capacity(labels.len());
+                for (name, value) in labels {
+                    stream.try_insert(name, value);
+                }
+                let values: Vec<Value> = entries
+                    .into_iter()
+                    .map(|entry| literal!([entry.timestamp.to_string(), entry.line]))
+                    .collect();
+                literal!({
+                    "stream": stream,
+                    "values": values
+                })
+            })
+            .collect();
+        literal!({ "streams": streams }).encode().into_bytes()
+    }
+}
+
+/// formats labels like `{app="tremor", env="prod"}`
+fn format_labels(labels: &Labels) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", labels.join(", "))
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct PushRequest {
+    #[prost(message, repeated, tag = "1")]
+    streams: Vec<StreamAdapter>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct StreamAdapter {
+    #[prost(string, tag = "1")]
+    labels: String,
+    #[prost(message, repeated, tag = "2")]
+    entries: Vec<EntryAdapter>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EntryAdapter {
+    #[prost(message, optional, tag = "1")]
+    timestamp: Option<Timestamp>,
+    #[prost(string, tag = "2")]
+    line: String,
+}
+
+/// `google.protobuf.Timestamp`
+#[derive(Clone, PartialEq, Message)]
+struct Timestamp {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &[(&str, &str)]) -> Labels {
+        labels
+            .iter()
+            .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+            .collect()
+    }
+
+    fn streams() -> Streams {
+        let mut streams = Streams::default();
+        streams.push(
+            labels(&[("app", "tremor")]),
+            Entry {
+                timestamp: 2_000_000_001,
+                line: "badger".to_string(),
+            },
+        );
+        streams.push(
+            labels(&[("app", "tremor")]),
+            Entry {
+                timestamp: 1_000_000_001,
+                line: "snot".to_string(),
+            },
+        );
+        streams.push(
+            labels(&[("app", "other"), ("env", "prod")]),
+            Entry {
+                timestamp: 3,
+                line: "other".to_string(),
+            },
+        );
+        streams
+    }
+
+    #[test]
+    fn label_names() {
+        assert!(is_valid_label_name("app"));
+        assert!(is_valid_label_name("_app_2"));
+        assert!(!is_valid_label_name(""));
+        assert!(!is_valid_label_name("2app"));
+        assert!(!is_valid_label_name("app-name"));
+    }
+
+    #[test]
+    fn label_formatting() {
+        assert_eq!("{}", format_labels(&Labels::new()));
+        assert_eq!(
+            r#"{app="tremor", msg="a \"quoted\"\n\\line"}"#,
+            format_labels(&labels(&[
+                ("msg", "a \"quoted\"\n\\line"),
+                ("app", "tremor")
+            ]))
+        );
+    }
+
+    #[test]
+    fn json() -> Result<()> {
+        let streams = streams();
+        assert_eq!(3, streams.len());
+        let mut body = streams.encode(Encoding::Json)?;
+        let body: Value = tremor_value::parse_to_value(&mut body)?;
+        assert_eq!(
+            literal!({
+                "streams": [
+                    {
+                        "stream": {"app": "other", "env": "prod"},
+                        "values": [["3", "other"]]
+                    },
+                    {
+                        "stream": {"app": "tremor"},
+                        "values": [["1000000001", "snot"], ["2000000001", "badger"]]
+                    }
+                ]
+            }),
+            body
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn protobuf() -> Result<()> {
+        let body = streams().encode(Encoding::Protobuf)?;
+        let body = snap::raw::Decoder::new().decompress_vec(&body)?;
+        let request = PushRequest::decode(body.as_slice())?;
+        assert_eq!(2, request.streams.len());
+        assert_eq!(r#"{app="other", env="prod"}"#, request.streams[0].labels);
+        let stream = &request.streams[1];
+        assert_eq!(r#"{app="tremor"}"#, stream.labels);
+        assert_eq!(
+            vec![
+                EntryAdapter {
+                    timestamp: Some(Timestamp {
+                        seconds: 1,
+                        nanos: 1
+                    }),
+                    line: "snot".to_string()
+                },
+                EntryAdapter {
+                    timestamp: Some(Timestamp {
+                        seconds: 2,
+                        nanos: 1
+                    }),
+                    line: "badger".to_string()
+                }
+            ],
+            stream.entries
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,856 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! MQTT connector - publishes and subscribes via an MQTT v3.1.1 or v5 broker
+//!
+//! The source subscribes to the configured topic filters and emits every received message
+//! with its `topic`, `qos`, `retain` flag and, for v5, its `user_properties` as `$mqtt` metadata.
+//! `user_properties` are an array of `[key, value]` pairs, as a key can be given more than once.
+//! Messages with a QoS above 0 are acknowledged to the broker once the event is acked,
+//! failed events are not acknowledged and are redelivered by the broker on the next session
+//! if `clean_session` is `false`. Messages received before a reconnect are never acknowledged
+//! on the new session, the broker redelivers them.
+//!
+//! The sink publishes every event to the `topic` with the `qos` and `retain` flag from its `$mqtt`
+//! metadata, falling back to the configured defaults. `user_properties` are only sent with v5.
+//! Events are acknowledged once the broker acknowledged all their messages with a QoS above 0,
+//! and failed if the connection is lost before.
+#![allow(clippy::module_name_repetitions)]
+
+use crate::connectors::prelude::*;
+use crate::connectors::utils::tls::{load_certs, load_keys, TLSClientConfig};
+use crate::errors::err_conector_def;
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::prelude::FutureExt;
+use async_std::sync::{Mutex, RwLock};
+use async_std::task::JoinHandle;
+use either::Either;
+use rumqttc::v5::mqttbytes::qos as v5_qos;
+use rumqttc::v5::mqttbytes::v5::{
+    Filter, Packet as V5Packet, Publish as V5Publish, PublishProperties,
+};
+use rumqttc::{Outgoing, SubscribeFilter, TlsConfiguration, Transport};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::Duration;
+use tremor_common::time::nanotime;
+
+const URL_SCHEME: &str = "tremor-mqtt";
+/// time we wait for the broker to accept our connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) struct MqttDefaults;
+impl Defaults for MqttDefaults {
+    const SCHEME: &'static str = "mqtt";
+    const HOST: &'static str = "localhost";
+    const PORT: u16 = 1883;
+}
+
+/// MQTT protocol version
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Version {
+    /// MQTT 3.1.1
+    #[serde(rename = "3.1.1")]
+    V311,
+    /// MQTT 5
+    #[serde(rename = "5")]
+    V5,
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Self::V311
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// url of the broker
+    url: Url<MqttDefaults>,
+    /// protocol version, `3.1.1` or `5`
+    #[serde(default)]
+    version: Version,
+    /// client identifier, defaults to `tremor-<hostname>-<alias>`
+    client_id: Option<String>,
+    /// keep alive interval in seconds
+    #[serde(default = "default_keep_alive")]
+    keep_alive: u64,
+    /// start with a clean session, a persistent session keeps subscriptions and unacked messages
+    #[serde(default = "default_true")]
+    clean_session: bool,
+    username: Option<String>,
+    password: Option<String>,
+    /// topic filters to subscribe to
+    #[serde(default = "Default::default")]
+    subscribe: Vec<String>,
+    /// QoS for subscriptions and the default QoS for publishing
+    #[serde(default = "default_qos")]
+    qos: u8,
+    /// default topic to publish to
+    topic: Option<String>,
+    /// default retain flag for publishing
+    #[serde(default = "default_false")]
+    retain: bool,
+    #[serde(with = "either::serde_untagged_optional", default = "Default::default")]
+    tls: Option<Either<TLSClientConfig, bool>>,
+}
+
+impl ConfigImpl for Config {}
+
+fn default_keep_alive() -> u64 {
+    30
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn invalid_qos(qos: u8) -> Error {
+    format!("Invalid MQTT QoS {qos}, expected 0, 1 or 2").into()
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Builder {}
+
+impl Builder {
+    const KEEP_ALIVE_TOO_SHORT: &'static str = "`keep_alive` needs to be at least 5 seconds";
+    const TLS_DOMAIN: &'static str =
+        "`tls.domain` is not supported, the certificate is verified against the `url` host";
+    const TLS_CERT_AND_KEY: &'static str = "`tls.cert` and `tls.key` need to be given together";
+    const TLS_CLIENT_AUTH_CAFILE: &'static str = "client certificates need a `tls.cafile`";
+}
+
+/// Builds the transport to the broker from the `tls` config, reusing the tls config of the other
+/// connectors
+async fn transport(alias: &str, tls: Option<&Either<TLSClientConfig, bool>>) -> Result<Transport> {
+    Ok(match tls {
+        Some(Either::Right(true)) => Transport::tls_with_config(TlsConfiguration::default()),
+        Some(Either::Left(tls)) => {
+            if tls.domain.is_some() {
+                return Err(err_conector_def(alias, Builder::TLS_DOMAIN));
+            }
+            let client_auth = match (tls.cert.as_ref(), tls.key.as_ref()) {
+                (Some(cert), Some(key)) => {
+                    // fail early on certificates and keys we can't use
+                    load_certs(cert)?;
+                    load_keys(key)?;
+                    Some((
+                        async_std::fs::read(cert).await?,
+                        async_std::fs::read(key).await?,
+                    ))
+                }
+                (None, None) => None,
+                _ => return Err(err_conector_def(alias, Builder::TLS_CERT_AND_KEY)),
+            };
+            match (tls.cafile.as_ref(), client_auth) {
+                (Some(cafile), client_auth) => {
+                    load_certs(cafile)?;
+                    Transport::tls_with_config(TlsConfiguration::Simple {
+                        ca: async_std::fs::read(cafile).await?,
+                        alpn: None,
+                        client_auth,
+                    })
+                }
+                (None, None) => Transport::tls_with_config(TlsConfiguration::default()),
+                (None, Some(_)) => {
+                    return Err(err_conector_def(alias, Builder::TLS_CLIENT_AUTH_CAFILE))
+                }
+            }
+        }
+        Some(Either::Right(false)) | None => Transport::tcp(),
+    })
+}
+
+#[async_trait::async_trait]
+impl ConnectorBuilder for Builder {
+    fn connector_type(&self) -> ConnectorType {
+        "mqtt".into()
+    }
+
+    async fn build_cfg(
+        &self,
+        alias: &str,
+        _: &ConnectorConfig,
+        raw: &Value,
+    ) -> Result<Box<dyn Connector>> {
+        let config = Config::new(raw)?;
+        if config.qos > 2 {
+            return Err(err_conector_def(
+                alias,
+                &invalid_qos(config.qos).to_string(),
+            ));
+        }
+        if config.keep_alive < 5 {
+            return Err(err_conector_def(alias, Self::KEEP_ALIVE_TOO_SHORT));
+        }
+        let transport = transport(alias, config.tls.as_ref()).await?;
+        let client_id = config
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("tremor-{}-{}", hostname(), alias));
+        let (tx, rx) = bounded(QSIZE.load(Ordering::Relaxed));
+
+        Ok(Box::new(Mqtt {
+            config: Arc::new(config),
+            client_id,
+            transport,
+            client: Arc::new(RwLock::new(None)),
+            session: Arc::new(AtomicU64::new(0)),
+            tx,
+            rx: Some(rx),
+            publishes: Arc::new(Mutex::new(Publishes::default())),
+            reply_tx: None,
+            task: None,
+        }))
+    }
+}
+
+/// A message received from the broker
+enum Message {
+    V311(rumqttc::Publish),
+    V5(V5Publish),
+}
+
+impl Message {
+    fn topic(&self) -> std::borrow::Cow<str> {
+        match self {
+            Self::V311(publish) => std::borrow::Cow::Borrowed(publish.topic.as_str()),
+            Self::V5(publish) => String::from_utf8_lossy(&publish.topic),
+        }
+    }
+
+    fn payload(&self) -> &[u8] {
+        match self {
+            Self::V311(publish) => &publish.payload,
+            Self::V5(publish) => &publish.payload,
+        }
+    }
+
+    /// QoS 0 messages are not acknowledged
+    fn needs_ack(&self) -> bool {
+        match self {
+            Self::V311(publish) => publish.qos != rumqttc::QoS::AtMostOnce,
+            Self::V5(publish) => publish.qos != rumqttc::v5::mqttbytes::QoS::AtMostOnce,
+        }
+    }
+
+    fn meta(&self) -> Value<'static> {
+        let topic = self.topic().to_string();
+        match self {
+            Self::V311(publish) => literal!({
+                "topic": topic,
+                "qos": publish.qos as u8,
+                "retain": publish.retain
+            }),
+            Self::V5(publish) => {
+                // properties can be given more than once, so they are kept as pairs
+                let user_properties: Vec<Value> = publish
+                    .properties
+                    .iter()
+                    .flat_map(|properties| properties.user_properties.iter())
+                    .map(|(k, v)| literal!([k.clone(), v.clone()]))
+                    .collect();
+                literal!({
+                    "topic": topic,
+                    "qos": publish.qos as u8,
+                    "retain": publish.retain,
+                    "user_properties": user_properties
+                })
+            }
+        }
+    }
+}
+
+/// Client of either protocol version, used to publish and acknowledge messages
+#[derive(Clone)]
+enum Client {
+    V311(rumqttc::AsyncClient),
+    V5(rumqttc::v5::AsyncClient),
+}
+
+/// The connection to the broker, driving the client
+enum EventLoop {
+    V311(rumqttc::EventLoop),
+    V5(rumqttc::v5::EventLoop),
+}
+
+enum Incoming {
+    Connected,
+    Message(Message),
+    /// one of our messages was sent with the packet id, 0 for QoS 0
+    Published(u16),
+    /// one of our messages waits for its packet id to be free again
+    Collision,
+    /// the broker acknowledged our message with the packet id
+    PublishAcked(u16),
+    Other,
+}
+
+/// Events of the sink waiting for the broker to acknowledge their messages
+#[derive(Default)]
+struct Publishes {
+    /// the event of every message handed to the client, in order,
+    /// `None` if it doesn't need to be acknowledged
+    queued: VecDeque<Option<u64>>,
+    /// the event of the message waiting for its packet id
+    collision: Option<Option<u64>>,
+    /// the event of every sent message by packet id, in order
+    sent: Vec<(u16, u64)>,
+    /// the events with their start time and the number of messages not acknowledged yet
+    events: HashMap<u64, (ContraflowData, u64, usize)>,
+    next_id: u64,
+}
+
+impl Publishes {
+    /// Tracks a new event, it is only acknowledged once `done` was called
+    fn add(&mut self, cf: ContraflowData, start: u64) -> u64 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.events.insert(id, (cf, start, 1));
+        id
+    }
+
+    /// A message of the event `id` is handed to the client
+    fn queue(&mut self, id: Option<u64>) {
+        let id = id.filter(|id| {
+            self.events
+                .get_mut(id)
+                .map(|(_, _, pending)| *pending += 1)
+                .is_some()
+        });
+        self.queued.push_back(id);
+    }
+
+    /// The last message couldn't be handed to the client
+    fn unqueue(&mut self) {
+        self.queued.pop_back();
+    }
+
+    fn published(&mut self, pkid: u16) {
+        let id = self
+            .collision
+            .take()
+            .unwrap_or_else(|| self.queued.pop_front().flatten());
+        if let Some(id) = id {
+            self.sent.push((pkid, id));
+        }
+    }
+
+    fn collision(&mut self) {
+        self.collision = Some(self.queued.pop_front().flatten());
+    }
+
+    /// Returns the event once all of its messages are acknowledged
+    fn acked(&mut self, pkid: u16) -> Option<(ContraflowData, u64)> {
+        let idx = self.sent.iter().position(|(p, _)| *p == pkid)?;
+        let (_, id) = self.sent.remove(idx);
+        self.done(id)
+    }
+
+    /// A message of the event `id` is acknowledged or all of them were handed to the
+    /// client, returns the event once nothing is pending anymore
+    fn done(&mut self, id: u64) -> Option<(ContraflowData, u64)> {
+        let (_, _, pending) = self.events.get_mut(&id)?;
+        *pending = pending.saturating_sub(1);
+        if *pending == 0 {
+            self.events.remove(&id).map(|(cf, start, _)| (cf, start))
+        } else {
+            None
+        }
+    }
+
+    /// Stops tracking the event `id`, returns `false` if it isn't tracked anymore
+    fn remove(&mut self, id: u64) -> bool {
+        self.events.remove(&id).is_some()
+    }
+
+    /// The connection is lost, returns all events that are not acknowledged
+    fn clear(&mut self) -> Vec<ContraflowData> {
+        self.queued.clear();
+        self.collision = None;
+        self.sent.clear();
+        self.events.drain().map(|(_, (cf, _, _))| cf).collect()
+    }
+}
+
+impl Client {
+    fn new(config: &Config, client_id: &str, transport: Transport) -> (Self, EventLoop) {
+        let qsize = QSIZE.load(Ordering::Relaxed);
+        let host = config.url.host_or_local();
+        let port = config.url.port_or_dflt();
+        let keep_alive = Duration::from_secs(config.keep_alive);
+        let credentials = config
+            .username
+            .as_ref()
+            .map(|username| (username, config.password.clone().unwrap_or_default()));
+        match config.version {
+            Version::V311 => {
+                let mut options = rumqttc::MqttOptions::new(client_id, host, port);
+                options
+                    .set_keep_alive(keep_alive)
+                    .set_clean_session(config.clean_session)
+                    .set_manual_acks(true)
+                    .set_transport(transport);
+                if let Some((username, password)) = credentials {
+                    options.set_credentials(username, password);
+                }
+                let (client, eventloop) = rumqttc::AsyncClient::new(options, qsize);
+                (Self::V311(client), EventLoop::V311(eventloop))
+            }
+            Version::V5 => {
+                let mut options = rumqttc::v5::MqttOptions::new(client_id, host, port);
+                options
+                    .set_keep_alive(keep_alive)
+                    .set_clean_start(config.clean_session)
+                    .set_manual_acks(true)
+                    .set_transport(transport);
+                if let Some((username, password)) = credentials {
+                    options.set_credentials(username, password);
+                }
+                let (client, eventloop) = rumqttc::v5::AsyncClient::new(options, qsize);
+                (Self::V5(client), EventLoop::V5(eventloop))
+            }
+        }
+    }
+
+    async fn subscribe(&self, topics: &[String], qos: u8) -> Result<()> {
+        if topics.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Self::V311(client) => {
+                let qos = rumqttc::qos(qos).map_err(|_| invalid_qos(qos))?;
+                let filters = topics
+                    .iter()
+                    .map(|topic| SubscribeFilter::new(topic.clone(), qos));
+                client.subscribe_many(filters).await?;
+            }
+            Self::V5(client) => {
+                let qos = v5_qos(qos).ok_or_else(|| invalid_qos(qos))?;
+                let filters = topics.iter().map(|topic| Filter::new(topic.clone(), qos));
+                client.subscribe_many(filters).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish(
+        &self,
+        topic: &str,
+        qos: u8,
+        retain: bool,
+        payload: Vec<u8>,
+        user_properties: Vec<(String, String)>,
+    ) -> Result<()> {
+        match self {
+            Self::V311(client) => {
+                let qos = rumqttc::qos(qos).map_err(|_| invalid_qos(qos))?;
+                client.publish(topic, qos, retain, payload).await?;
+            }
+            Self::V5(client) if user_properties.is_empty() => {
+                let qos = v5_qos(qos).ok_or_else(|| invalid_qos(qos))?;
+                client.publish(topic, qos, retain, payload).await?;
+            }
+            Self::V5(client) => {
+                let qos = v5_qos(qos).ok_or_else(|| invalid_qos(qos))?;
+                let properties = PublishProperties {
+                    user_properties,
+                    ..PublishProperties::default()
+                };
+                client
+                    .publish_with_properties(topic, qos, retain, payload, properties)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn ack(&self, message: &Message) -> Result<()> {
+        match (self, message) {
+            (Self::V311(client), Message::V311(publish)) => client.ack(publish).await?,
+            (Self::V5(client), Message::V5(publish)) => client.ack(publish).await?,
+            // the protocol version is fixed per connector, so this can't happen
+            _ => (),
+        }
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        match self {
+            Self::V311(client) => client.disconnect().await?,
+            Self::V5(client) => client.disconnect().await?,
+        }
+        Ok(())
+    }
+}
+
+impl EventLoop {
+    async fn poll(&mut self) -> Result<Incoming> {
+        Ok(match self {
+            Self::V311(eventloop) => match eventloop.poll().await? {
+                rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_)) => Incoming::Connected,
+                rumqttc::Event::Incoming(rumqttc::Packet::Publish(publish)) => {
+                    Incoming::Message(Message::V311(publish))
+                }
+                // QoS 1 messages are acknowledged with a `PubAck`, QoS 2 with a `PubComp`
+                rumqttc::Event::Incoming(rumqttc::Packet::PubAck(ack)) => {
+                    Incoming::PublishAcked(ack.pkid)
+                }
+                rumqttc::Event::Incoming(rumqttc::Packet::PubComp(comp)) => {
+                    Incoming::PublishAcked(comp.pkid)
+                }
+                rumqttc::Event::Outgoing(Outgoing::Publish(pkid)) => Incoming::Published(pkid),
+                rumqttc::Event::Outgoing(Outgoing::AwaitAck(_)) => Incoming::Collision,
+                _ => Incoming::Other,
+            },
+            Self::V5(eventloop) => match eventloop.poll().await? {
+                rumqttc::v5::Event::Incoming(V5Packet::ConnAck(_)) => Incoming::Connected,
+                rumqttc::v5::Event::Incoming(V5Packet::Publish(publish)) => {
+                    Incoming::Message(Message::V5(publish))
+                }
+                // acknowledgements with an error reason are connection errors
+                rumqttc::v5::Event::Incoming(V5Packet::PubAck(ack)) => {
+                    Incoming::PublishAcked(ack.pkid)
+                }
+                rumqttc::v5::Event::Incoming(V5Packet::PubComp(comp)) => {
+                    Incoming::PublishAcked(comp.pkid)
+                }
+                rumqttc::v5::Event::Outgoing(Outgoing::Publish(pkid)) => Incoming::Published(pkid),
+                rumqttc::v5::Event::Outgoing(Outgoing::AwaitAck(_)) => Incoming::Collision,
+                _ => Incoming::Other,
+            },
+        })
+    }
+}
+
+/// Drives the connection, forwards received messages to the source, acknowledges the events
+/// of the sink and notifies the runtime once the connection is lost, reconnecting is left to
+/// the reconnect strategy of the connector.
+async fn connection_task(
+    ctx: ConnectorContext,
+    mut eventloop: EventLoop,
+    client: Arc<RwLock<Option<Client>>>,
+    session: u64,
+    tx: Sender<(u64, Message)>,
+    publishes: Arc<Mutex<Publishes>>,
+    reply_tx: Option<Sender<AsyncSinkReply>>,
+) -> Result<()> {
+    loop {
+        match eventloop.poll().await {
+            Ok(Incoming::Message(message)) => tx.send((session, message)).await?,
+            Ok(Incoming::Published(pkid)) => publishes.lock().await.published(pkid),
+            Ok(Incoming::Collision) => publishes.lock().await.collision(),
+            Ok(Incoming::PublishAcked(pkid)) => {
+                let acked = publishes.lock().await.acked(pkid);
+                if let (Some((cf, start)), Some(reply_tx)) = (acked, reply_tx.as_ref()) {
+                    reply_tx
+                        .send(AsyncSinkReply::Ack(cf, nanotime() - start))
+                        .await?;
+                }
+            }
+            Ok(Incoming::Connected | Incoming::Other) => (),
+            Err(e) => {
+                error!("{ctx} MQTT connection error: {e}");
+                client.write().await.take();
+                // no more messages can be handed to the client once the event loop is gone
+                drop(eventloop);
+                fail_publishes(&publishes, reply_tx.as_ref()).await?;
+                ctx.notifier().connection_lost().await?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Fails all events of the sink that are not acknowledged yet
+async fn fail_publishes(
+    publishes: &Mutex<Publishes>,
+    reply_tx: Option<&Sender<AsyncSinkReply>>,
+) -> Result<()> {
+    let failed = publishes.lock().await.clear();
+    if let Some(reply_tx) = reply_tx {
+        for cf in failed {
+            reply_tx.send(AsyncSinkReply::Fail(cf)).await?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) struct Mqtt {
+    config: Arc<Config>,
+    client_id: String,
+    transport: Transport,
+    client: Arc<RwLock<Option<Client>>>,
+    /// the current session, every connection is a new one
+    session: Arc<AtomicU64>,
+    tx: Sender<(u64, Message)>,
+    rx: Option<Receiver<(u64, Message)>>,
+    publishes: Arc<Mutex<Publishes>>,
+    reply_tx: Option<Sender<AsyncSinkReply>>,
+    task: Option<JoinHandle<()>>,
+}
+
+#[async_trait::async_trait]
+impl Connector for Mqtt {
+    async fn create_source(
+        &mut self,
+        source_context: SourceContext,
+        builder: SourceManagerBuilder,
+    ) -> Result<Option<SourceAddr>> {
+        let rx = self.rx.take().ok_or("MQTT source already created")?;
+        let source = MqttSource {
+            config: self.config.clone(),
+            client: self.client.clone(),
+            session: self.session.clone(),
+            rx,
+            pending: HashMap::new(),
+        };
+        builder.spawn(source, source_context).map(Some)
+    }
+
+    async fn create_sink(
+        &mut self,
+        sink_context: SinkContext,
+        builder: SinkManagerBuilder,
+    ) -> Result<Option<SinkAddr>> {
+        self.reply_tx = Some(builder.reply_tx());
+        let sink = MqttSink {
+            config: self.config.clone(),
+            client: self.client.clone(),
+            publishes: self.publishes.clone(),
+        };
+        builder.spawn(sink, sink_context).map(Some)
+    }
+
+    async fn connect(&mut self, ctx: &ConnectorContext, _attempt: &Attempt) -> Result<bool> {
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        let (client, mut eventloop) =
+            Client::new(&self.config, &self.client_id, self.transport.clone());
+        // only report success once the broker accepted the connection
+        while !matches!(
+            eventloop.poll().timeout(CONNECT_TIMEOUT).await??,
+            Incoming::Connected
+        ) {}
+        client
+            .subscribe(&self.config.subscribe, self.config.qos)
+            .await?;
+        // packet ids of messages received before are meaningless to the new session
+        let session = self.session.fetch_add(1, Ordering::AcqRel) + 1;
+        *self.client.write().await = Some(client);
+        self.task = Some(spawn_task(
+            ctx.clone(),
+            connection_task(
+                ctx.clone(),
+                eventloop,
+                self.client.clone(),
+                session,
+                self.tx.clone(),
+                self.publishes.clone(),
+                self.reply_tx.clone(),
+            ),
+        ));
+        Ok(true)
+    }
+
+    async fn on_stop(&mut self, ctx: &ConnectorContext) -> Result<()> {
+        if let Some(client) = self.client.write().await.take() {
+            ctx.swallow_err(client.disconnect().await, "Error disconnecting from broker");
+        }
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        fail_publishes(&self.publishes, self.reply_tx.as_ref()).await
+    }
+
+    fn codec_requirements(&self) -> CodecReq {
+        CodecReq::Required
+    }
+}
+
+struct MqttSource {
+    config: Arc<Config>,
+    client: Arc<RwLock<Option<Client>>>,
+    session: Arc<AtomicU64>,
+    rx: Receiver<(u64, Message)>,
+    /// received messages, with their session, waiting for the ack of their event
+    pending: HashMap<u64, (u64, Message)>,
+}
+
+#[async_trait::async_trait]
+impl Source for MqttSource {
+    async fn pull_data(&mut self, pull_id: &mut u64, ctx: &SourceContext) -> Result<SourceReply> {
+        let (session, message) = self.rx.recv().await?;
+        let origin_uri = EventOriginUri {
+            scheme: URL_SCHEME.to_string(),
+            host: self.config.url.host_or_local().to_string(),
+            port: Some(self.config.url.port_or_dflt()),
+            path: vec![message.topic().to_string()],
+        };
+        let data = message.payload().to_vec();
+        let meta = ctx.meta(message.meta());
+        if message.needs_ack() {
+            self.pending.insert(*pull_id, (session, message));
+        }
+        Ok(SourceReply::Data {
+            origin_uri,
+            data,
+            meta: Some(meta),
+            stream: None,
+            port: None,
+            codec_overwrite: None,
+        })
+    }
+
+    async fn ack(&mut self, _stream_id: u64, pull_id: u64, ctx: &SourceContext) -> Result<()> {
+        if let Some((session, message)) = self.pending.remove(&pull_id) {
+            let client = self.client.read().await;
+            match client.as_ref() {
+                // the packet id is only valid within the session the message was received in
+                Some(client) if session == self.session.load(Ordering::Acquire) => {
+                    client.ack(&message).await?;
+                }
+                _ => debug!("{ctx} Not connected, the broker will redeliver message {pull_id}"),
+            }
+        }
+        Ok(())
+    }
+
+    async fn fail(&mut self, _stream_id: u64, pull_id: u64, _ctx: &SourceContext) -> Result<()> {
+        // MQTT has no negative acknowledgements, the message is redelivered with the next session
+        self.pending.remove(&pull_id);
+        Ok(())
+    }
+
+    async fn on_connection_lost(&mut self, _ctx: &SourceContext) -> Result<()> {
+        // the broker redelivers unacknowledged messages with the next session
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn is_transactional(&self) -> bool {
+        true
+    }
+
+    fn asynchronous(&self) -> bool {
+        true
+    }
+}
+
+struct MqttSink {
+    config: Arc<Config>,
+    client: Arc<RwLock<Option<Client>>>,
+    publishes: Arc<Mutex<Publishes>>,
+}
+
+impl MqttSink {
+    /// Publishes the messages of an event, tracking those with a QoS above 0 for the event `id`
+    async fn publish(
+        &self,
+        client: &Client,
+        event: &Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        id: Option<u64>,
+    ) -> Result<()> {
+        for (value, meta) in event.value_meta_iter() {
+            let mqtt_meta = ctx.extract_meta(meta);
+            let topic = mqtt_meta
+                .get_str("topic")
+                .or(self.config.topic.as_deref())
+                .ok_or("No topic given in `$mqtt.topic` or the `topic` config")?;
+            let qos = mqtt_meta.get_u8("qos").unwrap_or(self.config.qos);
+            let retain = mqtt_meta.get_bool("retain").unwrap_or(self.config.retain);
+            let user_properties: Vec<(String, String)> = mqtt_meta
+                .get_array("user_properties")
+                .map(|properties| {
+                    properties
+                        .iter()
+                        .filter_map(|pair| match pair.as_array().map(Vec::as_slice) {
+                            Some([k, v]) => {
+                                Some((k.as_str()?.to_string(), v.as_str()?.to_string()))
+                            }
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            for payload in serializer.serialize(value, event.ingest_ns)? {
+                // the client sends the messages in order, so we track them in order
+                self.publishes.lock().await.queue(id.filter(|_| qos > 0));
+                if let Err(e) = client
+                    .publish(topic, qos, retain, payload, user_properties.clone())
+                    .await
+                {
+                    self.publishes.lock().await.unqueue();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for MqttSink {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        event: Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        start: u64,
+    ) -> Result<SinkReply> {
+        let client = self
+            .client
+            .read()
+            .await
+            .clone()
+            .ok_or(ErrorKind::ClientNotAvailable(
+                "MQTT",
+                "The client is not connected",
+            ))?;
+        let id = if event.transactional {
+            Some(
+                self.publishes
+                    .lock()
+                    .await
+                    .add(ContraflowData::from(&event), start),
+            )
+        } else {
+            None
+        };
+        let res = self.publish(&client, &event, ctx, serializer, id).await;
+        let mut publishes = self.publishes.lock().await;
+        match (res, id) {
+            // all messages are acknowledged already or didn't need to be
+            (Ok(()), Some(id)) if publishes.done(id).is_some() => Ok(SinkReply::ACK),
+            (Ok(()), _) => Ok(SinkReply::NONE),
+            // the event was failed already as the connection was lost
+            (Err(e), Some(id)) if !publishes.remove(id) => {
+                debug!("{ctx} Error publishing: {e}");
+                Ok(SinkReply::NONE)
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    fn auto_ack(&self) -> bool {
+        // events are acknowledged once the broker acknowledged their messages
+        false
+    }
+}
@@ -0,0 +1,700 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! NATS connector - publishes, sends requests and subscribes via NATS and NATS `JetStream`
+//!
+//! The source emits every message received on the `subscribe` subjects, optionally as member
+//! of a `queue_group`, with its `subject`, `reply` subject and `headers` as `$nats` metadata.
+//! With `jetstream` it additionally consumes the given durable pull consumer of a stream,
+//! messages are acked once their event is acked and nak'ed for redelivery once it failed.
+//!
+//! The sink publishes every event to the `subject` from its `$nats` metadata, falling back to
+//! the configured `subject`, with `headers` from its `$nats` metadata. Requests can be answered
+//! by publishing to the `$nats.reply` subject of the request.
+//! With `request` it sends requests instead and emits their responses via the source,
+//! together with the `$correlation` metadata of the request.
+//! With `jetstream` events are acked once the stream stored all of their messages.
+//!
+//! The connection is re-established by the reconnect strategy of the connector,
+//! not by the client.
+#![allow(clippy::module_name_repetitions)]
+
+use crate::connectors::prelude::*;
+use crate::errors::err_conector_def;
+use async_nats::jetstream::{
+    self,
+    consumer::{pull, AckPolicy},
+    context::PublishAckFuture,
+    message::Acker,
+    AckKind,
+};
+use async_nats::{ConnectOptions, HeaderMap, Request, ServerAddr};
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::sync::RwLock;
+use async_std::task::{self, JoinHandle};
+use beef::Cow;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use tremor_common::time::nanotime;
+
+const URL_SCHEME: &str = "tremor-nats";
+
+/// A durable `JetStream` pull consumer
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct JetStreamConfig {
+    /// name of the stream
+    stream: String,
+    /// name of the durable consumer, it is created if it doesn't exist yet
+    consumer: String,
+    /// only consume messages of the stream matching this subject
+    #[serde(default = "Default::default")]
+    filter_subject: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// servers to connect to, e.g. `nats://localhost:4222`
+    #[serde(default = "default_servers")]
+    servers: Vec<String>,
+    /// connection name, defaults to `tremor-<hostname>-<alias>`
+    #[serde(default = "Default::default")]
+    name: Option<String>,
+    /// username for authentication
+    #[serde(default = "Default::default")]
+    username: Option<String>,
+    /// password for authentication
+    #[serde(default = "Default::default")]
+    password: Option<String>,
+    /// token for authentication
+    #[serde(default = "Default::default")]
+    token: Option<String>,
+    /// subjects to subscribe to
+    #[serde(default = "Default::default")]
+    subscribe: Vec<String>,
+    /// queue group to subscribe with, subscribers of the same group share the messages
+    #[serde(default = "Default::default")]
+    queue_group: Option<String>,
+    /// default subject to publish to
+    #[serde(default = "Default::default")]
+    subject: Option<String>,
+    /// send requests and emit their responses via the source
+    #[serde(default = "default_false")]
+    request: bool,
+    /// request timeout in nanoseconds
+    #[serde(default = "Default::default")]
+    timeout: Option<u64>,
+    /// `JetStream` consumer to consume, events sent to the sink are published via `JetStream`
+    #[serde(default = "Default::default")]
+    jetstream: Option<JetStreamConfig>,
+}
+
+impl ConfigImpl for Config {}
+
+fn default_servers() -> Vec<String> {
+    vec!["localhost:4222".to_string()]
+}
+
+fn nats_err<E>(e: E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    Error::from(async_nats::Error::from(e))
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Builder {}
+
+#[async_trait::async_trait]
+impl ConnectorBuilder for Builder {
+    fn connector_type(&self) -> ConnectorType {
+        "nats".into()
+    }
+
+    async fn build_cfg(
+        &self,
+        alias: &str,
+        _: &ConnectorConfig,
+        raw_config: &Value,
+    ) -> Result<Box<dyn Connector>> {
+        let config = Config::new(raw_config)?;
+        let servers = config
+            .servers
+            .iter()
+            .map(|server| {
+                server.parse::<ServerAddr>().map_err(|e| {
+                    err_conector_def(alias, &format!("Invalid server `{server}`: {e}"))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if servers.is_empty() {
+            return Err(err_conector_def(alias, "No `servers` given"));
+        }
+        if config.request && config.jetstream.is_some() {
+            return Err(err_conector_def(
+                alias,
+                "`request` can't be combined with `jetstream`",
+            ));
+        }
+        let name = config
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("tremor-{}-{}", hostname(), alias));
+        let (tx, rx) = bounded(QSIZE.load(Ordering::Relaxed));
+        Ok(Box::new(Nats {
+            config: Arc::new(config),
+            servers,
+            name,
+            client: Arc::new(RwLock::new(None)),
+            tx,
+            rx: Some(rx),
+            task: None,
+        }))
+    }
+}
+
+/// A message received by the client
+enum Received {
+    /// received via a subscription
+    Core(async_nats::Message),
+    /// received via the `JetStream` consumer
+    JetStream(jetstream::Message),
+    /// response to a request sent by the sink
+    Response {
+        message: async_nats::Message,
+        correlation: Option<Value<'static>>,
+    },
+}
+
+/// Sources of the connection task
+enum Incoming {
+    Event(async_nats::Event),
+    Core(async_nats::Message),
+    JetStream(std::result::Result<jetstream::Message, pull::MessagesError>),
+}
+
+fn headers_to_value(headers: Option<&HeaderMap>) -> Value<'static> {
+    headers.map_or_else(Value::object, |headers| {
+        Value::from(
+            headers
+                .iter()
+                .map(|(name, values)| {
+                    let values: Vec<Value> = values
+                        .iter()
+                        .map(|v| Value::from(v.as_str().to_string()))
+                        .collect();
+                    (Cow::from(name.to_string()), Value::from(values))
+                })
+                .collect::<Object>(),
+        )
+    })
+}
+
+/// supports single strings and arrays of strings as header values
+fn value_to_headers(headers: Option<&Object>) -> HeaderMap {
+    let mut res = HeaderMap::new();
+    for (name, value) in headers.into_iter().flatten() {
+        if let Some(values) = value.as_array() {
+            for v in values.iter().filter_map(Value::as_str) {
+                res.append(name.as_ref(), v);
+            }
+        } else if let Some(v) = value.as_str() {
+            res.insert(name.as_ref(), v);
+        }
+    }
+    res
+}
+
+impl Received {
+    fn message(&self) -> &async_nats::Message {
+        match self {
+            Received::Core(message) | Received::Response { message, .. } => message,
+            Received::JetStream(message) => &message.message,
+        }
+    }
+
+    fn meta(&self) -> Value<'static> {
+        let message = self.message();
+        let mut meta = literal!({
+            "subject": message.subject.to_string(),
+            "reply": message.reply.as_ref().map(ToString::to_string),
+            "headers": headers_to_value(message.headers.as_ref())
+        });
+        if let Received::JetStream(message) = self {
+            if let Ok(info) = message.info() {
+                meta.try_insert(
+                    "jetstream",
+                    literal!({
+                        "stream": info.stream.to_string(),
+                        "consumer": info.consumer.to_string(),
+                        "stream_sequence": info.stream_sequence,
+                        "consumer_sequence": info.consumer_sequence,
+                        "delivered": info.delivered,
+                        "pending": info.pending
+                    }),
+                );
+            }
+        }
+        meta
+    }
+}
+
+/// Forwards received messages to the source and notifies the runtime once the connection is lost,
+/// reconnecting is left to the reconnect strategy of the connector.
+async fn connection_task(
+    ctx: ConnectorContext,
+    mut incoming: BoxStream<'static, Incoming>,
+    client: Arc<RwLock<Option<async_nats::Client>>>,
+    tx: Sender<Received>,
+) -> Result<()> {
+    while let Some(incoming) = incoming.next().await {
+        match incoming {
+            Incoming::Core(message) => tx.send(Received::Core(message)).await?,
+            Incoming::JetStream(Ok(message)) => tx.send(Received::JetStream(message)).await?,
+            Incoming::JetStream(Err(e)) => warn!("{ctx} Error receiving JetStream message: {e}"),
+            Incoming::Event(async_nats::Event::Disconnected) => break,
+            Incoming::Event(event) => info!("{ctx} NATS connection event: {event}"),
+        }
+    }
+    error!("{ctx} NATS connection lost");
+    client.write().await.take();
+    ctx.notifier().connection_lost().await?;
+    Ok(())
+}
+
+pub(crate) struct Nats {
+    config: Arc<Config>,
+    servers: Vec<ServerAddr>,
+    name: String,
+    client: Arc<RwLock<Option<async_nats::Client>>>,
+    tx: Sender<Received>,
+    rx: Option<Receiver<Received>>,
+    task: Option<JoinHandle<()>>,
+}
+
+/// Delay of reconnect attempts by the client itself, it can't be told not to reconnect
+/// so a lost client is kept from reconnecting until it is cleaned up
+const CLIENT_RECONNECT_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl Nats {
+    fn connect_options(
+        &self,
+        events_tx: Sender<async_nats::Event>,
+        connected: Arc<AtomicBool>,
+    ) -> ConnectOptions {
+        let mut options = ConnectOptions::new()
+            .name(&self.name)
+            // reconnecting is left to the reconnect strategy of the connector, the client
+            // only gets to try every server once while connecting initially
+            .reconnect_delay_callback(move |_attempts| {
+                if connected.load(Ordering::Acquire) {
+                    CLIENT_RECONNECT_DELAY
+                } else {
+                    Duration::ZERO
+                }
+            })
+            .event_callback(move |event| {
+                // the callback future needs to be `Sync`, so we don't await sending here
+                if events_tx.try_send(event).is_err() {
+                    error!("Error forwarding NATS connection event");
+                }
+                std::future::ready(())
+            });
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            options = options.user_and_password(username.clone(), password.clone());
+        }
+        if let Some(token) = &self.config.token {
+            options = options.token(token.clone());
+        }
+        if let Some(timeout) = self.config.timeout {
+            options = options.request_timeout(Some(Duration::from_nanos(timeout)));
+        }
+        options
+    }
+}
+
+#[async_trait::async_trait]
+impl Connector for Nats {
+    async fn create_source(
+        &mut self,
+        source_context: SourceContext,
+        builder: SourceManagerBuilder,
+    ) -> Result<Option<SourceAddr>> {
+        let rx = self.rx.take().ok_or("NATS source already created")?;
+        let source = NatsSource {
+            servers: self.config.servers.join(","),
+            rx,
+            pending: HashMap::new(),
+        };
+        builder.spawn(source, source_context).map(Some)
+    }
+
+    async fn create_sink(
+        &mut self,
+        sink_context: SinkContext,
+        builder: SinkManagerBuilder,
+    ) -> Result<Option<SinkAddr>> {
+        let sink = NatsSink {
+            config: self.config.clone(),
+            client: self.client.clone(),
+            tx: self.tx.clone(),
+            reply_tx: builder.reply_tx(),
+        };
+        builder.spawn(sink, sink_context).map(Some)
+    }
+
+    async fn connect(&mut self, ctx: &ConnectorContext, _attempt: &Attempt) -> Result<bool> {
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        // dropping the previous client closes its connection
+        self.client.write().await.take();
+
+        let (events_tx, events_rx) = bounded(QSIZE.load(Ordering::Relaxed));
+        let connected = Arc::new(AtomicBool::new(false));
+        let client = self
+            .connect_options(events_tx, connected.clone())
+            .connect(self.servers.as_slice())
+            .await
+            .map_err(nats_err)?;
+        connected.store(true, Ordering::Release);
+
+        let mut incoming: Vec<BoxStream<'static, Incoming>> =
+            vec![events_rx.map(Incoming::Event).boxed()];
+        for subject in &self.config.subscribe {
+            let subscriber = if let Some(group) = &self.config.queue_group {
+                client.queue_subscribe(subject.clone(), group.clone()).await
+            } else {
+                client.subscribe(subject.clone()).await
+            }
+            .map_err(nats_err)?;
+            incoming.push(subscriber.map(Incoming::Core).boxed());
+        }
+        if let Some(js_config) = &self.config.jetstream {
+            let stream = jetstream::new(client.clone())
+                .get_stream(&js_config.stream)
+                .await
+                .map_err(nats_err)?;
+            let consumer = stream
+                .get_or_create_consumer(
+                    &js_config.consumer,
+                    pull::Config {
+                        durable_name: Some(js_config.consumer.clone()),
+                        filter_subject: js_config.filter_subject.clone(),
+                        ack_policy: AckPolicy::Explicit,
+                        ..pull::Config::default()
+                    },
+                )
+                .await
+                .map_err(nats_err)?;
+            let messages = consumer.messages().await.map_err(nats_err)?;
+            incoming.push(messages.map(Incoming::JetStream).boxed());
+        }
+
+        *self.client.write().await = Some(client);
+        self.task = Some(spawn_task(
+            ctx.clone(),
+            connection_task(
+                ctx.clone(),
+                stream::select_all(incoming).boxed(),
+                self.client.clone(),
+                self.tx.clone(),
+            ),
+        ));
+        Ok(true)
+    }
+
+    async fn on_stop(&mut self, ctx: &ConnectorContext) -> Result<()> {
+        if let Some(client) = self.client.write().await.take() {
+            ctx.swallow_err(
+                client.flush().await.map_err(nats_err),
+                "Error flushing the NATS client",
+            );
+        }
+        if let Some(task) = self.task.take() {
+            task.cancel().await;
+        }
+        Ok(())
+    }
+
+    fn codec_requirements(&self) -> CodecReq {
+        CodecReq::Required
+    }
+}
+
+struct NatsSource {
+    servers: String,
+    rx: Receiver<Received>,
+    /// ackers of `JetStream` messages waiting for their event to be acked or failed
+    pending: HashMap<u64, Acker>,
+}
+
+#[async_trait::async_trait]
+impl Source for NatsSource {
+    async fn pull_data(&mut self, pull_id: &mut u64, ctx: &SourceContext) -> Result<SourceReply> {
+        let received = self.rx.recv().await?;
+        let mut meta = ctx.meta(received.meta());
+        let (message, correlation) = match received {
+            Received::Core(message) => (message, None),
+            Received::Response {
+                message,
+                correlation,
+            } => (message, correlation),
+            Received::JetStream(message) => {
+                let (message, acker) = message.split();
+                self.pending.insert(*pull_id, acker);
+                (message, None)
+            }
+        };
+        if let Some(correlation) = correlation {
+            meta.try_insert("correlation", correlation);
+        }
+        let origin_uri = EventOriginUri {
+            scheme: URL_SCHEME.to_string(),
+            host: self.servers.clone(),
+            port: None,
+            path: vec![message.subject.to_string()],
+        };
+        Ok(SourceReply::Data {
+            origin_uri,
+            data: message.payload.to_vec(),
+            meta: Some(meta),
+            stream: None,
+            port: None,
+            codec_overwrite: None,
+        })
+    }
+
+    async fn ack(&mut self, _stream_id: u64, pull_id: u64, _ctx: &SourceContext) -> Result<()> {
+        if let Some(acker) = self.pending.remove(&pull_id) {
+            acker.ack().await?;
+        }
+        Ok(())
+    }
+
+    async fn fail(&mut self, _stream_id: u64, pull_id: u64, _ctx: &SourceContext) -> Result<()> {
+        if let Some(acker) = self.pending.remove(&pull_id) {
+            acker.ack_with(AckKind::Nak(None)).await?;
+        }
+        Ok(())
+    }
+
+    async fn on_connection_lost(&mut self, ctx: &SourceContext) -> Result<()> {
+        // messages of the lost connection are redelivered, they can't be acked anymore
+        if !self.pending.is_empty() {
+            debug!(
+                "{ctx} Dropping {} pending JetStream acks of the lost connection",
+                self.pending.len()
+            );
+            self.pending.clear();
+        }
+        Ok(())
+    }
+
+    fn is_transactional(&self) -> bool {
+        true
+    }
+
+    fn asynchronous(&self) -> bool {
+        true
+    }
+}
+
+struct NatsSink {
+    config: Arc<Config>,
+    client: Arc<RwLock<Option<async_nats::Client>>>,
+    /// responses to requests are emitted via the source
+    tx: Sender<Received>,
+    reply_tx: Sender<AsyncSinkReply>,
+}
+
+/// sends the requests of an event and forwards their responses to the source
+async fn send_requests(
+    ctx: SinkContext,
+    client: async_nats::Client,
+    requests: Vec<(String, HeaderMap, Vec<u8>)>,
+    correlation: Option<Value<'static>>,
+    tx: Sender<Received>,
+) -> Result<()> {
+    for (subject, headers, payload) in requests {
+        let request = Request::new().headers(headers).payload(payload.into());
+        match client.send_request(subject, request).await {
+            Ok(message) => {
+                tx.send(Received::Response {
+                    message,
+                    correlation: correlation.clone(),
+                })
+                .await?;
+            }
+            Err(e) => {
+                error!("{ctx} NATS request failed: {e}");
+                return Err(nats_err(e));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// waits for `JetStream` to store all messages of an event
+async fn wait_for_acks(ctx: SinkContext, acks: Vec<PublishAckFuture>) -> Result<()> {
+    for ack in acks {
+        if let Err(e) = ack.await {
+            error!("{ctx} Error publishing to JetStream: {e}");
+            return Err(nats_err(e));
+        }
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Sink for NatsSink {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        event: Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        start: u64,
+    ) -> Result<SinkReply> {
+        let client = self
+            .client
+            .read()
+            .await
+            .clone()
+            .ok_or(ErrorKind::ClientNotAvailable(
+                "NATS",
+                "The client is not connected",
+            ))?;
+        let ingest_ns = event.ingest_ns;
+        let mut messages = Vec::with_capacity(event.len());
+        for (value, meta) in event.value_meta_iter() {
+            let nats_meta = ctx.extract_meta(meta);
+            let subject = nats_meta
+                .get_str("subject")
+                .or(self.config.subject.as_deref())
+                .ok_or("No subject given in `$nats.subject` or the `subject` config")?;
+            let headers = value_to_headers(nats_meta.get_object("headers"));
+            for payload in serializer.serialize(value, ingest_ns)? {
+                messages.push((subject.to_string(), headers.clone(), payload));
+            }
+        }
+
+        let cf_data = if event.transactional {
+            Some(ContraflowData::from(&event))
+        } else {
+            None
+        };
+        if self.config.request {
+            // take the correlation metadata from the first element of the batch
+            let correlation = event
+                .value_meta_iter()
+                .next()
+                .and_then(|(_, meta)| meta.get("correlation"))
+                .map(Value::clone_static);
+            let task = send_requests(ctx.clone(), client, messages, correlation, self.tx.clone());
+            task::spawn(reply(
+                ctx.clone(),
+                task,
+                cf_data,
+                start,
+                self.reply_tx.clone(),
+            ));
+        } else if self.config.jetstream.is_some() {
+            let context = jetstream::new(client);
+            let mut acks = Vec::with_capacity(messages.len());
+            for (subject, headers, payload) in messages {
+                acks.push(
+                    context
+                        .publish_with_headers(subject, headers, payload.into())
+                        .await
+                        .map_err(nats_err)?,
+                );
+            }
+            let task = wait_for_acks(ctx.clone(), acks);
+            task::spawn(reply(
+                ctx.clone(),
+                task,
+                cf_data,
+                start,
+                self.reply_tx.clone(),
+            ));
+        } else {
+            for (subject, headers, payload) in messages {
+                client
+                    .publish_with_headers(subject, headers, payload.into())
+                    .await
+                    .map_err(nats_err)?;
+            }
+            return Ok(SinkReply::ACK);
+        }
+        Ok(SinkReply::NONE)
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn asynchronous(&self) -> bool {
+        true
+    }
+}
+
+/// acks or fails the event depending on the result of `task`
+async fn reply<F>(
+    ctx: SinkContext,
+    task: F,
+    cf_data: Option<ContraflowData>,
+    start: u64,
+    reply_tx: Sender<AsyncSinkReply>,
+) where
+    F: std::future::Future<Output = Result<()>>,
+{
+    let res = task.await;
+    if let Some(cf_data) = cf_data {
+        let reply = if res.is_ok() {
+            AsyncSinkReply::Ack(cf_data, nanotime() - start)
+        } else {
+            AsyncSinkReply::Fail(cf_data)
+        };
+        ctx.swallow_err(reply_tx.send(reply).await, "Error sending contraflow");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_roundtrip() {
+        let headers = literal!({
+            "snot": ["badger"],
+            "multi": ["a", "b"]
+        });
+        let header_map = value_to_headers(headers.as_object());
+        assert_eq!(Some("badger"), header_map.get("snot").map(|v| v.as_str()));
+        assert_eq!(headers, headers_to_value(Some(&header_map)));
+
+        // single values are sent as single header values
+        let header_map = value_to_headers(literal!({"snot": "badger"}).as_object());
+        assert_eq!(
+            literal!({"snot": ["badger"]}),
+            headers_to_value(Some(&header_map))
+        );
+        assert_eq!(Value::object(), headers_to_value(None));
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of the `pgoutput` logical replication protocol (version 1)
+//!
+//! See <https://www.postgresql.org/docs/current/protocol-logicalrep-message-formats.html>
+
+use crate::errors::{Error, Result};
+use beef::Cow;
+use tremor_value::{Object, Value};
+use value_trait::Builder;
+
+// type oids of the values we don't emit as strings
+const BOOL: u32 = 16;
+const INT8: u32 = 20;
+const INT2: u32 = 21;
+const INT4: u32 = 23;
+const OID: u32 = 26;
+const JSON: u32 = 114;
+const FLOAT4: u32 = 700;
+const FLOAT8: u32 = 701;
+const JSONB: u32 = 3802;
+
+/// A column of a relation
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) type_oid: u32,
+}
+
+/// A table, sent before the first change to it in every decoding session
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Relation {
+    pub(crate) id: u32,
+    pub(crate) namespace: String,
+    pub(crate) name: String,
+    pub(crate) columns: Vec<Column>,
+}
+
+/// The value of a column within a tuple
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Datum {
+    Null,
+    /// an unchanged TOASTed value, it is not sent again
+    Unchanged,
+    /// the value in its text representation
+    Text(String),
+}
+
+/// A logical replication message
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Message {
+    Begin {
+        xid: u32,
+    },
+    Commit {
+        /// the end of the transaction, the slot can be advanced to it once all its changes are handled
+        end_lsn: u64,
+    },
+    Relation(Relation),
+    Insert {
+        relation: u32,
+        new: Vec<Datum>,
+    },
+    Update {
+        relation: u32,
+        /// the old key or the whole old row, depending on the replica identity of the table
+        old: Option<Vec<Datum>>,
+        new: Vec<Datum>,
+    },
+    Delete {
+        relation: u32,
+        old: Vec<Datum>,
+    },
+    Truncate {
+        relations: Vec<u32>,
+    },
+    /// origin, type and generic messages
+    Other,
+}
+
+struct Reader<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> Reader<'d> {
+    fn take(&mut self, n: usize) -> Result<&'d [u8]> {
+        if self.data.len() < n {
+            return Err("Truncated pgoutput message".into());
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let mut buf = [0_u8; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut buf = [0_u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut buf = [0_u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let end = self
+            .data
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("Unterminated string in pgoutput message")?;
+        let s = String::from_utf8(self.take(end)?.to_vec())?;
+        self.take(1)?;
+        Ok(s)
+    }
+
+    fn tuple(&mut self) -> Result<Vec<Datum>> {
+        let n = self.u16()?;
+        (0..n)
+            .map(|_| match self.u8()? {
+                b'n' => Ok(Datum::Null),
+                b'u' => Ok(Datum::Unchanged),
+                b't' => {
+                    let len = self.u32()? as usize;
+                    Ok(Datum::Text(String::from_utf8(self.take(len)?.to_vec())?))
+                }
+                kind => Err(format!("Invalid tuple data kind `{}`", char::from(kind)).into()),
+            })
+            .collect()
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<()> {
+        let found = self.u8()?;
+        if found == tag {
+            Ok(())
+        } else {
+            Err(format!(
+                "Expected `{}` in pgoutput message, found `{}`",
+                char::from(tag),
+                char::from(found)
+            )
+            .into())
+        }
+    }
+}
+
+/// Decodes a single `pgoutput` message
+pub(crate) fn decode(data: &[u8]) -> Result<Message> {
+    let mut r = Reader { data };
+    Ok(match r.u8()? {
+        b'B' => {
+            let _final_lsn = r.u64()?;
+            let _commit_ts = r.u64()?;
+            Message::Begin { xid: r.u32()? }
+        }
+        b'C' => {
+            let _flags = r.u8()?;
+            let _commit_lsn = r.u64()?;
+            Message::Commit { end_lsn: r.u64()? }
+        }
+        b'R' => {
+            let id = r.u32()?;
+            let namespace = r.string()?;
+            let name = r.string()?;
+            let _replica_identity = r.u8()?;
+            let n = r.u16()?;
+            let columns = (0..n)
+                .map(|_| {
+                    let _flags = r.u8()?;
+                    let name = r.string()?;
+                    let type_oid = r.u32()?;
+                    let _type_modifier = r.u32()?;
+                    Ok(Column { name, type_oid })
+                })
+                .collect::<Result<_>>()?;
+            Message::Relation(Relation {
+                id,
+                namespace,
+                name,
+                columns,
+            })
+        }
+        b'I' => {
+            let relation = r.u32()?;
+            r.expect(b'N')?;
+            Message::Insert {
+                relation,
+                new: r.tuple()?,
+            }
+        }
+        b'U' => {
+            let relation = r.u32()?;
+            let old = match r.u8()? {
+                b'K' | b'O' => {
+                    let old = r.tuple()?;
+                    r.expect(b'N')?;
+                    Some(old)
+                }
+                b'N' => None,
+                kind => return Err(format!("Invalid update tuple `{}`", char::from(kind)).into()),
+            };
+            Message::Update {
+                relation,
+                old,
+                new: r.tuple()?,
+            }
+        }
+        b'D' => {
+            let relation = r.u32()?;
+            match r.u8()? {
+                b'K' | b'O' => Message::Delete {
+                    relation,
+                    old: r.tuple()?,
+                },
+                kind => return Err(format!("Invalid delete tuple `{}`", char::from(kind)).into()),
+            }
+        }
+        b'T' => {
+            let n = r.u32()?;
+            let _options = r.u8()?;
+            Message::Truncate {
+                relations: (0..n).map(|_| r.u32()).collect::<Result<_>>()?,
+            }
+        }
+        b'O' | b'Y' | b'M' => Message::Other,
+        tag => {
+            return Err(Error::from(format!(
+                "Unknown pgoutput message `{}`",
+                char::from(tag)
+            )))
+        }
+    })
+}
+
+/// Converts the text representation of a value according to its type,
+/// values without a native representation stay strings
+fn datum_value(type_oid: u32, text: String) -> Value<'static> {
+    match type_oid {
+        BOOL => Value::from(text == "t"),
+        INT2 | INT4 | INT8 | OID => text
+            .parse::<i64>()
+            .map_or_else(|_| Value::from(text), Value::from),
+        FLOAT4 | FLOAT8 => text
+            .parse::<f64>()
+            .map_or_else(|_| Value::from(text), Value::from),
+        JSON | JSONB => {
+            let mut bytes = text.clone().into_bytes();
+            tremor_value::parse_to_value(&mut bytes)
+                .map_or_else(|_| Value::from(text), |v| v.into_static())
+        }
+        _ => Value::from(text),
+    }
+}
+
+/// Turns a tuple into a record of its columns, unchanged TOASTed values are left out
+pub(crate) fn tuple_value(relation: &Relation, tuple: Vec<Datum>) -> Value<'static> {
+    let mut record = Object::with_capacity(tuple.len());
+    for (column, datum) in relation.columns.iter().zip(tuple) {
+        let value = match datum {
+            Datum::Null => Value::null(),
+            Datum::Unchanged => continue,
+            Datum::Text(text) => datum_value(column.type_oid, text),
+        };
+        record.insert(Cow::from(column.name.clone()), value);
+    }
+    Value::from(record)
+}
+
+/// Formats an LSN the way postgres does, e.g. `16/B374D848`
+pub(crate) fn format_lsn(lsn: u64) -> String {
+    format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tremor_value::literal;
+
+    fn relation_message() -> Vec<u8> {
+        let mut data = vec![b'R'];
+        data.extend_from_slice(&16384_u32.to_be_bytes());
+        data.extend_from_slice(b"public\0events\0d");
+        data.extend_from_slice(&3_u16.to_be_bytes());
+        for (name, oid) in [("id", INT4), ("body", 25_u32), ("doc", JSONB)] {
+            data.push(1);
+            data.extend_from_slice(name.as_bytes());
+            data.push(0);
+            data.extend_from_slice(&oid.to_be_bytes());
+            data.extend_from_slice(&u32::MAX.to_be_bytes());
+        }
+        data
+    }
+
+    fn text(data: &mut Vec<u8>, s: &str) {
+        data.push(b't');
+        data.extend_from_slice(&u32::try_from(s.len()).unwrap_or_default().to_be_bytes());
+        data.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn relation() -> Result<()> {
+        let relation = match decode(&relation_message())? {
+            Message::Relation(relation) => relation,
+            other => return Err(format!("Not a relation: {other:?}").into()),
+        };
+        assert_eq!(16384, relation.id);
+        assert_eq!("public", relation.namespace);
+        assert_eq!("events", relation.name);
+        assert_eq!(
+            vec!["id", "body", "doc"],
+            relation
+                .columns
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn changes() -> Result<()> {
+        let relation = match decode(&relation_message())? {
+            Message::Relation(relation) => relation,
+            other => return Err(format!("Not a relation: {other:?}").into()),
+        };
+
+        let mut data = vec![b'U'];
+        data.extend_from_slice(&16384_u32.to_be_bytes());
+        data.push(b'K');
+        data.extend_from_slice(&3_u16.to_be_bytes());
+        text(&mut data, "1");
+        data.push(b'n');
+        data.push(b'n');
+        data.push(b'N');
+        data.extend_from_slice(&3_u16.to_be_bytes());
+        text(&mut data, "2");
+        data.push(b'u');
+        text(&mut data, r#"{"snot": "badger"}"#);
+        let (old, new) = match decode(&data)? {
+            Message::Update {
+                relation: 16384,
+                old: Some(old),
+                new,
+            } => (old, new),
+            other => return Err(format!("Not an update: {other:?}").into()),
+        };
+        assert_eq!(
+            literal!({"id": 1, "body": null, "doc": null}),
+            tuple_value(&relation, old)
+        );
+        assert_eq!(
+            literal!({"id": 2, "doc": {"snot": "badger"}}),
+            tuple_value(&relation, new)
+        );
+
+        let mut data = vec![b'D'];
+        data.extend_from_slice(&16384_u32.to_be_bytes());
+        data.push(b'X');
+        assert!(decode(&data).is_err());
+        // truncated
+        assert!(decode(&data[..3]).is_err());
+
+        let mut data = vec![b'C', 0];
+        data.extend_from_slice(&1_u64.to_be_bytes());
+        data.extend_from_slice(&0x16_B374_D848_u64.to_be_bytes());
+        data.extend_from_slice(&0_u64.to_be_bytes());
+        assert_eq!(
+            Message::Commit {
+                end_lsn: 0x16_B374_D848
+            },
+            decode(&data)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lsn() {
+        assert_eq!("16/B374D848", format_lsn(0x16_B374_D848));
+        assert_eq!("0/0", format_lsn(0));
+    }
+}
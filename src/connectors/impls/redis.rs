@@ -0,0 +1,838 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Redis connector - streams, pub/sub and key-value commands
+//!
+//! The source reads the configured `streams` as member of a consumer group, emitting every
+//! entry as a record of its fields with its `stream` and `id` as `$redis` metadata.
+//! Entries are acknowledged with `XACK` once their event is acked, entries of failed events
+//! are emitted again. Entries which were delivered but never acknowledged before a restart
+//! or a reconnect are read again on connect, entries read before a reconnect are dropped
+//! instead of being acknowledged or emitted again. It also emits every message published to
+//! the channels in `subscribe` and the patterns in `psubscribe`, with its `channel` and `pattern`.
+//!
+//! The sink executes the command described in the `$redis` metadata of every event,
+//! in the style of the `kv` connector. Replies are emitted via the `out` port, together with
+//! the `$correlation` metadata of the command, errors are emitted via the `err` port.
+//! Values stored by `set` and `hset` are serialized with the configured codec,
+//! the replies of `get` and `hget` are decoded with it.
+
+use crate::connectors::prelude::*;
+use crate::errors::err_conector_def;
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::sync::RwLock;
+use async_std::task::JoinHandle;
+use beef::Cow;
+use futures::StreamExt;
+use redis::aio::{Connection, MultiplexedConnection, PubSub};
+use redis::streams::{StreamReadOptions, StreamReadReply};
+use redis::{AsyncCommands, Client, Msg, RedisError};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+const URL_SCHEME: &str = "tremor-redis";
+
+#[derive(Debug)]
+enum Command {
+    /// Format:
+    /// ```json
+    /// {"get": "the-key"}
+    /// ```
+    ///
+    /// Response: the value of "the-key" or `null`
+    Get { key: Vec<u8> },
+    /// Format:
+    /// ```json
+    /// {"set": "the-key", "ex": 60}
+    /// ```
+    /// Event Payload: the value to set, `ex` is an optional expiry in seconds
+    ///
+    /// Response: `"OK"`
+    Set { key: Vec<u8>, ex: Option<u64> },
+    /// Format:
+    /// ```json
+    /// {"hget": "the-key", "field": "the-field"}
+    /// ```
+    ///
+    /// Response: the value of "the-field" in the hash "the-key" or `null`
+    HGet { key: Vec<u8>, field: Vec<u8> },
+    /// Format:
+    /// ```json
+    /// {"hset": "the-key", "field": "the-field"}
+    /// ```
+    /// Event Payload: the value to set
+    ///
+    /// Response: the number of added fields
+    HSet { key: Vec<u8>, field: Vec<u8> },
+    /// Format:
+    /// ```json
+    /// {"xadd": "the-stream", "id": "*", "maxlen": 1000}
+    /// ```
+    /// Event Payload: a record of the entry fields, `id` defaults to `*`,
+    /// `maxlen` optionally trims the stream to about that many entries
+    ///
+    /// Response: the id of the added entry
+    XAdd {
+        key: Vec<u8>,
+        id: String,
+        maxlen: Option<u64>,
+    },
+    /// Format:
+    /// ```json
+    /// {"expire": "the-key", "seconds": 60}
+    /// ```
+    ///
+    /// Response: `1` if the expiry was set, `0` if the key doesn't exist
+    Expire { key: Vec<u8>, seconds: u64 },
+    /// Format:
+    /// ```json
+    /// {"del": "the-key"}
+    /// ```
+    ///
+    /// Response: the number of deleted keys
+    Del { key: Vec<u8> },
+}
+
+impl<'v> TryFrom<&'v Value<'v>> for Command {
+    type Error = crate::Error;
+
+    fn try_from(v: &'v Value<'v>) -> Result<Self> {
+        let v = v
+            .get("redis")
+            .ok_or("Missing `$redis` field for commands")?;
+        let field = || {
+            v.get_bytes("field")
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| Error::from("Missing `field`"))
+        };
+        if let Some(key) = v.get_bytes("get").map(<[u8]>::to_vec) {
+            Ok(Command::Get { key })
+        } else if let Some(key) = v.get_bytes("set").map(<[u8]>::to_vec) {
+            Ok(Command::Set {
+                key,
+                ex: v.get_u64("ex"),
+            })
+        } else if let Some(key) = v.get_bytes("hget").map(<[u8]>::to_vec) {
+            Ok(Command::HGet {
+                key,
+                field: field()?,
+            })
+        } else if let Some(key) = v.get_bytes("hset").map(<[u8]>::to_vec) {
+            Ok(Command::HSet {
+                key,
+                field: field()?,
+            })
+        } else if let Some(key) = v.get_bytes("xadd").map(<[u8]>::to_vec) {
+            Ok(Command::XAdd {
+                key,
+                id: v.get_str("id").unwrap_or("*").to_string(),
+                maxlen: v.get_u64("maxlen"),
+            })
+        } else if let Some(key) = v.get_bytes("expire").map(<[u8]>::to_vec) {
+            Ok(Command::Expire {
+                key,
+                seconds: v.get_u64("seconds").ok_or("Missing `seconds`")?,
+            })
+        } else if let Some(key) = v.get_bytes("del").map(<[u8]>::to_vec) {
+            Ok(Command::Del { key })
+        } else {
+            Err(format!("Invalid Redis command: {}", v).into())
+        }
+    }
+}
+
+impl Command {
+    fn op_name(&self) -> &'static str {
+        match self {
+            Command::Get { .. } => "get",
+            Command::Set { .. } => "set",
+            Command::HGet { .. } => "hget",
+            Command::HSet { .. } => "hset",
+            Command::XAdd { .. } => "xadd",
+            Command::Expire { .. } => "expire",
+            Command::Del { .. } => "del",
+        }
+    }
+
+    fn key(&self) -> &[u8] {
+        match self {
+            Command::Get { key }
+            | Command::Set { key, .. }
+            | Command::HGet { key, .. }
+            | Command::HSet { key, .. }
+            | Command::XAdd { key, .. }
+            | Command::Expire { key, .. }
+            | Command::Del { key } => key,
+        }
+    }
+
+    /// `get` and `hget` replies are decoded with the configured codec
+    fn decodes_reply(&self) -> bool {
+        matches!(self, Command::Get { .. } | Command::HGet { .. })
+    }
+}
+
+/// Reads a stream as member of a consumer group
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct StreamsConfig {
+    /// keys of the streams to read
+    keys: Vec<String>,
+    /// consumer group, it is created if it doesn't exist yet
+    group: String,
+    /// consumer name within the group, defaults to `tremor-<hostname>-<alias>`
+    #[serde(default = "Default::default")]
+    consumer: Option<String>,
+    /// id to start reading from when creating the group, `$` for new entries only
+    #[serde(default = "default_start")]
+    start: String,
+    /// maximum number of entries to read at once
+    #[serde(default = "default_count")]
+    count: usize,
+    /// time to wait for new entries in milliseconds, before checking again
+    #[serde(default = "default_block_ms")]
+    block_ms: usize,
+}
+
+fn default_start() -> String {
+    "$".to_string()
+}
+
+fn default_count() -> usize {
+    128
+}
+
+fn default_block_ms() -> usize {
+    1000
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// redis url, e.g. `redis://localhost:6379/0`
+    #[serde(default = "default_url")]
+    url: String,
+    /// streams to read
+    #[serde(default = "Default::default")]
+    streams: Option<StreamsConfig>,
+    /// channels to subscribe to
+    #[serde(default = "Default::default")]
+    subscribe: Vec<String>,
+    /// channel patterns to subscribe to
+    #[serde(default = "Default::default")]
+    psubscribe: Vec<String>,
+}
+
+impl ConfigImpl for Config {}
+
+fn default_url() -> String {
+    "redis://localhost:6379".to_string()
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Builder {}
+
+#[async_trait::async_trait]
+impl ConnectorBuilder for Builder {
+    fn connector_type(&self) -> ConnectorType {
+        "redis".into()
+    }
+
+    async fn build_cfg(
+        &self,
+        alias: &str,
+        _: &ConnectorConfig,
+        raw_config: &Value,
+    ) -> Result<Box<dyn Connector>> {
+        let config = Config::new(raw_config)?;
+        let client = Client::open(config.url.as_str())
+            .map_err(|e| err_conector_def(alias, &format!("Invalid `url`: {e}")))?;
+        let info = client.get_connection_info();
+        let origin_uri = EventOriginUri {
+            scheme: URL_SCHEME.to_string(),
+            host: info.addr.to_string(),
+            port: None,
+            path: vec![],
+        };
+        let consumer = config
+            .streams
+            .as_ref()
+            .and_then(|streams| streams.consumer.clone())
+            .unwrap_or_else(|| format!("tremor-{}-{}", hostname(), alias));
+        let (tx, rx) = bounded(QSIZE.load(Ordering::Relaxed));
+        Ok(Box::new(Redis {
+            config,
+            client,
+            consumer,
+            origin_uri,
+            connection: Arc::new(RwLock::new(None)),
+            session: Arc::new(AtomicU64::new(0)),
+            tx,
+            rx: Some(rx),
+            tasks: Vec::new(),
+        }))
+    }
+}
+
+/// Received by the source
+enum Received {
+    /// stream entry
+    Entry(Entry),
+    /// pub/sub message
+    Message(Msg),
+    /// reply to a command executed by the sink
+    Reply(SourceReply),
+}
+
+#[derive(Clone)]
+struct Entry {
+    /// the session of the connection the entry was read with
+    session: u64,
+    stream: String,
+    id: String,
+    fields: Value<'static>,
+}
+
+fn to_value(value: redis::Value) -> Value<'static> {
+    match value {
+        redis::Value::Nil => Value::null(),
+        redis::Value::Int(i) => Value::from(i),
+        redis::Value::Data(data) => match String::from_utf8(data) {
+            Ok(s) => Value::from(s),
+            Err(e) => Value::Bytes(e.into_bytes().into()),
+        },
+        redis::Value::Bulk(values) => {
+            Value::from(values.into_iter().map(to_value).collect::<Vec<_>>())
+        }
+        redis::Value::Status(s) => Value::from(s),
+        redis::Value::Okay => Value::from("OK"),
+    }
+}
+
+/// strings and bytes are stored as they are, other values as json
+fn to_field(value: &Value) -> Vec<u8> {
+    value
+        .as_bytes()
+        .map_or_else(|| value.encode().into_bytes(), <[u8]>::to_vec)
+}
+
+/// connection errors need a reconnect, others are command errors
+fn is_connection_error(e: &RedisError) -> bool {
+    e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal()
+}
+
+/// Reads the streams and forwards their entries to the source.
+/// Starts with the entries that were delivered to this consumer but never acknowledged.
+async fn streams_task(
+    ctx: ConnectorContext,
+    mut connection: Connection,
+    session: u64,
+    config: StreamsConfig,
+    consumer: String,
+    tx: Sender<Received>,
+) -> Result<()> {
+    let options = StreamReadOptions::default()
+        .group(&config.group, &consumer)
+        .count(config.count)
+        .block(config.block_ms);
+    // `0` reads the pending entries after the given id, `>` new entries
+    let mut ids: Vec<String> = config.keys.iter().map(|_| "0".to_string()).collect();
+    loop {
+        let reply: StreamReadReply =
+            match connection.xread_options(&config.keys, &ids, &options).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    error!("{ctx} Error reading Redis streams: {e}");
+                    ctx.notifier().connection_lost().await?;
+                    return Ok(());
+                }
+            };
+        for (key, id) in config.keys.iter().zip(ids.iter_mut()) {
+            if id == ">" {
+                continue;
+            }
+            // done with the pending entries of this stream once there are no more
+            *id = reply
+                .keys
+                .iter()
+                .find(|stream| &stream.key == key)
+                .and_then(|stream| stream.ids.last())
+                .map_or_else(|| ">".to_string(), |entry| entry.id.clone());
+        }
+        for stream in reply.keys {
+            for entry in stream.ids {
+                let fields = entry
+                    .map
+                    .into_iter()
+                    .map(|(k, v)| (Cow::from(k), to_value(v)))
+                    .collect::<Object>();
+                let entry = Entry {
+                    session,
+                    stream: stream.key.clone(),
+                    id: entry.id,
+                    fields: Value::from(fields),
+                };
+                tx.send(Received::Entry(entry)).await?;
+            }
+        }
+    }
+}
+
+/// Forwards pub/sub messages to the source
+async fn pubsub_task(ctx: ConnectorContext, pubsub: PubSub, tx: Sender<Received>) -> Result<()> {
+    let mut messages = Box::pin(pubsub.into_on_message());
+    while let Some(message) = messages.next().await {
+        tx.send(Received::Message(message)).await?;
+    }
+    error!("{ctx} Redis pub/sub connection lost");
+    ctx.notifier().connection_lost().await?;
+    Ok(())
+}
+
+/// Redis connector
+///
+/// Executing commands received via its sink and emitting stream entries,
+/// pub/sub messages and command replies via its source.
+pub(crate) struct Redis {
+    config: Config,
+    client: Client,
+    consumer: String,
+    origin_uri: EventOriginUri,
+    connection: Arc<RwLock<Option<MultiplexedConnection>>>,
+    /// the current session, every connection is a new one
+    session: Arc<AtomicU64>,
+    tx: Sender<Received>,
+    rx: Option<Receiver<Received>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+#[async_trait::async_trait]
+impl Connector for Redis {
+    async fn create_source(
+        &mut self,
+        source_context: SourceContext,
+        builder: SourceManagerBuilder,
+    ) -> Result<Option<SourceAddr>> {
+        let rx = self.rx.take().ok_or("Redis source already created")?;
+        let source = RedisSource {
+            group: self.config.streams.as_ref().map(|s| s.group.clone()),
+            origin_uri: self.origin_uri.clone(),
+            connection: self.connection.clone(),
+            session: self.session.clone(),
+            rx,
+            pending: HashMap::new(),
+            retry: VecDeque::new(),
+        };
+        builder.spawn(source, source_context).map(Some)
+    }
+
+    async fn create_sink(
+        &mut self,
+        sink_context: SinkContext,
+        builder: SinkManagerBuilder,
+    ) -> Result<Option<SinkAddr>> {
+        let sink = RedisSink {
+            origin_uri: self.origin_uri.clone(),
+            connection: self.connection.clone(),
+            tx: self.tx.clone(),
+        };
+        builder.spawn(sink, sink_context).map(Some)
+    }
+
+    async fn connect(&mut self, ctx: &ConnectorContext, _attempt: &Attempt) -> Result<bool> {
+        for task in self.tasks.drain(..) {
+            task.cancel().await;
+        }
+        let connection = self.client.get_multiplexed_async_std_connection().await?;
+        // the entries pending with the previous connection are read again with this one
+        let session = self.session.fetch_add(1, Ordering::AcqRel) + 1;
+        if let Some(streams) = &self.config.streams {
+            let mut reader = self.client.get_async_std_connection().await?;
+            for key in &streams.keys {
+                let created: redis::RedisResult<()> = reader
+                    .xgroup_create_mkstream(key, &streams.group, &streams.start)
+                    .await;
+                match created {
+                    Err(e) if e.code() != Some("BUSYGROUP") => return Err(e.into()),
+                    // the group already exists
+                    Ok(()) | Err(_) => (),
+                }
+            }
+            self.tasks.push(spawn_task(
+                ctx.clone(),
+                streams_task(
+                    ctx.clone(),
+                    reader,
+                    session,
+                    streams.clone(),
+                    self.consumer.clone(),
+                    self.tx.clone(),
+                ),
+            ));
+        }
+        if !self.config.subscribe.is_empty() || !self.config.psubscribe.is_empty() {
+            let mut pubsub = self.client.get_async_std_connection().await?.into_pubsub();
+            for channel in &self.config.subscribe {
+                pubsub.subscribe(channel).await?;
+            }
+            for pattern in &self.config.psubscribe {
+                pubsub.psubscribe(pattern).await?;
+            }
+            self.tasks.push(spawn_task(
+                ctx.clone(),
+                pubsub_task(ctx.clone(), pubsub, self.tx.clone()),
+            ));
+        }
+        *self.connection.write().await = Some(connection);
+        Ok(true)
+    }
+
+    async fn on_stop(&mut self, _ctx: &ConnectorContext) -> Result<()> {
+        self.connection.write().await.take();
+        for task in self.tasks.drain(..) {
+            task.cancel().await;
+        }
+        Ok(())
+    }
+
+    fn codec_requirements(&self) -> CodecReq {
+        CodecReq::Optional("json")
+    }
+}
+
+struct RedisSource {
+    group: Option<String>,
+    origin_uri: EventOriginUri,
+    connection: Arc<RwLock<Option<MultiplexedConnection>>>,
+    session: Arc<AtomicU64>,
+    rx: Receiver<Received>,
+    /// stream entries waiting for their event to be acked or failed
+    pending: HashMap<u64, Entry>,
+    /// stream entries of failed events, to be emitted again
+    retry: VecDeque<Entry>,
+}
+
+impl RedisSource {
+    /// entries of a previous session are read again with the current one
+    fn is_current(&self, entry: &Entry) -> bool {
+        entry.session == self.session.load(Ordering::Acquire)
+    }
+
+    fn entry_reply(&mut self, pull_id: u64, entry: Entry, ctx: &SourceContext) -> SourceReply {
+        let meta = ctx.meta(literal!({
+            "stream": entry.stream.clone(),
+            "id": entry.id.clone()
+        }));
+        let mut origin_uri = self.origin_uri.clone();
+        origin_uri.path = vec![entry.stream.clone()];
+        let payload = (entry.fields.clone(), meta).into();
+        self.pending.insert(pull_id, entry);
+        SourceReply::Structured {
+            origin_uri,
+            payload,
+            stream: DEFAULT_STREAM_ID,
+            port: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Source for RedisSource {
+    async fn pull_data(&mut self, pull_id: &mut u64, ctx: &SourceContext) -> Result<SourceReply> {
+        while let Some(entry) = self.retry.pop_front() {
+            if self.is_current(&entry) {
+                return Ok(self.entry_reply(*pull_id, entry, ctx));
+            }
+        }
+        let mut received = self.rx.recv().await?;
+        while matches!(&received, Received::Entry(entry) if !self.is_current(entry)) {
+            received = self.rx.recv().await?;
+        }
+        Ok(match received {
+            Received::Entry(entry) => self.entry_reply(*pull_id, entry, ctx),
+            Received::Message(message) => {
+                let pattern: Option<String> = if message.from_pattern() {
+                    message.get_pattern().ok()
+                } else {
+                    None
+                };
+                let mut origin_uri = self.origin_uri.clone();
+                origin_uri.path = vec![message.get_channel_name().to_string()];
+                SourceReply::Data {
+                    origin_uri,
+                    data: message.get_payload_bytes().to_vec(),
+                    meta: Some(ctx.meta(literal!({
+                        "channel": message.get_channel_name().to_string(),
+                        "pattern": pattern
+                    }))),
+                    stream: None,
+                    port: None,
+                    codec_overwrite: None,
+                }
+            }
+            Received::Reply(reply) => reply,
+        })
+    }
+
+    async fn ack(&mut self, _stream_id: u64, pull_id: u64, ctx: &SourceContext) -> Result<()> {
+        if let (Some(entry), Some(group)) = (self.pending.remove(&pull_id), &self.group) {
+            if !self.is_current(&entry) {
+                debug!("{ctx} Entry {} is read again after a reconnect", entry.id);
+            } else if let Some(mut connection) = self.connection.read().await.clone() {
+                let _acked: u64 = connection.xack(&entry.stream, group, &[&entry.id]).await?;
+            } else {
+                debug!("{ctx} Not connected, entry {} stays pending", entry.id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn fail(&mut self, _stream_id: u64, pull_id: u64, _ctx: &SourceContext) -> Result<()> {
+        if let Some(entry) = self.pending.remove(&pull_id) {
+            if self.is_current(&entry) {
+                self.retry.push_back(entry);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.group.is_some()
+    }
+
+    fn asynchronous(&self) -> bool {
+        true
+    }
+}
+
+struct RedisSink {
+    origin_uri: EventOriginUri,
+    connection: Arc<RwLock<Option<MultiplexedConnection>>>,
+    tx: Sender<Received>,
+}
+
+impl RedisSink {
+    async fn execute(
+        connection: &mut MultiplexedConnection,
+        cmd: &Command,
+        value: &Value<'_>,
+        ingest_ns: u64,
+        serializer: &mut EventSerializer,
+    ) -> Result<redis::Value> {
+        let mut serialize = |value: &Value| -> Result<Vec<u8>> {
+            Ok(serializer.serialize(value, ingest_ns)?.concat())
+        };
+        let redis_cmd = match cmd {
+            Command::Get { key } => {
+                let mut c = redis::cmd("GET");
+                c.arg(key);
+                c
+            }
+            Command::Set { key, ex } => {
+                let mut c = redis::cmd("SET");
+                c.arg(key).arg(serialize(value)?);
+                if let Some(ex) = ex {
+                    c.arg("EX").arg(*ex);
+                }
+                c
+            }
+            Command::HGet { key, field } => {
+                let mut c = redis::cmd("HGET");
+                c.arg(key).arg(field);
+                c
+            }
+            Command::HSet { key, field } => {
+                let mut c = redis::cmd("HSET");
+                c.arg(key).arg(field).arg(serialize(value)?);
+                c
+            }
+            Command::XAdd { key, id, maxlen } => {
+                let fields = value
+                    .as_object()
+                    .ok_or("The payload of `xadd` needs to be a record")?;
+                let mut c = redis::cmd("XADD");
+                c.arg(key);
+                if let Some(maxlen) = maxlen {
+                    c.arg("MAXLEN").arg("~").arg(*maxlen);
+                }
+                c.arg(id);
+                for (k, v) in fields.iter() {
+                    c.arg(k.as_bytes()).arg(to_field(v));
+                }
+                c
+            }
+            Command::Expire { key, seconds } => {
+                let mut c = redis::cmd("EXPIRE");
+                c.arg(key).arg(*seconds);
+                c
+            }
+            Command::Del { key } => {
+                let mut c = redis::cmd("DEL");
+                c.arg(key);
+                c
+            }
+        };
+        Ok(redis_cmd.query_async(connection).await?)
+    }
+
+    fn reply(&self, cmd: &Command, reply: redis::Value, meta: Value<'static>) -> SourceReply {
+        match reply {
+            redis::Value::Data(data) if cmd.decodes_reply() => SourceReply::Data {
+                origin_uri: self.origin_uri.clone(),
+                data,
+                meta: Some(meta),
+                stream: None,
+                port: Some(OUT),
+                codec_overwrite: None,
+            },
+            reply => SourceReply::Structured {
+                origin_uri: self.origin_uri.clone(),
+                payload: (to_value(reply), meta).into(),
+                stream: DEFAULT_STREAM_ID,
+                port: Some(OUT),
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for RedisSink {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        event: Event,
+        ctx: &SinkContext,
+        serializer: &mut EventSerializer,
+        _start: u64,
+    ) -> Result<SinkReply> {
+        let mut connection =
+            self.connection
+                .read()
+                .await
+                .clone()
+                .ok_or(ErrorKind::ClientNotAvailable(
+                    "Redis",
+                    "The client is not connected",
+                ))?;
+        let ingest_ns = event.ingest_ns;
+
+        let mut r = SinkReply::ACK;
+        for (v, m) in event.value_meta_iter() {
+            let correlation = m.get("correlation");
+            let executed = match Command::try_from(m) {
+                Ok(cmd) => {
+                    let name = cmd.op_name();
+                    let key = cmd.key().to_vec();
+                    match Self::execute(&mut connection, &cmd, v, ingest_ns, serializer).await {
+                        Ok(reply) => Ok((cmd, reply)),
+                        Err(e) => Err((Some(name), Some(key), e)),
+                    }
+                }
+                Err(e) => Err((None, None, e)),
+            };
+            let reply = match executed {
+                Ok((cmd, reply)) => {
+                    let mut meta = literal!({
+                        "redis": {
+                            "op": cmd.op_name(),
+                            "ok": Value::Bytes(cmd.key().to_vec().into())
+                        }
+                    });
+                    if let Some(correlation) = correlation {
+                        meta.try_insert("correlation", correlation.clone_static());
+                    }
+                    self.reply(&cmd, reply, meta)
+                }
+                Err((op, key, e)) => {
+                    if let ErrorKind::RedisError(redis_error) = e.kind() {
+                        if is_connection_error(redis_error) {
+                            error!("{ctx} Redis connection error: {e}");
+                            ctx.notifier().connection_lost().await?;
+                        }
+                    }
+                    // send ERR response and log err
+                    let mut meta = literal!({
+                        "error": e.to_string(),
+                        "redis": op.map(|op| literal!({ "op": op, "key": key.map(|key| Value::Bytes(key.into())) }))
+                    });
+                    if let Some(correlation) = correlation {
+                        meta.try_insert("correlation", correlation.clone_static());
+                    }
+                    r = SinkReply::FAIL;
+                    SourceReply::Structured {
+                        origin_uri: self.origin_uri.clone(),
+                        payload: ((), meta).into(),
+                        stream: DEFAULT_STREAM_ID,
+                        port: Some(ERR),
+                    }
+                }
+            };
+            if let Err(e) = self.tx.send(Received::Reply(reply)).await {
+                error!("{}, Failed to send to source: {}", &ctx, e);
+            };
+        }
+        Ok(r)
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() -> Result<()> {
+        let meta = literal!({"redis": {"set": "snot", "ex": 60}});
+        let cmd = Command::try_from(&meta)?;
+        assert_eq!("set", cmd.op_name());
+        assert_eq!(b"snot", cmd.key());
+        assert!(matches!(cmd, Command::Set { ex: Some(60), .. }));
+
+        let meta = literal!({"redis": {"xadd": "snot"}});
+        assert!(matches!(
+            Command::try_from(&meta)?,
+            Command::XAdd { id, maxlen: None, .. } if id == "*"
+        ));
+
+        let meta = literal!({"redis": {"hget": "snot"}});
+        assert!(Command::try_from(&meta).is_err());
+        let meta = literal!({"redis": {"expire": "snot"}});
+        assert!(Command::try_from(&meta).is_err());
+        let meta = literal!({"redis": {"snot": "badger"}});
+        assert!(Command::try_from(&meta).is_err());
+        let meta = literal!({"kv": {"get": "snot"}});
+        assert!(Command::try_from(&meta).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(Value::null(), to_value(redis::Value::Nil));
+        assert_eq!(Value::from("OK"), to_value(redis::Value::Okay));
+        assert_eq!(
+            literal!([1, "snot", Value::Bytes(vec![0xff].into())]),
+            to_value(redis::Value::Bulk(vec![
+                redis::Value::Int(1),
+                redis::Value::Data(b"snot".to_vec()),
+                redis::Value::Data(vec![0xff])
+            ]))
+        );
+        assert_eq!(b"snot".to_vec(), to_field(&Value::from("snot")));
+        assert_eq!(b"{\"snot\":1}".to_vec(), to_field(&literal!({"snot": 1})));
+    }
+}
@@ -13,26 +13,209 @@
 // limitations under the License.
 
 // #![cfg_attr(coverage, no_coverage)]
-use std::{sync::Arc, time::Duration};
+
+//! Write ahead log
+//!
+//! Events received by the sink are written to the WAL and emitted by the source, the sink notifies
+//! the source about every write. Entries are removed once the events emitted for them are acked,
+//! a failed event makes the source emit all unacknowledged entries again.
+//!
+//! Written entries are synced to disk according to `fsync`: after `always` write before the event
+//! is acked, every `fsync_interval_ms` for `interval` or `never`, leaving it to the OS. Only the
+//! files of the WAL that changed since the last sync are synced, acknowledgements lost in a crash
+//! make the source emit the affected entries again. With `always` an event is failed if its entry
+//! can't be synced, as it is in the WAL already it is emitted twice if it is sent again.
+//!
+//! The sink reports the number of entries not acknowledged yet as `depth` and the age of the oldest
+//! of them as `oldest_entry_age_ns` in the `wal` metric. Entries written before a restart are
+//! only accounted for once they are read again.
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use crate::connectors::prelude::*;
-use async_std::{sync::Mutex, task};
+use crate::connectors::utils::metrics::make_metrics_payload;
+use async_std::{
+    channel::{bounded, Receiver, Sender},
+    fs,
+    sync::Mutex,
+};
+use beef::Cow;
+use futures::StreamExt;
+use halfbrown::HashMap;
+use tremor_common::time::nanotime;
 
 use simd_json_derive::{Deserialize, Serialize};
 
+/// When to sync written entries to disk
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Fsync {
+    /// after every write
+    Always,
+    /// every `fsync_interval_ms`
+    Interval,
+    /// leave it to the OS
+    Never,
+}
+
+impl Default for Fsync {
+    fn default() -> Self {
+        Self::Never
+    }
+}
+
 #[derive(serde::Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     dir: String,
     chunk_size: u64,
     max_chunks: usize,
+    /// when to sync written entries to disk
+    #[serde(default)]
+    fsync: Fsync,
+    /// interval in milliseconds to sync written entries to disk with the `interval` policy
+    #[serde(default = "default_fsync_interval_ms")]
+    fsync_interval_ms: u64,
+}
+
+fn default_fsync_interval_ms() -> u64 {
+    1000
 }
 
 impl ConfigImpl for Config {}
 
+/// The WAL and what we know about its entries
+struct State {
+    wal: qwal::Wal,
+    dir: PathBuf,
+    /// write times of the entries not read yet, oldest first
+    unread: VecDeque<u64>,
+    /// ids and write times of the entries read but not acknowledged yet, oldest first
+    unacked: VecDeque<(u64, u64)>,
+    /// the files of the WAL as of the last sync
+    chunks: Vec<Chunk>,
+    /// if a file was created since the last sync
+    created: bool,
+    /// if there were writes since the last sync
+    dirty: bool,
+    synced: SystemTime,
+}
+
+/// A file of the WAL and its size when it was synced last
+struct Chunk {
+    path: PathBuf,
+    /// opened for writing, as not every platform allows syncing read-only files
+    file: fs::File,
+    len: u64,
+}
+
+impl State {
+    async fn push(&mut self, event: Event) -> Result<()> {
+        self.wal.push(Payload(event)).await?;
+        self.unread.push_back(nanotime());
+        self.dirty = true;
+        Ok(())
+    }
+
+    async fn pop(&mut self) -> Result<Option<(u64, Event)>> {
+        let entry = self.wal.pop::<Payload>().await?;
+        if let Some((id, event)) = entry.as_ref() {
+            // entries written before a restart are unknown, their events are the best guess
+            let written = self.unread.pop_front().unwrap_or(event.ingest_ns);
+            self.unacked.push_back((*id, written));
+        }
+        Ok(entry)
+    }
+
+    async fn ack(&mut self, id: u64) -> Result<()> {
+        self.wal.ack(id).await?;
+        while self
+            .unacked
+            .front()
+            .map_or(false, |(unacked, _)| *unacked <= id)
+        {
+            self.unacked.pop_front();
+        }
+        Ok(())
+    }
+
+    async fn revert(&mut self) -> Result<()> {
+        self.wal.revert().await?;
+        // all unacknowledged entries are read again
+        while let Some((_, written)) = self.unacked.pop_back() {
+            self.unread.push_front(written);
+        }
+        Ok(())
+    }
+
+    fn depth(&self) -> usize {
+        self.unread.len() + self.unacked.len()
+    }
+
+    fn oldest(&self) -> Option<u64> {
+        self.unacked
+            .front()
+            .map(|(_, written)| *written)
+            .or_else(|| self.unread.front().copied())
+    }
+
+    /// syncs the files of the WAL that changed since the last sync, qwal doesn't expose the files
+    /// it writes to, so they are told apart by their size
+    async fn sync(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let mut present = Vec::with_capacity(self.chunks.len());
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if !entry.file_type().await?.is_file() {
+                continue;
+            }
+            let path: PathBuf = entry.path().into();
+            let len = entry.metadata().await?.len();
+            if let Some(chunk) = self.chunks.iter_mut().find(|chunk| chunk.path == path) {
+                if chunk.len != len {
+                    chunk.file.sync_data().await?;
+                    chunk.len = len;
+                }
+            } else {
+                let file = fs::OpenOptions::new().write(true).open(&path).await?;
+                file.sync_data().await?;
+                self.chunks.push(Chunk {
+                    path: path.clone(),
+                    file,
+                    len,
+                });
+                self.created = true;
+            }
+            present.push(path);
+        }
+        // forget about the files qwal removed
+        self.chunks.retain(|chunk| present.contains(&chunk.path));
+        // new files need their directory entry to be synced as well
+        #[cfg(unix)]
+        if self.created {
+            fs::File::open(&self.dir).await?.sync_all().await?;
+        }
+        self.created = false;
+        self.dirty = false;
+        self.synced = SystemTime::now();
+        Ok(())
+    }
+}
+
 struct Wal {
     event_origin_uri: EventOriginUri,
-    wal: Arc<Mutex<qwal::Wal>>,
+    config: Config,
+    state: Arc<Mutex<State>>,
+    written_tx: Sender<()>,
+    written_rx: Receiver<()>,
 }
 
 #[derive(Debug, Default)]
@@ -58,17 +241,33 @@ impl ConnectorBuilder for Builder {
             path: config.dir.split('/').map(ToString::to_string).collect(),
         };
         let wal = qwal::Wal::open(&config.dir, config.chunk_size, config.max_chunks).await?;
+        let state = State {
+            wal,
+            dir: PathBuf::from(&config.dir),
+            unread: VecDeque::new(),
+            unacked: VecDeque::new(),
+            chunks: Vec::new(),
+            created: false,
+            dirty: false,
+            synced: SystemTime::now(),
+        };
+        // a pending notification is enough to wake up the source
+        let (written_tx, written_rx) = bounded(1);
 
         Ok(Box::new(Wal {
             event_origin_uri,
-            wal: Arc::new(Mutex::new(wal)),
+            config,
+            state: Arc::new(Mutex::new(state)),
+            written_tx,
+            written_rx,
         }))
     }
 }
 
 struct WalSource {
     origin_uri: EventOriginUri,
-    wal: Arc<Mutex<qwal::Wal>>,
+    state: Arc<Mutex<State>>,
+    written: Receiver<()>,
 }
 
 struct Payload(Event);
@@ -89,10 +288,8 @@ impl qwal::Entry for Payload {
 #[async_trait::async_trait]
 impl Source for WalSource {
     async fn pull_data(&mut self, pull_id: &mut u64, _ctx: &SourceContext) -> Result<SourceReply> {
-        // This is a busy loop until we get data to avoid hogging the cpu
-        // TODO: improve this by adding  notifyer on write
         loop {
-            if let Some((id, event)) = self.wal.lock().await.pop::<Payload>().await? {
+            if let Some((id, event)) = self.state.lock().await.pop().await? {
                 // the wal is creating its own ids, we take over here
                 *pull_id = id;
                 return Ok(SourceReply::Structured {
@@ -102,17 +299,18 @@ impl Source for WalSource {
                     port: None,
                 });
             }
-            task::sleep(Duration::from_millis(10)).await;
+            // wait for the sink to write the next entry
+            self.written.recv().await?;
         }
     }
 
     async fn ack(&mut self, _stream_id: u64, pull_id: u64, _ctx: &SourceContext) -> Result<()> {
-        self.wal.lock().await.ack(pull_id).await?;
+        self.state.lock().await.ack(pull_id).await?;
         Ok(())
     }
 
     async fn fail(&mut self, _stream_id: u64, _pull_id: u64, _ctx: &SourceContext) -> Result<()> {
-        self.wal.lock().await.revert().await?;
+        self.state.lock().await.revert().await?;
         Ok(())
     }
 
@@ -126,7 +324,10 @@ impl Source for WalSource {
 }
 
 struct WalSink {
-    wal: Arc<Mutex<qwal::Wal>>,
+    state: Arc<Mutex<State>>,
+    written: Sender<()>,
+    fsync: Fsync,
+    fsync_interval: u64,
 }
 
 #[async_trait::async_trait]
@@ -139,13 +340,56 @@ impl Sink for WalSink {
         &mut self,
         _input: &str,
         event: Event,
-        _ctx: &SinkContext,
+        ctx: &SinkContext,
         _serializer: &mut EventSerializer,
         _start: u64,
     ) -> Result<SinkReply> {
-        self.wal.lock().await.push(Payload(event)).await?;
+        let mut state = self.state.lock().await;
+        state.push(event).await?;
+        // if there is a notification pending already, the source will see this entry as well
+        let _ = self.written.try_send(());
+        if self.fsync == Fsync::Always {
+            if let Err(e) = state.sync().await {
+                error!("{ctx} Error syncing the WAL: {e}");
+                // the entry might not survive a crash, so the event isn't safe yet
+                return Ok(SinkReply::FAIL);
+            }
+        }
+        Ok(SinkReply::NONE)
+    }
+
+    async fn on_signal(
+        &mut self,
+        _signal: Event,
+        ctx: &SinkContext,
+        _serializer: &mut EventSerializer,
+    ) -> Result<SinkReply> {
+        if self.fsync == Fsync::Interval {
+            let mut state = self.state.lock().await;
+            let elapsed = state.synced.elapsed().unwrap_or_default();
+            if elapsed >= Duration::from_millis(self.fsync_interval) {
+                if let Err(e) = state.sync().await {
+                    error!("{ctx} Error syncing the WAL: {e}");
+                }
+            }
+        }
         Ok(SinkReply::NONE)
     }
+
+    async fn metrics(&mut self, timestamp: u64, ctx: &SinkContext) -> Vec<EventPayload> {
+        let state = self.state.lock().await;
+        let mut fields = HashMap::with_capacity(2);
+        fields.insert(Cow::const_str("depth"), Value::from(state.depth()));
+        if let Some(oldest) = state.oldest() {
+            fields.insert(
+                Cow::const_str("oldest_entry_age_ns"),
+                Value::from(nanotime().saturating_sub(oldest)),
+            );
+        }
+        let mut tags = HashMap::with_capacity(1);
+        tags.insert(Cow::const_str("connector"), Value::from(ctx.alias.clone()));
+        vec![make_metrics_payload("wal", fields, tags, timestamp)]
+    }
 }
 
 #[async_trait::async_trait]
@@ -156,8 +400,9 @@ impl Connector for Wal {
         builder: SourceManagerBuilder,
     ) -> Result<Option<SourceAddr>> {
         let s = WalSource {
-            wal: self.wal.clone(),
+            state: self.state.clone(),
             origin_uri: self.event_origin_uri.clone(),
+            written: self.written_rx.clone(),
         };
         builder.spawn(s, source_context).map(Some)
     }
@@ -168,13 +413,20 @@ impl Connector for Wal {
         builder: SinkManagerBuilder,
     ) -> Result<Option<SinkAddr>> {
         let s = WalSink {
-            wal: self.wal.clone(),
+            state: self.state.clone(),
+            written: self.written_tx.clone(),
+            fsync: self.config.fsync,
+            fsync_interval: self.config.fsync_interval_ms,
         };
         builder.spawn(s, sink_context).map(Some)
     }
 
     async fn on_stop(&mut self, _ctx: &ConnectorContext) -> Result<()> {
-        self.wal.lock().await.preserve_ack().await?;
+        let mut state = self.state.lock().await;
+        state.wal.preserve_ack().await?;
+        if self.config.fsync != Fsync::Never {
+            state.sync().await?;
+        }
         Ok(())
     }
 
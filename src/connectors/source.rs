@@ -40,7 +40,7 @@ use crate::connectors::{
     utils::reconnect::{Attempt, ConnectionLostNotifier},
     ConnectorType, Context, Msg, QuiescenceBeacon, StreamDone,
 };
-use crate::errors::{Error, Result};
+use crate::errors::{Error, Kind as ErrorKind, Result};
 use crate::pipeline;
 use crate::preprocessor::{finish, make_preprocessors, preprocess, Preprocessors};
 use crate::{
@@ -86,6 +86,8 @@ pub(crate) enum SourceMsg {
     Stop(Sender<Result<()>>),
     /// drain the source - bears a sender for sending out a SourceDrained status notification
     Drain(Sender<Msg>),
+    /// seek to a connector specific position and send the result back
+    Seek(Value<'static>, Sender<Result<()>>),
     #[cfg(test)]
     Ping(Sender<()>),
 }
@@ -170,6 +172,20 @@ pub(crate) trait Source: Send {
         Ok(())
     }
 
+    /// This callback is called when the data provided from
+    /// pull_event created events, with the number of acks or fails to expect for them,
+    /// one for every event and pipeline it is sent to. This is needed for sources
+    /// that only consider data delivered once all of its events are acknowledged
+    async fn on_events(
+        &mut self,
+        _pull_id: u64,
+        _stream: u64,
+        _expected_replies: usize,
+        _ctx: &SourceContext,
+    ) -> Result<()> {
+        Ok(())
+    }
+
     /// Pulls custom metrics from the source
     fn metrics(&mut self, _timestamp: u64, _ctx: &SourceContext) -> Vec<EventPayload> {
         vec![]
@@ -204,6 +220,14 @@ pub(crate) trait Source: Send {
     async fn on_resume(&mut self, _ctx: &SourceContext) -> Result<()> {
         Ok(())
     }
+    /// called when the source is asked to continue reading from the given connector specific position,
+    /// e.g. to replay data. Sources not supporting this return an error.
+    async fn on_seek(&mut self, _position: &Value<'static>, ctx: &SourceContext) -> Result<()> {
+        Err(
+            ErrorKind::InvalidSeek(ctx.alias.clone(), "Seeking is not supported".to_string())
+                .into(),
+        )
+    }
     /// called when the source is stopped. This happens only once in the whole source lifecycle, as the very last callback
     async fn on_stop(&mut self, _ctx: &SourceContext) -> Result<()> {
         Ok(())
@@ -675,6 +699,20 @@ where
                 Control::Terminate
             }
             SourceMsg::Drain(drained_sender) => self.handle_drain(drained_sender).await,
+            SourceMsg::Seek(position, sender) => {
+                let res = if matches!(self.state, Running | Paused) {
+                    self.source.on_seek(&position, &self.ctx).await
+                } else {
+                    Err(ErrorKind::InvalidSeek(
+                        self.ctx.alias.clone(),
+                        format!("Cannot seek in {state} state"),
+                    )
+                    .into())
+                };
+                let res = sender.send(res).await;
+                self.ctx.swallow_err(res, "Error sending Seek reply");
+                Control::Continue
+            }
             SourceMsg::ConnectionLost => {
                 self.connectivity = Connectivity::Disconnected;
                 let res = self.source.on_connection_lost(&self.ctx).await;
@@ -916,6 +954,22 @@ where
         send_error
     }
 
+    /// the number of acks or fails to expect for the given events, one for every pipeline they are sent to
+    fn expected_replies(&self, events: &[(Cow<'static, str>, Event)]) -> usize {
+        events
+            .iter()
+            .map(|(port, _)| {
+                if port.eq_ignore_ascii_case(OUT.as_ref()) {
+                    self.pipelines_out.len()
+                } else if port.eq_ignore_ascii_case(ERR.as_ref()) {
+                    self.pipelines_err.len()
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+
     /// should this manager pull data from its source?
     fn should_pull_data(&mut self) -> bool {
         // asynchronous sources need to be drained from their asynchronous task which consumes from
@@ -1024,6 +1078,12 @@ where
                     .await;
                 self.ctx.swallow_err(res, "Error on no events callback");
             } else {
+                let expected_replies = self.expected_replies(&results);
+                let res = self
+                    .source
+                    .on_events(pull_id, stream_id, expected_replies, &self.ctx)
+                    .await;
+                self.ctx.swallow_err(res, "Error on events callback");
                 let error = self.route_events(results).await;
                 if error {
                     self.ctx.swallow_err(
@@ -1092,6 +1152,12 @@ where
                 let expr = self.source.on_no_events(pull_id, stream, &self.ctx).await;
                 self.ctx.swallow_err(expr, "Error on no events callback");
             } else {
+                let expected_replies = self.expected_replies(&results);
+                let res = self
+                    .source
+                    .on_events(pull_id, stream, expected_replies, &self.ctx)
+                    .await;
+                self.ctx.swallow_err(res, "Error on events callback");
                 let error = self.route_events(results).await;
                 if error {
                     self.ctx.swallow_err(
@@ -1135,6 +1201,12 @@ where
                     .await;
                 self.ctx.swallow_err(res, "Error on no events callback");
             } else {
+                let expected_replies = self.expected_replies(&results);
+                let res = self
+                    .source
+                    .on_events(pull_id, DEFAULT_STREAM_ID, expected_replies, &self.ctx)
+                    .await;
+                self.ctx.swallow_err(res, "Error on events callback");
                 let error = self.route_events(results).await;
                 if error {
                     self.ctx.swallow_err(
@@ -0,0 +1,149 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{free_port::find_free_tcp_port, ConnectorHarness};
+use crate::connectors::impls::clickhouse;
+use crate::errors::Result;
+use async_std::task;
+use clickhouse_rs::{ClientHandle, Pool};
+use serial_test::serial;
+use std::time::{Duration, Instant};
+use testcontainers::{clients::Cli as DockerCli, images::generic::GenericImage, RunnableImage};
+use tremor_common::ports::IN;
+use tremor_pipeline::{CbAction, Event, EventId};
+use tremor_value::{literal, Value};
+use value_trait::Builder;
+
+const IMAGE: &str = "clickhouse/clickhouse-server";
+const VERSION: &str = "22.3";
+
+/// connects once the server accepts connections
+async fn connect(url: &str) -> Result<ClientHandle> {
+    let pool = Pool::new(url);
+    let start = Instant::now();
+    loop {
+        match pool.get_handle().await {
+            Ok(handle) => return Ok(handle),
+            Err(e) if start.elapsed() > Duration::from_secs(60) => return Err(e.into()),
+            Err(_) => task::sleep(Duration::from_millis(500)).await,
+        }
+    }
+}
+
+async fn count(client: &mut ClientHandle) -> Result<u64> {
+    let block = client
+        .query("SELECT count() AS c FROM events")
+        .fetch_all()
+        .await?;
+    Ok(block.get(0, "c")?)
+}
+
+fn event(id: u64, value: Value<'static>) -> Event {
+    Event {
+        id: EventId::from_id(1, 1, id),
+        data: (value, Value::object()).into(),
+        transactional: true,
+        ..Event::default()
+    }
+}
+
+#[async_std::test]
+#[serial(clickhouse)]
+async fn connector_clickhouse_batched_inserts() -> Result<()> {
+    let _ = env_logger::try_init();
+    let docker = DockerCli::default();
+    let port = find_free_tcp_port().await?;
+    let image =
+        RunnableImage::from(GenericImage::new(IMAGE, VERSION)).with_mapped_port((port, 9000_u16));
+    let _container = docker.run(image);
+
+    let url = format!("tcp://127.0.0.1:{port}/default");
+    let mut client = connect(&url).await?;
+    client
+        .execute(
+            "CREATE TABLE events (id UInt64, name String, tags Array(String), ts DateTime64(3), maybe Nullable(Int32), status String DEFAULT 'new') ENGINE = Memory",
+        )
+        .await?;
+
+    let defn = literal!({
+        "config": {
+            "url": url,
+            "table": "events",
+            "batch_size": 2,
+            "flush_interval_ms": 500
+        }
+    });
+    let harness =
+        ConnectorHarness::new(function_name!(), &clickhouse::Builder::default(), &defn).await?;
+    let in_pipe = harness.get_pipe(IN).expect("No pipe connected to port IN");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    // inserted once the batch is full
+    harness
+        .send_to_sink(
+            event(
+                1,
+                literal!({
+                    "id": 1,
+                    "name": "snot",
+                    "tags": ["snot", "badger"],
+                    "ts": "2022-01-01T00:00:00.123Z",
+                    "maybe": 42,
+                    "status": "done"
+                }),
+            ),
+            IN,
+        )
+        .await?;
+    harness
+        .send_to_sink(event(2, literal!({"id": 2, "name": "badger"})), IN)
+        .await?;
+    for id in 1..=2 {
+        let cf = in_pipe.get_contraflow().await?;
+        assert_eq!(CbAction::Ack, cf.cb);
+        assert_eq!(EventId::from_id(1, 1, id), cf.id);
+    }
+    assert_eq!(2, count(&mut client).await?);
+    // missing fields of columns with a default expression get their default
+    let block = client
+        .query("SELECT status FROM events ORDER BY id")
+        .fetch_all()
+        .await?;
+    assert_eq!("done", block.get::<String, _>(0, "status")?);
+    assert_eq!("new", block.get::<String, _>(1, "status")?);
+
+    // records that don't fit the table are failed right away
+    harness
+        .send_to_sink(event(3, literal!({"id": "snot"})), IN)
+        .await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Fail, cf.cb);
+
+    // inserted once the flush interval passed
+    harness
+        .send_to_sink(event(4, literal!({"id": 4})), IN)
+        .await?;
+    task::sleep(Duration::from_millis(600)).await;
+    harness.signal_tick_to_sink().await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Ack, cf.cb);
+    assert_eq!(EventId::from_id(1, 1, 4), cf.id);
+    assert_eq!(3, count(&mut client).await?);
+
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+    Ok(())
+}
@@ -0,0 +1,109 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::ConnectorHarness;
+use crate::{connectors::impls::dir, errors::Result};
+use async_std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tremor_pipeline::CbAction;
+use tremor_value::literal;
+use value_trait::ValueAccess;
+
+async fn wait_for(path: &Path, exists: bool) -> Result<()> {
+    let start = Instant::now();
+    while fs::metadata(path).await.is_ok() != exists {
+        if start.elapsed() > Duration::from_secs(5) {
+            return Err(format!(
+                "Timeout waiting for {} to {}",
+                path.display(),
+                if exists { "appear" } else { "disappear" }
+            )
+            .into());
+        }
+        async_std::task::sleep(Duration::from_millis(50)).await;
+    }
+    Ok(())
+}
+
+#[async_std::test]
+async fn dir_connector() -> Result<()> {
+    let _ = env_logger::try_init();
+    let dir = tempfile::Builder::new().tempdir()?;
+    let input = dir.path().join("in");
+    let processed = dir.path().join("processed");
+    fs::create_dir(&input).await?;
+    fs::write(input.join("a.csv"), "snot\nbadger\n").await?;
+    fs::write(input.join("ignored.txt"), "ignored\n").await?;
+
+    let defn = literal!({
+        "codec": "string",
+        "preprocessors": ["separate"],
+        "config": {
+            "path": input.display().to_string(),
+            "include": ["*.csv"],
+            "processed": "move",
+            "processed_dir": processed.display().to_string(),
+            "poll_interval_ms": 50
+        }
+    });
+    let harness = ConnectorHarness::new(function_name!(), &dir::Builder::default(), &defn).await?;
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    let out = harness.out().expect("No out pipeline");
+
+    let event = out.get_event().await?;
+    assert_eq!(Some("snot"), event.data.suffix().value().as_str());
+    assert_eq!(
+        literal!({
+            "dir": {
+                "path": input.join("a.csv").display().to_string()
+            }
+        }),
+        event.data.suffix().meta()
+    );
+    let last = out.get_event().await?;
+    assert_eq!(Some("badger"), last.data.suffix().value().as_str());
+    assert_eq!(event.id.stream_id(), last.id.stream_id());
+
+    // the file is moved once all of its events are acknowledged, acks are cumulative
+    // so acknowledging the last event covers the earlier ones
+    harness
+        .send_contraflow(CbAction::Ack, last.id.clone())
+        .await?;
+    wait_for(&processed.join("a.csv"), true).await?;
+    wait_for(&input.join("a.csv"), false).await?;
+
+    // every file is a stream of its own
+    fs::write(input.join("b.csv"), "b\n").await?;
+    let event = out.get_event().await?;
+    assert_eq!(Some("b"), event.data.suffix().value().as_str());
+    assert_ne!(last.id.stream_id(), event.id.stream_id());
+
+    // files with failed events are left in place
+    harness
+        .send_contraflow(CbAction::Fail, event.id.clone())
+        .await?;
+    assert!(out
+        .expect_no_event_for(Duration::from_millis(200))
+        .await
+        .is_ok());
+    assert!(fs::metadata(input.join("b.csv")).await.is_ok());
+    assert!(fs::metadata(processed.join("b.csv")).await.is_err());
+    assert!(fs::metadata(input.join("ignored.txt")).await.is_ok());
+
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+    Ok(())
+}
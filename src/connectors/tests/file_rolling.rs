@@ -0,0 +1,119 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::ConnectorHarness;
+use crate::{connectors::impls::file, errors::Result};
+use std::io::Read;
+use std::path::Path;
+use tremor_common::ports::IN;
+use tremor_pipeline::{CbAction, Event, EventId};
+use tremor_value::{literal, Value};
+
+fn files(dir: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        files.push(entry?.file_name().to_string_lossy().to_string());
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[async_std::test]
+async fn file_connector_rolling() -> Result<()> {
+    let _ = env_logger::try_init();
+    let dir = tempfile::Builder::new().tempdir()?;
+    let defn = literal!({
+        "codec": "string",
+        "postprocessors": ["separate"],
+        "config": {
+            "path": dir.path().join("{app}").join("out-%Y.log").display().to_string(),
+            "mode": "rolling",
+            "max_size": 10,
+            "compression": "gzip",
+            "max_files": 2
+        }
+    });
+    // files not written by the connector are never cleaned up
+    let app_dir = dir.path().join("snot");
+    std::fs::create_dir_all(&app_dir)?;
+    std::fs::write(app_dir.join("out-old.log.keep"), "keep")?;
+    let harness = ConnectorHarness::new(function_name!(), &file::Builder::default(), &defn).await?;
+    let in_pipe = harness.get_pipe(IN).expect("No pipe connected to port IN");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    // files are rotated once they reach `max_size`
+    for (i, line) in ["snot", "badger", "0123456789", "abcdefghij"]
+        .iter()
+        .enumerate()
+    {
+        let event = Event {
+            id: EventId::from_id(1, 1, i as u64),
+            data: (Value::from(*line), literal!({"file": {"app": "snot"}})).into(),
+            transactional: true,
+            ..Event::default()
+        };
+        harness.send_to_sink(event, IN).await?;
+        let cf = in_pipe.get_contraflow().await?;
+        assert_eq!(CbAction::Ack, cf.cb);
+    }
+    // events without the metadata required by the template are failed
+    let event = Event {
+        id: EventId::from_id(1, 1, 4),
+        data: (Value::from("snot"), Value::object()).into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Fail, cf.cb);
+
+    // metadata must not point outside of the template directory
+    let event = Event {
+        id: EventId::from_id(1, 1, 5),
+        data: (
+            Value::from("snot"),
+            literal!({"file": {"app": "../badger"}}),
+        )
+            .into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Fail, cf.cb);
+
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+
+    assert!(!dir.path().join("badger").exists());
+
+    // only the 2 newest files are kept, all of them compressed
+    let mut files = files(&app_dir)?;
+    assert!(files.contains(&"out-old.log.keep".to_string()), "{files:?}");
+    files.retain(|f| f != "out-old.log.keep");
+    assert_eq!(2, files.len(), "{files:?}");
+    assert!(files.iter().all(|f| f.ends_with(".gz")), "{files:?}");
+    let mut content = Vec::new();
+    for file in files {
+        let mut decoder = libflate::gzip::Decoder::new(std::fs::File::open(app_dir.join(file))?)?;
+        let mut data = String::new();
+        decoder.read_to_string(&mut data)?;
+        content.push(data);
+    }
+    content.sort();
+    assert_eq!(vec!["0123456789\n", "abcdefghij\n"], content);
+    Ok(())
+}
@@ -0,0 +1,147 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::ConnectorHarness;
+use crate::{connectors::impls::file, errors::Result};
+use async_std::fs::{self, OpenOptions};
+use futures::AsyncWriteExt;
+use std::path::Path;
+use std::time::Duration;
+use tremor_pipeline::CbAction;
+use tremor_value::{literal, Value};
+use value_trait::ValueAccess;
+
+async fn append(path: &Path, data: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(data.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+async fn tail(dir: &Path, id: &str) -> Result<ConnectorHarness> {
+    let defn = literal!({
+        "codec": "string",
+        "preprocessors": ["separate"],
+        "config": {
+            "path": dir.join("*.log").display().to_string(),
+            "mode": "tail",
+            "poll_interval_ms": 50,
+            "offsets_path": dir.join("offsets.json").display().to_string()
+        }
+    });
+    let harness = ConnectorHarness::new(id, &file::Builder::default(), &defn).await?;
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    Ok(harness)
+}
+
+#[async_std::test]
+async fn file_connector_tail() -> Result<()> {
+    let _ = env_logger::try_init();
+    let dir = tempfile::Builder::new().tempdir()?;
+    let log = dir.path().join("app.log");
+    append(&log, "snot\nbad").await?;
+
+    let harness = tail(dir.path(), "file_connector_tail").await?;
+    let out = harness.out().expect("No out pipeline");
+
+    let event = out.get_event().await?;
+    assert_eq!(Some("snot"), event.data.suffix().value().as_str());
+    assert_eq!(
+        literal!({
+            "file": {
+                "path": log.display().to_string()
+            }
+        }),
+        event.data.suffix().meta()
+    );
+    // incomplete lines are held back
+    assert!(out
+        .expect_no_event_for(Duration::from_millis(200))
+        .await
+        .is_ok());
+    append(&log, "ger\n").await?;
+    let event = out.get_event().await?;
+    assert_eq!(Some("badger"), event.data.suffix().value().as_str());
+
+    // rotation: the old file is read until its end, then the new one is followed
+    append(&log, "late\n").await?;
+    fs::rename(&log, dir.path().join("app.log.1")).await?;
+    append(&log, "new\n").await?;
+    let event = out.get_event().await?;
+    assert_eq!(Some("late"), event.data.suffix().value().as_str());
+    let event = out.get_event().await?;
+    assert_eq!(Some("new"), event.data.suffix().value().as_str());
+
+    // truncation: the file is read again from its start
+    fs::write(&log, "t\n").await?;
+    let event = out.get_event().await?;
+    assert_eq!(Some("t"), event.data.suffix().value().as_str());
+    harness
+        .send_contraflow(CbAction::Ack, event.id.clone())
+        .await?;
+
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+
+    // reading resumes after the acknowledged data
+    append(&log, "u\n").await?;
+    let harness = tail(dir.path(), "file_connector_tail_resumed").await?;
+    let out = harness.out().expect("No out pipeline");
+    let event = out.get_event().await?;
+    assert_eq!(Value::from("u"), event.data.suffix().value());
+
+    // data acknowledged after failed data isn't skipped
+    append(&log, "v\n").await?;
+    let failed = event;
+    let event = out.get_event().await?;
+    assert_eq!(Value::from("v"), event.data.suffix().value());
+    harness
+        .send_contraflow(CbAction::Fail, failed.id.clone())
+        .await?;
+    harness
+        .send_contraflow(CbAction::Ack, event.id.clone())
+        .await?;
+
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+
+    let harness = tail(dir.path(), "file_connector_tail_failed").await?;
+    let out = harness.out().expect("No out pipeline");
+    let event = out.get_event().await?;
+    assert_eq!(Some("u"), event.data.suffix().value().as_str());
+    let event = out.get_event().await?;
+    assert_eq!(Some("v"), event.data.suffix().value().as_str());
+
+    // acks are cumulative, acknowledging the last line covers the ones before it
+    harness
+        .send_contraflow(CbAction::Ack, event.id.clone())
+        .await?;
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+
+    append(&log, "w\n").await?;
+    let harness = tail(dir.path(), "file_connector_tail_acked").await?;
+    let out = harness.out().expect("No out pipeline");
+    let event = out.get_event().await?;
+    assert_eq!(Some("w"), event.data.suffix().value().as_str());
+
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+    Ok(())
+}
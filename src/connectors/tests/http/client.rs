@@ -662,3 +662,48 @@ async fn missing_config() -> Result<()> {
 
     Ok(())
 }
+
+fn metric(name: &'static str, timestamp: u64) -> Value<'static> {
+    literal!({
+        "measurement": name,
+        "tags": {"host": "snot"},
+        "fields": {"value": 1.0},
+        "timestamp": timestamp
+    })
+}
+
+#[async_std::test]
+async fn http_client_prometheus_remote_write() -> Result<()> {
+    let target = find_free_tcp_endpoint_str().await;
+    let event = Event {
+        data: (
+            literal!([
+                {"data": {"value": metric("cpu", 1_000_000_000), "meta": {}}},
+                {"data": {"value": metric("mem", 2_000_000_000), "meta": {}}}
+            ]),
+            literal!({}),
+        )
+            .into(),
+        is_batch: true,
+        ..Default::default()
+    };
+    let res = rtt("http", target, "prometheus-remote-write", None, event).await?;
+
+    assert_with_request_headers!(res, meta, {
+        assert_eq!(
+            Some(&literal!(["application/x-protobuf"])),
+            meta.get("content-type")
+        );
+        assert_eq!(Some(&literal!(["snappy"])), meta.get("content-encoding"));
+        assert_eq!(
+            Some(&literal!(["0.1.0"])),
+            meta.get("x-prometheus-remote-write-version")
+        );
+    });
+    // the server echoes the body, the whole batch was sent as a single request
+    assert_eq!(
+        &literal!([metric("cpu", 1_000_000_000), metric("mem", 2_000_000_000)]),
+        res.value()
+    );
+    Ok(())
+}
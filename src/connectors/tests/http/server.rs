@@ -18,6 +18,7 @@ use std::{
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::{
+    codec::{prometheus::RemoteWrite, Codec},
     connectors::{
         impls::http::server,
         sink::SinkMsg,
@@ -443,3 +444,70 @@ value: ~
 
     Ok(())
 }
+
+#[async_std::test]
+async fn http_server_prometheus_remote_write() -> Result<()> {
+    let _ = env_logger::try_init();
+    let port = free_port::find_free_tcp_port().await?;
+    let url = format!("http://localhost:{port}/");
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "url": url.clone()
+        }
+    });
+    let connector =
+        ConnectorHarness::new(function_name!(), &server::Builder::default(), &defn).await?;
+    connector.start().await?;
+    connector.wait_for_connected().await?;
+
+    let records = literal!([{
+        "measurement": "cpu",
+        "tags": {"host": "snot"},
+        "fields": {"value": 1.0},
+        "timestamp": 1_000_000_000
+    }]);
+    let body = RemoteWrite {}.encode(&records)?;
+    // cloning a request drops its body, so we build a new one for every attempt
+    let req = || -> Result<surf::Request> {
+        Ok(
+            surf::Request::builder(Method::Post, Url::parse(url.as_str())?)
+                .header("content-type", "application/x-protobuf")
+                .header("content-encoding", "snappy")
+                .header("x-prometheus-remote-write-version", "0.1.0")
+                .body_bytes(body.clone())
+                .build(),
+        )
+    };
+    // respond with the decoded request
+    let respond = |req_data: &ValueAndMeta<'_>| -> ValueAndMeta<'static> {
+        let meta = literal!({
+            "http_server": {
+                "response": {
+                    "status": 200
+                }
+            }
+        });
+        (req_data.value().clone_static(), meta).into()
+    };
+
+    // retry until the http server is actually up
+    let start = Instant::now();
+    let timeout = Duration::from_secs(30);
+    let mut res = handle_req(req()?, respond, &connector, false).await;
+    while let Err(e) = res {
+        if start.elapsed() > timeout {
+            return Err(format!("HTTP Server not listening after {timeout:?}: {e}").into());
+        }
+        res = handle_req(req()?, respond, &connector, false).await;
+    }
+    let mut res = res?;
+    assert_eq!(StatusCode::Ok, res.status());
+    // the request was decoded with the remote write codec, not the configured json codec
+    let body = res.body_json::<StaticValue>().await?.into_value();
+    assert_eq!(records, body);
+
+    let (_out, err) = connector.stop().await?;
+    assert!(err.is_empty());
+    Ok(())
+}
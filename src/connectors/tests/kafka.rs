@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 mod consumer;
+mod exactly_once;
 mod producer;
 
 use crate::connectors::tests::free_port::find_free_tcp_port;
@@ -39,6 +40,10 @@ async fn redpanda_container<'d>(docker: &'d DockerCli) -> Result<Container<'d, G
         "--check=false",
         "--kafka-addr=0.0.0.0:9092",
         &format!("--advertise-kafka-addr=127.0.0.1:{kafka_port}"),
+        "--set",
+        "redpanda.enable_idempotence=true",
+        "--set",
+        "redpanda.enable_transactions=true",
     ]
     .into_iter()
     .map(ToString::to_string)
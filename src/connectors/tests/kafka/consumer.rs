@@ -896,3 +896,119 @@ async fn connector_kafka_consumer_pause_resume() -> Result<()> {
     drop(container);
     Ok(())
 }
+
+#[async_std::test]
+#[serial(kafka)]
+async fn connector_kafka_consumer_assigned_partitions_seek() -> Result<()> {
+    serial_test::set_max_wait(Duration::from_secs(600));
+
+    let _ = env_logger::try_init();
+
+    let docker = DockerCli::default();
+    let container = redpanda_container(&docker).await?;
+
+    let port = container.get_host_port_ipv4(9092);
+    let mut admin_config = ClientConfig::new();
+
+    let broker = format!("127.0.0.1:{port}");
+    let topic = "tremor_test_seek";
+    let group_id = "group_seek";
+
+    admin_config
+        .set("client.id", "test-admin")
+        .set("bootstrap.servers", &broker);
+    let admin_client = AdminClient::from_config(&admin_config)?;
+    let options = AdminOptions::default();
+    let res = admin_client
+        .create_topics(
+            vec![&NewTopic::new(topic, 2, TopicReplication::Fixed(1))],
+            &options,
+        )
+        .await?;
+    for r in res {
+        if let Err((topic, err)) = r {
+            error!("Error creating topic {}: {}", &topic, err);
+        }
+    }
+
+    let producer: BaseProducer = ClientConfig::new()
+        .set("bootstrap.servers", &broker)
+        .create()
+        .expect("Producer creation error");
+    for (i, partition) in [(1_i64, 0), (2, 0), (3, 0), (4, 1)] {
+        let payload = i.to_string();
+        let record = BaseRecord::<(), _>::to(topic)
+            .payload(&payload)
+            .partition(partition)
+            .timestamp(i * 1000);
+        if producer.send(record).is_err() {
+            return Err("Unable to send record to Kafka".into());
+        }
+    }
+    producer.flush(Duration::from_secs(1));
+
+    // only partition 0 is consumed, starting from offset 1
+    let connector_config = literal!({
+        "codec": "json-sorted",
+        "config": {
+            "brokers": [
+                broker
+            ],
+            "group_id": group_id,
+            "partitions": [
+                {"topic": topic, "partition": 0}
+            ],
+            "start_offset": {"offset": 1}
+        }
+    });
+    let harness = ConnectorHarness::new(
+        function_name!(),
+        &kafka::consumer::Builder::default(),
+        &connector_config,
+    )
+    .await?;
+    let out = harness.out().expect("No pipe connected to port OUT");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+
+    let e1 = out.get_event().await?;
+    assert_eq!(Value::from(2), e1.data.suffix().value());
+    let e2 = out.get_event().await?;
+    assert_eq!(Value::from(3), e2.data.suffix().value());
+    assert!(out
+        .expect_no_event_for(Duration::from_millis(500))
+        .await
+        .is_ok());
+
+    // replay from a timestamp
+    harness
+        .seek(literal!({"offset": {"timestamp": 1_500_000_000}}))
+        .await?;
+    let e3 = out.get_event().await?;
+    assert_eq!(Value::from(2), e3.data.suffix().value());
+    // read again with a pull id of its own, acks of the first read are ignored
+    assert_ne!(e1.id.pull_id(), e3.id.pull_id());
+    let e4 = out.get_event().await?;
+    assert_eq!(Value::from(3), e4.data.suffix().value());
+
+    // replay everything
+    harness
+        .seek(literal!({"topic": topic, "partition": 0, "offset": "earliest"}))
+        .await?;
+    let e5 = out.get_event().await?;
+    assert_eq!(Value::from(1), e5.data.suffix().value());
+
+    // partition 1 isn't assigned
+    assert!(harness
+        .seek(literal!({"partition": 1, "offset": "earliest"}))
+        .await
+        .is_err());
+    assert!(harness.seek(literal!({"offset": "snot"})).await.is_err());
+
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+
+    // cleanup
+    drop(container);
+    Ok(())
+}
@@ -0,0 +1,185 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::super::ConnectorHarness;
+use super::redpanda_container;
+use crate::{connectors::impls::kafka, errors::Result};
+use async_std::prelude::FutureExt;
+use async_std::task;
+use futures::StreamExt;
+use rdkafka::{
+    admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
+    config::FromClientConfig,
+    consumer::{BaseConsumer, Consumer, StreamConsumer},
+    producer::{BaseProducer, BaseRecord, Producer},
+    ClientConfig, Message, Offset, TopicPartitionList,
+};
+use serial_test::serial;
+use std::time::Duration;
+use testcontainers::clients::Cli as DockerCli;
+use tremor_common::ports::IN;
+use tremor_pipeline::CbAction;
+use tremor_value::literal;
+
+#[async_std::test]
+#[serial(kafka)]
+async fn connector_kafka_exactly_once() -> Result<()> {
+    serial_test::set_max_wait(Duration::from_secs(600));
+
+    let _ = env_logger::try_init();
+    let docker = DockerCli::default();
+    let container = redpanda_container(&docker).await?;
+
+    let port = container.get_host_port_ipv4(9092);
+    let broker = format!("127.0.0.1:{port}");
+    let in_topic = "tremor_in";
+    let out_topic = "tremor_out";
+    let group_id = "exactly_once";
+    let mut admin_config = ClientConfig::new();
+    admin_config
+        .set("client.id", "test-admin")
+        .set("bootstrap.servers", &broker);
+    let admin_client = AdminClient::from_config(&admin_config)?;
+    let res = admin_client
+        .create_topics(
+            vec![
+                &NewTopic::new(in_topic, 1, TopicReplication::Fixed(1)),
+                &NewTopic::new(out_topic, 1, TopicReplication::Fixed(1)),
+            ],
+            &AdminOptions::default(),
+        )
+        .await?;
+    for r in res {
+        if let Err((topic, err)) = r {
+            error!("Error creating topic {}: {}", &topic, err);
+        }
+    }
+
+    let consumer_config = literal!({
+        "codec": "json-sorted",
+        "config": {
+            "brokers": [broker.clone()],
+            "group_id": group_id,
+            "topics": [in_topic],
+            "exactly_once": true
+        }
+    });
+    let consumer_harness = ConnectorHarness::new(
+        "connector_kafka_exactly_once_consumer",
+        &kafka::consumer::Builder::default(),
+        &consumer_config,
+    )
+    .await?;
+    let out = consumer_harness
+        .out()
+        .expect("No pipe connected to port OUT");
+    consumer_harness.start().await?;
+    consumer_harness.wait_for_connected().await?;
+
+    let producer_config = literal!({
+        "codec": "json-sorted",
+        "config": {
+            "brokers": [broker.clone()],
+            "topic": out_topic,
+            "transactional_id": "tremor_test",
+            "transaction_size": 1
+        }
+    });
+    let producer_harness = ConnectorHarness::new(
+        "connector_kafka_exactly_once_producer",
+        &kafka::producer::Builder::default(),
+        &producer_config,
+    )
+    .await?;
+    let in_pipe = producer_harness
+        .get_pipe(IN)
+        .expect("No pipe connected to port IN");
+    producer_harness.start().await?;
+    producer_harness.wait_for_connected().await?;
+    producer_harness.consume_initial_sink_contraflow().await?;
+
+    task::sleep(Duration::from_secs(5)).await;
+
+    let producer: BaseProducer = ClientConfig::new()
+        .set("bootstrap.servers", &broker)
+        .create()
+        .expect("Producer creation error");
+    for payload in ["{\"snot\":\"badger\"}", "{\"snot\":\"snot\"}"] {
+        if producer
+            .send(BaseRecord::<(), _>::to(in_topic).payload(payload))
+            .is_err()
+        {
+            return Err("Unable to send record to kafka".into());
+        }
+    }
+    producer.flush(Duration::from_secs(1));
+
+    // the record and the consumed offset are committed together
+    let e1 = out.get_event().await?;
+    producer_harness.send_to_sink(e1, IN).await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Ack, cf.cb);
+
+    let out_consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &broker)
+        .set("group.id", "exactly_once_check")
+        .set("auto.offset.reset", "earliest")
+        .set("isolation.level", "read_committed")
+        .create()
+        .expect("Consumer creation error");
+    out_consumer.subscribe(&[out_topic])?;
+    match out_consumer
+        .stream()
+        .next()
+        .timeout(Duration::from_secs(30))
+        .await?
+    {
+        Some(Ok(msg)) => {
+            assert_eq!(Some("{\"snot\":\"badger\"}".as_bytes()), msg.payload());
+        }
+        Some(Err(e)) => return Err(e.into()),
+        None => return Err("EOF on kafka topic".into()),
+    }
+
+    let group_consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &broker)
+        .set("group.id", group_id)
+        .create()
+        .expect("Consumer creation error");
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition(in_topic, 0);
+    let committed = group_consumer.committed_offsets(tpl, Duration::from_secs(5))?;
+    assert_eq!(
+        Some(Offset::Offset(1)),
+        committed
+            .find_partition(in_topic, 0)
+            .map(|elem| elem.offset())
+    );
+
+    // failed events are consumed again
+    let e2 = out.get_event().await?;
+    consumer_harness
+        .send_contraflow(CbAction::Fail, e2.id.clone())
+        .await?;
+    let e3 = out.get_event().await?;
+    assert_eq!(e2.data.suffix().value(), e3.data.suffix().value());
+
+    let (out_events, err_events) = producer_harness.stop().await?;
+    assert!(out_events.is_empty());
+    assert!(err_events.is_empty());
+    let (_out_events, err_events) = consumer_harness.stop().await?;
+    assert!(err_events.is_empty());
+    drop(container);
+    Ok(())
+}
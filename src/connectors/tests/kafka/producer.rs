@@ -29,7 +29,7 @@ use std::time::Duration;
 use testcontainers::clients::Cli as DockerCli;
 use tremor_common::ports::IN;
 use tremor_pipeline::EventId;
-use tremor_value::literal;
+use tremor_value::{literal, Value};
 
 #[async_std::test]
 #[serial(kafka)]
@@ -189,6 +189,49 @@ async fn connector_kafka_producer() -> Result<()> {
         }
     }
 
+    // metadata as exposed by the consumer
+    let data3 = literal!("snot");
+    let meta3 = literal!({
+        "kafka_producer": {
+            "key": Value::Bytes(b"snot".to_vec().into()),
+            "headers": {
+                "foo": Value::Bytes(b"bar".to_vec().into())
+            },
+            "topic": "other_topic",
+            "partition": 1,
+            "offset": 42,
+            "timestamp": 456_000_000
+        }
+    });
+    let e3 = Event {
+        id: EventId::from_id(0, 0, 3),
+        data: (data3, meta3).into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(e3, IN).await?;
+    match message_stream
+        .next()
+        .timeout(Duration::from_secs(5))
+        .await?
+    {
+        Some(Ok(msg)) => {
+            assert_eq!(Some("snot".as_bytes()), msg.key());
+            assert_eq!(1_i32, msg.partition());
+            assert_eq!(Some(456), msg.timestamp().to_millis());
+            let headers = msg.headers().unwrap();
+            assert_eq!(1, headers.count());
+            assert_eq!(Some(("foo", "bar".as_bytes())), headers.get(0));
+            consumer.commit_message(&msg, CommitMode::Sync).unwrap();
+        }
+        Some(Err(e)) => {
+            return Err(e.into());
+        }
+        None => {
+            return Err("EOF on kafka topic".into());
+        }
+    }
+
     // batched event
     let batched_data = literal!([{
         "data": {
@@ -0,0 +1,147 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{ConnectorHarness, TestPipeline};
+use crate::{connectors::impls::kv, errors::Result};
+use std::time::Duration;
+use tremor_common::ports::IN;
+use tremor_pipeline::{Event, EventId};
+use tremor_value::{literal, Value};
+use value_trait::ValueAccess;
+
+async fn command(
+    harness: &ConnectorHarness,
+    out: &TestPipeline,
+    id: u64,
+    command: Value<'static>,
+    value: Value<'static>,
+) -> Result<Event> {
+    let event = Event {
+        id: EventId::from_id(1, 1, id),
+        data: (value, literal!({ "kv": command })).into(),
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    out.get_event().await
+}
+
+#[async_std::test]
+async fn kv_ttl_batch_watch() -> Result<()> {
+    let _ = env_logger::try_init();
+    let dir = tempfile::Builder::new().tempdir()?;
+    let defn = literal!({
+        "config": {
+            "dir": dir.path().display().to_string(),
+            "ttl_check_interval_ms": 50
+        }
+    });
+    let harness = ConnectorHarness::new(function_name!(), &kv::Builder::default(), &defn).await?;
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+    let out = harness.out().expect("No out pipeline");
+
+    // keys with a TTL are gone once it expired
+    let event = command(
+        &harness,
+        out,
+        1,
+        literal!({"put": "snot", "ttl": 500}),
+        Value::from("badger"),
+    )
+    .await?;
+    assert_eq!(Some("badger"), event.data.suffix().value().as_str());
+    let event = command(&harness, out, 2, literal!({"get": "snot"}), Value::null()).await?;
+    assert_eq!(Some("badger"), event.data.suffix().value().as_str());
+    async_std::task::sleep(Duration::from_millis(800)).await;
+    let event = command(&harness, out, 3, literal!({"get": "snot"}), Value::null()).await?;
+    assert!(event.data.suffix().value().is_null());
+
+    // batches are applied at once and reported to watches
+    let event = command(
+        &harness,
+        out,
+        4,
+        literal!({"watch": "user/"}),
+        Value::null(),
+    )
+    .await?;
+    assert_eq!(
+        Some("watch"),
+        event.data.suffix().meta().get("kv").get_str("op")
+    );
+    let event = Event {
+        id: EventId::from_id(1, 1, 5),
+        data: (
+            Value::null(),
+            literal!({
+                "kv": {
+                    "batch": [
+                        {"put": "user/1", "value": "snot"},
+                        {"put": "user/2", "value": "badger"},
+                        {"put": "other", "value": 42}
+                    ]
+                }
+            }),
+        )
+            .into(),
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let mut ops = Vec::new();
+    let mut watched = Vec::new();
+    for _ in 0..3 {
+        let event = out.get_event().await?;
+        let meta = event.data.suffix().meta();
+        ops.push(meta.get("kv").get_str("op").unwrap_or_default().to_string());
+        if let Some(key) = meta.get("kv").get_bytes("key") {
+            watched.push((
+                key.to_vec(),
+                event
+                    .data
+                    .suffix()
+                    .value()
+                    .as_str()
+                    .map(ToString::to_string),
+            ));
+        }
+    }
+    ops.sort();
+    assert_eq!(vec!["batch", "watch", "watch"], ops);
+    watched.sort();
+    assert_eq!(
+        vec![
+            (b"user/1".to_vec(), Some("snot".to_string())),
+            (b"user/2".to_vec(), Some("badger".to_string()))
+        ],
+        watched
+    );
+
+    // prefix scans only return the keys with the prefix
+    let event = command(
+        &harness,
+        out,
+        6,
+        literal!({"scan_prefix": "user/"}),
+        Value::null(),
+    )
+    .await?;
+    assert_eq!(Some("snot"), event.data.suffix().value().as_str());
+    let event = out.get_event().await?;
+    assert_eq!(Some("badger"), event.data.suffix().value().as_str());
+
+    let (_out_events, err_events) = harness.stop().await?;
+    assert!(err_events.is_empty());
+    Ok(())
+}
@@ -0,0 +1,129 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{free_port::find_free_tcp_port, ConnectorHarness};
+use crate::connectors::impls::loki;
+use crate::errors::Result;
+use async_std::sync::{Arc, Mutex};
+use async_std::task;
+use std::time::Duration;
+use tremor_common::ports::IN;
+use tremor_pipeline::{CbAction, Event, EventId};
+use tremor_value::{literal, Value};
+use value_trait::{Builder, ValueAccess};
+
+/// bodies of the received push requests
+type Pushes = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// rejects every other push request as overloaded
+async fn push(mut req: tide::Request<Pushes>) -> tide::Result<tide::Response> {
+    let body = req.body_bytes().await?;
+    let mut pushes = req.state().lock().await;
+    pushes.push(body);
+    let status = if pushes.len() % 2 == 1 {
+        tide::StatusCode::TooManyRequests
+    } else {
+        tide::StatusCode::NoContent
+    };
+    Ok(tide::Response::new(status))
+}
+
+fn event(id: u64, value: Value<'static>, meta: Value<'static>) -> Event {
+    Event {
+        id: EventId::from_id(1, 1, id),
+        data: (value, meta).into(),
+        transactional: true,
+        ..Event::default()
+    }
+}
+
+#[async_std::test]
+async fn connector_loki_push() -> Result<()> {
+    let _ = env_logger::try_init();
+    let port = find_free_tcp_port().await?;
+    let pushes = Pushes::default();
+    let mut server = tide::with_state(pushes.clone());
+    server.at("/loki/api/v1/push").post(push);
+    let server = task::spawn(server.listen(format!("127.0.0.1:{port}")));
+    task::sleep(Duration::from_millis(200)).await;
+
+    let defn = literal!({
+        "config": {
+            "url": format!("http://127.0.0.1:{port}/loki/api/v1/push"),
+            "labels": ["app"],
+            "encoding": "json",
+            "batch_size": 2,
+            "backoff_ms": 10
+        }
+    });
+    let harness = ConnectorHarness::new(function_name!(), &loki::Builder::default(), &defn).await?;
+    let in_pipe = harness.get_pipe(IN).expect("No pipe connected to port IN");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    harness
+        .send_to_sink(
+            event(
+                1,
+                Value::from("snot"),
+                literal!({"loki": {"labels": {"app": "tremor"}, "timestamp": 1}}),
+            ),
+            IN,
+        )
+        .await?;
+    harness
+        .send_to_sink(event(2, literal!({"app": "badger"}), Value::object()), IN)
+        .await?;
+    for id in 1..=2 {
+        let cf = in_pipe.get_contraflow().await?;
+        assert_eq!(CbAction::Ack, cf.cb);
+        assert_eq!(EventId::from_id(1, 1, id), cf.id);
+    }
+
+    // the first push was rejected and retried
+    let mut pushes = pushes.lock().await.clone();
+    assert_eq!(2, pushes.len());
+    let body = tremor_value::parse_to_value(&mut pushes[1])?;
+    let streams = body.get_array("streams").cloned().unwrap_or_default();
+    assert_eq!(2, streams.len());
+    assert_eq!(Some(&literal!({"app": "badger"})), streams[0].get("stream"));
+    assert_eq!(
+        Some(r#"{"app":"badger"}"#),
+        streams[0]
+            .get_array("values")
+            .and_then(|values| values.first())
+            .and_then(|value| value.get_idx(1))
+            .and_then(ValueAccess::as_str)
+    );
+    assert_eq!(
+        literal!({
+            "stream": {"app": "tremor"},
+            "values": [["1", "snot"]]
+        }),
+        streams[1]
+    );
+
+    // lines without labels are failed
+    harness
+        .send_to_sink(event(3, Value::from("snot"), Value::object()), IN)
+        .await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Fail, cf.cb);
+
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+    server.cancel().await;
+    Ok(())
+}
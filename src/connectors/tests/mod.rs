@@ -16,8 +16,14 @@
 //! Connector testing framework
 //!
 //! ....
+#[cfg(feature = "amqp-integration")]
+mod amqp;
+#[cfg(feature = "clickhouse-integration")]
+mod clickhouse;
 #[cfg(feature = "crononome-integration")]
 mod crononome;
+#[cfg(feature = "dir-integration")]
+mod dir;
 #[cfg(feature = "es-integration")]
 mod elastic;
 #[cfg(feature = "file-integration")]
@@ -25,6 +31,10 @@ mod file;
 #[cfg(feature = "file-integration")]
 mod file_non_existent;
 #[cfg(feature = "file-integration")]
+mod file_rolling;
+#[cfg(feature = "file-integration")]
+mod file_tail;
+#[cfg(feature = "file-integration")]
 mod file_xz;
 #[cfg(feature = "gcp-integration")]
 mod gpubsub;
@@ -32,9 +42,21 @@ mod gpubsub;
 mod http;
 #[cfg(feature = "kafka-integration")]
 mod kafka;
+#[cfg(feature = "kv-integration")]
+mod kv;
+#[cfg(feature = "loki-integration")]
+mod loki;
 #[cfg(feature = "metronome-integration")]
 mod metronome;
+#[cfg(feature = "mqtt-integration")]
+mod mqtt;
+#[cfg(feature = "nats-integration")]
+mod nats;
 mod pause_resume;
+#[cfg(feature = "postgres-integration")]
+mod postgres;
+#[cfg(feature = "redis-integration")]
+mod redis;
 #[cfg(feature = "s3-integration")]
 mod s3;
 #[cfg(feature = "net-integration")]
@@ -184,6 +206,13 @@ impl ConnectorHarness {
         Ok(self.addr.send(connectors::Msg::Resume).await?)
     }
 
+    // this is only used in integration tests,
+    // otherwise this throws an error when compiled for non-integration tests
+    #[allow(dead_code)]
+    pub(crate) async fn seek(&self, position: Value<'static>) -> Result<()> {
+        self.addr.seek(position).await
+    }
+
     pub(crate) async fn stop(self) -> Result<(Vec<Event>, Vec<Event>)> {
         let (tx, rx) = bounded(1);
 
@@ -276,7 +305,11 @@ impl ConnectorHarness {
         self.get_pipe(OUT)
     }
 
-    #[cfg(any(feature = "kafka-integration", feature = "es-integration",))]
+    #[cfg(any(
+        feature = "kafka-integration",
+        feature = "es-integration",
+        feature = "redis-integration"
+    ))]
 
     /// get the err pipeline - if any
     pub(crate) fn err(&self) -> Option<&TestPipeline> {
@@ -284,10 +317,19 @@ impl ConnectorHarness {
     }
     #[cfg(any(
         feature = "http-integration",
+        feature = "clickhouse-integration",
+        feature = "loki-integration",
         feature = "es-integration",
+        feature = "file-integration",
+        feature = "kv-integration",
         feature = "socket-integration",
         feature = "net-integration",
-        feature = "ws-integration"
+        feature = "ws-integration",
+        feature = "mqtt-integration",
+        feature = "amqp-integration",
+        feature = "nats-integration",
+        feature = "postgres-integration",
+        feature = "redis-integration"
     ))]
     pub(crate) async fn send_to_sink(&self, event: Event, port: Cow<'static, str>) -> Result<()> {
         self.addr.send_sink(SinkMsg::Event { event, port }).await
@@ -308,7 +350,17 @@ impl ConnectorHarness {
             .await
     }
 
-    #[cfg(any(feature = "kafka-integration", feature = "wal-integration"))]
+    #[cfg(any(
+        feature = "kafka-integration",
+        feature = "file-integration",
+        feature = "dir-integration",
+        feature = "wal-integration",
+        feature = "mqtt-integration",
+        feature = "amqp-integration",
+        feature = "nats-integration",
+        feature = "postgres-integration",
+        feature = "redis-integration"
+    ))]
     pub(crate) async fn send_contraflow(&self, cb: CbAction, id: EventId) -> Result<()> {
         self.addr.send_source(SourceMsg::Cb(cb, id)).await
     }
@@ -353,9 +405,14 @@ impl TestPipeline {
     // wait for a contraflow
     #[cfg(any(
         feature = "kafka-integration",
+        feature = "clickhouse-integration",
+        feature = "loki-integration",
         feature = "es-integration",
         feature = "s3-integration",
         feature = "net-integration",
+        feature = "amqp-integration",
+        feature = "nats-integration",
+        feature = "postgres-integration",
     ))]
     pub(crate) async fn get_contraflow(&self) -> Result<Event> {
         match self.rx_cf.recv().timeout(Duration::from_secs(20)).await?? {
@@ -436,8 +493,15 @@ impl TestPipeline {
 
 #[cfg(any(
     feature = "http-integration",
+    feature = "clickhouse-integration",
+    feature = "loki-integration",
     feature = "ws-integration",
-    feature = "s3-integration"
+    feature = "s3-integration",
+    feature = "mqtt-integration",
+    feature = "amqp-integration",
+    feature = "nats-integration",
+    feature = "postgres-integration",
+    feature = "redis-integration"
 ))]
 mod free_port {
 
@@ -0,0 +1,189 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{free_port::find_free_tcp_port, ConnectorHarness};
+use crate::connectors::impls::mqtt;
+use crate::errors::Result;
+use async_std::{
+    net::TcpStream,
+    process::{Child, Command},
+    task,
+};
+use std::time::{Duration, Instant};
+use tremor_common::ports::IN;
+use tremor_pipeline::{CbAction, Event, EventId};
+use tremor_value::{literal, Value};
+use value_trait::Builder;
+
+/// starts a local mosquitto broker, it is killed once the returned child is dropped
+async fn mosquitto() -> Result<(Child, u16)> {
+    let port = find_free_tcp_port().await?;
+    let broker = Command::new("mosquitto")
+        .args(["-p", &port.to_string()])
+        .kill_on_drop(true)
+        .spawn()?;
+    let start = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+        if start.elapsed() > Duration::from_secs(10) {
+            return Err("mosquitto did not start within 10s".into());
+        }
+        task::sleep(Duration::from_millis(100)).await;
+    }
+    Ok((broker, port))
+}
+
+async fn roundtrip(version: &'static str, alias: &str) -> Result<()> {
+    let _ = env_logger::try_init();
+    let (_broker, port) = mosquitto().await?;
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "url": format!("mqtt://127.0.0.1:{port}"),
+            "version": version,
+            "subscribe": ["tremor/#"],
+            "topic": "tremor/default"
+        }
+    });
+    let harness = ConnectorHarness::new(alias, &mqtt::Builder::default(), &defn).await?;
+    let out = harness.out().expect("No pipe connected to port OUT");
+    let in_pipe = harness.get_pipe(IN).expect("No pipe connected to port IN");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    // published to the configured topic, acknowledged by the broker
+    let event = Event {
+        id: EventId::from_id(1, 1, 1),
+        data: (Value::from("snot"), Value::object()).into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Ack, cf.cb);
+    assert_eq!(EventId::from_id(1, 1, 1), cf.id);
+    let received = out.get_event().await?;
+    assert_eq!(&Value::from("snot"), received.data.suffix().value());
+    let meta = if version == "5" {
+        literal!({
+            "mqtt": {
+                "topic": "tremor/default",
+                "qos": 1,
+                "retain": false,
+                "user_properties": []
+            }
+        })
+    } else {
+        literal!({
+            "mqtt": {
+                "topic": "tremor/default",
+                "qos": 1,
+                "retain": false
+            }
+        })
+    };
+    assert_eq!(&meta, received.data.suffix().meta());
+    harness
+        .send_contraflow(CbAction::Ack, received.id.clone())
+        .await?;
+
+    // published according to the event metadata, QoS 0 is acknowledged right away
+    let event = Event {
+        id: EventId::from_id(1, 1, 2),
+        data: (
+            literal!({"snot": "badger"}),
+            literal!({
+                "mqtt": {
+                    "topic": "tremor/meta",
+                    "qos": 0,
+                    "user_properties": [["snot", "badger"], ["snot", "muchos"]]
+                }
+            }),
+        )
+            .into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Ack, cf.cb);
+    assert_eq!(EventId::from_id(1, 1, 2), cf.id);
+    let received = out.get_event().await?;
+    assert_eq!(
+        &literal!({"snot": "badger"}),
+        received.data.suffix().value()
+    );
+    let meta = if version == "5" {
+        literal!({
+            "mqtt": {
+                "topic": "tremor/meta",
+                "qos": 0,
+                "retain": false,
+                "user_properties": [["snot", "badger"], ["snot", "muchos"]]
+            }
+        })
+    } else {
+        literal!({
+            "mqtt": {
+                "topic": "tremor/meta",
+                "qos": 0,
+                "retain": false
+            }
+        })
+    };
+    assert_eq!(&meta, received.data.suffix().meta());
+
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+    Ok(())
+}
+
+#[async_std::test]
+async fn mqtt_v311_roundtrip() -> Result<()> {
+    roundtrip("3.1.1", function_name!()).await
+}
+
+#[async_std::test]
+async fn mqtt_v5_roundtrip() -> Result<()> {
+    roundtrip("5", function_name!()).await
+}
+
+#[async_std::test]
+async fn mqtt_invalid_config() -> Result<()> {
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "url": "mqtt://127.0.0.1:1883",
+            "qos": 3
+        }
+    });
+    assert!(
+        ConnectorHarness::new(function_name!(), &mqtt::Builder::default(), &defn)
+            .await
+            .is_err()
+    );
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "url": "mqtt://127.0.0.1:1883",
+            "version": "4"
+        }
+    });
+    assert!(
+        ConnectorHarness::new(function_name!(), &mqtt::Builder::default(), &defn)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
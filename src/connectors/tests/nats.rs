@@ -0,0 +1,254 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{free_port::find_free_tcp_port, ConnectorHarness};
+use crate::connectors::impls::nats;
+use crate::errors::Result;
+use async_nats::jetstream;
+use futures::StreamExt;
+use serial_test::serial;
+use testcontainers::{
+    clients::Cli as DockerCli, core::WaitFor, images::generic::GenericImage, Container,
+    RunnableImage,
+};
+use tremor_common::ports::IN;
+use tremor_pipeline::{CbAction, Event, EventId};
+use tremor_value::{literal, Value};
+use value_trait::{Builder, ValueAccess};
+
+const IMAGE: &str = "nats";
+const VERSION: &str = "2.9-alpine";
+
+async fn nats_container<'d>(docker: &'d DockerCli) -> Result<(Container<'d, GenericImage>, u16)> {
+    let port = find_free_tcp_port().await?;
+    let image = GenericImage::new(IMAGE, VERSION).with_wait_for(WaitFor::StdErrMessage {
+        message: "Server is ready".to_string(),
+    });
+    let image =
+        RunnableImage::from((image, vec!["-js".to_string()])).with_mapped_port((port, 4222_u16));
+    Ok((docker.run(image), port))
+}
+
+#[async_std::test]
+#[serial(nats)]
+async fn connector_nats_publish_and_request() -> Result<()> {
+    let _ = env_logger::try_init();
+    let docker = DockerCli::default();
+    let (_container, port) = nats_container(&docker).await?;
+    let server = format!("127.0.0.1:{port}");
+
+    // echoes requests with a header
+    let client = async_nats::connect(&server)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut requests = client
+        .subscribe("tremor.echo".to_string())
+        .await
+        .map_err(|e| e.to_string())?;
+    let responder = client.clone();
+    async_std::task::spawn(async move {
+        while let Some(request) = requests.next().await {
+            if let Some(reply) = request.reply {
+                let mut headers = async_nats::HeaderMap::new();
+                headers.insert("echo", "true");
+                let _ = responder
+                    .publish_with_headers(reply, headers, request.payload)
+                    .await;
+            }
+        }
+    });
+
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "servers": [server.clone()],
+            "subscribe": ["tremor.core"],
+            "subject": "tremor.core"
+        }
+    });
+    let harness = ConnectorHarness::new(function_name!(), &nats::Builder::default(), &defn).await?;
+    let out = harness.out().expect("No pipe connected to port OUT");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    let event = Event {
+        id: EventId::from_id(1, 1, 1),
+        data: (
+            literal!({"snot": "badger"}),
+            literal!({"nats": {"headers": {"snot": "badger"}}}),
+        )
+            .into(),
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let received = out.get_event().await?;
+    assert_eq!(
+        &literal!({"snot": "badger"}),
+        received.data.suffix().value()
+    );
+    assert_eq!(
+        &literal!({
+            "nats": {
+                "subject": "tremor.core",
+                "reply": null,
+                "headers": {"snot": ["badger"]}
+            }
+        }),
+        received.data.suffix().meta()
+    );
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+
+    // responses carry the correlation of their request
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "servers": [server.clone()],
+            "subject": "tremor.echo",
+            "request": true,
+            "timeout": 5_000_000_000_u64
+        }
+    });
+    let harness = ConnectorHarness::new(function_name!(), &nats::Builder::default(), &defn).await?;
+    let out = harness.out().expect("No pipe connected to port OUT");
+    let in_pipe = harness.get_pipe(IN).expect("No pipe connected to port IN");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    let event = Event {
+        id: EventId::from_id(1, 1, 2),
+        data: (Value::from("snot"), literal!({"correlation": 42})).into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let received = out.get_event().await?;
+    assert_eq!(&Value::from("snot"), received.data.suffix().value());
+    let meta = received.data.suffix().meta();
+    assert_eq!(Some(42), meta.get_u64("correlation"));
+    assert_eq!(
+        Some(&literal!({"echo": ["true"]})),
+        meta.get("nats").and_then(|m| m.get("headers"))
+    );
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Ack, cf.cb);
+
+    // requests nobody answers are failed
+    let event = Event {
+        id: EventId::from_id(1, 1, 3),
+        data: (
+            Value::from("snot"),
+            literal!({"nats": {"subject": "tremor.nobody"}}),
+        )
+            .into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Fail, cf.cb);
+
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+    Ok(())
+}
+
+#[async_std::test]
+#[serial(nats)]
+async fn connector_nats_jetstream() -> Result<()> {
+    let _ = env_logger::try_init();
+    let docker = DockerCli::default();
+    let (_container, port) = nats_container(&docker).await?;
+    let server = format!("127.0.0.1:{port}");
+
+    let client = async_nats::connect(&server)
+        .await
+        .map_err(|e| e.to_string())?;
+    jetstream::new(client)
+        .create_stream(jetstream::stream::Config {
+            name: "tremor".to_string(),
+            subjects: vec!["tremor.js.>".to_string()],
+            ..jetstream::stream::Config::default()
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "servers": [server],
+            "subject": "tremor.js.events",
+            "jetstream": {
+                "stream": "tremor",
+                "consumer": "tremor"
+            }
+        }
+    });
+    let harness = ConnectorHarness::new(function_name!(), &nats::Builder::default(), &defn).await?;
+    let out = harness.out().expect("No pipe connected to port OUT");
+    let in_pipe = harness.get_pipe(IN).expect("No pipe connected to port IN");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    // acked once stored in the stream
+    let event = Event {
+        id: EventId::from_id(1, 1, 1),
+        data: (Value::from("snot"), Value::object()).into(),
+        transactional: true,
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let cf = in_pipe.get_contraflow().await?;
+    assert_eq!(CbAction::Ack, cf.cb);
+
+    let received = out.get_event().await?;
+    assert_eq!(&Value::from("snot"), received.data.suffix().value());
+    let jetstream_meta = received
+        .data
+        .suffix()
+        .meta()
+        .get("nats")
+        .and_then(|m| m.get("jetstream"))
+        .cloned()
+        .unwrap_or_default();
+    assert_eq!(Some("tremor"), jetstream_meta.get_str("stream"));
+    assert_eq!(Some(1), jetstream_meta.get_u64("delivered"));
+
+    // failed events are nak'ed and redelivered
+    harness
+        .send_contraflow(CbAction::Fail, received.id.clone())
+        .await?;
+    let received = out.get_event().await?;
+    assert_eq!(&Value::from("snot"), received.data.suffix().value());
+    assert_eq!(
+        Some(2),
+        received
+            .data
+            .suffix()
+            .meta()
+            .get("nats")
+            .and_then(|m| m.get("jetstream"))
+            .and_then(|m| m.get_u64("delivered"))
+    );
+    harness
+        .send_contraflow(CbAction::Ack, received.id.clone())
+        .await?;
+
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+    Ok(())
+}
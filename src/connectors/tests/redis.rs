@@ -0,0 +1,186 @@
+// Copyright 2022, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{free_port::find_free_tcp_port, ConnectorHarness};
+use crate::connectors::impls::redis;
+use crate::errors::Result;
+use ::redis::AsyncCommands;
+use serial_test::serial;
+use testcontainers::{
+    clients::Cli as DockerCli, core::WaitFor, images::generic::GenericImage, RunnableImage,
+};
+use tremor_common::ports::IN;
+use tremor_pipeline::{CbAction, Event, EventId};
+use tremor_value::{literal, Value};
+use value_trait::{Builder, ValueAccess};
+
+const IMAGE: &str = "redis";
+const VERSION: &str = "7-alpine";
+
+#[async_std::test]
+#[serial(redis)]
+async fn connector_redis() -> Result<()> {
+    let _ = env_logger::try_init();
+    let docker = DockerCli::default();
+    let image = GenericImage::new(IMAGE, VERSION).with_wait_for(WaitFor::StdOutMessage {
+        message: "Ready to accept connections".to_string(),
+    });
+    let port = find_free_tcp_port().await?;
+    let _container = docker.run(RunnableImage::from(image).with_mapped_port((port, 6379_u16)));
+    let url = format!("redis://127.0.0.1:{port}");
+
+    let defn = literal!({
+        "codec": "json",
+        "config": {
+            "url": url.clone(),
+            "streams": {
+                "keys": ["tremor"],
+                "group": "tremor",
+                "block_ms": 100
+            },
+            "subscribe": ["snot"]
+        }
+    });
+    let harness =
+        ConnectorHarness::new(function_name!(), &redis::Builder::default(), &defn).await?;
+    let out = harness.out().expect("No pipe connected to port OUT");
+    let err = harness.err().expect("No pipe connected to port ERR");
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    // commands, replies carry the correlation
+    let event = Event {
+        id: EventId::from_id(1, 1, 1),
+        data: (
+            literal!({"snot": "badger"}),
+            literal!({"redis": {"set": "snot"}, "correlation": 1}),
+        )
+            .into(),
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let reply = out.get_event().await?;
+    assert_eq!(&Value::from("OK"), reply.data.suffix().value());
+    assert_eq!(
+        &literal!({"redis": {"op": "set", "ok": Value::Bytes(b"snot".to_vec().into())}, "correlation": 1}),
+        reply.data.suffix().meta()
+    );
+
+    let event = Event {
+        id: EventId::from_id(1, 1, 2),
+        data: (
+            Value::null(),
+            literal!({"redis": {"get": "snot"}, "correlation": 2}),
+        )
+            .into(),
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let reply = out.get_event().await?;
+    assert_eq!(&literal!({"snot": "badger"}), reply.data.suffix().value());
+    assert_eq!(Some(2), reply.data.suffix().meta().get_u64("correlation"));
+
+    let event = Event {
+        id: EventId::from_id(1, 1, 3),
+        data: (Value::null(), literal!({"redis": {"hget": "snot"}})).into(),
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    let error = err.get_event().await?;
+    assert_eq!(
+        Some("Missing `field`"),
+        error.data.suffix().meta().get_str("error")
+    );
+
+    // stream entries are acked with XACK, failed entries are emitted again
+    let event = Event {
+        id: EventId::from_id(1, 1, 4),
+        data: (
+            literal!({"snot": "badger", "count": 1}),
+            literal!({"redis": {"xadd": "tremor"}}),
+        )
+            .into(),
+        ..Event::default()
+    };
+    harness.send_to_sink(event, IN).await?;
+    // the reply and the entry race each other
+    let (reply, entry) = {
+        let first = out.get_event().await?;
+        let second = out.get_event().await?;
+        if first
+            .data
+            .suffix()
+            .meta()
+            .get("redis")
+            .and_then(|m| m.get("stream"))
+            .is_some()
+        {
+            (second, first)
+        } else {
+            (first, second)
+        }
+    };
+    let id = reply
+        .data
+        .suffix()
+        .value()
+        .as_str()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    assert_eq!(
+        &literal!({"snot": "badger", "count": "1"}),
+        entry.data.suffix().value()
+    );
+    assert_eq!(
+        &literal!({"redis": {"stream": "tremor", "id": id.clone()}}),
+        entry.data.suffix().meta()
+    );
+    harness
+        .send_contraflow(CbAction::Fail, entry.id.clone())
+        .await?;
+    let entry = out.get_event().await?;
+    assert_eq!(
+        Some(id.as_str()),
+        entry
+            .data
+            .suffix()
+            .meta()
+            .get("redis")
+            .and_then(|m| m.get_str("id"))
+    );
+    harness
+        .send_contraflow(CbAction::Ack, entry.id.clone())
+        .await?;
+
+    // pub/sub messages
+    let client = ::redis::Client::open(url.as_str())?;
+    let mut connection = client.get_async_std_connection().await?;
+    let _receivers: u64 = connection.publish("snot", "\"badger\"").await?;
+    let message = out.get_event().await?;
+    assert_eq!(&Value::from("badger"), message.data.suffix().value());
+    assert_eq!(
+        &literal!({"redis": {"channel": "snot", "pattern": null}}),
+        message.data.suffix().meta()
+    );
+
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+
+    // the entry was acked
+    let pending: ::redis::streams::StreamPendingReply =
+        connection.xpending("tremor", "tremor").await?;
+    assert_eq!(0, pending.count());
+    Ok(())
+}
@@ -115,3 +115,50 @@ async fn wal() -> Result<()> {
 
     Ok(())
 }
+
+#[async_std::test]
+async fn wal_fsync_always() -> Result<()> {
+    let _ = env_logger::try_init();
+    let temp_dir = tempfile::Builder::new().tempdir()?;
+
+    let config = literal!({
+        "config": {
+            "dir": temp_dir.path().display().to_string(),
+            "chunk_size": 1024,
+            "max_chunks": 100,
+            "fsync": "always"
+        }
+    });
+    let harness =
+        ConnectorHarness::new(function_name!(), &wal::Builder::default(), &config).await?;
+    harness.start().await?;
+    harness.wait_for_connected().await?;
+    harness.consume_initial_sink_contraflow().await?;
+
+    let out = harness.out().expect("No pipeline connected to WAL out.");
+    let mut id_gen = EventIdGenerator::new(SourceId::new(1));
+
+    // the source picks up every write, also after waiting for one
+    for value in ["snot", "badger"] {
+        let event = Event {
+            id: id_gen.next_id(),
+            data: (Value::from(value), Value::object()).into(),
+            transactional: false,
+            ..Event::default()
+        };
+        harness.send_to_sink(event, IN).await?;
+        let event = out.get_event().await?;
+        assert_eq!(&Value::from(value), event.data.suffix().value());
+        harness
+            .send_contraflow(CbAction::Ack, event.id.clone())
+            .await?;
+        assert!(out
+            .expect_no_event_for(Duration::from_millis(100))
+            .await
+            .is_ok());
+    }
+
+    let (_out, err) = harness.stop().await?;
+    assert!(err.is_empty());
+    Ok(())
+}
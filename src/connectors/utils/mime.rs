@@ -27,7 +27,7 @@ const MIME_TYPES: [(&str, &str); 9] = [
 ];
 
 /// additional mapping from codec to mime-types
-const CODEC_TO_MIME_TYPES: [(&str, &str); 11] = [
+const CODEC_TO_MIME_TYPES: [(&str, &str); 13] = [
     ("json-sorted", "application/json"),
     ("json", "application/json"),
     ("csv", "text/csv"),
@@ -39,6 +39,9 @@ const CODEC_TO_MIME_TYPES: [(&str, &str); 11] = [
     ("influx", "text/plain"),
     ("binflux", "application/octet-stream"),
     ("statsd", "text/plain"),
+    ("prometheus", "text/plain"),
+    // only used for requests, `http_server` detects remote write requests by their headers
+    ("prometheus-remote-write", "application/x-protobuf"),
 ];
 
 /// Map from mime-type / content-type to codec name
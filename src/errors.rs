@@ -137,10 +137,13 @@ error_chain! {
     foreign_links {
         AddrParseError(std::net::AddrParseError);
         AnyhowError(anyhow::Error);
+        AmqpError(lapin::Error);
         AsyncChannelRecvError(async_std::channel::RecvError);
+        AvroError(apache_avro::Error);
         AsyncChannelTryRecvError(async_std::channel::TryRecvError);
         Base64Error(base64::DecodeError);
         ChannelReceiveError(std::sync::mpsc::RecvError);
+        ClickHouse(clickhouse_rs::errors::Error);
         Common(tremor_common::Error);
         CronError(cron::error::Error);
         CsvError(csv::Error);
@@ -159,11 +162,17 @@ error_chain! {
         JsonError(simd_json::Error);
         KafkaError(rdkafka::error::KafkaError);
         ModeParseError(file_mode::ModeParseError);
+        MqttClientError(rumqttc::ClientError);
+        MqttConnectionError(rumqttc::ConnectionError);
+        Mqtt5ClientError(rumqttc::v5::ClientError);
+        Mqtt5ConnectionError(rumqttc::v5::ConnectionError);
         MsgPackDecoderError(rmp_serde::decode::Error);
         MsgPackEncoderError(rmp_serde::encode::Error);
         ParseIntError(std::num::ParseIntError);
         ParseFloatError(std::num::ParseFloatError);
-        //Postgres(postgres::Error);
+        ProtobufDecodeError(prost::DecodeError);
+        RedisError(redis::RedisError);
+        Postgres(tokio_postgres::Error);
         RegexError(regex::Error);
         ReqwestError(reqwest::Error);
         InvalidHeaderName(reqwest::header::InvalidHeaderName);
@@ -209,8 +218,8 @@ error_chain! {
         }
 
         NotCSVSerializableValue(value: String) {
-            description("The value cannot be serialized to CSV. Expected an array.")
-            display("The value {} cannot be serialized to CSV. Expected an array.", value)
+            description("The value cannot be serialized to CSV. Expected an array or a record.")
+            display("The value {} cannot be serialized to CSV. Expected an array or a record.", value)
         }
 
         // TODO: Old errors, verify if needed
@@ -315,6 +324,10 @@ error_chain! {
             description("Connector not found")
                 display("Connector \"{}\" not found in Flow \"{}\"", alias, flow_id)
         }
+        InvalidSeek(alias: String, msg: String) {
+            description("Invalid seek")
+                display("Invalid seek for Connector \"{}\": {}", alias, msg)
+        }
         InvalidInputData(msg: &'static str) {
             description("Invalid Input data")
                 display("Invalid Input data: {}", msg)
@@ -180,4 +180,58 @@ mod tests {
         troy_file.close()?;
         Ok(())
     }
+
+    fn deploy(src: &str) -> Result<tremor_script::ast::DeployFlow<'static>> {
+        let aggr_reg = tremor_script::aggr_registry();
+        let deployable = Deploy::parse(src, &*FN_REGISTRY.read()?, &aggr_reg)?;
+        deployable
+            .iter_flows()
+            .next()
+            .cloned()
+            .ok_or_else(|| "No flow deployment found".into())
+    }
+
+    #[async_std::test]
+    async fn test_replace_flow() -> Result<()> {
+        let (world, handle) = World::start(WorldConfig::default()).await?;
+        let running = deploy(
+            r#"
+        define flow test
+        flow
+            define pipeline main
+            pipeline
+                select event from in into out;
+            end;
+            create pipeline main;
+        end;
+        deploy flow test;
+        "#,
+        )?;
+        let broken = deploy(
+            r#"
+        define flow test
+        flow
+            define connector foo from snot;
+            create connector foo;
+        end;
+        deploy flow test;
+        "#,
+        )?;
+        let timeout = std::time::Duration::from_secs(2);
+        world.start_flow(&running).await?;
+
+        // a deployment that fails to start leaves the replaced one running
+        assert!(world.replace_flow(&broken, timeout).await.is_err());
+        let flow = world.get_flow("test".to_string()).await?;
+        assert_eq!(&running, flow.deploy());
+
+        assert!(world.replace_flow(&running, timeout).await?);
+        world.stop_flow("test".to_string(), timeout).await?;
+        // without a running deployment the flow is just started
+        assert!(!world.replace_flow(&running, timeout).await?);
+
+        world.stop(ShutdownMode::Graceful).await?;
+        handle.cancel().await;
+        Ok(())
+    }
 }
@@ -13,8 +13,9 @@
 // limitations under the License.
 use crate::{
     connectors::{self, sink::SinkMsg, source::SourceMsg},
-    errors::Result,
+    errors::{Error, Result},
     instance::State,
+    log_error,
     primerge::PriorityMerge,
 };
 use async_std::{
@@ -23,10 +24,10 @@ use async_std::{
     task::{self, JoinHandle},
 };
 use beef::Cow;
-use std::{fmt, sync::atomic::Ordering, time::Duration};
+use std::{fmt, path::Path, sync::atomic::Ordering, time::Duration};
 use tremor_common::{ids::OperatorIdGen, time::nanotime};
 use tremor_pipeline::{
-    errors::ErrorKind as PipelineErrorKind, CbAction, Event, ExecutableGraph, SignalKind,
+    errors::ErrorKind as PipelineErrorKind, state, CbAction, Event, ExecutableGraph, SignalKind,
 };
 use tremor_script::{ast::DeployEndpoint, highlighter::Dumb, prelude::BaseExpr};
 
@@ -74,8 +75,11 @@ impl Addr {
         Ok(self.mgmt_addr.send(msg).await?)
     }
 
+    /// stops the pipeline, waiting until its operator state is persisted and released
     pub(crate) async fn stop(&self) -> Result<()> {
-        self.send_mgmt(MgmtMsg::Stop).await
+        let (tx, rx) = bounded(1);
+        self.send_mgmt(MgmtMsg::Stop(tx)).await?;
+        rx.recv().await?
     }
 
     pub(crate) async fn start(&self) -> Result<()> {
@@ -143,6 +147,7 @@ impl TryFrom<connectors::Addr> for OutputTarget {
 }
 
 pub(crate) fn spawn(
+    flow_alias: &str,
     alias: &str,
     config: &tremor_pipeline::query::Query,
     operator_id_gen: &mut OperatorIdGen,
@@ -150,6 +155,16 @@ pub(crate) fn spawn(
     let qsize = crate::QSIZE.load(Ordering::Relaxed);
     let mut pipeline = config.to_pipe(operator_id_gen)?;
     pipeline.optimize();
+    if let Some(state_dir) = config.state_dir() {
+        // every pipeline of every flow gets its own store, so the same
+        // flow picks up its state again when it is redeployed
+        let dir = Path::new(state_dir).join(flow_alias).join(alias);
+        info!(
+            "[Pipeline::{alias}] Restoring operator state from {}",
+            dir.display()
+        );
+        pipeline.set_state_backend(Box::new(state::Sled::open(dir)?))?;
+    }
 
     let (tx, rx) = bounded::<Box<Msg>>(qsize);
     // We use a unbounded channel for counterflow, while an unbounded channel seems dangerous
@@ -215,8 +230,9 @@ pub(crate) enum MgmtMsg {
     Pause,
     /// resume from pause - currently a no-op
     Resume,
-    /// stop the pipeline
-    Stop,
+    /// stop the pipeline, the result is sent once the operator state is persisted
+    /// and the state backend is closed
+    Stop(Sender<Result<()>>),
     #[cfg(test)]
     Inspect(Sender<report::StatusReport>),
 }
@@ -456,6 +472,7 @@ pub(crate) async fn pipeline_task(
     let mut eventset = Vec::new();
 
     let mut state: State = State::Initializing;
+    let mut stopped = None;
 
     info!("[Pipeline::{alias}] Starting Pipeline.");
 
@@ -580,8 +597,13 @@ pub(crate) async fn pipeline_task(
                     alias, &state
                 );
             }
-            AnyMsg::Mgmt(MgmtMsg::Stop) => {
+            AnyMsg::Mgmt(MgmtMsg::Stop(sender)) => {
                 info!("[Pipeline::{}] Stopping...", alias);
+                let res = pipeline.snapshot().map_err(Error::from);
+                if let Err(e) = &res {
+                    error!("[Pipeline::{alias}] Failed to snapshot operator state: {e}");
+                }
+                stopped = Some((sender, res));
                 break;
             }
             #[cfg(test)]
@@ -614,8 +636,17 @@ pub(crate) async fn pipeline_task(
     }
     // stop ticks
     tick_handler.cancel().await;
+    // close the state backend before the stop is acknowledged, so a redeployment
+    // of the same pipeline can open it again
+    drop(pipeline);
 
     info!("[Pipeline::{alias}] Stopped.");
+    if let Some((sender, res)) = stopped {
+        log_error!(
+            sender.send(res).await,
+            "[Pipeline::{alias}] Error sending Stop result: {e}"
+        );
+    }
     Ok(())
 }
 
@@ -641,7 +672,7 @@ mod tests {
         let aggr_reg = aggr_registry();
         let query =
             tremor_pipeline::query::Query::parse(trickle, &*FN_REGISTRY.read()?, &aggr_reg)?;
-        let addr = spawn("test-pipe", &query, &mut operator_id_gen)?;
+        let addr = spawn("test-flow", "test-pipe", &query, &mut operator_id_gen)?;
 
         let (tx, rx) = unbounded();
         addr.send_mgmt(MgmtMsg::Inspect(tx.clone())).await?;
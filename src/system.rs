@@ -18,7 +18,7 @@ mod flow_supervisor;
 
 use self::flow::Flow;
 use crate::errors::{Error, Kind as ErrorKind, Result};
-use crate::{connectors, QSIZE};
+use crate::{connectors, log_error, QSIZE};
 use async_std::channel::bounded;
 use async_std::prelude::*;
 use async_std::task::JoinHandle;
@@ -76,6 +76,8 @@ impl World {
             .await?;
         if let Err(e) = rx.recv().await? {
             let err_str = match e {
+                // callers need to be able to tell a conflicting deployment apart
+                e @ Error(ErrorKind::DuplicateFlow(_), _) => return Err(e),
                 Error(
                     ErrorKind::Script(e)
                     | ErrorKind::Pipeline(tremor_pipeline::errors::ErrorKind::Script(e)),
@@ -130,6 +132,84 @@ impl World {
         flow_rx.recv().await?
     }
 
+    /// Drains and stops the flow identified by `flow_id` and removes it from the runtime,
+    /// waiting at most `timeout` for each of the two steps. The flow is only removed once it
+    /// stopped, so it stays registered if stopping fails or times out.
+    ///
+    /// # Errors
+    ///  * if there is no flow with the given `flow_id`
+    ///  * if the flow failed to stop
+    pub async fn stop_flow(&self, flow_id: String, timeout: Duration) -> Result<()> {
+        let flow = self.get_flow(flow_id.clone()).await?;
+
+        let (tx, rx) = bounded(1);
+        flow.drain(tx).await?;
+        if let Ok(res) = rx.recv().timeout(timeout).await {
+            log_error!(
+                res?,
+                "Error draining Flow {alias}: {e}",
+                alias = flow.alias()
+            );
+        } else {
+            warn!(
+                "Timeout draining Flow {} after {}s",
+                flow.alias(),
+                timeout.as_secs()
+            );
+        }
+        let (tx, rx) = bounded(1);
+        flow.stop(tx).await?;
+        rx.recv().timeout(timeout).await???;
+
+        // only forget the flow once it stopped, a flow that failed to stop still holds on to
+        // its resources and needs to stay reachable for another attempt
+        let (flow_tx, flow_rx) = bounded(1);
+        self.system
+            .send(flow_supervisor::Msg::RemoveDeploy(
+                flow::Id(flow_id),
+                flow_tx,
+            ))
+            .await?;
+        flow_rx.recv().await??;
+        Ok(())
+    }
+
+    /// Replaces the deployment of the flow `flow` with the given one. The running deployment,
+    /// if any, is stopped first, waiting at most `timeout` for each of draining and stopping it,
+    /// and started again if the new deployment fails to start.
+    ///
+    /// Returns `true` if a running deployment was replaced.
+    ///
+    /// # Errors
+    ///  * if the running deployment failed to stop
+    ///  * if the new deployment failed to start
+    pub async fn replace_flow(
+        &self,
+        flow: &ast::DeployFlow<'static>,
+        timeout: Duration,
+    ) -> Result<bool> {
+        let alias = flow.instance_alias.clone();
+        let replaced = match self.get_flow(alias.clone()).await {
+            Ok(running) => Some(running.deploy().clone()),
+            Err(Error(ErrorKind::FlowNotFound(_), _)) => None,
+            Err(e) => return Err(e),
+        };
+        if replaced.is_some() {
+            self.stop_flow(alias.clone(), timeout).await?;
+        }
+        if let Err(e) = self.start_flow(flow).await {
+            if let Some(replaced) = &replaced {
+                warn!("Restarting the replaced deployment of Flow {alias}");
+                log_error!(
+                    self.start_flow(replaced).await,
+                    "Error restarting the replaced deployment of Flow {alias}: {e}"
+                );
+            }
+            return Err(e);
+        }
+        Ok(replaced.is_some())
+    }
+
     /// list the currently deployed flows
     ///
     /// # Errors
@@ -26,7 +26,7 @@ use async_std::{
     task,
 };
 use hashbrown::HashMap;
-use std::{borrow::Borrow, collections::HashSet};
+use std::{borrow::Borrow, collections::HashSet, sync::Arc};
 use std::{sync::atomic::Ordering, time::Duration};
 use tremor_common::ids::{ConnectorIdGen, OperatorIdGen};
 use tremor_script::{
@@ -126,6 +126,7 @@ type Addr = Sender<Msg>;
 pub struct Flow {
     alias: String,
     addr: Addr,
+    deploy: Arc<DeployFlow<'static>>,
 }
 
 /// Status Report for a Flow instance
@@ -143,6 +144,10 @@ impl Flow {
     pub(crate) fn alias(&self) -> &str {
         self.alias.as_str()
     }
+    /// the deployment this flow was started from
+    pub(crate) fn deploy(&self) -> &DeployFlow<'static> {
+        &self.deploy
+    }
     pub(crate) async fn stop(&self, tx: Sender<Result<()>>) -> Result<()> {
         self.addr.send(Msg::Stop(tx)).await.map_err(Error::from)
     }
@@ -238,7 +243,8 @@ impl Flow {
                     let pipeline = tremor_pipeline::query::Query(
                         tremor_script::query::Query::from_query(query),
                     );
-                    let addr = pipeline::spawn(alias, &pipeline, operator_id_gen)?;
+                    let addr =
+                        pipeline::spawn(&flow.instance_alias, alias, &pipeline, operator_id_gen)?;
                     pipelines.insert(PipelineId::from(alias), addr);
                 }
             }
@@ -262,6 +268,7 @@ impl Flow {
         let this = Flow {
             alias: flow.instance_alias.to_string(),
             addr,
+            deploy: Arc::new(flow),
         };
 
         Ok(this)
@@ -573,25 +580,17 @@ async fn spawn_task(
                 }
                 MsgWrapper::Msg(Msg::Stop(sender)) => {
                     info!("{prefix} Stopping...");
-                    if connectors.is_empty() {
-                        log_error!(
-                            sender.send(Ok(())).await,
-                            "{prefix} Error sending Stop result: {e}"
-                        );
-                    } else {
-                        stop_senders.push(sender);
-                        for connector in
-                            end_points.iter().chain(&start_points).chain(&mixed_pickles)
-                        {
-                            if !log_error!(
-                                connector.stop(stop_tx.clone()).await,
-                                "{prefix} Error stopping connector {connector}: {e}"
-                            ) {
-                                expected_stops += 1;
-                            }
+                    for connector in end_points.iter().chain(&start_points).chain(&mixed_pickles) {
+                        if !log_error!(
+                            connector.stop(stop_tx.clone()).await,
+                            "{prefix} Error stopping connector {connector}: {e}"
+                        ) {
+                            expected_stops += 1;
                         }
                     }
 
+                    // wait for the pipelines to persist and release their state,
+                    // so the flow can be deployed again right after it reported to be stopped
                     for pipeline in &pipelines {
                         if let Err(e) = pipeline.stop().await {
                             error!("{prefix} Error stopping pipeline {pipeline:?}: {e}");
@@ -599,6 +598,14 @@ async fn spawn_task(
                     }
 
                     state = State::Stopped;
+                    if expected_stops == 0 {
+                        log_error!(
+                            sender.send(Ok(())).await,
+                            "{prefix} Error sending Stop result: {e}"
+                        );
+                    } else {
+                        stop_senders.push(sender);
+                    }
                 }
                 MsgWrapper::Msg(Msg::Report(sender)) => {
                     // TODO: aggregate states of all containing instances
@@ -712,6 +719,7 @@ mod tests {
     use tremor_common::ids::{ConnectorIdGen, OperatorIdGen};
     use tremor_script::{ast::DeployStmt, deploy::Deploy, FN_REGISTRY};
     use tremor_value::literal;
+    use value_trait::ValueAccess;
 
     mod connector {
 
@@ -917,4 +925,92 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn flow_redeploy_with_state() -> Result<()> {
+        let mut operator_id_gen = OperatorIdGen::default();
+        let mut connector_id_gen = ConnectorIdGen::default();
+        let aggr_reg = tremor_script::aggr_registry();
+        let state_dir = tempfile::tempdir()?;
+        let src = format!(
+            r#"
+        define flow test
+        flow
+            define connector foo from fake
+            with
+                codec = "json",
+                config = {{}}
+            end;
+
+            define pipeline main
+            pipeline
+                #!config state_dir = "{}"
+                define script counter
+                script
+                    let state = match state of case null => 1 default => state + 1 end;
+                    state
+                end;
+                create script counter;
+                select event from in into counter;
+                select event from counter into out;
+            end;
+
+            create connector foo;
+            create pipeline main;
+
+            connect /connector/foo to /pipeline/main;
+            connect /pipeline/main to /connector/foo;
+        end;
+        deploy flow test;
+        "#,
+            state_dir.path().display()
+        );
+        let deployable = Deploy::parse(&src, &*FN_REGISTRY.read()?, &aggr_reg)?;
+        let deploy = deployable
+            .deploy
+            .stmts
+            .into_iter()
+            .find_map(|stmt| match stmt {
+                DeployStmt::DeployFlowStmt(deploy_flow) => Some((*deploy_flow).clone()),
+                _other => None,
+            })
+            .expect("No deploy in the given troy file");
+
+        let mut last = 0;
+        for _ in 0..2 {
+            let mut known_connectors = Known::new();
+            let (connector_tx, connector_rx) = unbounded();
+            let builder = connector::FakeBuilder { tx: connector_tx };
+            known_connectors.insert(builder.connector_type(), Box::new(builder));
+            // the redeployment opens the state of the stopped deployment again
+            let flow = Flow::start(
+                deploy.clone(),
+                &mut operator_id_gen,
+                &mut connector_id_gen,
+                &known_connectors,
+            )
+            .await?;
+
+            // the counter continues where the stopped deployment left off
+            let first = connector_rx
+                .recv()
+                .await?
+                .data
+                .suffix()
+                .value()
+                .as_u64()
+                .unwrap_or_default();
+            assert!(first > last, "{first} is not greater than {last}");
+            last = first;
+            for _ in 0..10 {
+                let event = connector_rx.recv().await?;
+                last = event.data.suffix().value().as_u64().unwrap_or_default();
+            }
+
+            let (tx, rx) = bounded(1);
+            flow.stop(tx).await?;
+            rx.recv().await??;
+        }
+        Ok(())
+    }
 }
@@ -43,6 +43,8 @@ pub(crate) enum Msg {
         /// the builder
         builder: Box<dyn ConnectorBuilder>,
     },
+    /// remove a stopped Flow from the supervisor
+    RemoveDeploy(Id, Sender<Result<Flow>>),
     GetFlows(Sender<Result<Vec<Flow>>>),
     GetFlow(Id, Sender<Result<Flow>>),
     /// Initiate the Quiescence process
@@ -101,6 +103,18 @@ impl FlowSupervisor {
             "Error sending StartDeploy Err Result: {e}"
         );
     }
+    async fn handle_remove_deploy(&mut self, id: Id, reply_tx: Sender<Result<Flow>>) {
+        log_error!(
+            reply_tx
+                .send(
+                    self.flows
+                        .remove(&id)
+                        .ok_or_else(|| ErrorKind::FlowNotFound(id.0).into()),
+                )
+                .await,
+            "Error sending RemoveDeploy response {e}"
+        );
+    }
     async fn handle_get_flows(&self, reply_tx: Sender<Result<Vec<Flow>>>) {
         let flows = self.flows.values().cloned().collect();
         log_error!(
@@ -199,6 +213,9 @@ impl FlowSupervisor {
                     Msg::StartDeploy { flow, sender } => {
                         self.handle_start_deploy(*flow, sender).await;
                     }
+                    Msg::RemoveDeploy(id, reply_tx) => {
+                        self.handle_remove_deploy(id, reply_tx).await
+                    }
                     Msg::GetFlows(reply_tx) => self.handle_get_flows(reply_tx).await,
                     Msg::GetFlow(id, reply_tx) => self.handle_get_flow(id, reply_tx).await,
                     Msg::Stop => {
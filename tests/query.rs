@@ -124,6 +124,9 @@ test_cases!(
     pp_alias_operator,
     pp_config_directive,
     // INSERT
+    window_session,
+    window_sliding_time,
+    window_sliding_size,
     route_emit,
     drop_event,
     pipeline_group_by_size,
@@ -183,11 +183,14 @@ pub fn serve(host: String, world: &World) -> JoinHandle<Result<()>> {
         .get(|r| handle_api_request(r, status::get_runtime_status));
     v1_app
         .at("/flows")
-        .get(|r| handle_api_request(r, flow::list_flows));
+        .get(|r| handle_api_request(r, flow::list_flows))
+        .post(|r| handle_api_request(r, flow::create_flows));
     v1_app
         .at("/flows/:id")
         .get(|r| handle_api_request(r, flow::get_flow))
-        .patch(|r| handle_api_request(r, flow::patch_flow_status));
+        .put(|r| handle_api_request(r, flow::replace_flow))
+        .patch(|r| handle_api_request(r, flow::patch_flow_status))
+        .delete(|r| handle_api_request(r, flow::delete_flow));
     v1_app
         .at("/flows/:id/connectors")
         .get(|r| handle_api_request(r, flow::get_flow_connectors));
@@ -230,7 +233,7 @@ mod tests {
     use tremor_script::{aggr_registry, ast::DeployStmt, deploy::Deploy, FN_REGISTRY};
     use tremor_value::{literal, value::StaticValue};
 
-    use crate::flow::PatchStatus;
+    use crate::flow::{PatchConnectorStatus, PatchStatus};
 
     use super::*;
 
@@ -541,6 +544,98 @@ mod tests {
             body
         );
 
+        // the null connector doesn't support seeking
+        let mut res = client
+            .patch("/v1/flows/api_test/connectors/my_null")
+            .body_json(&PatchConnectorStatus {
+                status: InstanceState::Running,
+                seek: Some(simd_json::json!({"offset": "earliest"})),
+            })?
+            .await?;
+        assert_eq!(StatusCode::BadRequest, res.status());
+        let _ = res.body_bytes().await?; // consume the body
+
+        // deploy a flow
+        let src = r#"
+        define flow api_deploy_test
+        flow
+            define connector my_null from `null`;
+            create connector my_null;
+        end;
+        deploy flow api_deploy_test;
+        "#;
+        let body = client
+            .post("/v1/flows")
+            .content_type("application/vnd.troy")
+            .body_string(src.to_string())
+            .await?
+            .body_json::<Vec<StatusReport>>()
+            .await?;
+        assert_eq!(1, body.len());
+        assert_eq!("api_deploy_test".to_string(), body[0].alias);
+        assert_eq!(1, body[0].connectors.len());
+
+        // deploying it twice is a conflict
+        let mut res = client
+            .post("/v1/flows")
+            .content_type("application/vnd.troy")
+            .body_string(src.to_string())
+            .await?;
+        assert_eq!(StatusCode::Conflict, res.status());
+        let _ = res.body_bytes().await?; // consume the body
+
+        // invalid troy
+        let mut res = client
+            .post("/v1/flows")
+            .content_type("application/vnd.troy")
+            .body_string("define flow snot".to_string())
+            .await?;
+        assert_eq!(StatusCode::BadRequest, res.status());
+        let _ = res.body_bytes().await?; // consume the body
+
+        // redeploy it with another connector
+        let src = r#"
+        define flow api_deploy_test
+        flow
+            define connector other_null from `null`;
+            create connector other_null;
+        end;
+        deploy flow api_deploy_test;
+        "#;
+        let mut res = client
+            .put("/v1/flows/api_deploy_test")
+            .content_type("application/vnd.troy")
+            .body_string(src.to_string())
+            .await?;
+        assert_eq!(StatusCode::Ok, res.status());
+        let body = res.body_json::<StatusReport>().await?;
+        assert_eq!("api_deploy_test".to_string(), body.alias);
+        assert_eq!(vec![ConnectorAlias::from("other_null")], body.connectors);
+
+        // the alias in the path needs to match a deployment
+        let mut res = client
+            .put("/v1/flows/i_do_not_exist")
+            .content_type("application/vnd.troy")
+            .body_string(src.to_string())
+            .await?;
+        assert_eq!(StatusCode::BadRequest, res.status());
+        let _ = res.body_bytes().await?; // consume the body
+
+        // undeploy it
+        let res = client.delete("/v1/flows/api_deploy_test").await?;
+        assert_eq!(StatusCode::NoContent, res.status());
+        let res = client.get("/v1/flows/api_deploy_test").await?;
+        assert_eq!(StatusCode::NotFound, res.status());
+        let res = client.delete("/v1/flows/api_deploy_test").await?;
+        assert_eq!(StatusCode::NotFound, res.status());
+
+        let body = client
+            .get("/v1/flows")
+            .await?
+            .body_json::<Vec<StatusReport>>()
+            .await?;
+        assert_eq!(1, body.len());
+
         // cleanup
         world.stop(ShutdownMode::Graceful).await?;
         world_handle.cancel().await;
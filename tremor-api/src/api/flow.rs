@@ -15,7 +15,35 @@
 //! Flow API
 
 use crate::api::prelude::*;
+use async_std::task;
+use std::time::Duration;
 use tremor_runtime::instance::State;
+use tremor_script::{aggr_registry, ast::DeployFlow, deploy::Deploy, FN_REGISTRY};
+
+/// Timeout for each of draining and stopping a flow that is undeployed via the API
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Compiles the troy source in the request body into the flow deployments it contains
+async fn deployments(req: &mut Request) -> Result<Vec<DeployFlow<'static>>> {
+    match content_type(req) {
+        Some(ResourceType::Troy) | None => {}
+        Some(other) => {
+            return Err(Error::new(
+                StatusCode::UnsupportedMediaType,
+                format!("Expected troy source, got {other}"),
+            ));
+        }
+    }
+    let src = req.body_string().await?;
+    let aggr_reg = aggr_registry();
+    let deployable = Deploy::parse(&src, &*FN_REGISTRY.read()?, &aggr_reg)?;
+    let flows: Vec<_> = deployable.iter_flows().cloned().collect();
+    if flows.is_empty() {
+        Err(Error::bad_request("No flow deployment found".into()))
+    } else {
+        Ok(flows)
+    }
+}
 
 pub(crate) async fn list_flows(req: Request) -> Result<Response> {
     let world = &req.state().world;
@@ -28,6 +56,74 @@ pub(crate) async fn list_flows(req: Request) -> Result<Response> {
     reply(&req, result, StatusCode::Ok)
 }
 
+pub(crate) async fn create_flows(mut req: Request) -> Result<Response> {
+    let flows = deployments(&mut req).await?;
+    let world = req.state().world.clone();
+    // deploying and rolling back runs in its own task, so a request cancelled by the
+    // `DEFAULT_API_TIMEOUT` can't leave only some of the flows deployed
+    let deployed = flows.clone();
+    task::spawn(async move {
+        for (i, flow) in deployed.iter().enumerate() {
+            if let Err(e) = world.start_flow(flow).await {
+                // either all flows of the request are deployed or none of them
+                for started in &deployed[..i] {
+                    if let Err(e) = world
+                        .stop_flow(started.instance_alias.clone(), STOP_TIMEOUT)
+                        .await
+                    {
+                        error!(
+                            "Error undeploying flow {} after a failed deployment: {e}",
+                            started.instance_alias
+                        );
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    })
+    .await?;
+    let world = &req.state().world;
+    let mut result = Vec::with_capacity(flows.len());
+    for flow in &flows {
+        let status = world
+            .get_flow(flow.instance_alias.clone())
+            .await?
+            .report_status()
+            .await?;
+        result.push(status);
+    }
+    reply(&req, result, StatusCode::Created)
+}
+
+pub(crate) async fn replace_flow(mut req: Request) -> Result<Response> {
+    let flow_id = req.param("id")?.to_string();
+    let flow = deployments(&mut req)
+        .await?
+        .into_iter()
+        .find(|flow| flow.instance_alias == flow_id)
+        .ok_or_else(|| Error::bad_request(format!("No deployment of flow {flow_id} found")))?;
+    let world = req.state().world.clone();
+    // the running deployment is restarted if the new one fails to start, this runs in its own
+    // task so a request cancelled by the `DEFAULT_API_TIMEOUT` can't lose the flow midway
+    let replaced = task::spawn(async move { world.replace_flow(&flow, STOP_TIMEOUT).await });
+    let code = if replaced.await? {
+        StatusCode::Ok
+    } else {
+        StatusCode::Created
+    };
+    let world = &req.state().world;
+    let report = world.get_flow(flow_id).await?.report_status().await?;
+    reply(&req, report, code)
+}
+
+pub(crate) async fn delete_flow(req: Request) -> Result<Response> {
+    let world = req.state().world.clone();
+    let flow_id = req.param("id")?.to_string();
+    task::spawn(async move { world.stop_flow(flow_id, STOP_TIMEOUT).await }).await?;
+    Ok(Response::new(StatusCode::NoContent))
+}
+
 pub(crate) async fn get_flow(req: Request) -> Result<Response> {
     let world = &req.state().world;
     let flow_id = req.param("id")?.to_string();
@@ -96,8 +192,16 @@ pub(crate) async fn get_flow_connector_status(req: Request) -> Result<Response>
     reply(&req, report, StatusCode::Ok)
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct PatchConnectorStatus {
+    pub(crate) status: State,
+    /// connector specific position to seek the source of the connector to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) seek: Option<simd_json::OwnedValue>,
+}
+
 pub(crate) async fn patch_flow_connector_status(mut req: Request) -> Result<Response> {
-    let patch_status_payload: PatchStatus = req.body_json().await?;
+    let patch_status_payload: PatchConnectorStatus = req.body_json().await?;
     let flow_id = req.param("id")?.to_string();
     let connector_id = req.param("connector")?.to_string();
 
@@ -105,25 +209,32 @@ pub(crate) async fn patch_flow_connector_status(mut req: Request) -> Result<Resp
     let flow = world.get_flow(flow_id.clone()).await?;
     let connector = flow.get_connector(connector_id.clone()).await?;
     let current_status = connector.report_status().await?;
-    let report = match (current_status.status, patch_status_payload.status) {
-        (state1, state2) if state1 == state2 => {
-            // desired status == current status
-            current_status
-        }
+    let (current, desired) = (current_status.status, patch_status_payload.status);
+    // TODO: we could stop a deployment by patching its status to `Stopped`
+    let valid = current == desired
+        || matches!(
+            (current, desired),
+            (State::Running, State::Paused) | (State::Paused, State::Running)
+        );
+    if !valid {
+        return Err(Error::bad_request(format!("Cannot patch status of connector {connector_id} in flow {flow_id} from {current} to {desired}")));
+    }
+    // seek before changing the status, so a failing request leaves the connector untouched
+    if let Some(position) = patch_status_payload.seek {
+        let position = tremor_value::to_value(position)
+            .map_err(|e| Error::bad_request(format!("Invalid seek position: {e}")))?;
+        connector.seek(position).await?;
+    }
+    let report = match (current, desired) {
         (State::Running, State::Paused) => {
             connector.pause().await?;
             connector.report_status().await?
         }
-
         (State::Paused, State::Running) => {
             connector.resume().await?;
             connector.report_status().await?
         }
-        // TODO: we could stop a deployment by patching its status to `Stopped`
-        (current, desired) => {
-            // throw error
-            return Err(Error::bad_request(format!("Cannot patch status of connector {connector_id} in flow {flow_id} from {current} to {desired}")));
-        }
+        _ => connector.report_status().await?,
     };
     reply(&req, report, StatusCode::Ok)
 }
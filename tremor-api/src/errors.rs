@@ -15,7 +15,7 @@
 use async_std::channel::RecvError;
 use http_types::{headers, StatusCode};
 use serde::Serialize;
-use std::sync::{MutexGuard, PoisonError};
+use std::sync::{MutexGuard, PoisonError, RwLockReadGuard};
 use tide::Response;
 use tremor_runtime::errors::{Error as TremorError, Kind as ErrorKind};
 
@@ -130,6 +130,23 @@ impl From<PoisonError<MutexGuard<'_, tremor_script::Registry>>> for Error {
     }
 }
 
+impl From<PoisonError<RwLockReadGuard<'_, tremor_script::Registry>>> for Error {
+    fn from(e: PoisonError<RwLockReadGuard<tremor_script::Registry>>) -> Self {
+        Self::new(
+            StatusCode::InternalServerError,
+            format!("Locking error: {}", e),
+        )
+    }
+}
+
+impl From<tremor_script::errors::Error> for Error {
+    fn from(e: tremor_script::errors::Error) -> Self {
+        let msg =
+            tremor_script::highlighter::Dumb::error_to_string(&e).unwrap_or_else(|_| e.to_string());
+        Self::new(StatusCode::BadRequest, format!("Script error: {}", msg))
+    }
+}
+
 impl From<TremorError> for Error {
     fn from(e: TremorError) -> Self {
         match e.0 {
@@ -140,6 +157,15 @@ impl From<TremorError> for Error {
                 StatusCode::NotFound,
                 format!("Connector {id} not found in Flow {flow_id}"),
             ),
+            ErrorKind::DuplicateFlow(id) => Error::new(
+                StatusCode::Conflict,
+                format!("Flow {id} is already deployed"),
+            ),
+            ErrorKind::DeployFlowError(id, e) => Error::new(
+                StatusCode::BadRequest,
+                format!("Error deploying Flow {id}: {e}"),
+            ),
+            e @ ErrorKind::InvalidSeek(..) => Error::new(StatusCode::BadRequest, e.to_string()),
             _e => Error::new(
                 StatusCode::InternalServerError,
                 "Internal server error".into(),
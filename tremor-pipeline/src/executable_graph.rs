@@ -20,13 +20,20 @@ use crate::{
     errors::{Error, ErrorKind},
     metrics::value_count,
     op::prelude::IN,
-    ConfigMap, ExecPortIndexMap, MetricsMsg, MetricsSender, NodeLookupFn,
+    state, ConfigMap, ExecPortIndexMap, MetricsMsg, MetricsSender, NodeLookupFn, SignalKind,
+};
+use crate::{
+    op::{trickle::window, EventAndInsights},
+    Event, NodeKind, Operator,
 };
-use crate::{op::EventAndInsights, Event, NodeKind, Operator};
 use beef::Cow;
 use halfbrown::HashMap;
 use tremor_common::{ids::OperatorId, stry};
 use tremor_script::{ast::Helper, ast::Stmt, Value};
+use tremor_value::{
+    literal,
+    prelude::{ValueAccessTrait, ValueTrait},
+};
 
 /// Configuration for a node
 #[derive(Debug, Clone, Default)]
@@ -37,6 +44,8 @@ pub struct NodeConfig {
     pub(crate) config: ConfigMap,
     pub(crate) label: Option<String>,
     pub(crate) stmt: Option<Stmt<'static>>,
+    /// The windows of a select, resolved once when the query is turned into a graph
+    pub(crate) windows: Vec<(String, window::Impl)>,
 }
 
 impl Display for NodeConfig {
@@ -66,7 +75,7 @@ impl NodeConfig {
     }
 }
 
-// We ignore stmt on equality and hasing as they're only
+// We ignore stmt and windows on equality and hasing as they're only
 // carried through for implementation purposes not part
 // if the identiy of a node
 
@@ -161,6 +170,13 @@ impl Operator for OperatorNode {
     fn skippable(&self) -> bool {
         self.op.skippable()
     }
+
+    fn snapshot(&self) -> Result<Option<Value<'static>>> {
+        self.op.snapshot()
+    }
+    fn restore(&mut self, snapshot: &Value<'static>) -> Result<()> {
+        self.op.restore(snapshot)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -255,6 +271,9 @@ pub struct ExecutableGraph {
     pub(crate) last_metrics: u64,
     pub(crate) metric_interval: Option<u64>,
     pub(crate) metrics_channel: MetricsSender,
+    pub(crate) state_backend: Option<Box<dyn state::Backend>>,
+    pub(crate) snapshot_interval: u64,
+    pub(crate) last_snapshot: u64,
     /// snot
     pub insights: Vec<(usize, Event)>,
     /// the dot representation of the graph
@@ -571,9 +590,64 @@ impl ExecutableGraph {
     /// if the singal fails to be processed in the singal flow or if any forward going
     /// events spawned by this signal fail to be processed
     pub fn enqueue_signal(&mut self, signal: Event, returns: &mut Returns) -> Result<()> {
+        let snapshot = signal.kind == Some(SignalKind::Tick)
+            && self.state_backend.is_some()
+            && signal.ingest_ns.saturating_sub(self.last_snapshot) > self.snapshot_interval;
+        let ingest_ns = signal.ingest_ns;
         if stry!(self.signalflow(signal)) {
             stry!(self.run(returns));
         }
+        if snapshot {
+            // a failed snapshot is retried with the next interval, it should
+            // not stop the pipeline from processing signals
+            if let Err(e) = self.snapshot() {
+                error!(
+                    "[Pipeline::{}] Failed to snapshot operator state: {}",
+                    self.id, e
+                );
+            }
+            self.last_snapshot = ingest_ns;
+        }
+        Ok(())
+    }
+
+    /// Restores the state of all operators from the snapshots in `backend`
+    /// and persists snapshots to it from now on.
+    ///
+    /// # Errors
+    /// if a snapshot can not be read or restored
+    pub fn set_state_backend(&mut self, backend: Box<dyn state::Backend>) -> Result<()> {
+        for (node, state) in self.graph.iter_mut().zip(self.state.ops.iter_mut()) {
+            if let Some(snapshot) = stry!(backend.load(&node.id)) {
+                if let Some(node_state) = snapshot.get("state") {
+                    *state = node_state.clone_static();
+                }
+                if let Some(op) = snapshot.get("op").filter(|op| !op.is_null()) {
+                    stry!(node.restore(op));
+                }
+            }
+        }
+        self.state_backend = Some(backend);
+        Ok(())
+    }
+
+    /// Persists a snapshot of the state of all operators, this is a noop
+    /// without a state backend.
+    ///
+    /// # Errors
+    /// if the state of an operator can not be captured or stored
+    pub fn snapshot(&mut self) -> Result<()> {
+        if let Some(backend) = &self.state_backend {
+            for (node, state) in self.graph.iter().zip(self.state.ops.iter()) {
+                let op = stry!(node.snapshot()).unwrap_or_default();
+                let snapshot = literal!({
+                    "state": state.clone(),
+                    "op": op,
+                });
+                stry!(backend.store(&node.id, &snapshot));
+            }
+            stry!(backend.flush());
+        }
         Ok(())
     }
 
@@ -820,6 +894,9 @@ mod test {
             insights: vec![],
             dot: String::from(""),
             metrics_channel: METRICS_CHANNEL.tx(),
+            state_backend: None,
+            snapshot_interval: 0,
+            last_snapshot: 0,
         };
 
         // Test with one event
@@ -917,6 +994,9 @@ mod test {
             insights: vec![],
             dot: String::from(""),
             metrics_channel: METRICS_CHANNEL.tx(),
+            state_backend: None,
+            snapshot_interval: 0,
+            last_snapshot: 0,
         };
         assert!(g.optimize().is_some());
         // Test with one event
@@ -64,6 +64,8 @@ pub(crate) mod op;
 
 /// Tools to turn tremor query into pipelines
 pub mod query;
+/// Persistent operator state
+pub mod state;
 pub use crate::event::{Event, ValueIter, ValueMetaIter};
 pub use crate::executable_graph::{ExecutableGraph, OperatorNode};
 pub(crate) use crate::executable_graph::{NodeMetrics, State};
@@ -141,6 +141,23 @@ pub trait Operator: std::fmt::Debug + Send + Sync {
     fn skippable(&self) -> bool {
         false
     }
+
+    /// Captures the internal state of the operator so it can be
+    /// restored after a restart, defaults to no state.
+    ///
+    /// # Errors
+    /// if the state can not be captured
+    fn snapshot(&self) -> Result<Option<Value<'static>>> {
+        Ok(None)
+    }
+
+    /// Restores the internal state captured by `snapshot`, defaults to a noop.
+    ///
+    /// # Errors
+    /// if the state can not be restored
+    fn restore(&mut self, _snapshot: &Value<'static>) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// Initialisable trait that can be turned from a `NodeConfig`
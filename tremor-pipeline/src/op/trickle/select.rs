@@ -18,9 +18,10 @@
 mod test;
 
 use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use super::window::{self, Group, Window};
-use crate::op::prelude::trickle::window::{GroupWindow, SelectCtx, Trait};
+use crate::op::prelude::trickle::window::{EventTime, GroupWindow, SelectCtx, Trait};
 use crate::{errors::Result, SignalKind};
 use crate::{op::prelude::*, EventIdGenerator};
 use crate::{Event, EventId, Operator};
@@ -46,6 +47,13 @@ pub(crate) struct Select {
     recursion_limit: u32,
     dflt_group: Group,
     max_groups: usize,
+    /// the highest event time the event time window of the select has seen,
+    /// the watermark derived from it is shared by all groups
+    max_event_time: Option<u64>,
+    /// the first aggregate function whose state can't be persisted, if any
+    unpersistable: Option<String>,
+    /// if we warned about windows not being persisted already
+    warned: AtomicBool,
 }
 
 impl Select {
@@ -67,12 +75,17 @@ impl Select {
         let dflt_group = Group {
             value: Value::const_null(),
             windows: GroupWindow::from_windows(aggregates, &EventId::default(), windows_itr),
+            restored: None,
         };
         let windows_itr = windows.iter();
         let max_groups = windows_itr
             .map(|w| w.window_impl.max_groups())
             .min()
             .unwrap_or(0) as usize;
+        let unpersistable = aggregates
+            .iter()
+            .find(|aggr| aggr.invocable.snapshot().is_none())
+            .map(|aggr| aggr.invocable.fqn());
         Self {
             windows,
             select: select.clone(),
@@ -81,6 +94,9 @@ impl Select {
             recursion_limit: tremor_script::recursion_limit(),
             dflt_group,
             max_groups,
+            max_event_time: None,
+            unpersistable,
+            warned: AtomicBool::new(false),
         }
     }
     const fn opts() -> ExecOpts {
@@ -139,6 +155,25 @@ pub(crate) fn execute_select_and_having(
     }
 }
 
+/// Creates the event for the `late` port from an event that arrived
+/// after the allowed lateness of an event time window
+pub(crate) fn late_event(ctx: &SelectCtx, data: &ValueAndMeta) -> (Cow<'static, str>, Event) {
+    let (event_payload, event_meta) = data.parts();
+    (
+        LATE,
+        Event {
+            id: ctx.event_id.clone(),
+            ingest_ns: ctx.ingest_ns,
+            origin_uri: ctx.origin_uri.clone(),
+            op_meta: ctx.op_meta.clone(),
+            is_batch: false,
+            data: (event_payload.clone_static(), event_meta.clone_static()).into(),
+            transactional: ctx.transactional,
+            ..Event::default()
+        },
+    )
+}
+
 fn env<'run, 'script>(
     context: &'run EventContext<'run>,
     consts: RunConsts<'run, 'script>,
@@ -189,6 +224,7 @@ impl Operator for Select {
             recursion_limit,
             dflt_group,
             max_groups,
+            max_event_time,
             ..
         } = self;
         let Event {
@@ -254,6 +290,23 @@ impl Operator for Select {
             // for the entire window depth
             let mut events = Vec::with_capacity(group_values.len() * 2);
 
+            // an event time window has a single watermark for all groups
+            let event_time = if let Some(Window {
+                window_impl: window::Impl::TumblingEventTimeBased(w),
+                ..
+            }) = windows.first()
+            {
+                let time = stry!(w.event_time(event, ingest_ns, origin_uri));
+                let max_time = max_event_time.map_or(time, |max_time| max_time.max(time));
+                *max_event_time = Some(max_time);
+                Some(EventTime {
+                    time,
+                    watermark: w.watermark(max_time),
+                })
+            } else {
+                None
+            };
+
             // with the `each` grouping an event could be in more then one group, so we
             // iterate over all groups we found
             for group_value in group_values {
@@ -273,6 +326,7 @@ impl Operator for Select {
                     origin_uri,
                     transactional,
                     recursion_limit: *recursion_limit,
+                    event_time,
                 };
 
                 // see if we know the group already, we use the `entry` here so we don't
@@ -310,6 +364,39 @@ impl Operator for Select {
                     }
                 }
             }
+
+            // the watermark might have passed the windows of other groups as well
+            if let Some(EventTime { watermark, .. }) = event_time {
+                let data: ValueAndMeta = (Value::const_null(), Value::object()).into();
+                let op_meta = OpMeta::default();
+                let mut to_remove = vec![];
+                for (group_str, g) in groups.iter_mut() {
+                    if let Some(w) = &mut g.windows {
+                        let mut run = consts.run();
+                        run.group = &g.value;
+                        let mut ctx = SelectCtx {
+                            select,
+                            local_stack: &locals,
+                            opts,
+                            ctx: &ctx,
+                            event_id: event_id_gen.next_id(),
+                            event_id_gen,
+                            ingest_ns,
+                            op_meta: &op_meta,
+                            origin_uri: &None,
+                            transactional: false,
+                            recursion_limit: *recursion_limit,
+                            event_time: None,
+                        };
+                        if stry!(w.on_watermark(&mut ctx, run, &data, &mut events, watermark)) {
+                            to_remove.push(group_str.clone());
+                        }
+                    }
+                }
+                for g in to_remove {
+                    groups.remove(&g);
+                }
+            }
             Ok(Res::Data(events.into()))
         })?;
 
@@ -369,15 +456,11 @@ impl Operator for Select {
 
                 let mut run = consts.run();
                 run.group = &g.value;
-                run.window = &w.name;
                 let window_event = w.window.on_tick(ingest_ns);
                 let mut can_remove = window_event.emit;
 
                 if window_event.emit {
                     // push
-                    let mut env = env(&ctx, run, recursion_limit);
-                    env.aggrs = &w.aggrs;
-
                     let mut outgoing_event_id = event_id_gen.next_id();
 
                     mem::swap(&mut outgoing_event_id, &mut w.id);
@@ -394,14 +477,10 @@ impl Operator for Select {
                         origin_uri: &None,
                         transactional: w.transactional,
                         recursion_limit,
+                        event_time: None,
                     };
-                    if w.holds_data {
-                        if let Some(port_and_event) =
-                            super::select::execute_select_and_having(&ctx, &env, &data)?
-                        {
-                            res.events.push(port_and_event);
-                        };
-                    }
+                    w.emit(&mut ctx, run, &data, &mut res.events)?;
+                    w.close_pane(&ctx.event_id);
                     // re-initialize aggr state for new window
                     // reset transactional state for outgoing events
 
@@ -416,6 +495,8 @@ impl Operator for Select {
                         )?;
                     }
                     w.reset();
+                    // sliding windows hold on to the data of older panes
+                    can_remove = can_remove && !w.panes_hold_data();
                 }
                 if can_remove {
                     to_remove.push(group_str.clone());
@@ -431,6 +512,48 @@ impl Operator for Select {
     fn handles_signal(&self) -> bool {
         true
     }
+
+    fn snapshot(&self) -> Result<Option<Value<'static>>> {
+        if self.windows.is_empty() {
+            return Ok(None);
+        }
+        // if an aggregate can't be captured we can't capture the windows
+        if let Some(aggr) = &self.unpersistable {
+            if !self.warned.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "[Select] The windows of `select` statements using `aggr::{aggr}` are not persisted, their state can't be captured"
+                );
+            }
+            return Ok(None);
+        }
+        let mut groups = Value::object_with_capacity(self.groups.len());
+        for (key, group) in &self.groups {
+            if let Some(snapshot) = group.snapshot() {
+                groups.try_insert(key.clone(), snapshot);
+            } else {
+                return Ok(None);
+            }
+        }
+        Ok(Some(literal!({
+            "groups": groups,
+            "max_event_time": self.max_event_time,
+        })))
+    }
+
+    fn restore(&mut self, snapshot: &Value<'static>) -> Result<()> {
+        self.max_event_time = snapshot.get_u64("max_event_time");
+        if let Some(groups) = snapshot.get_object("groups") {
+            for (key, snapshot) in groups {
+                let mut group = self.dflt_group.clone();
+                group.reset();
+                if !group.restore(snapshot, &mut self.event_id_gen) {
+                    return Err(format!("Invalid snapshot for group {}", key).into());
+                }
+                self.groups.insert(key.to_string(), group);
+            }
+        }
+        Ok(())
+    }
 }
 
 fn run_guard(
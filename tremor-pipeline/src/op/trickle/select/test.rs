@@ -15,7 +15,7 @@
 // mod test { <- this is for safety.sh
 
 #![allow(clippy::float_cmp)]
-use crate::op::prelude::trickle::window::{Actions, Trait};
+use crate::op::prelude::trickle::window::{Actions, Assignment, Slot, Trait};
 use crate::query::window_defn_to_impl;
 use crate::EventId;
 
@@ -968,3 +968,433 @@ fn tumbling_window_on_number_emit() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn sliding_window_on_number_emit() -> Result<()> {
+    let mut window =
+        window::SlidingOnNumber::from_stmt(2, 3, window::Impl::DEFAULT_MAX_GROUPS, None);
+    assert_eq!(3, window.panes());
+
+    let vm = literal!({
+       "h2g2" : 42,
+    })
+    .into();
+
+    // do not emit yet
+    assert_eq!(
+        Actions::all_false(),
+        window.on_event(&vm, ingest_ns(0), &None)?
+    );
+    assert_eq!(Actions::all_false(), window.on_tick(1_000_000_000));
+    // emit on every second event
+    assert_eq!(
+        Actions::all_true(),
+        window.on_event(&vm, ingest_ns(1), &None)?
+    );
+    assert_eq!(
+        Actions::all_false(),
+        window.on_event(&vm, ingest_ns(2), &None)?
+    );
+    assert_eq!(
+        Actions::all_true(),
+        window.on_event(&vm, ingest_ns(3), &None)?
+    );
+    Ok(())
+}
+
+#[test]
+fn select_sliding_win_on_signal() -> Result<()> {
+    let mut select = select_stmt_from_query(
+        r#"
+        define window window1 from sliding
+        with
+            interval = 4,
+            slide = 2
+        end;
+        select aggr::win::collect_flattened(event) from in[window1] group by event.g into out;
+        "#,
+    )?;
+    let uid = OperatorId::new(42);
+    let mut state = Value::null();
+
+    let event = Event {
+        id: (1, 1, 300).into(),
+        ingest_ns: 2,
+        data: literal!({
+           "g": "group"
+        })
+        .into(),
+        ..Event::default()
+    };
+    let mut eis = select.on_event(uid, "IN", &mut state, event)?;
+    assert!(eis.insights.is_empty());
+    assert_eq!(0, eis.events.len());
+
+    // first slide
+    let mut tick1 = test_tick(4);
+    eis = select.on_signal(uid, &mut state, &mut tick1)?;
+    assert_eq!(1, eis.events.len());
+    assert_eq!(
+        r#"[{"g":"group"}]"#,
+        sorted_serialize(eis.events[0].1.data.parts().0)?
+    );
+    assert_eq!(1, select.groups.len());
+
+    // second slide, the event is still within the window
+    let mut tick2 = test_tick(6);
+    eis = select.on_signal(uid, &mut state, &mut tick2)?;
+    assert_eq!(1, eis.events.len());
+    assert_eq!(
+        r#"[{"g":"group"}]"#,
+        sorted_serialize(eis.events[0].1.data.parts().0)?
+    );
+    // the event slid out of the window so the group is gone
+    assert!(select.groups.is_empty());
+
+    let mut tick3 = test_tick(8);
+    eis = select.on_signal(uid, &mut state, &mut tick3)?;
+    assert_eq!(0, eis.events.len());
+    Ok(())
+}
+
+#[test]
+fn select_sliding_win_gap() -> Result<()> {
+    let mut select = select_stmt_from_query(
+        r#"
+        define window window1 from sliding
+        with
+            interval = 6,
+            slide = 2
+        end;
+        select aggr::win::collect_flattened(event) from in[window1] into out;
+        "#,
+    )?;
+    let uid = OperatorId::new(42);
+    let mut state = Value::null();
+    let mut send = |id: u64, ingest_ns: u64| -> Result<Vec<String>> {
+        let event = Event {
+            id: (1, 1, id).into(),
+            ingest_ns,
+            data: literal!({ "id": id }).into(),
+            ..Event::default()
+        };
+        let eis = select.on_event(uid, "IN", &mut state, event)?;
+        let mut res = Vec::new();
+        for (_, e) in eis.events {
+            res.push(sorted_serialize(e.data.parts().0)?);
+        }
+        Ok(res)
+    };
+
+    assert!(send(1, 1)?.is_empty());
+    assert_eq!(vec![r#"[{"id":1}]"#], send(2, 3)?);
+    assert_eq!(vec![r#"[{"id":1},{"id":2}]"#], send(3, 5)?);
+    // after a gap of several slides the older panes slid out of the window
+    assert_eq!(vec![r#"[{"id":3}]"#], send(4, 20)?);
+    assert_eq!(vec![r#"[{"id":4}]"#], send(5, 22)?);
+    Ok(())
+}
+
+#[test]
+fn sliding_window_bad_slide() -> Result<()> {
+    let reg = Registry::default();
+    let aggr_reg = AggrRegistry::default();
+    let q = tremor_script::query::Query::parse(
+        r#"
+            define window my_window from sliding
+            with
+                size = 5,
+                slide = 2
+            end;"#,
+        &reg,
+        &aggr_reg,
+    )?;
+    let window_defn = match q.query.scope.content.windows.values().next() {
+        Some(defn) => defn,
+        other => return Err(format!("Didnt get a window defn, got: {:?}", other).into()),
+    };
+    assert!(window_defn_to_impl(window_defn).is_err());
+    Ok(())
+}
+
+#[test]
+fn session_window_on_time_emit() -> Result<()> {
+    let mut window = window::SessionOnTime::from_stmt(10, window::Impl::DEFAULT_MAX_GROUPS, None);
+    let vm = literal!({
+       "h2g2" : 42,
+    })
+    .into();
+    // nothing to close without a session
+    assert_eq!(Actions::all_false(), window.on_tick(100));
+    // start a session
+    assert_eq!(Actions::all_false(), window.on_event(&vm, 100, &None)?);
+    // extend the session
+    assert_eq!(Actions::all_false(), window.on_event(&vm, 105, &None)?);
+    assert_eq!(Actions::all_false(), window.on_tick(114));
+    // gap reached, close the session
+    assert_eq!(
+        Actions {
+            include: false,
+            emit: true
+        },
+        window.on_tick(115)
+    );
+    assert_eq!(Actions::all_false(), window.on_tick(130));
+    // start a new session
+    assert_eq!(Actions::all_false(), window.on_event(&vm, 200, &None)?);
+    // the event arrives after the gap, it closes the session and starts a new one
+    assert_eq!(
+        Actions {
+            include: false,
+            emit: true
+        },
+        window.on_event(&vm, 210, &None)?
+    );
+    Ok(())
+}
+
+#[test]
+fn tumbling_window_on_event_time_assign() -> Result<()> {
+    let reg = Registry::default();
+    let aggr_reg = AggrRegistry::default();
+    let q = tremor_script::query::Query::parse(
+        r#"
+            define window my_window from tumbling
+            with
+                interval = 10,
+                event_time = true,
+                max_out_of_orderness = 5,
+                allowed_lateness = 10
+            script
+                event.t
+            end;"#,
+        &reg,
+        &aggr_reg,
+    )?;
+    let window_defn = match q.query.scope.content.windows.values().next() {
+        Some(defn) => defn,
+        other => return Err(format!("Didnt get a window defn, got: {:?}", other).into()),
+    };
+    let mut window = window::TumblingOnEventTime::from_stmt(
+        10,
+        5,
+        10,
+        window::Impl::DEFAULT_MAX_GROUPS,
+        Some(window_defn),
+    );
+    assert_eq!(1, window.closed_windows());
+    let mut max_time = 0;
+    let mut assign = |t: u64| -> Result<Assignment> {
+        let time = window.event_time(&literal!({ "t": t }).into(), 0, &None)?;
+        max_time = max_time.max(time);
+        let watermark = window.watermark(max_time);
+        Ok(window.assign(time, watermark))
+    };
+    let open = |closes| Assignment {
+        closes,
+        slot: Slot::Open,
+    };
+    // the first event opens the window [0, 10)
+    assert_eq!(open(0), assign(0)?);
+    // the watermark (7) has not reached the end of the window yet
+    assert_eq!(
+        Assignment {
+            closes: 0,
+            slot: Slot::Pending(1)
+        },
+        assign(12)?
+    );
+    assert_eq!(open(0), assign(3)?);
+    // the watermark (11) closes [0, 10)
+    assert_eq!(open(1), assign(16)?);
+    // still within the allowed lateness of [0, 10)
+    assert_eq!(
+        Assignment {
+            closes: 0,
+            slot: Slot::Closed(1)
+        },
+        assign(8)?
+    );
+    // the watermark (25) closes [10, 20)
+    assert_eq!(
+        Assignment {
+            closes: 1,
+            slot: Slot::Pending(1)
+        },
+        assign(30)?
+    );
+    // [0, 10) is past its allowed lateness
+    assert_eq!(
+        Assignment {
+            closes: 0,
+            slot: Slot::Late
+        },
+        assign(2)?
+    );
+    assert_eq!(
+        Assignment {
+            closes: 0,
+            slot: Slot::Closed(1)
+        },
+        assign(15)?
+    );
+    Ok(())
+}
+
+#[test]
+fn select_event_time_win_late() -> Result<()> {
+    let mut select = select_stmt_from_query(
+        r#"
+        define window window1 from tumbling
+        with
+            interval = 10,
+            event_time = true,
+            max_out_of_orderness = 5,
+            allowed_lateness = 10
+        script
+            event.t
+        end;
+        select aggr::win::collect_flattened(event.t) from in[window1] into out;
+        "#,
+    )?;
+    let uid = OperatorId::new(42);
+    let mut state = Value::null();
+    let mut enqueue = |t: u64| {
+        let event = Event {
+            id: (1, 1, t).into(),
+            ingest_ns: t,
+            data: literal!({ "t": t }).into(),
+            ..Event::default()
+        };
+        select.on_event(uid, "IN", &mut state, event)
+    };
+
+    for t in [0, 12, 3] {
+        assert!(enqueue(t)?.events.is_empty());
+    }
+    // closes [0, 10), 12 was already placed in [10, 20)
+    let eis = enqueue(16)?;
+    assert_eq!(1, eis.events.len());
+    assert_eq!("out", eis.events[0].0);
+    assert_eq!("[0,3]", sorted_serialize(eis.events[0].1.data.parts().0)?);
+
+    // late but allowed, [0, 10) is updated and emitted again
+    let eis = enqueue(8)?;
+    assert_eq!(1, eis.events.len());
+    assert_eq!("out", eis.events[0].0);
+    assert_eq!("[0,3,8]", sorted_serialize(eis.events[0].1.data.parts().0)?);
+
+    // closes [10, 20)
+    let eis = enqueue(30)?;
+    assert_eq!(1, eis.events.len());
+    assert_eq!("[12,16]", sorted_serialize(eis.events[0].1.data.parts().0)?);
+
+    // too late for [0, 10), the event goes to the `late` port untouched
+    let eis = enqueue(2)?;
+    assert_eq!(1, eis.events.len());
+    assert_eq!("late", eis.events[0].0);
+    assert_eq!(
+        r#"{"t":2}"#,
+        sorted_serialize(eis.events[0].1.data.parts().0)?
+    );
+    Ok(())
+}
+
+#[test]
+fn select_event_time_win_shared_watermark() -> Result<()> {
+    let mut select = select_stmt_from_query(
+        r#"
+        define window window1 from tumbling
+        with
+            interval = 10,
+            event_time = true
+        script
+            event.t
+        end;
+        select aggr::win::collect_flattened(event.t) from in[window1] group by event.g into out;
+        "#,
+    )?;
+    let uid = OperatorId::new(42);
+    let mut state = Value::null();
+    let mut enqueue = |g: &str, t: u64| {
+        let event = Event {
+            id: (1, 1, t).into(),
+            ingest_ns: t,
+            data: literal!({ "g": g.to_string(), "t": t }).into(),
+            ..Event::default()
+        };
+        select.on_event(uid, "IN", &mut state, event)
+    };
+
+    assert!(enqueue("a", 0)?.events.is_empty());
+    assert!(enqueue("a", 3)?.events.is_empty());
+    // the watermark advanced by group `b` closes the window of group `a` as well
+    let eis = enqueue("b", 12)?;
+    assert_eq!(1, eis.events.len());
+    assert_eq!("out", eis.events[0].0);
+    assert_eq!("[0,3]", sorted_serialize(eis.events[0].1.data.parts().0)?);
+    Ok(())
+}
+
+#[test]
+fn tumbling_window_event_time_opt_in() -> Result<()> {
+    let reg = Registry::default();
+    let aggr_reg = AggrRegistry::default();
+    let windows = [
+        // lateness settings require an event time window
+        r#"
+            define window my_window from tumbling
+            with
+                interval = 10,
+                allowed_lateness = 10
+            script
+                event.t
+            end;"#,
+        // event time windows need a script providing the event time
+        r#"
+            define window my_window from tumbling
+            with
+                interval = 10,
+                event_time = true
+            end;"#,
+        // only tumbling windows with an interval support event time
+        r#"
+            define window my_window from tumbling
+            with
+                size = 10,
+                event_time = true
+            script
+                event.t
+            end;"#,
+    ];
+    for window in windows {
+        let q = tremor_script::query::Query::parse(window, &reg, &aggr_reg)?;
+        let window_defn = match q.query.scope.content.windows.values().next() {
+            Some(defn) => defn,
+            other => return Err(format!("Didnt get a window defn, got: {:?}", other).into()),
+        };
+        assert!(window_defn_to_impl(window_defn).is_err());
+    }
+
+    // a scripted window without `event_time` stays a processing time window
+    let q = tremor_script::query::Query::parse(
+        r#"
+            define window my_window from tumbling
+            with
+                interval = 10
+            script
+                event.t
+            end;"#,
+        &reg,
+        &aggr_reg,
+    )?;
+    let window_defn = match q.query.scope.content.windows.values().next() {
+        Some(defn) => defn,
+        other => return Err(format!("Didnt get a window defn, got: {:?}", other).into()),
+    };
+    assert!(matches!(
+        window_defn_to_impl(window_defn)?,
+        window::Impl::TumblingTimeBased(_)
+    ));
+    Ok(())
+}
@@ -15,17 +15,18 @@
 use crate::{Event, EventId, EventIdGenerator, OpMeta};
 use beef::Cow;
 use std::borrow::Cow as SCow;
+use std::collections::VecDeque;
 use tremor_common::stry;
 use tremor_script::{
     self,
-    ast::{AggrSlice, Aggregates, Consts, RunConsts, Select, WindowDefinition},
+    ast::{AggrSlice, Aggregates, Consts, RunConsts, Script, Select, WindowDefinition},
     errors::Result,
     interpreter::{Env, LocalStack},
     prelude::*,
     Value, NO_AGGRS,
 };
 
-use super::select::execute_select_and_having;
+use super::select::{execute_select_and_having, late_event};
 
 pub(crate) struct SelectCtx<'run, 'script, 'local> {
     pub(crate) select: &'run Select<'script>,
@@ -39,6 +40,16 @@ pub(crate) struct SelectCtx<'run, 'script, 'local> {
     pub(crate) origin_uri: &'run Option<EventOriginUri>,
     pub(crate) transactional: bool,
     pub(crate) recursion_limit: u32,
+    /// The event time of the event if the select has an event time window
+    pub(crate) event_time: Option<EventTime>,
+}
+
+/// The time of an event for an event time window and the watermark of that
+/// window once it saw the event. The watermark is shared by all groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventTime {
+    pub(crate) time: u64,
+    pub(crate) watermark: u64,
 }
 
 /// A singular tilt frame (window) inside a group
@@ -63,6 +74,45 @@ pub struct GroupWindow {
     pub(crate) next: Option<Box<GroupWindow>>,
     /// If the window holds any data
     pub(crate) holds_data: bool,
+    /// Closed panes of a sliding window that are still part of
+    /// the window, oldest first. Always empty for tumbling windows.
+    pub(crate) panes: VecDeque<Pane>,
+    /// Windows of an event time window the watermark has not reached
+    /// yet, the first one directly follows the open window.
+    pub(crate) pending: VecDeque<Pane>,
+    /// Already emitted windows of an event time window that are kept
+    /// around to be updated by late events, oldest first.
+    pub(crate) closed: VecDeque<Pane>,
+}
+
+/// A closed slide of a sliding window
+#[derive(Clone, Debug)]
+pub struct Pane {
+    /// The aggregates of this pane
+    aggrs: Aggregates<'static>,
+    /// The event id(s) of all events tracked in this pane
+    id: EventId,
+    /// If the pane holds transactional data
+    transactional: bool,
+    /// If the pane holds any data
+    holds_data: bool,
+    /// When the pane ended, only set for sliding windows over time
+    end: u64,
+}
+
+impl Pane {
+    /// Accumulates data into the pane, `consts.window` needs to be set already
+    fn accumulate(
+        &mut self,
+        ctx: &SelectCtx,
+        consts: RunConsts,
+        data: &ValueAndMeta,
+    ) -> Result<()> {
+        self.id.track(&ctx.event_id);
+        self.transactional |= ctx.transactional;
+        self.holds_data = true;
+        accumulate_aggrs(&mut self.aggrs, ctx, consts, data)
+    }
 }
 
 impl GroupWindow {
@@ -84,6 +134,9 @@ impl GroupWindow {
                 transactional: false,
                 next: GroupWindow::from_windows(aggrs, id, iter),
                 holds_data: false,
+                panes: VecDeque::new(),
+                pending: VecDeque::new(),
+                closed: VecDeque::new(),
             })
         })
     }
@@ -96,6 +149,169 @@ impl GroupWindow {
         self.holds_data = false;
     }
 
+    /// If any of the closed panes of a sliding window or any pending
+    /// window of an event time window still holds data
+    pub(crate) fn panes_hold_data(&self) -> bool {
+        self.panes
+            .iter()
+            .chain(self.pending.iter())
+            .any(|p| p.holds_data)
+    }
+
+    /// Creates a pane without any data
+    fn empty_pane(&self, id: EventId) -> Pane {
+        let mut aggrs = self.aggrs.clone();
+        for aggr in &mut aggrs {
+            aggr.invocable.init();
+        }
+        Pane {
+            aggrs,
+            id,
+            transactional: false,
+            holds_data: false,
+            end: 0,
+        }
+    }
+
+    /// Drops the panes of a sliding window over time that slid out of
+    /// the window, this happens for windows that didn't see events for
+    /// longer than a slide.
+    fn evict_panes(&mut self) {
+        if let Some(expiry) = self.window.pane_expiry() {
+            while self.panes.front().map_or(false, |p| p.end <= expiry) {
+                self.panes.pop_front();
+            }
+        }
+    }
+
+    /// Retains the current pane of a sliding window for the following
+    /// emissions and drops the oldest pane once it slid out of the window.
+    ///
+    /// This needs to be called after emitting but before resetting the window.
+    /// For event time windows the emitted window is kept for late events
+    /// as long as the allowed lateness requires.
+    pub(crate) fn close_pane(&mut self, id: &EventId) {
+        let panes = self.window.panes();
+        if panes > 1 {
+            self.panes.push_back(Pane {
+                aggrs: self.aggrs.clone(),
+                id: id.clone(),
+                transactional: self.transactional,
+                holds_data: self.holds_data,
+                end: self.window.pane_end(),
+            });
+            while self.panes.len() >= panes {
+                self.panes.pop_front();
+            }
+        }
+        let closed = self.window.closed_windows();
+        if closed > 0 {
+            self.closed.push_back(Pane {
+                aggrs: self.aggrs.clone(),
+                id: id.clone(),
+                transactional: self.transactional,
+                holds_data: self.holds_data,
+                end: 0,
+            });
+            while self.closed.len() > closed {
+                self.closed.pop_front();
+            }
+        }
+    }
+
+    /// Executes the select body and `having` clause on the windows data.
+    ///
+    /// For sliding windows the aggregates of all panes still inside the
+    /// window are merged first, oldest first, and the emitted event
+    /// tracks the ids of all those panes.
+    pub(crate) fn emit(
+        &mut self,
+        ctx: &mut SelectCtx,
+        consts: RunConsts,
+        data: &ValueAndMeta,
+        events: &mut Vec<(Cow<'static, str>, Event)>,
+    ) -> Result<()> {
+        self.evict_panes();
+        let mut consts = consts;
+        consts.window = &self.name;
+        if self.panes.is_empty() {
+            if self.holds_data {
+                let env = Env {
+                    context: ctx.ctx,
+                    consts,
+                    aggrs: &self.aggrs,
+                    recursion_limit: ctx.recursion_limit,
+                };
+
+                // execute the select body and apply the `having` to see if we publish an event
+                if let Some(port_and_event) = stry!(execute_select_and_having(ctx, &env, data)) {
+                    events.push(port_and_event);
+                };
+            }
+            return Ok(());
+        }
+
+        let mut merged = self.empty_pane(ctx.event_id.clone());
+        // the current pane's ids and transactionality are already in the context
+        merged.transactional = ctx.transactional;
+        let closed = self
+            .panes
+            .iter()
+            .map(|p| (p.holds_data, &p.aggrs, Some((&p.id, p.transactional))));
+        let current = std::iter::once((self.holds_data, &self.aggrs, None));
+        for (pane_holds_data, pane_aggrs, pane_ids) in closed.chain(current) {
+            if !pane_holds_data {
+                continue;
+            }
+            for (this, prev) in merged.aggrs.iter_mut().zip(pane_aggrs.iter()) {
+                stry!(this.invocable.merge(&prev.invocable).map_err(|e| {
+                    let r: Option<&Registry> = None;
+                    e.into_err(prev, prev, r)
+                }));
+            }
+            if let Some((pane_id, pane_transactional)) = pane_ids {
+                merged.id.track(pane_id);
+                merged.transactional |= pane_transactional;
+            }
+            merged.holds_data = true;
+        }
+        self.emit_pane(ctx, consts, &merged, data, events)
+    }
+
+    /// Executes the select body and `having` clause on the data of a pane
+    /// that isn't the open window, the emitted event carries the pane's
+    /// event ids and transactionality.
+    fn emit_pane(
+        &self,
+        ctx: &mut SelectCtx,
+        consts: RunConsts,
+        pane: &Pane,
+        data: &ValueAndMeta,
+        events: &mut Vec<(Cow<'static, str>, Event)>,
+    ) -> Result<()> {
+        if !pane.holds_data {
+            return Ok(());
+        }
+        let mut consts = consts;
+        consts.window = &self.name;
+        let env = Env {
+            context: ctx.ctx,
+            consts,
+            aggrs: &pane.aggrs,
+            recursion_limit: ctx.recursion_limit,
+        };
+        // we swap the context for the execution and restore it afterwards
+        let own_id = std::mem::replace(&mut ctx.event_id, pane.id.clone());
+        let own_transactional = std::mem::replace(&mut ctx.transactional, pane.transactional);
+        let res = execute_select_and_having(ctx, &env, data);
+        ctx.event_id = own_id;
+        ctx.transactional = own_transactional;
+        if let Some(port_and_event) = stry!(res) {
+            events.push(port_and_event);
+        };
+        Ok(())
+    }
+
     /// Accumultes data into the window
     pub(crate) fn accumulate(
         &mut self,
@@ -114,52 +330,7 @@ impl GroupWindow {
         let mut consts = consts;
         consts.window = &self.name;
 
-        // create an execution environment for the accumulation
-        // note: we set aggrs to no_aggrs sice nested aggregation
-        // is not supported and the `env` is used to evaluate
-        // the function arguments for the aggregates not the
-        // aggregates themsefls
-        let env = Env {
-            context: ctx.ctx,
-            consts,
-            aggrs: &NO_AGGRS,
-            recursion_limit: ctx.recursion_limit,
-        };
-
-        let (event_data, event_meta) = data.parts();
-        let SelectCtx { opts, .. } = ctx;
-        for aggr in &mut self.aggrs {
-            let invocable = &mut aggr.invocable;
-            // We need two arrays to handle the we know the lenght so
-            // we pre-allocate. We need this to minimize copying and allocations
-            // the functions take a refference to a value and since we
-            // might get owned data back in the `Cow` we don't know for
-            // sure if we can reference it without keeping ownership.
-
-            // the first one is the computed data in `Cow`s
-            let mut argv: Vec<SCow<Value>> = Vec::with_capacity(aggr.args.len());
-            // the second vector are refernces to the first vector
-            let mut argv1: Vec<&Value> = Vec::with_capacity(aggr.args.len());
-
-            // evaluate the arguments
-            for arg in &aggr.args {
-                let result =
-                    stry!(arg.run(*opts, &env, event_data, &NULL, event_meta, ctx.local_stack));
-                argv.push(result);
-            }
-
-            // collect references to them
-            for arg in &argv {
-                argv1.push(arg);
-            }
-            // now execute the fnctions
-            stry!(invocable.accumulate(argv1.as_slice()).map_err(|e| {
-                // TODO nice error
-                let r: Option<&Registry> = None;
-                e.into_err(aggr, aggr, r)
-            }));
-        }
-        Ok(())
+        accumulate_aggrs(&mut self.aggrs, ctx, consts, data)
     }
 
     /// Merge data from the privious tilt frame / window into this one
@@ -199,6 +370,14 @@ impl GroupWindow {
         prev: Option<(bool, &Aggregates<'static>)>,
         mut can_remove: bool,
     ) -> Result<bool> {
+        // event time windows place root level events by their timestamp
+        if let (None, Some(event_time)) = (prev, ctx.event_time) {
+            if let Impl::TumblingEventTimeBased(w) = &mut self.window {
+                let assignment = w.assign(event_time.time, event_time.watermark);
+                return self.on_event_time(ctx, consts, data, events, assignment, can_remove);
+            }
+        }
+
         // determin what to do with the event
         let window_event = stry!(self.window.on_event(data, ctx.ingest_ns, ctx.origin_uri));
 
@@ -218,57 +397,13 @@ impl GroupWindow {
 
         // if we should emit, do that
         if window_event.emit {
-            // create a new event id for the next window recording
-
-            // Move the recorded event ID into the context so it is
-            // used for inclusion for the following windows.
-            std::mem::swap(&mut ctx.event_id, &mut self.id);
-            // then create a new event ID for the next window
-            self.id = ctx.event_id_gen.next_id();
-
-            // for the context the transactionality of any following window
-            // is the transactionality of this window (since we propagate
-            // the current data along the tilt frames)
-            ctx.transactional = self.transactional;
-
-            // Set the window name for emission
-
-            if self.holds_data {
-                let mut consts = consts;
-                consts.window = &self.name;
-                let env = Env {
-                    context: ctx.ctx,
-                    consts,
-                    aggrs: &self.aggrs,
-                    recursion_limit: ctx.recursion_limit,
-                };
-
-                // execute thw select body and apply the `having` to see if we publish an event
-                if let Some(port_and_event) = stry!(execute_select_and_having(ctx, &env, data)) {
-                    events.push(port_and_event);
-                };
-            }
-            // if we have another tilt frame after that emit our aggregated data to it
-            // this happens after emitting so we keep order of the events from the
-            // smallest to the largest window
-            if let Some(next) = &mut self.next {
-                can_remove = can_remove
-                    && stry!(next.on_event(
-                        ctx,
-                        consts,
-                        data,
-                        events,
-                        Some((self.holds_data, &self.aggrs)),
-                        can_remove
-                    ));
-            }
-            // since we emitted we now can reset this window
-            self.reset();
+            can_remove = stry!(self.close(ctx, consts, data, events, can_remove));
         }
         if window_event.include {
             // if include is set we recorded the event earlier, meaning that
             // from the point of view of this window we could remove the group
-            Ok(can_remove)
+            // unless a sliding window still holds on to data in older panes
+            Ok(can_remove && !self.panes_hold_data())
         } else {
             // The event wasn't recorded earlier so we need to record it now
             // either by merging the pervious aggregates or accumulating the
@@ -284,6 +419,289 @@ impl GroupWindow {
             Ok(false)
         }
     }
+
+    /// Emits the window, passes its data on to the next tilt frame
+    /// and resets it.
+    ///
+    /// # Returns
+    ///
+    /// If the group can still be removed from the point of view of the
+    /// following tilt frames.
+    fn close(
+        &mut self,
+        ctx: &mut SelectCtx,
+        consts: RunConsts,
+        data: &ValueAndMeta,
+        events: &mut Vec<(Cow<'static, str>, Event)>,
+        mut can_remove: bool,
+    ) -> Result<bool> {
+        // create a new event id for the next window recording
+
+        // Move the recorded event ID into the context so it is
+        // used for inclusion for the following windows.
+        std::mem::swap(&mut ctx.event_id, &mut self.id);
+        // then create a new event ID for the next window
+        self.id = ctx.event_id_gen.next_id();
+
+        // for the context the transactionality of any following window
+        // is the transactionality of this window (since we propagate
+        // the current data along the tilt frames)
+        ctx.transactional = self.transactional;
+
+        // execute the select body and apply the `having` to see if we publish an event
+        stry!(self.emit(ctx, consts, data, events));
+        // keep the closed pane around if we are a sliding or event time window,
+        // this has to happen before the next tilt frame swaps out the event id
+        self.close_pane(&ctx.event_id);
+
+        // if we have another tilt frame after that emit our aggregated data to it
+        // this happens after emitting so we keep order of the events from the
+        // smallest to the largest window. For sliding windows only the data of the
+        // closed pane is passed on so no event is counted twice.
+        if let Some(next) = &mut self.next {
+            can_remove = can_remove
+                && stry!(next.on_event(
+                    ctx,
+                    consts,
+                    data,
+                    events,
+                    Some((self.holds_data, &self.aggrs)),
+                    can_remove
+                ));
+        }
+        // since we emitted we now can reset this window
+        self.reset();
+        Ok(can_remove)
+    }
+
+    /// Handles an event for an event time window, the window decided
+    /// by the events timestamp how many windows the watermark passed
+    /// and where the event belongs.
+    ///
+    /// # Returns
+    ///
+    /// The same as `on_event`
+    fn on_event_time(
+        &mut self,
+        ctx: &mut SelectCtx,
+        consts: RunConsts,
+        data: &ValueAndMeta,
+        events: &mut Vec<(Cow<'static, str>, Event)>,
+        assignment: Assignment,
+        can_remove: bool,
+    ) -> Result<bool> {
+        let can_remove =
+            stry!(self.close_windows(ctx, consts, data, events, assignment.closes, can_remove));
+
+        match assignment.slot {
+            Slot::Open => {
+                stry!(self.accumulate(ctx, consts, data));
+                return Ok(false);
+            }
+            Slot::Pending(n) => {
+                let mut consts = consts;
+                consts.window = &self.name;
+                while self.pending.len() < n {
+                    let pane = self.empty_pane(ctx.event_id_gen.next_id());
+                    self.pending.push_back(pane);
+                }
+                stry!(self.pending[n - 1].accumulate(ctx, consts, data));
+                return Ok(false);
+            }
+            Slot::Closed(n) if n <= self.closed.len() => {
+                // the window was already emitted but we are still within the
+                // allowed lateness, so we update it and emit it again
+                let idx = self.closed.len() - n;
+                let mut consts = consts;
+                consts.window = &self.name;
+                stry!(self.closed[idx].accumulate(ctx, consts, data));
+                stry!(self.emit_pane(ctx, consts, &self.closed[idx], data, events));
+            }
+            Slot::Closed(_) | Slot::Late => {
+                events.push(late_event(ctx, data));
+            }
+        }
+        Ok(can_remove && !self.holds_data && !self.panes_hold_data())
+    }
+
+    /// Closes the windows of an event time window that the watermark, advanced
+    /// by an event of another group, passed.
+    ///
+    /// # Returns
+    ///
+    /// If the group can be removed, as it holds no data any more
+    pub(crate) fn on_watermark(
+        &mut self,
+        ctx: &mut SelectCtx,
+        consts: RunConsts,
+        data: &ValueAndMeta,
+        events: &mut Vec<(Cow<'static, str>, Event)>,
+        watermark: u64,
+    ) -> Result<bool> {
+        let closes = match &mut self.window {
+            Impl::TumblingEventTimeBased(w) => w.advance(watermark),
+            _ => 0,
+        };
+        if closes == 0 {
+            return Ok(false);
+        }
+        let can_remove = stry!(self.close_windows(ctx, consts, data, events, closes, true));
+        Ok(can_remove && !self.holds_data && !self.panes_hold_data())
+    }
+
+    /// Closes `closes` windows of an event time window, pending windows
+    /// become the open window in turn.
+    ///
+    /// # Returns
+    ///
+    /// If the group can still be removed from the point of view of the
+    /// following tilt frames.
+    fn close_windows(
+        &mut self,
+        ctx: &mut SelectCtx,
+        consts: RunConsts,
+        data: &ValueAndMeta,
+        events: &mut Vec<(Cow<'static, str>, Event)>,
+        mut closes: u64,
+        mut can_remove: bool,
+    ) -> Result<bool> {
+        // closing windows moves their ids into the context, we keep the
+        // events own id and transactionality to place it afterwards
+        let event_id = ctx.event_id.clone();
+        let transactional = ctx.transactional;
+        while closes > 0 {
+            closes -= 1;
+            can_remove = stry!(self.close(ctx, consts, data, events, can_remove));
+            if let Some(pane) = self.pending.pop_front() {
+                // the next pending window becomes the open window
+                self.aggrs = pane.aggrs;
+                self.id = pane.id;
+                self.transactional = pane.transactional;
+                self.holds_data = pane.holds_data;
+            } else {
+                // all remaining windows the watermark passed are empty, there is
+                // nothing to emit so we only move the kept windows along
+                let keep = self.window.closed_windows();
+                let empty = usize::try_from(closes).unwrap_or(usize::MAX).min(keep);
+                for _ in 0..empty {
+                    let pane = self.empty_pane(ctx.event_id_gen.next_id());
+                    self.closed.push_back(pane);
+                }
+                while self.closed.len() > keep {
+                    self.closed.pop_front();
+                }
+                break;
+            }
+        }
+        ctx.event_id = event_id;
+        ctx.transactional = transactional;
+
+        Ok(can_remove)
+    }
+}
+
+impl GroupWindow {
+    /// Captures the open window of this tilt frame, returns `None`
+    /// if one of the aggregates can't be captured
+    fn snapshot(&self) -> Option<Value<'static>> {
+        let aggrs = self
+            .aggrs
+            .iter()
+            .map(|aggr| aggr.invocable.snapshot())
+            .collect::<Option<Vec<_>>>()?;
+        // the ids of the events in the window, so they are acknowledged once it emits
+        let id = simd_json_derive::Serialize::json_string(&self.id).ok()?;
+        Some(literal!({
+            "window": self.window.snapshot(),
+            "holds_data": self.holds_data,
+            "aggrs": aggrs,
+            "id": id,
+        }))
+    }
+
+    /// Restores the open window captured by `snapshot`, `id` is used if it
+    /// doesn't contain the ids of the events in the window
+    fn restore(&mut self, snapshot: &Value, id: EventId) -> bool {
+        let aggrs = match snapshot.get_array("aggrs") {
+            Some(aggrs) if aggrs.len() == self.aggrs.len() => aggrs,
+            _ => return false,
+        };
+        if let Some(window) = snapshot.get("window") {
+            self.window.restore(window);
+        }
+        self.id = snapshot
+            .get_str("id")
+            .and_then(|id| {
+                simd_json_derive::Deserialize::from_slice(&mut id.as_bytes().to_vec()).ok()
+            })
+            .unwrap_or(id);
+        self.holds_data = snapshot.get_bool("holds_data").unwrap_or_default();
+        self.aggrs
+            .iter_mut()
+            .zip(aggrs)
+            .all(|(aggr, state)| aggr.invocable.restore(state))
+    }
+}
+
+/// Accumulates an event into a set of aggregates, `consts.window`
+/// needs to be set already
+fn accumulate_aggrs(
+    aggrs: &mut Aggregates<'static>,
+    ctx: &SelectCtx,
+    consts: RunConsts,
+    data: &ValueAndMeta,
+) -> Result<()> {
+    // create an execution environment for the accumulation
+    // note: we set aggrs to no_aggrs sice nested aggregation
+    // is not supported and the `env` is used to evaluate
+    // the function arguments for the aggregates not the
+    // aggregates themsefls
+    let env = Env {
+        context: ctx.ctx,
+        consts,
+        aggrs: &NO_AGGRS,
+        recursion_limit: ctx.recursion_limit,
+    };
+
+    let (event_data, event_meta) = data.parts();
+    for aggr in aggrs {
+        let invocable = &mut aggr.invocable;
+        // We need two arrays to handle the we know the lenght so
+        // we pre-allocate. We need this to minimize copying and allocations
+        // the functions take a refference to a value and since we
+        // might get owned data back in the `Cow` we don't know for
+        // sure if we can reference it without keeping ownership.
+
+        // the first one is the computed data in `Cow`s
+        let mut argv: Vec<SCow<Value>> = Vec::with_capacity(aggr.args.len());
+        // the second vector are refernces to the first vector
+        let mut argv1: Vec<&Value> = Vec::with_capacity(aggr.args.len());
+
+        // evaluate the arguments
+        for arg in &aggr.args {
+            let result = stry!(arg.run(
+                ctx.opts,
+                &env,
+                event_data,
+                &NULL,
+                event_meta,
+                ctx.local_stack
+            ));
+            argv.push(result);
+        }
+
+        // collect references to them
+        for arg in &argv {
+            argv1.push(arg);
+        }
+        // now execute the fnctions
+        stry!(invocable.accumulate(argv1.as_slice()).map_err(|e| {
+            // TODO nice error
+            let r: Option<&Registry> = None;
+            e.into_err(aggr, aggr, r)
+        }));
+    }
+    Ok(())
 }
 
 /// A group wiht a number of none or more tilt frames
@@ -293,6 +711,9 @@ pub struct Group {
     pub(crate) value: Value<'static>,
     /// the first window in the group (or none)
     pub(crate) windows: Option<Box<GroupWindow>>,
+    /// The ids of the events in the restored windows, sources send
+    /// them again as they were not acknowledged yet
+    pub(crate) restored: Option<EventId>,
 }
 
 impl Group {
@@ -301,9 +722,13 @@ impl Group {
     /// the data but also sets to windo into a state of 'never
     /// having seen an element'.
     pub(crate) fn reset(&mut self) {
+        self.restored = None;
         let mut w = &mut self.windows;
         while let Some(g) = w {
             g.reset();
+            g.panes.clear();
+            g.pending.clear();
+            g.closed.clear();
             g.window.reset();
             w = &mut g.next;
         }
@@ -324,6 +749,14 @@ impl Group {
         data: &ValueAndMeta,
         events: &mut Vec<(Cow<'static, str>, Event)>,
     ) -> Result<bool> {
+        // events replayed after a restart are in the restored windows already
+        if self
+            .restored
+            .as_ref()
+            .map_or(false, |restored| restored.is_tracking(&ctx.event_id))
+        {
+            return Ok(false);
+        }
         // Set the group value for the exeuction
         let mut run = consts.run();
         run.group = &self.value;
@@ -348,6 +781,55 @@ impl Group {
     }
 }
 
+impl Group {
+    /// Captures the open windows of all tilt frames of the group, returns
+    /// `None` if one of the aggregates can't be captured.
+    ///
+    /// Only the open windows are captured, the panes of sliding windows and
+    /// pending or closed windows of event time windows start over empty.
+    pub(crate) fn snapshot(&self) -> Option<Value<'static>> {
+        let mut windows = Vec::new();
+        let mut w = &self.windows;
+        while let Some(g) = w {
+            windows.push(g.snapshot()?);
+            w = &g.next;
+        }
+        Some(literal!({
+            "value": self.value.clone(),
+            "windows": windows,
+        }))
+    }
+
+    /// Restores the windows captured by `snapshot`. The events in them are
+    /// not acknowledged until the windows emit, so sources send them again
+    /// after a restart, those are not accumulated a second time.
+    pub(crate) fn restore(
+        &mut self,
+        snapshot: &Value,
+        event_id_gen: &mut EventIdGenerator,
+    ) -> bool {
+        let (value, windows) = match (snapshot.get("value"), snapshot.get_array("windows")) {
+            (Some(value), Some(windows)) => (value, windows),
+            _ => return false,
+        };
+        self.value = value.clone_static();
+        let mut windows = windows.iter();
+        let mut w = &mut self.windows;
+        let mut restored = event_id_gen.next_id();
+        while let Some(g) = w {
+            match windows.next() {
+                Some(snapshot) if g.restore(snapshot, event_id_gen.next_id()) => {
+                    restored.track(&g.id);
+                }
+                _ => return false,
+            }
+            w = &mut g.next;
+        }
+        self.restored = Some(restored);
+        true
+    }
+}
+
 // Windowing implementaitons and traits
 
 pub trait Trait: std::fmt::Debug {
@@ -366,6 +848,32 @@ pub trait Trait: std::fmt::Debug {
     /// decreasing this value will guard against runwaway memory growth
     /// when faced with unexpected huge cardinalities for grouping dimensions
     fn max_groups(&self) -> usize;
+    /// number of panes (slides) a window spans, every emission covers
+    /// the data of the last `panes` slides. Tumbling windows span exactly one.
+    fn panes(&self) -> usize {
+        1
+    }
+    /// the time the pane a sliding window over time closed last ended at
+    fn pane_end(&self) -> u64 {
+        0
+    }
+    /// panes of a sliding window over time that ended at or before
+    /// this time slid out of the window
+    fn pane_expiry(&self) -> Option<u64> {
+        None
+    }
+    /// number of already emitted windows an event time window keeps
+    /// around so late events within the allowed lateness can update them
+    fn closed_windows(&self) -> usize {
+        0
+    }
+    /// Captures the progress of the window, like the number of events
+    /// seen, so it can be persisted
+    fn snapshot(&self) -> Value<'static> {
+        Value::null()
+    }
+    /// Restores the progress captured by `snapshot`
+    fn restore(&mut self, _snapshot: &Value) {}
 }
 
 #[derive(Debug)]
@@ -384,6 +892,10 @@ impl Window {
 pub enum Impl {
     TumblingCountBased(TumblingOnNumber),
     TumblingTimeBased(TumblingOnTime),
+    SlidingCountBased(SlidingOnNumber),
+    SlidingTimeBased(SlidingOnTime),
+    SessionTimeBased(SessionOnTime),
+    TumblingEventTimeBased(TumblingOnEventTime),
 }
 
 impl Impl {
@@ -395,6 +907,10 @@ impl Impl {
         match self {
             Self::TumblingTimeBased(w) => w.reset(),
             Self::TumblingCountBased(w) => w.reset(),
+            Self::SlidingTimeBased(w) => w.reset(),
+            Self::SlidingCountBased(w) => w.reset(),
+            Self::SessionTimeBased(w) => w.reset(),
+            Self::TumblingEventTimeBased(w) => w.reset(),
         }
     }
 }
@@ -409,6 +925,10 @@ impl Trait for Impl {
         match self {
             Self::TumblingTimeBased(w) => w.on_event(data, ingest_ns, origin_uri),
             Self::TumblingCountBased(w) => w.on_event(data, ingest_ns, origin_uri),
+            Self::SlidingTimeBased(w) => w.on_event(data, ingest_ns, origin_uri),
+            Self::SlidingCountBased(w) => w.on_event(data, ingest_ns, origin_uri),
+            Self::SessionTimeBased(w) => w.on_event(data, ingest_ns, origin_uri),
+            Self::TumblingEventTimeBased(w) => w.on_event(data, ingest_ns, origin_uri),
         }
     }
 
@@ -416,6 +936,10 @@ impl Trait for Impl {
         match self {
             Self::TumblingTimeBased(w) => w.on_tick(ns),
             Self::TumblingCountBased(w) => w.on_tick(ns),
+            Self::SlidingTimeBased(w) => w.on_tick(ns),
+            Self::SlidingCountBased(w) => w.on_tick(ns),
+            Self::SessionTimeBased(w) => w.on_tick(ns),
+            Self::TumblingEventTimeBased(w) => w.on_tick(ns),
         }
     }
 
@@ -423,6 +947,76 @@ impl Trait for Impl {
         match self {
             Self::TumblingTimeBased(w) => w.max_groups(),
             Self::TumblingCountBased(w) => w.max_groups(),
+            Self::SlidingTimeBased(w) => w.max_groups(),
+            Self::SlidingCountBased(w) => w.max_groups(),
+            Self::SessionTimeBased(w) => w.max_groups(),
+            Self::TumblingEventTimeBased(w) => w.max_groups(),
+        }
+    }
+
+    fn panes(&self) -> usize {
+        match self {
+            Self::TumblingTimeBased(w) => w.panes(),
+            Self::TumblingCountBased(w) => w.panes(),
+            Self::SlidingTimeBased(w) => w.panes(),
+            Self::SlidingCountBased(w) => w.panes(),
+            Self::SessionTimeBased(w) => w.panes(),
+            Self::TumblingEventTimeBased(w) => w.panes(),
+        }
+    }
+
+    fn pane_end(&self) -> u64 {
+        match self {
+            Self::TumblingTimeBased(w) => w.pane_end(),
+            Self::TumblingCountBased(w) => w.pane_end(),
+            Self::SlidingTimeBased(w) => w.pane_end(),
+            Self::SlidingCountBased(w) => w.pane_end(),
+            Self::SessionTimeBased(w) => w.pane_end(),
+            Self::TumblingEventTimeBased(w) => w.pane_end(),
+        }
+    }
+
+    fn pane_expiry(&self) -> Option<u64> {
+        match self {
+            Self::TumblingTimeBased(w) => w.pane_expiry(),
+            Self::TumblingCountBased(w) => w.pane_expiry(),
+            Self::SlidingTimeBased(w) => w.pane_expiry(),
+            Self::SlidingCountBased(w) => w.pane_expiry(),
+            Self::SessionTimeBased(w) => w.pane_expiry(),
+            Self::TumblingEventTimeBased(w) => w.pane_expiry(),
+        }
+    }
+
+    fn closed_windows(&self) -> usize {
+        match self {
+            Self::TumblingTimeBased(w) => w.closed_windows(),
+            Self::TumblingCountBased(w) => w.closed_windows(),
+            Self::SlidingTimeBased(w) => w.closed_windows(),
+            Self::SlidingCountBased(w) => w.closed_windows(),
+            Self::SessionTimeBased(w) => w.closed_windows(),
+            Self::TumblingEventTimeBased(w) => w.closed_windows(),
+        }
+    }
+
+    fn snapshot(&self) -> Value<'static> {
+        match self {
+            Self::TumblingTimeBased(w) => w.snapshot(),
+            Self::TumblingCountBased(w) => w.snapshot(),
+            Self::SlidingTimeBased(w) => w.snapshot(),
+            Self::SlidingCountBased(w) => w.snapshot(),
+            Self::SessionTimeBased(w) => w.snapshot(),
+            Self::TumblingEventTimeBased(w) => w.snapshot(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: &Value) {
+        match self {
+            Self::TumblingTimeBased(w) => w.restore(snapshot),
+            Self::TumblingCountBased(w) => w.restore(snapshot),
+            Self::SlidingTimeBased(w) => w.restore(snapshot),
+            Self::SlidingCountBased(w) => w.restore(snapshot),
+            Self::SessionTimeBased(w) => w.restore(snapshot),
+            Self::TumblingEventTimeBased(w) => w.restore(snapshot),
         }
     }
 }
@@ -437,6 +1031,26 @@ impl From<TumblingOnTime> for Impl {
         Self::TumblingTimeBased(w)
     }
 }
+impl From<SlidingOnNumber> for Impl {
+    fn from(w: SlidingOnNumber) -> Self {
+        Self::SlidingCountBased(w)
+    }
+}
+impl From<SlidingOnTime> for Impl {
+    fn from(w: SlidingOnTime) -> Self {
+        Self::SlidingTimeBased(w)
+    }
+}
+impl From<SessionOnTime> for Impl {
+    fn from(w: SessionOnTime) -> Self {
+        Self::SessionTimeBased(w)
+    }
+}
+impl From<TumblingOnEventTime> for Impl {
+    fn from(w: TumblingOnEventTime) -> Self {
+        Self::TumblingEventTimeBased(w)
+    }
+}
 
 #[derive(Debug, PartialEq, Default)]
 pub struct Actions {
@@ -475,6 +1089,32 @@ impl Trait for No {
     }
 }
 
+/// Runs the script of a window definition and returns the `u64` it evaluates
+/// to. This is used for data based windows to provide a timestamp or a count
+/// for an event.
+fn script_value(
+    script: &Script<'static>,
+    data: &ValueAndMeta,
+    ingest_ns: u64,
+    origin_uri: &Option<EventOriginUri>,
+) -> Result<u64> {
+    let context = EventContext::new(ingest_ns, origin_uri.as_ref());
+    let (unwind_event, event_meta) = data.parts();
+    let value = stry!(script.run_imut(
+        &context,
+        AggrType::Emit,
+        unwind_event,   // event
+        &Value::null(), // state for the window
+        event_meta,     // $
+    ));
+    let data = match value {
+        Return::Emit { value, .. } => value.as_u64(),
+        Return::EmitEvent { .. } => unwind_event.as_u64(),
+        Return::Drop { .. } => None,
+    };
+    data.ok_or_else(|| "Data based window didn't provide a valid value".into())
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct TumblingOnTime {
     pub(crate) next_window: Option<u64>,
@@ -502,6 +1142,21 @@ impl TumblingOnTime {
         }
     }
 
+    /// The time of an event, provided by the script or the ingest time
+    fn time(
+        &self,
+        data: &ValueAndMeta,
+        ingest_ns: u64,
+        origin_uri: &Option<EventOriginUri>,
+    ) -> Result<u64> {
+        self.script
+            .as_ref()
+            .and_then(|script| script.script.as_ref())
+            .map_or(Ok(ingest_ns), |script| {
+                script_value(script, data, ingest_ns, origin_uri)
+            })
+    }
+
     fn get_window_event(&mut self, time: u64) -> Actions {
         match self.next_window {
             None => {
@@ -524,34 +1179,19 @@ impl Trait for TumblingOnTime {
     fn max_groups(&self) -> usize {
         self.max_groups
     }
+    fn snapshot(&self) -> Value<'static> {
+        Value::from(self.next_window)
+    }
+    fn restore(&mut self, snapshot: &Value) {
+        self.next_window = snapshot.as_u64();
+    }
     fn on_event(
         &mut self,
         data: &ValueAndMeta,
         ingest_ns: u64,
         origin_uri: &Option<EventOriginUri>,
     ) -> Result<Actions> {
-        let time = stry!(self
-            .script
-            .as_ref()
-            .and_then(|script| script.script.as_ref())
-            .map(|script| {
-                let context = EventContext::new(ingest_ns, origin_uri.as_ref());
-                let (unwind_event, event_meta) = data.parts();
-                let value = stry!(script.run_imut(
-                    &context,
-                    AggrType::Emit,
-                    unwind_event,   // event
-                    &Value::null(), // state for the window
-                    event_meta,     // $
-                ));
-                let data = match value {
-                    Return::Emit { value, .. } => value.as_u64(),
-                    Return::EmitEvent { .. } => unwind_event.as_u64(),
-                    Return::Drop { .. } => None,
-                };
-                data.ok_or_else(|| "Data based window didn't provide a valid value".into())
-            })
-            .unwrap_or(Ok(ingest_ns)));
+        let time = stry!(self.time(data, ingest_ns, origin_uri));
         Ok(self.get_window_event(time))
     }
 
@@ -598,6 +1238,15 @@ impl Trait for TumblingOnNumber {
     fn max_groups(&self) -> usize {
         self.max_groups
     }
+    fn snapshot(&self) -> Value<'static> {
+        literal!([self.count, self.next_eviction])
+    }
+    fn restore(&mut self, snapshot: &Value) {
+        if let Some([count, next_eviction]) = snapshot.as_array().map(Vec::as_slice) {
+            self.count = count.as_u64().unwrap_or_default();
+            self.next_eviction = next_eviction.as_u64().unwrap_or_default();
+        }
+    }
     fn on_event(
         &mut self,
         data: &ValueAndMeta,
@@ -608,23 +1257,9 @@ impl Trait for TumblingOnNumber {
             .script
             .as_ref()
             .and_then(|script| script.script.as_ref())
-            .map_or(Ok(1), |script| {
-                let context = EventContext::new(ingest_ns, origin_uri.as_ref());
-                let (unwind_event, event_meta) = data.parts();
-                let value = stry!(script.run_imut(
-                    &context,
-                    AggrType::Emit,
-                    unwind_event,   // event
-                    &Value::null(), // state for the window
-                    event_meta,     // $
-                ));
-                let data = match value {
-                    Return::Emit { value, .. } => value.as_u64(),
-                    Return::EmitEvent { .. } => unwind_event.as_u64(),
-                    Return::Drop { .. } => None,
-                };
-                data.ok_or_else(|| "Data based window didn't provide a valid value".into())
-            }));
+            .map_or(Ok(1), |script| script_value(
+                script, data, ingest_ns, origin_uri
+            )));
 
         // If we're above count we emit and set the new count to 1
         // ( we emit on the ) previous event
@@ -639,3 +1274,418 @@ impl Trait for TumblingOnNumber {
         }
     }
 }
+
+/// A sliding window over time, it emits every `slide` nanoseconds
+/// with the data of the last `interval` nanoseconds.
+///
+/// The window is split into `interval / slide` panes, every pane is
+/// a tumbling window of `slide` nanoseconds. Panes that ended `interval`
+/// nanoseconds or longer before the latest event or tick are dropped, so
+/// a gap without events doesn't keep them in the window.
+#[derive(Default, Debug, Clone)]
+pub struct SlidingOnTime {
+    slide: TumblingOnTime,
+    panes: usize,
+    /// When the last closed pane ended
+    pane_end: u64,
+    /// The latest time the window saw
+    now: u64,
+}
+
+impl SlidingOnTime {
+    pub(crate) fn reset(&mut self) {
+        self.slide.reset();
+        self.pane_end = 0;
+        self.now = 0;
+    }
+
+    fn get_window_event(&mut self, time: u64) -> Actions {
+        let end = self.slide.next_window;
+        let actions = self.slide.get_window_event(time);
+        if actions.emit {
+            self.pane_end = end.unwrap_or(time);
+        }
+        self.now = self.now.max(time);
+        actions
+    }
+
+    /// creates a window of `panes` slides, each `slide` nanoseconds long
+    pub fn from_stmt(
+        slide: u64,
+        panes: usize,
+        max_groups: usize,
+        script: Option<&WindowDefinition<'static>>,
+    ) -> Self {
+        Self {
+            slide: TumblingOnTime::from_stmt(slide, max_groups, script),
+            panes,
+            pane_end: 0,
+            now: 0,
+        }
+    }
+}
+
+impl Trait for SlidingOnTime {
+    fn max_groups(&self) -> usize {
+        self.slide.max_groups()
+    }
+    fn snapshot(&self) -> Value<'static> {
+        self.slide.snapshot()
+    }
+    fn restore(&mut self, snapshot: &Value) {
+        self.slide.restore(snapshot);
+    }
+    fn panes(&self) -> usize {
+        self.panes
+    }
+    fn pane_end(&self) -> u64 {
+        self.pane_end
+    }
+    fn pane_expiry(&self) -> Option<u64> {
+        let size = self
+            .slide
+            .interval
+            .saturating_mul(u64::try_from(self.panes).unwrap_or(u64::MAX));
+        self.now.checked_sub(size)
+    }
+    fn on_event(
+        &mut self,
+        data: &ValueAndMeta,
+        ingest_ns: u64,
+        origin_uri: &Option<EventOriginUri>,
+    ) -> Result<Actions> {
+        let time = stry!(self.slide.time(data, ingest_ns, origin_uri));
+        Ok(self.get_window_event(time))
+    }
+    fn on_tick(&mut self, ns: u64) -> Actions {
+        if self.slide.script.is_none() {
+            self.get_window_event(ns)
+        } else {
+            // we basically ignore ticks when we have a script with a custom timestamp
+            Actions::all_false()
+        }
+    }
+}
+
+/// A sliding window over a number of events, it emits every `slide`
+/// events with the data of the last `size` events.
+///
+/// The window is split into `size / slide` panes, every pane is
+/// a tumbling window of `slide` events.
+#[derive(Default, Debug, Clone)]
+pub struct SlidingOnNumber {
+    slide: TumblingOnNumber,
+    panes: usize,
+}
+
+impl SlidingOnNumber {
+    pub(crate) fn reset(&mut self) {
+        self.slide.reset();
+    }
+
+    /// creates a window of `panes` slides, each `slide` events long
+    pub fn from_stmt(
+        slide: u64,
+        panes: usize,
+        max_groups: usize,
+        script: Option<&WindowDefinition<'static>>,
+    ) -> Self {
+        Self {
+            slide: TumblingOnNumber::from_stmt(slide, max_groups, script),
+            panes,
+        }
+    }
+}
+
+impl Trait for SlidingOnNumber {
+    fn max_groups(&self) -> usize {
+        self.slide.max_groups()
+    }
+    fn snapshot(&self) -> Value<'static> {
+        self.slide.snapshot()
+    }
+    fn restore(&mut self, snapshot: &Value) {
+        self.slide.restore(snapshot);
+    }
+    fn panes(&self) -> usize {
+        self.panes
+    }
+    fn on_event(
+        &mut self,
+        data: &ValueAndMeta,
+        ingest_ns: u64,
+        origin_uri: &Option<EventOriginUri>,
+    ) -> Result<Actions> {
+        self.slide.on_event(data, ingest_ns, origin_uri)
+    }
+}
+
+/// A session window, it collects events as long as they arrive
+/// within `gap` nanoseconds of each other and emits once no event
+/// was seen for `gap` nanoseconds.
+///
+/// Since every group has its own copy of the window, sessions are
+/// tracked per group.
+///
+/// With a script the session is driven by the timestamps the script
+/// returns only, ticks are ignored, so a session is closed by the
+/// next event of its group past the `gap`.
+#[derive(Default, Debug, Clone)]
+pub struct SessionOnTime {
+    /// The time the last event of the current session was seen
+    pub(crate) last_seen: Option<u64>,
+    pub(crate) max_groups: usize,
+    /// How long a session can be inactive before it is closed
+    pub(crate) gap: u64,
+    pub(crate) script: Option<WindowDefinition<'static>>,
+}
+
+impl SessionOnTime {
+    pub(crate) fn reset(&mut self) {
+        self.last_seen = None;
+    }
+
+    pub fn from_stmt(
+        gap: u64,
+        max_groups: usize,
+        script: Option<&WindowDefinition<'static>>,
+    ) -> Self {
+        let script = script.cloned();
+        Self {
+            last_seen: None,
+            max_groups,
+            gap,
+            script,
+        }
+    }
+}
+
+impl Trait for SessionOnTime {
+    fn max_groups(&self) -> usize {
+        self.max_groups
+    }
+    fn snapshot(&self) -> Value<'static> {
+        Value::from(self.last_seen)
+    }
+    fn restore(&mut self, snapshot: &Value) {
+        self.last_seen = snapshot.as_u64();
+    }
+    fn on_event(
+        &mut self,
+        data: &ValueAndMeta,
+        ingest_ns: u64,
+        origin_uri: &Option<EventOriginUri>,
+    ) -> Result<Actions> {
+        let time = stry!(self
+            .script
+            .as_ref()
+            .and_then(|script| script.script.as_ref())
+            .map_or(Ok(ingest_ns), |script| {
+                script_value(script, data, ingest_ns, origin_uri)
+            }));
+        match self.last_seen {
+            Some(last_seen) if time.saturating_sub(last_seen) >= self.gap => {
+                // the session expired before this event arrived, so the event
+                // starts a new session
+                self.last_seen = Some(time);
+                Ok(Actions {
+                    include: false,
+                    emit: true,
+                })
+            }
+            Some(last_seen) => {
+                // the event extends the current session, late events
+                // are merged into it but do not move it back
+                self.last_seen = Some(last_seen.max(time));
+                Ok(Actions::all_false())
+            }
+            None => {
+                self.last_seen = Some(time);
+                Ok(Actions::all_false())
+            }
+        }
+    }
+
+    fn on_tick(&mut self, ns: u64) -> Actions {
+        match self.last_seen {
+            // we ignore ticks when we have a script with a custom timestamp
+            Some(last_seen)
+                if self.script.is_none() && ns.saturating_sub(last_seen) >= self.gap =>
+            {
+                self.last_seen = None;
+                Actions {
+                    include: false,
+                    emit: true,
+                }
+            }
+            _ => Actions::all_false(),
+        }
+    }
+}
+
+/// Where an event time window places an event
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Slot {
+    /// The open window
+    Open,
+    /// The window `n` intervals after the open window, that the
+    /// watermark has not reached yet
+    Pending(usize),
+    /// The window `n` intervals before the open window, it was already
+    /// emitted but is still within the allowed lateness
+    Closed(usize),
+    /// The event is later than the allowed lateness permits
+    Late,
+}
+
+/// How an event time window handles an event
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Assignment {
+    /// The number of windows the watermark closed before the event is placed
+    pub closes: u64,
+    /// Where the event belongs, relative to the open window after closing
+    pub slot: Slot,
+}
+
+/// A tumbling window over the time provided by the windows script, rather
+/// than the ingest time.
+///
+/// Windows are closed once the watermark, the highest event time seen minus
+/// `max_out_of_orderness`, passes their end. Events for windows that have
+/// been emitted already update and re-emit those windows as long as they
+/// are within `allowed_lateness`, later events are considered late.
+///
+/// The highest event time is tracked by the select for the window as a whole,
+/// so the watermark advanced by one group closes the windows of all groups.
+#[derive(Default, Debug, Clone)]
+pub struct TumblingOnEventTime {
+    /// Start of the open window
+    pub(crate) start: Option<u64>,
+    pub(crate) max_groups: usize,
+    /// How long a window lasts
+    pub(crate) interval: u64,
+    /// How far the watermark trails the highest event time seen
+    pub(crate) max_out_of_orderness: u64,
+    /// How long after the watermark passed a windows end it can still be updated
+    pub(crate) allowed_lateness: u64,
+    pub(crate) script: Option<WindowDefinition<'static>>,
+}
+
+impl TumblingOnEventTime {
+    pub(crate) fn reset(&mut self) {
+        self.start = None;
+    }
+
+    pub fn from_stmt(
+        interval: u64,
+        max_out_of_orderness: u64,
+        allowed_lateness: u64,
+        max_groups: usize,
+        script: Option<&WindowDefinition<'static>>,
+    ) -> Self {
+        let script = script.cloned();
+        Self {
+            start: None,
+            max_groups,
+            interval,
+            max_out_of_orderness,
+            allowed_lateness,
+            script,
+        }
+    }
+
+    /// The time of an event, as provided by the windows script
+    ///
+    /// # Errors
+    /// if the windows script fails or doesn't provide a timestamp
+    pub fn event_time(
+        &self,
+        data: &ValueAndMeta,
+        ingest_ns: u64,
+        origin_uri: &Option<EventOriginUri>,
+    ) -> Result<u64> {
+        self.script
+            .as_ref()
+            .and_then(|script| script.script.as_ref())
+            .map_or(Ok(ingest_ns), |script| {
+                script_value(script, data, ingest_ns, origin_uri)
+            })
+    }
+
+    /// The watermark once `max_time` is the highest event time seen
+    #[must_use]
+    pub fn watermark(&self, max_time: u64) -> u64 {
+        max_time.saturating_sub(self.max_out_of_orderness)
+    }
+
+    /// Moves the open window along to the `watermark`, returns how many
+    /// windows were closed by it
+    pub fn advance(&mut self, watermark: u64) -> u64 {
+        match self.start {
+            Some(start) if watermark >= start.saturating_add(self.interval) => {
+                let closes = (watermark - start) / self.interval;
+                self.start = Some(start + closes * self.interval);
+                closes
+            }
+            _ => 0,
+        }
+    }
+
+    /// Assigns an event with the event time `time` to a window and moves the
+    /// open window along to the `watermark`
+    pub fn assign(&mut self, time: u64, watermark: u64) -> Assignment {
+        // the first event opens the first window
+        let start = *self.start.get_or_insert(time);
+        let closes = self.advance(watermark);
+        let start = self.start.unwrap_or(start);
+        let interval = self.interval;
+
+        let slot = if time >= start {
+            match (time - start) / interval {
+                0 => Slot::Open,
+                n => Slot::Pending(usize::try_from(n).unwrap_or(usize::MAX)),
+            }
+        } else {
+            // 1 is the window right before the open one
+            let n = (start - time - 1) / interval + 1;
+            let end = start - (n - 1) * interval;
+            if watermark < end.saturating_add(self.allowed_lateness) {
+                Slot::Closed(usize::try_from(n).unwrap_or(usize::MAX))
+            } else {
+                Slot::Late
+            }
+        };
+        Assignment { closes, slot }
+    }
+}
+
+impl Trait for TumblingOnEventTime {
+    fn max_groups(&self) -> usize {
+        self.max_groups
+    }
+    fn snapshot(&self) -> Value<'static> {
+        literal!(self.start)
+    }
+    fn restore(&mut self, snapshot: &Value) {
+        self.start = snapshot.as_u64();
+    }
+    fn closed_windows(&self) -> usize {
+        let windows = (self.allowed_lateness + self.interval - 1) / self.interval;
+        usize::try_from(windows).unwrap_or(usize::MAX)
+    }
+    /// Only gives a simplified view with the events own time as watermark,
+    /// the select operator uses `assign` to handle out of order and late events.
+    fn on_event(
+        &mut self,
+        data: &ValueAndMeta,
+        ingest_ns: u64,
+        origin_uri: &Option<EventOriginUri>,
+    ) -> Result<Actions> {
+        let time = stry!(self.event_time(data, ingest_ns, origin_uri));
+        let assignment = self.assign(time, self.watermark(time));
+        Ok(Actions {
+            include: false,
+            emit: assignment.closes > 0,
+        })
+    }
+}
@@ -18,7 +18,7 @@ use crate::{
     op::{
         self,
         identity::PassthroughFactory,
-        prelude::{IN, OUT},
+        prelude::{IN, LATE, OUT},
         trickle::{operator::TrickleOperator, select::Select, simple_select::SimpleSelect, window},
     },
     ConfigGraph, Connection, ExecPortIndexMap, ExecutableGraph, NodeConfig, NodeKind, NodeMetrics,
@@ -98,26 +98,80 @@ fn resolve_output_port(port: &(Ident, Ident)) -> OutputPort {
 }
 
 pub(crate) fn window_defn_to_impl(d: &WindowDefinition<'static>) -> Result<window::Impl> {
-    use op::trickle::window::{TumblingOnNumber, TumblingOnTime};
+    use op::trickle::window::{
+        SessionOnTime, SlidingOnNumber, SlidingOnTime, TumblingOnEventTime, TumblingOnNumber,
+        TumblingOnTime,
+    };
+    let script = if d.script.is_some() { Some(d) } else { None };
+    let with = d.params.render()?;
+    let max_groups = with
+        .get(WindowDefinition::MAX_GROUPS)
+        .and_then(Value::as_usize)
+        .unwrap_or(window::Impl::DEFAULT_MAX_GROUPS);
+    let interval = with.get(WindowDefinition::INTERVAL).and_then(Value::as_u64);
+    let size = with.get(WindowDefinition::SIZE).and_then(Value::as_u64);
+    let event_time = with
+        .get(WindowDefinition::EVENT_TIME)
+        .and_then(Value::as_bool)
+        .unwrap_or_default();
+    let max_out_of_orderness = with
+        .get(WindowDefinition::MAX_OUT_OF_ORDERNESS)
+        .and_then(Value::as_u64);
+    let allowed_lateness = with
+        .get(WindowDefinition::ALLOWED_LATENESS)
+        .and_then(Value::as_u64);
+    if event_time && !(d.kind == WindowKind::Tumbling && interval.is_some() && size.is_none()) {
+        return Err(Error::from(
+            "Bad window configuration, only `tumbling` windows with an `interval` support `event_time`.",
+        ));
+    }
+    if !event_time && (max_out_of_orderness.is_some() || allowed_lateness.is_some()) {
+        return Err(Error::from(
+            "Bad window configuration, `max_out_of_orderness` and `allowed_lateness` require `event_time = true`.",
+        ));
+    }
+
     match &d.kind {
-        WindowKind::Sliding => Err("Sliding windows are not yet implemented".into()),
-        WindowKind::Tumbling => {
-            let script = if d.script.is_some() { Some(d) } else { None };
-            let with = d.params.render()?;
-            let max_groups = with
-                .get(WindowDefinition::MAX_GROUPS)
-                .and_then(Value::as_usize)
-                .unwrap_or(window::Impl::DEFAULT_MAX_GROUPS);
-
-            match (
-                with.get(WindowDefinition::INTERVAL).and_then(Value::as_u64),
-                with.get(WindowDefinition::SIZE).and_then(Value::as_u64),
-            ) {
-                (Some(interval), None) => Ok(window::Impl::from(TumblingOnTime::from_stmt(
-                    interval, max_groups, script,
+        WindowKind::Session => {
+            let gap = with
+                .get(WindowDefinition::GAP)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| {
+                    Error::from("Bad window configuration, session windows require a `gap`.")
+                })?;
+            if interval.is_some() || size.is_some() {
+                Err(Error::from(
+                    "Bad window configuration, session windows allow neither `size` nor `interval`.",
+                ))
+            } else if gap == 0 {
+                Err(Error::from(
+                    "Bad window configuration, `gap` must be greater than zero.",
+                ))
+            } else {
+                Ok(window::Impl::from(SessionOnTime::from_stmt(
+                    gap, max_groups, script,
+                )))
+            }
+        }
+        WindowKind::Sliding => {
+            let slide = with
+                .get(WindowDefinition::SLIDE)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| {
+                    Error::from("Bad window configuration, sliding windows require a `slide`.")
+                })?;
+            match (interval, size) {
+                (Some(interval), None) => Ok(window::Impl::from(SlidingOnTime::from_stmt(
+                    slide,
+                    sliding_window_panes(interval, slide)?,
+                    max_groups,
+                    script,
                 ))),
-                (None, Some(size)) => Ok(window::Impl::from(TumblingOnNumber::from_stmt(
-                    size, max_groups, script,
+                (None, Some(size)) => Ok(window::Impl::from(SlidingOnNumber::from_stmt(
+                    slide,
+                    sliding_window_panes(size, slide)?,
+                    max_groups,
+                    script,
                 ))),
                 (Some(_), Some(_)) => Err(Error::from(
                     "Bad window configuration, only one of `size` or `interval` is allowed.",
@@ -127,8 +181,59 @@ pub(crate) fn window_defn_to_impl(d: &WindowDefinition<'static>) -> Result<windo
                 )),
             }
         }
+        WindowKind::Tumbling => match (interval, size) {
+            // an event time window uses the time its script provides, so it
+            // is driven by a watermark instead of ticks
+            (Some(interval), None) if event_time => {
+                if script.is_none() {
+                    Err(Error::from(
+                        "Bad window configuration, `event_time` windows require a script providing the event time.",
+                    ))
+                } else if interval == 0 {
+                    Err(Error::from(
+                        "Bad window configuration, `interval` must be greater than zero.",
+                    ))
+                } else {
+                    Ok(window::Impl::from(TumblingOnEventTime::from_stmt(
+                        interval,
+                        max_out_of_orderness.unwrap_or_default(),
+                        allowed_lateness.unwrap_or_default(),
+                        max_groups,
+                        script,
+                    )))
+                }
+            }
+            (Some(interval), None) => Ok(window::Impl::from(TumblingOnTime::from_stmt(
+                interval, max_groups, script,
+            ))),
+            (None, Some(size)) => Ok(window::Impl::from(TumblingOnNumber::from_stmt(
+                size, max_groups, script,
+            ))),
+            (Some(_), Some(_)) => Err(Error::from(
+                "Bad window configuration, only one of `size` or `interval` is allowed.",
+            )),
+            (None, None) => Err(Error::from(
+                "Bad window configuration, either `size` or `interval` is required.",
+            )),
+        },
+    }
+}
+
+/// Number of panes a sliding window of `len` is split into, `len` needs to be
+/// a multiple of `slide` so every emission covers the same amount of panes.
+fn sliding_window_panes(len: u64, slide: u64) -> Result<usize> {
+    if slide == 0 || slide > len || len % slide != 0 {
+        return Err(Error::from(
+            "Bad window configuration, `slide` must be greater than zero and divide the window `size` or `interval` evenly.",
+        ));
     }
+    usize::try_from(len / slide)
+        .map_err(|_| Error::from("Bad window configuration, too many slides."))
 }
+
+/// How often operator state is persisted if a `state_dir` is configured
+const DEFAULT_SNAPSHOT_INTERVAL_S: u64 = 10;
+
 /// A Tremor Query
 #[derive(Clone, Debug)]
 pub struct Query(pub tremor_script::query::Query);
@@ -139,6 +244,15 @@ impl Query {
         self.0.query.config.get("id").and_then(ValueAccess::as_str)
     }
 
+    /// Fetches the directory operator state is persisted in, if it was provided
+    pub fn state_dir(&self) -> Option<&str> {
+        self.0
+            .query
+            .config
+            .get("state_dir")
+            .and_then(ValueAccess::as_str)
+    }
+
     /// Parse a query
     ///
     /// # Errors
@@ -172,6 +286,14 @@ impl Query {
             .get("metrics_interval_s")
             .and_then(Value::as_u64)
             .map(|i| i * 1_000_000_000);
+        let snapshot_interval = self
+            .0
+            .query
+            .config
+            .get("snapshot_interval_s")
+            .and_then(Value::as_u64)
+            .unwrap_or(DEFAULT_SNAPSHOT_INTERVAL_S)
+            * 1_000_000_000;
 
         let pipeline_id = self
             .0
@@ -293,6 +415,42 @@ impl Query {
                         }
                     }
 
+                    // events that are too late for an event time window are
+                    // sent to the `late` output port of the pipeline
+                    let windows = select_windows(&select, &helper)?;
+                    if let Some((_, window::Impl::TumblingEventTimeBased(_))) = windows.first() {
+                        let select_late = OutputPort {
+                            id: select_in.id.clone(),
+                            port: LATE,
+                            had_port: false,
+                            mid: Box::new(s.meta().clone()),
+                        };
+                        // a `late` output declared by the query is used as is
+                        let name: Cow<'static, str> =
+                            if self.0.query.into.iter().any(|i| i.id == LATE) {
+                                LATE
+                            } else {
+                                format!("out/{LATE}").into()
+                            };
+                        if !nodes_by_name.contains_key(&name) {
+                            let id = pipe_graph.add_node(NodeConfig {
+                                id: name.to_string(),
+                                label: Some(name.to_string()),
+                                kind: NodeKind::Output(LATE),
+                                op_type: "passthrough".to_string(),
+                                ..NodeConfig::default()
+                            });
+                            nodes_by_name.insert(name.clone(), id);
+                        }
+                        let late = InputPort {
+                            id: name,
+                            port: IN,
+                            had_port: false,
+                            mid: Box::new(s.meta().clone()),
+                        };
+                        links.entry(select_late).or_default().push(late);
+                    }
+
                     links.entry(from).or_default().push(select_in.clone());
                     links.entry(select_out).or_default().push(into);
 
@@ -302,6 +460,7 @@ impl Query {
                         kind: NodeKind::Select,
                         op_type: "trickle::select".to_string(),
                         stmt: Some(stmt.clone()),
+                        windows,
                         ..NodeConfig::default()
                     };
                     let id = pipe_graph.add_node(node.clone());
@@ -657,6 +816,9 @@ impl Query {
                 contraflow,
                 signalflow,
                 metric_interval,
+                state_backend: None,
+                snapshot_interval,
+                last_snapshot: 0,
                 insights: Vec::new(),
                 dot: format!("{}", dot),
                 metrics_channel: METRICS_CHANNEL.tx(),
@@ -713,7 +875,6 @@ fn select(
     operator_uid: OperatorId,
     config: &NodeConfig,
     node: &ast::SelectStmt<'static>,
-    helper: &Helper<'static, '_>,
 ) -> Result<Box<dyn Operator>> {
     let select_type = node.complexity();
     match select_type {
@@ -722,30 +883,50 @@ fn select(
             op.node_to_operator(operator_uid, config)
         }
         SelectType::Simple => Ok(Box::new(SimpleSelect::with_stmt(node))),
-        SelectType::Normal => {
-            let windows: Result<Vec<(String, window::Impl)>> = node
-                .stmt
-                .windows
-                .iter()
-                .map(|w| {
-                    helper
-                        .get::<WindowDefinition>(&w.id)?
-                        .ok_or_else(|| {
-                            Error::from(ErrorKind::BadOpConfig(format!(
-                                "Unknown window: {} available",
-                                &w.id,
-                            )))
-                        })
-                        .and_then(|mut imp| {
-                            ConstFolder::new(helper).walk_window_defn(&mut imp)?;
-                            Ok((w.id.id().to_string(), window_defn_to_impl(&imp)?))
-                        })
-                })
-                .collect();
+        SelectType::Normal => Ok(Box::new(Select::from_stmt(
+            operator_uid,
+            config.windows.clone(),
+            node,
+        ))),
+    }
+}
 
-            Ok(Box::new(Select::from_stmt(operator_uid, windows?, node)))
-        }
+/// Resolves the windows of a select statement to their implementations
+fn select_windows(
+    node: &ast::SelectStmt<'static>,
+    helper: &Helper<'static, '_>,
+) -> Result<Vec<(String, window::Impl)>> {
+    let windows = node
+        .stmt
+        .windows
+        .iter()
+        .map(|w| {
+            helper
+                .get::<WindowDefinition>(&w.id)?
+                .ok_or_else(|| {
+                    Error::from(ErrorKind::BadOpConfig(format!(
+                        "Unknown window: {} available",
+                        &w.id,
+                    )))
+                })
+                .and_then(|mut imp| {
+                    ConstFolder::new(helper).walk_window_defn(&mut imp)?;
+                    Ok((w.id.id().to_string(), window_defn_to_impl(&imp)?))
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // later tilt frames get the aggregates of the windows before them
+    // instead of events, so they have no event time to go by
+    if let Some((name, _)) = windows
+        .iter()
+        .skip(1)
+        .find(|(_, w)| matches!(w, window::Impl::TumblingEventTimeBased(_)))
+    {
+        return Err(Error::from(format!(
+            "Bad window configuration, the event time window `{name}` can only be the first window of a select."
+        )));
     }
+    Ok(windows)
 }
 
 fn operator(
@@ -774,7 +955,7 @@ pub(crate) fn supported_operators(
                 warnings: BTreeSet::new(),
             }))
         }
-        Some(tremor_script::ast::Stmt::SelectStmt(s)) => select(uid, config, s, helper)?,
+        Some(tremor_script::ast::Stmt::SelectStmt(s)) => select(uid, config, s)?,
         Some(ast::Stmt::OperatorDefinition(o)) => operator(uid, o, helper)?,
         _ => crate::operator(uid, config)?,
     };
@@ -794,6 +975,8 @@ mod test {
     use tremor_common::ids::Id;
 
     use super::*;
+    use crate::Event;
+    use tremor_value::literal;
     #[test]
     fn query() {
         let aggr_reg = tremor_script::aggr_registry();
@@ -826,4 +1009,140 @@ mod test {
         assert_eq!(out.id, "out/test_out");
         assert_eq!(out.kind, NodeKind::Output("test_out".into()));
     }
+
+    #[async_std::test]
+    async fn restore_state() -> Result<()> {
+        let aggr_reg = tremor_script::aggr_registry();
+        let src = r#"
+        define window two from tumbling with size = 2 end;
+        define script counter
+        script
+          let state = match state of case null => 1 default => state + 1 end;
+          state
+        end;
+        create script counter;
+        select event from in into counter;
+        select aggr::stats::sum(event) from counter[two] into out;
+        "#;
+        let q = Query::parse(src, &*tremor_script::FN_REGISTRY.read()?, &aggr_reg)?;
+        let dir = tempfile::tempdir()?;
+        let mut idgen = OperatorIdGen::new();
+        let mut returns = vec![];
+
+        let mut g = q.to_pipe(&mut idgen)?;
+        g.set_state_backend(Box::new(crate::state::Sled::open(dir.path())?))?;
+        g.enqueue("in", Event::default(), &mut returns).await?;
+        assert!(returns.is_empty());
+        g.snapshot()?;
+        drop(g);
+
+        // the script state and the open window survive the restart
+        let mut g = q.to_pipe(&mut idgen)?;
+        g.set_state_backend(Box::new(crate::state::Sled::open(dir.path())?))?;
+        g.enqueue("in", Event::default(), &mut returns).await?;
+        assert_eq!(1, returns.len());
+        assert_eq!(&Value::from(3.0), returns[0].1.data.suffix().value());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn restore_state_replayed_events() -> Result<()> {
+        let aggr_reg = tremor_script::aggr_registry();
+        let src = r#"
+        define window three from tumbling with size = 3 end;
+        select aggr::stats::sum(event.v) from in[three] into out;
+        "#;
+        let q = Query::parse(src, &*tremor_script::FN_REGISTRY.read()?, &aggr_reg)?;
+        let dir = tempfile::tempdir()?;
+        let mut idgen = OperatorIdGen::new();
+        let mut returns = vec![];
+        let event = |pull_id: u64| Event {
+            id: (1, 1, pull_id).into(),
+            data: literal!({ "v": pull_id }).into(),
+            transactional: true,
+            ..Event::default()
+        };
+
+        let mut g = q.to_pipe(&mut idgen)?;
+        g.set_state_backend(Box::new(crate::state::Sled::open(dir.path())?))?;
+        g.enqueue("in", event(1), &mut returns).await?;
+        g.enqueue("in", event(2), &mut returns).await?;
+        assert!(returns.is_empty());
+        g.snapshot()?;
+        drop(g);
+
+        // the source sends the unacknowledged events again, they are in the restored window already
+        let mut g = q.to_pipe(&mut idgen)?;
+        g.set_state_backend(Box::new(crate::state::Sled::open(dir.path())?))?;
+        g.enqueue("in", event(1), &mut returns).await?;
+        g.enqueue("in", event(2), &mut returns).await?;
+        assert!(returns.is_empty());
+        g.enqueue("in", event(3), &mut returns).await?;
+        assert_eq!(1, returns.len());
+        let (_, emitted) = &returns[0];
+        assert_eq!(&Value::from(6.0), emitted.data.suffix().value());
+        // acknowledging the window acknowledges the events from before the restart
+        assert_eq!(Some(1), emitted.id.get_min_by_stream(1, 1));
+        assert_eq!(Some(3), emitted.id.get_max_by_stream(1, 1));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn event_time_late_events() -> Result<()> {
+        let aggr_reg = tremor_script::aggr_registry();
+        let src = r#"
+        define window ten from tumbling
+        with
+            interval = 10,
+            event_time = true
+        script
+            event.t
+        end;
+        select aggr::stats::count(event) from in[ten] into out;
+        "#;
+        let q = Query::parse(src, &*tremor_script::FN_REGISTRY.read()?, &aggr_reg)?;
+        let mut idgen = OperatorIdGen::new();
+        let mut g = q.to_pipe(&mut idgen)?;
+        let mut returns = vec![];
+        for t in [0_u64, 5, 12, 3] {
+            let event = Event {
+                id: (1, 1, t).into(),
+                ingest_ns: t,
+                data: literal!({ "t": t }).into(),
+                ..Event::default()
+            };
+            g.enqueue("in", event, &mut returns).await?;
+        }
+        // [0, 10) is closed by 12, 3 is too late for it and is sent to the `late` port
+        assert_eq!(2, returns.len());
+        assert_eq!("out", returns[0].0);
+        assert_eq!(Some(2), returns[0].1.data.suffix().value().as_u64());
+        assert_eq!("late", returns[1].0);
+        assert_eq!(Some(3), returns[1].1.data.suffix().value().get_u64("t"));
+        Ok(())
+    }
+
+    #[test]
+    fn event_time_tilt_frames() -> Result<()> {
+        let aggr_reg = tremor_script::aggr_registry();
+        let src = r#"
+        define window ten from tumbling
+        with
+            interval = 10,
+            event_time = true
+        script
+            event.t
+        end;
+        define window two from tumbling
+        with
+            size = 2
+        end;
+        select aggr::stats::count(event) from in[two, ten] into out;
+        "#;
+        let q = Query::parse(src, &*tremor_script::FN_REGISTRY.read()?, &aggr_reg)?;
+        let mut idgen = OperatorIdGen::new();
+        // event time windows can only be the first window of a select
+        assert!(q.to_pipe(&mut idgen).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,101 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::errors::Result;
+use std::path::Path;
+use tremor_script::prelude::*;
+
+/// A store for the snapshots of operator state, snapshots are
+/// keyed by the id of the node in the pipeline.
+pub trait Backend: std::fmt::Debug + Send + Sync {
+    /// Loads the last snapshot of a node
+    ///
+    /// # Errors
+    /// if the snapshot can't be read or decoded
+    fn load(&self, node: &str) -> Result<Option<Value<'static>>>;
+
+    /// Stores the snapshot of a node, replacing the previous one
+    ///
+    /// # Errors
+    /// if the snapshot can't be written
+    fn store(&self, node: &str, snapshot: &Value) -> Result<()>;
+
+    /// Makes sure all stored snapshots are persisted
+    ///
+    /// # Errors
+    /// if the snapshots can't be persisted
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A local on-disk state backend using sled
+#[derive(Debug)]
+pub struct Sled {
+    db: sled::Db,
+}
+
+impl Sled {
+    /// Opens, or creates, the store in `dir`
+    ///
+    /// # Errors
+    /// if the store can't be opened
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Ok(Self {
+            db: sled::open(dir)?,
+        })
+    }
+}
+
+impl Backend for Sled {
+    fn load(&self, node: &str) -> Result<Option<Value<'static>>> {
+        if let Some(data) = self.db.get(node)? {
+            let mut data = data.to_vec();
+            Ok(Some(tremor_value::parse_to_value(&mut data)?.into_static()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn store(&self, node: &str, snapshot: &Value) -> Result<()> {
+        self.db.insert(node, snapshot.encode().into_bytes())?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    #[test]
+    fn sled_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let backend = Sled::open(dir.path())?;
+        assert_eq!(None, backend.load("select_0")?);
+        let snapshot = literal!({"state": [1, 2.5, "snot"], "op": null});
+        backend.store("select_0", &snapshot)?;
+        backend.flush()?;
+        drop(backend);
+
+        let backend = Sled::open(dir.path())?;
+        assert_eq!(Some(snapshot), backend.load("select_0")?);
+        Ok(())
+    }
+}
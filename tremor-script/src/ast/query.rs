@@ -326,6 +326,8 @@ pub enum WindowKind {
     Sliding,
     /// we're forced to make this pub because of lalrpop
     Tumbling,
+    /// we're forced to make this pub because of lalrpop
+    Session,
 }
 
 /// A window definition
@@ -353,6 +355,16 @@ impl<'script> WindowDefinition<'script> {
     pub const INTERVAL: &'static str = "interval";
     /// `size` setting
     pub const SIZE: &'static str = "size";
+    /// `slide` setting
+    pub const SLIDE: &'static str = "slide";
+    /// `gap` setting
+    pub const GAP: &'static str = "gap";
+    /// `event_time` setting
+    pub const EVENT_TIME: &'static str = "event_time";
+    /// `max_out_of_orderness` setting
+    pub const MAX_OUT_OF_ORDERNESS: &'static str = "max_out_of_orderness";
+    /// `allowed_lateness` setting
+    pub const ALLOWED_LATENESS: &'static str = "allowed_lateness";
 }
 
 /// A select statement
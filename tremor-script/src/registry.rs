@@ -71,6 +71,16 @@ pub trait TremorAggrFn: DowncastSync + Sync + Send {
     fn warning(&self) -> Option<String> {
         None
     }
+    /// Captures the accumulated state so it can be persisted, returns
+    /// `None` if the function does not support this.
+    fn snapshot(&self) -> Option<Value<'static>> {
+        None
+    }
+    /// Restores the state captured by `snapshot`, returns `false`
+    /// if the state could not be restored.
+    fn restore(&mut self, _state: &Value) -> bool {
+        false
+    }
 }
 impl_downcast!(sync TremorAggrFn);
 
@@ -774,6 +784,23 @@ impl TremorAggrFnWrapper {
         use std::borrow::Borrow;
         self.fun.merge(src.fun.borrow())
     }
+
+    /// The fully qualified name of the function
+    #[must_use]
+    pub fn fqn(&self) -> String {
+        format!("{}::{}", self.module, self.name)
+    }
+
+    /// Captures the accumulated state so it can be persisted
+    #[must_use]
+    pub fn snapshot(&self) -> Option<Value<'static>> {
+        self.fun.snapshot()
+    }
+
+    /// Restores the state captured by `snapshot`
+    pub fn restore(&mut self, state: &Value) -> bool {
+        self.fun.restore(state)
+    }
 }
 
 // #[cfg_attr(coverage, no_coverage)]
@@ -71,6 +71,12 @@ impl TremorAggrFn for Count {
     fn arity(&self) -> RangeInclusive<usize> {
         0..=0
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(Value::from(self.0))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        state.as_i64().map(|v| self.0 = v).is_some()
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -111,6 +117,12 @@ impl TremorAggrFn for Sum {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(Value::from(self.0))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        state.cast_f64().map(|v| self.0 = v).is_some()
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -157,6 +169,22 @@ impl TremorAggrFn for Mean {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(Value::from(vec![Value::from(self.0), Value::from(self.1)]))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        match state.as_array().map(Vec::as_slice) {
+            Some([n, sum]) => n
+                .as_i64()
+                .zip(sum.cast_f64())
+                .map(|(n, sum)| {
+                    self.0 = n;
+                    self.1 = sum;
+                })
+                .is_some(),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -201,6 +229,17 @@ impl TremorAggrFn for Min {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(self.0.map_or_else(Value::null, Value::from))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        if state.is_null() {
+            self.0 = None;
+            true
+        } else {
+            state.cast_f64().map(|v| self.0 = Some(v)).is_some()
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -244,6 +283,17 @@ impl TremorAggrFn for Max {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(self.0.map_or_else(Value::null, Value::from))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        if state.is_null() {
+            self.0 = None;
+            true
+        } else {
+            state.cast_f64().map(|v| self.0 = Some(v)).is_some()
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -305,6 +355,25 @@ impl TremorAggrFn for Var {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(Value::from(vec![
+            Value::from(self.n),
+            Value::from(self.k),
+            Value::from(self.ex),
+            Value::from(self.ex2),
+        ]))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        if let Some([n, k, ex, ex2]) = state.as_array().map(Vec::as_slice) {
+            if let (Some(n), Some(k), Some(ex), Some(ex2)) =
+                (n.as_u64(), k.cast_f64(), ex.cast_f64(), ex2.cast_f64())
+            {
+                *self = Self { n, k, ex, ex2 };
+                return true;
+            }
+        }
+        false
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -338,6 +407,12 @@ impl TremorAggrFn for Stdev {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        self.0.snapshot()
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        self.0.restore(state)
+    }
 }
 
 #[derive(Clone)]
@@ -792,6 +867,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn snapshot_restore() -> Result<()> {
+        let one = Value::from(1);
+        let four = Value::from(4);
+        let mut a = Var::default();
+        a.init();
+        a.accumulate(&[&one])?;
+        a.accumulate(&[&four])?;
+        let state = a.snapshot().ok_or_else(|| FunctionError::RuntimeError {
+            mfa: mfa("stats", "var", 1),
+            error: "no snapshot".to_string(),
+        })?;
+        let mut b = Var::default();
+        assert!(b.restore(&state));
+        assert_eq!(a.emit()?, b.emit()?);
+
+        let mut c = Mean::default();
+        assert!(!c.restore(&Value::from("snot")));
+        assert!(c.restore(&Value::from(vec![2, 5])));
+        assert_eq!(c.emit()?, 2.5);
+
+        let mut d = Min::default();
+        assert!(d.restore(&Value::null()));
+        assert!(d.restore(&one));
+        assert_eq!(d.emit()?, 1.0);
+        Ok(())
+    }
+
     #[test]
     fn min() -> Result<()> {
         let mut a = Min::default();
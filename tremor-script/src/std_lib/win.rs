@@ -55,6 +55,18 @@ impl TremorAggrFn for First {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        // wrapped in an array so a `null` value can be told apart from no value
+        Some(Value::from(self.0.iter().cloned().collect::<Vec<_>>()))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        match state.as_array().map(Vec::as_slice) {
+            Some([]) => self.0 = None,
+            Some([v]) => self.0 = Some(v.clone_static()),
+            _ => return false,
+        }
+        true
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -92,6 +104,18 @@ impl TremorAggrFn for Last {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        // wrapped in an array so a `null` value can be told apart from no value
+        Some(Value::from(self.0.iter().cloned().collect::<Vec<_>>()))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        match state.as_array().map(Vec::as_slice) {
+            Some([]) => self.0 = None,
+            Some([v]) => self.0 = Some(v.clone_static()),
+            _ => return false,
+        }
+        true
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -130,6 +154,15 @@ impl TremorAggrFn for CollectFlattened {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(Value::from(self.0.clone()))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        state
+            .as_array()
+            .map(|a| self.0 = a.iter().map(|v| v.clone_static()).collect())
+            .is_some()
+    }
     fn warning(&self) -> Option<String> {
         Some(String::from(
             "Collect functions are very expensive memory wise, try avoiding them.",
@@ -173,6 +206,15 @@ impl TremorAggrFn for CollectNested {
     fn arity(&self) -> RangeInclusive<usize> {
         1..=1
     }
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(Value::from(self.0.clone()))
+    }
+    fn restore(&mut self, state: &Value) -> bool {
+        state
+            .as_array()
+            .map(|a| self.0 = a.iter().map(|v| v.clone_static()).collect())
+            .is_some()
+    }
     fn warning(&self) -> Option<String> {
         Some(String::from(
             "Collect functions are very expensive memory wise, try avoiding them.",
@@ -237,6 +279,22 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn first_snapshot_restore() -> Result<()> {
+        let mut a = First::default();
+        let mut b = First::default();
+        a.init();
+        b.init();
+        // an empty window and a window with a `null` value are different
+        assert!(b.restore(&a.snapshot().unwrap_or_default()));
+        assert!(b.0.is_none());
+        a.accumulate(&[&Value::null()])?;
+        assert!(b.restore(&a.snapshot().unwrap_or_default()));
+        assert_eq!(b.0, Some(Value::null()));
+        assert!(!b.restore(&Value::from(1)));
+        Ok(())
+    }
+
     #[test]
     fn collect() -> Result<()> {
         let mut a = CollectFlattened::default();